@@ -41,11 +41,11 @@ export type RewriteTypeWithValue = {
     value: string;
 };
 
-export type Rewrite = { type: "Filename" | "MIME" } | RewriteTypeWithValue;
+export type Rewrite = { type: "Filename" | "MIME" | "DeletionToken" } | RewriteTypeWithValue;
 
 export type URLEncodedBody = {
     type: "URLEncoded";
-    content: [
+    value: [
         { [key: string]: string },
         {
             name: string;
@@ -55,18 +55,80 @@ export type URLEncodedBody = {
 };
 
 export type MultipartBody = {
-    type: "Multipart";
-    content: [
+    type: "MultipartForm";
+    value: [
         { [key: string]: string },
         FormDataEntryKey,
     ];
 };
 
-export type HTTPBody = { type: "Raw" } | URLEncodedBody | MultipartBody;
+export type JSONBody = {
+    type: "JSON";
+    value: [any, string];
+};
+
+export type MultipartFileField = {
+    name: FormDataEntryKey;
+    kind: { type: "Original" } | { type: "Thumbnail"; value: number };
+};
+
+export type MultipartFilesBody = {
+    type: "MultipartFiles";
+    value: [
+        { [key: string]: string },
+        MultipartFileField[],
+    ];
+};
+
+export type HTTPBody = { type: "Raw" } | URLEncodedBody | MultipartBody | JSONBody | MultipartFilesBody;
+
+export type HTTPDeleteRequest = {
+    url_template: string;
+    method: "GET" | "POST" | "PUT" | "PATCH" | "DELETE" | "HEAD";
+    header_templates: { [key: string]: string };
+};
+
+export type HTTPStep = {
+    url_template: string;
+    method: "GET" | "POST" | "PUT" | "PATCH" | "DELETE" | "HEAD";
+    header_templates: { [key: string]: string };
+    body: { type: "Empty" } | { type: "Text"; value: string } | { type: "URLEncoded"; value: { [key: string]: string } };
+    captures: { [key: string]: string };
+};
+
+export type HTTPNetworkOptions = {
+    connect_timeout: number | null;
+    read_timeout: number | null;
+    max_retries: number;
+    proxy: string | null;
+};
+
+export type HTTPSigning = {
+    type: "HMAC";
+    key_template: string;
+    signature_header: string;
+    timestamp_header: string;
+    encoding: "hex" | "base64";
+} | {
+    type: "SigV4";
+    access_key_id_template: string;
+    secret_access_key_template: string;
+    session_token_template: string | null;
+    region_template: string;
+    service: string;
+    unsigned_payload: boolean;
+};
+
+export type PHPLimits = {
+    timeout: number;
+    memory_limit: number;
+    data_quota: number;
+};
 
 export type CustomUploaderHandler = {
     type: "php";
     code: string;
+    limits: PHPLimits;
 } | {
     type: "http";
     rewrites: { [key: string]: Rewrite };
@@ -75,10 +137,29 @@ export type CustomUploaderHandler = {
     header_templates: { [key: string]: string };
     body: HTTPBody;
     response_expr: string;
+    delete_expr: string | null;
+    delete_request: HTTPDeleteRequest | null;
+    pre_steps: HTTPStep[];
+    upload_captures: { [key: string]: string };
+    post_steps: HTTPStep[];
+    error_expr: string | null;
+    network: HTTPNetworkOptions;
+    signing: HTTPSigning | null;
+};
+
+// Defines the metadata added in V2. MIME types can end in /* and an empty list means any file. The max file
+// size is in bytes.
+export type CustomUploaderMetadata = {
+    author: string | null;
+    homepage: string | null;
+    min_app_version: string | null;
+    supported_mime_types: string[];
+    max_file_size: number | null;
 };
 
 export type CustomUploader = {
-    version: "v1";
+    version: "v1" | "v2";
+    metadata?: CustomUploaderMetadata;
     id: string;
     name: string;
     description: string;
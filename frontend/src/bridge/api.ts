@@ -43,9 +43,9 @@ async function baseRequestor(type: string, params?: { [key: string]: any }) {
     );
 }
 
-// Handles deleting a capture.
-export async function deleteCapture(id: string) {
-    await baseRequestor("delete_capture", { id });
+// Handles deleting a capture. If remote is set, the file is deleted from the uploaders as well.
+export async function deleteCapture(id: string, remote: boolean = false) {
+    await baseRequestor("delete_capture", { id, remote });
 }
 
 // Opens the URL for a capture.
@@ -128,6 +128,8 @@ export type Uploader = {
     description: string;
     icon_path: string;
     options: [string, ConfigOption][];
+    accepted_mime_types: string[];
+    max_file_size: number | null;
 };
 
 // Gets the uploaders.
@@ -140,6 +142,11 @@ export async function getCustomUploaders(): Promise<{ [id: string]: Uploader }>
     return baseRequestor("get_custom_uploaders");
 }
 
+// Gets why any custom uploaders could not be loaded, keyed by the uploader ID.
+export async function getCustomUploaderErrors(): Promise<{ [id: string]: string }> {
+    return baseRequestor("get_custom_uploader_errors");
+}
+
 // Creates a custom uploader. Returns true if it actually inserted.
 export async function insertCustomUploader(uploader: CustomUploader, replace: boolean) {
     try {
@@ -153,11 +160,71 @@ export async function insertCustomUploader(uploader: CustomUploader, replace: bo
     return true;
 }
 
+// Imports a ShareX .sxcu file as a custom uploader. Returns null if it already exists and replace is false.
+export async function importSxcu(data: string, replace: boolean): Promise<CustomUploader | null> {
+    try {
+        return await baseRequestor("import_sxcu", { data, replace });
+    } catch (e) {
+        if (e instanceof APIError && !e.userFacing && e.message === "E_ALREADY_EXISTS") {
+            return null;
+        }
+        throw e;
+    }
+}
+
+export type ExportedBundle = {
+    path: string;
+    signer: string | null;
+};
+
+// Exports a custom uploader to a bundle. Returns null if the user cancelled the save dialog.
+export function exportCustomUploader(
+    id: string, includeData: boolean, sign: boolean,
+): Promise<ExportedBundle | null> {
+    return baseRequestor("export_custom_uploader", { id, include_data: includeData, sign });
+}
+
+export type ImportedBundle = {
+    uploader: CustomUploader;
+
+    // The signer is only set if the bundle was signed by a trusted key. A signature by any other key is only a
+    // claim, so it is in untrusted_signer and the bundle should be treated as unsigned.
+    signer: string | null;
+    untrusted_signer: string | null;
+    has_data: boolean;
+};
+
+// Imports a custom uploader bundle. Returns null if the user cancelled the file dialog. Throws an APIError
+// with the message E_ALREADY_EXISTS if the uploader exists and replace is false.
+export function importCustomUploaderBundle(replace: boolean): Promise<ImportedBundle | null> {
+    return baseRequestor("import_custom_uploader_bundle", { replace });
+}
+
+// Gets the public key used to sign bundles exported from this install.
+export function getBundleSigningKey(): Promise<string> {
+    return baseRequestor("get_bundle_signing_key", {});
+}
+
+// Trusts the hex encoded public key to sign bundles. The key of this install is always trusted.
+export async function trustBundleSigner(key: string) {
+    await baseRequestor("trust_bundle_signer", { key });
+}
+
 // Deletes a custom uploader if it exists.
 export async function deleteCustomUploader(id: string) {
     await baseRequestor("delete_custom_uploader", { id });
 }
 
+// Gets the log the PHP script for a custom uploader wrote. This is blank if nothing was logged.
+export function getCustomUploaderLog(id: string): Promise<string> {
+    return baseRequestor("get_custom_uploader_log", { id });
+}
+
+// Clears the log for a custom uploader.
+export async function clearCustomUploaderLog(id: string) {
+    await baseRequestor("clear_custom_uploader_log", { id });
+}
+
 // Defines the callback for hotkeys.
 export let hotkeyCallback: (hotkey: string) => void | null = null;
 
@@ -178,9 +245,44 @@ export async function stopHotkeyCapture() {
     await baseRequestor("stop_hotkey_capture");
 }
 
+// Defines the error returned by an uploader.
+export type UploadError = {
+    type: "config";
+    field: string | null;
+    message: string;
+} | {
+    type: "auth" | "network" | "io" | "local";
+    message: string;
+} | {
+    type: "remote_rejected";
+    status: number;
+    body: string;
+} | {
+    type: "cancelled" | "shutting_down";
+};
+
+// Defines the error thrown when an uploader test fails.
+export class UploaderTestError extends Error {
+    error: UploadError;
+
+    constructor(message: string, error: UploadError) {
+        super(message);
+        this.error = error;
+    }
+
+    // Gets the option that caused the error if it is known.
+    get field() {
+        return this.error.type === "config" ? this.error.field : null;
+    }
+}
+
 // Allows you to test a uploaders configuration.
 export async function testUploader(id: string) {
-    await baseRequestor("test_uploader", { id });
+    const res = await baseRequestor("test_uploader", { id }) as {
+        error: UploadError;
+        message: string;
+    } | null;
+    if (res) throw new UploaderTestError(res.message, res.error);
 }
 
 // Open a save dialog.
@@ -212,3 +314,53 @@ export async function saveConfig() {
 export async function getBuildInfo(key: string) {
     return baseRequestor("get_build_info", { key });
 }
+
+// Defines the result of uploading a capture to one of its destinations.
+export type CaptureUpload = {
+    capture_id: number;
+    uploader_id: string;
+    created_at: string;
+    url: string | null;
+    error: string | null;
+    primary: boolean;
+    thumbnail_url: string | null;
+};
+
+// Gets the result of every upload for a capture.
+export async function getCaptureUploads(id: string): Promise<CaptureUpload[]> {
+    return baseRequestor("get_capture_uploads", { id });
+}
+
+// Defines a upload which failed and is queued to be retried.
+export type QueuedUpload = {
+    id: number;
+    capture_id: number;
+    created_at: string;
+    uploader_id: string;
+    filename: string;
+    file_path: string;
+    owned_file: boolean;
+    attempts: number;
+    last_error: string;
+    next_attempt_at: number | null;
+};
+
+// Lists the uploads which are queued to be retried.
+export async function listUploadQueue(): Promise<QueuedUpload[]> {
+    return baseRequestor("list_upload_queue");
+}
+
+// Retries a queued upload now. Returns the URL if it was successful.
+export async function retryUpload(id: string): Promise<string> {
+    return baseRequestor("retry_upload", { id });
+}
+
+// Cancels a queued upload.
+export async function cancelUpload(id: string) {
+    await baseRequestor("cancel_upload", { id });
+}
+
+// Cancels an upload which is running. The ID is from the upload progress events.
+export async function cancelActiveUpload(id: number) {
+    await baseRequestor("cancel_active_upload", { id: id.toString() });
+}
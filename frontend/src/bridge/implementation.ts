@@ -1,5 +1,6 @@
 import { toBytes } from "fast-base64";
 import { dispatchCaptureHtml } from "./newCaptures";
+import { dispatchUploadProgress } from "./uploadProgress";
 import { getHotkeyCallback } from "./api";
 
 // Defines a map of promises.
@@ -12,6 +13,7 @@ let nextId = 0;
 const persistentHandlers = new Map<number, (data: Uint8Array) => void>([
     [-1, dispatchCaptureHtml],
     [-2, val => getHotkeyCallback()?.(new TextDecoder().decode(val))],
+    [-3, dispatchUploadProgress],
 ]);
 
 // @ts-expect-error: Handling the bridge response.
@@ -0,0 +1,35 @@
+// Defines the progress of an upload which is running.
+export type UploadProgress = {
+    id: number;
+    uploader_id: string;
+    filename: string;
+    sent: number;
+    total: number;
+    done: boolean;
+};
+
+// Defines watchers for upload progress.
+const watchers = new Map<number, (progress: UploadProgress) => void>();
+
+// Defines the next ID.
+let nextId = 0;
+
+// Adds a watcher for upload progress.
+export function addUploadProgressWatcher(watcher: (progress: UploadProgress) => void) {
+    const id = nextId++;
+    watchers.set(id, watcher);
+    return id;
+}
+
+// Removes a watcher for upload progress.
+export function removeUploadProgressWatcher(id: number) {
+    watchers.delete(id);
+}
+
+// Handles dispatching upload progress.
+export function dispatchUploadProgress(progressU8: Uint8Array) {
+    const progress: UploadProgress = JSON.parse(new TextDecoder().decode(progressU8));
+    for (const watcher of watchers.values()) {
+        watcher(progress);
+    }
+}
@@ -74,6 +74,6 @@ export default function Embedded({ uploader, componentName, dbKey, label, descri
             description && <Description description={description} />
         }
 
-        <Embedded value={itemValue} set={set} />
+        <Embedded value={itemValue} set={set} items={uploader?.items} />
     </>;
 }
@@ -3,6 +3,14 @@ import { Props } from "./shared";
 
 const VALID_SUBDOMAIN_REGEX = /^[a-z0-9-]+$/;
 
+const DEFAULT_API_URL = "https://elixi.re";
+
+// Gets the URL to load the domains from. elixi.re goes through a proxy, and self-hosted instances are asked directly.
+function domainsUrl(apiUrl: string) {
+    if (apiUrl === DEFAULT_API_URL) return "https://elixire-domains-list-proxy.astrids.workers.dev/";
+    return `${apiUrl}/api/domains`;
+}
+
 function SubdomainInput({ value, setValue }: {
     value: string | null;
     setValue: (value: string | null) => void;
@@ -44,8 +52,9 @@ function SubdomainInput({ value, setValue }: {
     </>
 }
 
-function DomainSelector({ initValue, domainsResult, setConfig }: {
+function DomainSelector({ initValue, domainsResult, setConfig, host }: {
     initValue: [string | null, string, boolean] | null;
+    host: string;
     domainsResult: {[key: string]: any} | Error | null;
     setConfig: (value: [string | null, string, boolean]) => Promise<void>;
 }) {
@@ -72,12 +81,12 @@ function DomainSelector({ initValue, domainsResult, setConfig }: {
 
     // Handle if the domain result is still loading.
     if (domainsResult === null) return <p className="mt-2">
-        <i>Loading domain information from elixi.re...</i>
+        <i>Loading domain information from {host}...</i>
     </p>;
 
     // Handle if the domain result is an error.
     if (domainsResult instanceof Error) return <p className="mt-2">
-        Error loading domain information from elixi.re:<code className="ml-1">{domainsResult.message}</code>
+        Error loading domain information from {host}:<code className="ml-1">{domainsResult.message}</code>
     </p>;
 
     // The domains object is a map of numbers -> domain names. Sort them by the number which is the key.
@@ -119,11 +128,14 @@ const NO_SELECTION = Symbol("NO_SELECTION");
 export default function ElixireDomainConfig(props: Props) {
     const [value, setValue] = useState(props.value || NO_SELECTION);
     const [domainsResult, setDomainsResult] = useState<{[key: string]: any} | Error | null>(null);
+    const apiUrl = ((props.items?.api_url as string | undefined) || DEFAULT_API_URL).replace(/\/+$/, "");
+    const host = apiUrl.replace(/^https?:\/\//, "");
 
     useEffect(() => {
         let cancelled = false;
+        setDomainsResult(null);
 
-        fetch("https://elixire-domains-list-proxy.astrids.workers.dev/").then(async res => {
+        fetch(domainsUrl(apiUrl)).then(async res => {
             if (res.ok) {
                 const data = await res.json();
                 if (cancelled) return;
@@ -138,7 +150,7 @@ export default function ElixireDomainConfig(props: Props) {
         });
 
         return () => { cancelled = true; };
-    });
+    }, [apiUrl]);
     const id1 = useId();
     const id2 = useId();
 
@@ -155,7 +167,7 @@ export default function ElixireDomainConfig(props: Props) {
                     id={id1} name="domain_management"
                 />
 
-                Use elixi.re to manage my used domain
+                Use {host} to manage my used domain
             </label>
 
             <label htmlFor={id2} className="flex items-center align-middle">
@@ -171,7 +183,7 @@ export default function ElixireDomainConfig(props: Props) {
         </form>
 
         {value === NO_SELECTION ? null : <DomainSelector
-            initValue={value} domainsResult={domainsResult} setConfig={props.set}
+            initValue={value} domainsResult={domainsResult} setConfig={props.set} host={host}
         />}
     </>;
 }
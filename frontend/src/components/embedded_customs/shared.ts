@@ -1,4 +1,5 @@
 export type Props = {
     value: any;
     set: (value: any) => Promise<void>;
+    items?: {[key: string]: any};
 };
@@ -24,7 +24,14 @@ const rootFlow: BuilderFlow = [
 export default function CustomUploaderBuilder({ revise }: { revise: () => void }) {
     const [flow, setFlow] = React.useState<BuilderFlow | undefined>(rootFlow);
     const [config] = React.useState<any>({
-        version: "v1",
+        version: "v2",
+        metadata: {
+            author: null,
+            homepage: null,
+            min_app_version: null,
+            supported_mime_types: [],
+            max_file_size: null,
+        },
     });
 
     const progressFlow = (index: number) => {
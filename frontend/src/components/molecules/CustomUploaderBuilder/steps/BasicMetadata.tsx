@@ -130,6 +130,46 @@ function MetadataIcon({ setOk, configKey, config }: MetadataIconProps) {
     </form>;
 }
 
+type OptionalMetadataProps = {
+    metadata: any;
+    configKey: string;
+    name: string;
+    description: string;
+    parse: (value: string) => any;
+    format: (value: any) => string;
+};
+
+// Handles metadata which can be left blank. Blank values are stored as null.
+function OptionalMetadata({ metadata, configKey, name, description, parse, format }: OptionalMetadataProps) {
+    const [val, setVal] = useState(format(metadata[configKey]));
+
+    const labelId = useId();
+    return <form autoComplete="off" className="block" onSubmit={e => e.preventDefault()}>
+        <div id={labelId}>
+            <p className="mb-1 font-semibold">
+                {name}
+            </p>
+
+            <Description description={description} />
+        </div>
+
+        <div>
+            <input
+                type="text" placeholder={name}
+                className="w-full mt-1 mb-2 dark:bg-slate-900 bg-slate-50 p-1 rounded-lg"
+                value={val} aria-labelledby={labelId}
+                onChange={e => {
+                    setVal(e.target.value);
+                    metadata[configKey] = parse(e.target.value.trim());
+                }}
+            />
+        </div>
+    </form>;
+}
+
+const optionalString = (value: string) => value === "" ? null : value;
+const formatString = (value: string | null) => value || "";
+
 function bubbleMany(...args: boolean[]) {
     const [val, setVal] = useState(args.every((x) => x));
     useEffect(() => {
@@ -185,6 +225,41 @@ export default function BasicMetadata({ setNextStep, config }: BuilderProps) {
             config={config}
         />
 
+        <OptionalMetadata
+            metadata={config.metadata} configKey="author" name="Author"
+            description="The person or group who made the uploader. This is optional."
+            parse={optionalString} format={formatString}
+        />
+
+        <OptionalMetadata
+            metadata={config.metadata} configKey="homepage" name="Homepage"
+            description="A URL with more information about the uploader. This is optional."
+            parse={optionalString} format={formatString}
+        />
+
+        <OptionalMetadata
+            metadata={config.metadata} configKey="min_app_version" name="Minimum App Version"
+            description="The oldest version of MagicCap that can use the uploader, such as 1.2.0. This is for reference only and is not checked. This is optional."
+            parse={optionalString} format={formatString}
+        />
+
+        <OptionalMetadata
+            metadata={config.metadata} configKey="supported_mime_types" name="Supported MIME Types"
+            description="A comma separated list of the MIME types the uploader accepts, such as image/*. Leave this blank to accept any file."
+            parse={v => v.split(",").map(x => x.trim()).filter(x => x !== "")}
+            format={v => (v || []).join(", ")}
+        />
+
+        <OptionalMetadata
+            metadata={config.metadata} configKey="max_file_size" name="Max File Size (MB)"
+            description="The largest file in megabytes the uploader accepts. Leave this blank for no limit."
+            parse={v => {
+                const n = Number(v);
+                return v === "" || isNaN(n) || n <= 0 ? null : Math.floor(n * 1024 * 1024);
+            }}
+            format={v => v ? String(v / (1024 * 1024)) : ""}
+        />
+
         <div className="mt-2">
             <Button
                 color="primary"
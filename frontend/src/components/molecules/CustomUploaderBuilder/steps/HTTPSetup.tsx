@@ -1,4 +1,4 @@
-import { type FC, useCallback, useEffect, useRef, useState } from "react";
+import { type FC, useCallback, useEffect, useId, useRef, useState } from "react";
 import Button from "../../../atoms/Button";
 import Divider from "../../../atoms/Divider";
 import Description from "../../../atoms/Description";
@@ -6,7 +6,7 @@ import {
     ObjectBuilder, InnerProps, wrapObject, useValueOkHandler, valueInitiallyOk,
 } from "../../../atoms/ObjectBuilder";
 import {
-    URLEncodedBody, MultipartBody, HTTPBody, Rewrite, RewriteTypeWithValue,
+    HTTPSigning, URLEncodedBody, MultipartBody, JSONBody, MultipartFilesBody, MultipartFileField, HTTPBody, Rewrite, RewriteTypeWithValue, HTTPDeleteRequest, HTTPStep,
 } from "../../../../bridge/CustomUploader";
 import { BuilderProps } from "../shared";
 import ConfigEditor from "../ConfigEditor";
@@ -14,6 +14,12 @@ import ConfigEditor from "../ConfigEditor";
 // @ts-expect-error: The description is a markdown file.
 import { plainText as responseExprMd } from "./descriptions/response_expr.md";
 
+// @ts-expect-error: The description is a markdown file.
+import { plainText as deleteExprMd } from "./descriptions/delete_expr.md";
+
+// @ts-expect-error: The description is a markdown file.
+import { plainText as errorExprMd } from "./descriptions/error_expr.md";
+
 function RewriteTypeSelector({ value, onColumnsEdit, addOkCallback }: InnerProps<Rewrite>) {
     const setOk = useValueOkHandler(true, addOkCallback);
     return <form autoComplete="off" className="block" onSubmit={e => e.preventDefault()}>
@@ -28,7 +34,7 @@ function RewriteTypeSelector({ value, onColumnsEdit, addOkCallback }: InnerProps
             className="dark:text-black w-full"
         >
             {
-                ["Filename", "MIME", "Config", "Static"].map(type => (
+                ["Filename", "MIME", "DeletionToken", "Config", "Static"].map(type => (
                     <option key={type} value={type}>{type}</option>
                 ))
             }
@@ -46,6 +52,7 @@ function rewritesComponentsBuilder(rewrite: Rewrite): [number, string, FC<InnerP
     switch (rewrite.type) {
         case "Filename":
         case "MIME":
+        case "DeletionToken":
             return [[-1, "Type", RewriteTypeSelector]] as const;
         case "Config":
             return [[-1, "Type", RewriteTypeSelector], [0, "Option Key", NonBlankValueColumn]] as const;
@@ -104,12 +111,434 @@ function stringKvWithObject(obj: { [key: string]: string }, setOk: (ok: boolean)
     />;
 }
 
+function MethodSelector({ methods, handler }: { methods: string[]; handler: { method: string } }) {
+    const [methodInput, setMethodInput] = useState(handler.method);
+    const name = useId();
+
+    return <form onSubmit={e => e.preventDefault()}>
+        {
+            methods.map(method => (
+                <label key={method} className="flex items-center space-x-2">
+                    <input
+                        type="radio"
+                        name={name}
+                        value={method}
+                        checked={methodInput === method}
+                        onChange={() => {
+                            handler.method = method;
+                            setMethodInput(method);
+                        }}
+                    />
+                    <span>{method}</span>
+                </label>
+            ))
+        }
+    </form>;
+}
+
+function DeleteRequestHandler({ handler, setOk }: { handler: any; setOk: (ok: boolean) => void }) {
+    const [enabled, setEnabled] = useState(!!handler.delete_request);
+    const [urlTemplateOk, setUrlTemplateOk] = useState(true);
+    const [headerTemplatesOk, setHeaderTemplatesOk] = useState(true);
+
+    useEffect(() => {
+        setOk(!enabled || (urlTemplateOk && headerTemplatesOk));
+    }, [enabled, urlTemplateOk, headerTemplatesOk]);
+
+    const checkboxId = useId();
+    const request: HTTPDeleteRequest | null = handler.delete_request;
+
+    return <>
+        <form autoComplete="off" className="block" onSubmit={e => e.preventDefault()}>
+            <label htmlFor={checkboxId} className="flex items-center align-middle">
+                <input
+                    type="checkbox" className="mr-1"
+                    checked={enabled}
+                    onChange={e => {
+                        handler.delete_request = e.target.checked ? {
+                            url_template: "https://upload.example.com/{token}",
+                            method: "DELETE",
+                            header_templates: {},
+                        } : null;
+                        setUrlTemplateOk(true);
+                        setHeaderTemplatesOk(true);
+                        setEnabled(e.target.checked);
+                    }}
+                    id={checkboxId}
+                />
+
+                Send a delete request
+            </label>
+        </form>
+
+        {enabled && request && <>
+            <h4 className="text-md font-semibold mt-8 mb-4">
+                Delete URL Template
+            </h4>
+
+            <NonBlankString
+                handler={request}
+                setOk={setUrlTemplateOk}
+                valueKey="url_template"
+            />
+
+            <h4 className="text-md font-semibold mt-8 mb-4">
+                Delete Method Type
+            </h4>
+
+            <MethodSelector methods={["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD"]} handler={request} />
+
+            <h4 className="text-md font-semibold mt-8 mb-4">
+                Delete Header Templates
+            </h4>
+
+            {stringKvWithObject(request.header_templates, setHeaderTemplatesOk)}
+        </>}
+    </>;
+}
+
+function StepBodyHandler({ step }: { step: HTTPStep }) {
+    const [type, setType] = useState(step.body.type);
+    const [text, setText] = useState(step.body.type === "Text" ? step.body.value : "");
+
+    return <>
+        <form autoComplete="off" className="block" onSubmit={e => e.preventDefault()}>
+            <select
+                value={type}
+                onChange={e => {
+                    const type = e.target.value as HTTPStep["body"]["type"];
+                    setType(type);
+                    setText("");
+                    if (type === "Text") step.body = { type, value: "" };
+                    else if (type === "URLEncoded") step.body = { type, value: {} };
+                    else step.body = { type: "Empty" };
+                }}
+                className="dark:text-black w-full"
+            >
+                <option value="Empty">Empty</option>
+                <option value="Text">Text</option>
+                <option value="URLEncoded">URL Encoded</option>
+            </select>
+        </form>
+
+        {step.body.type === "Text" && <form className="mt-2" onSubmit={e => e.preventDefault()}>
+            <textarea
+                value={text}
+                onChange={e => {
+                    setText(e.target.value);
+                    step.body = { type: "Text", value: e.target.value };
+                }}
+                className="w-full dark:bg-slate-900 bg-slate-50 p-2 rounded-lg font-mono"
+            />
+        </form>}
+
+        {step.body.type === "URLEncoded" && <div className="mt-2">
+            {stringKvWithObject(step.body.value, () => {})}
+        </div>}
+    </>;
+}
+
+function StepEditor({ step, remove, setOk }: { step: HTTPStep; remove: () => void; setOk: (ok: boolean) => void }) {
+    const [urlTemplateOk, setUrlTemplateOk] = useState(true);
+    const [headerTemplatesOk, setHeaderTemplatesOk] = useState(true);
+    const [capturesOk, setCapturesOk] = useState(true);
+
+    useEffect(() => {
+        setOk(urlTemplateOk && headerTemplatesOk && capturesOk);
+    }, [urlTemplateOk, headerTemplatesOk, capturesOk]);
+
+    return <div className="block p-2 my-2 rounded-lg border border-slate-300 dark:border-slate-600">
+        <h4 className="text-md font-semibold mb-2">URL Template</h4>
+        <NonBlankString handler={step} setOk={setUrlTemplateOk} valueKey="url_template" />
+
+        <h4 className="text-md font-semibold mt-4 mb-2">Method Type</h4>
+        <MethodSelector methods={["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD"]} handler={step} />
+
+        <h4 className="text-md font-semibold mt-4 mb-2">Header Templates</h4>
+        {stringKvWithObject(step.header_templates, setHeaderTemplatesOk)}
+
+        <h4 className="text-md font-semibold mt-4 mb-2">Body</h4>
+        <StepBodyHandler step={step} />
+
+        <h4 className="text-md font-semibold mt-4 mb-2">Captures</h4>
+        <h5 className="text-sm mb-2">
+            Each key is a rewrite which is set to the result of the expression run on the response.
+        </h5>
+        {stringKvWithObject(step.captures, setCapturesOk)}
+
+        <div className="mt-4">
+            <Button color="danger" onClick={remove}>Remove Step</Button>
+        </div>
+    </div>;
+}
+
+function StepsEditor({ steps, setOk }: { steps: HTTPStep[]; setOk: (ok: boolean) => void }) {
+    // The generation is bumped when a step is removed so the editors are rebuilt from the array.
+    const [generation, setGeneration] = useState(0);
+    const oks = useRef<boolean[]>(steps.map(() => true));
+    const report = () => setOk(oks.current.every(ok => ok));
+
+    return <>
+        {steps.map((step, i) => <StepEditor
+            key={`${generation}-${i}`}
+            step={step}
+            setOk={ok => {
+                oks.current[i] = ok;
+                report();
+            }}
+            remove={() => {
+                steps.splice(i, 1);
+                oks.current.splice(i, 1);
+                setGeneration(generation + 1);
+                report();
+            }}
+        />)}
+
+        <Button onClick={() => {
+            steps.push({
+                url_template: "https://upload.example.com",
+                method: "POST",
+                header_templates: {},
+                body: { type: "Empty" },
+                captures: {},
+            });
+            oks.current.push(true);
+            setGeneration(generation + 1);
+        }}>
+            Add Step
+        </Button>
+    </>;
+}
+
+function OptionalString({ handler, valueKey }: { handler: any; valueKey: string }) {
+    const [value, setValue] = useState(handler[valueKey] || "");
+
+    return <form onSubmit={e => e.preventDefault()}>
+        <input
+            type="text"
+            value={value}
+            onChange={e => {
+                const value = e.target.value;
+                setValue(value);
+                handler[valueKey] = value.trim().length > 0 ? value : null;
+            }}
+            className="w-full dark:bg-slate-900 bg-slate-50 p-2 rounded-lg"
+        />
+    </form>;
+}
+
+function WholeNumber({ handler, valueKey, optional }: { handler: any; valueKey: string; optional: boolean }) {
+    const [value, setValue] = useState(handler[valueKey] === null || handler[valueKey] === undefined ? "" : String(handler[valueKey]));
+
+    return <form onSubmit={e => e.preventDefault()}>
+        <input
+            type="number"
+            min={0}
+            value={value}
+            onChange={e => {
+                const value = e.target.value;
+                setValue(value);
+                const n = Math.max(0, Math.floor(Number(value)) || 0);
+                handler[valueKey] = value.trim().length === 0 && optional ? null : n;
+            }}
+            className="w-full dark:bg-slate-900 bg-slate-50 p-2 rounded-lg"
+        />
+    </form>;
+}
+
+function NetworkOptions({ handler }: { handler: any }) {
+    if (!handler.network) handler.network = { connect_timeout: null, read_timeout: null, max_retries: 0, proxy: null };
+
+    return <>
+        <h4 className="text-md font-semibold mb-2">
+            Connect Timeout
+        </h4>
+
+        <h5 className="text-sm mb-2">
+            The number of seconds to wait to connect to the server. Leave this blank to wait as long as it takes.
+        </h5>
+
+        <div className="mb-4">
+            <WholeNumber handler={handler.network} valueKey="connect_timeout" optional={true} />
+        </div>
+
+        <h4 className="text-md font-semibold mb-2">
+            Read Timeout
+        </h4>
+
+        <h5 className="text-sm mb-2">
+            The number of seconds to wait for the server to respond. Leave this blank to wait as long as it takes.
+        </h5>
+
+        <div className="mb-4">
+            <WholeNumber handler={handler.network} valueKey="read_timeout" optional={true} />
+        </div>
+
+        <h4 className="text-md font-semibold mb-2">
+            Retries
+        </h4>
+
+        <h5 className="text-sm mb-2">
+            The number of times to try a request again if the server responds with a 5xx or 429 status. The
+            Retry-After header is used to decide how long to wait if the server sends it.
+        </h5>
+
+        <div className="mb-4">
+            <WholeNumber handler={handler.network} valueKey="max_retries" optional={false} />
+        </div>
+
+        <h4 className="text-md font-semibold mb-2">
+            Proxy
+        </h4>
+
+        <h5 className="text-sm mb-2">
+            The proxy to send requests through, such as <code>socks5://localhost:1080</code>. This can use rewrites.
+        </h5>
+
+        <OptionalString handler={handler.network} valueKey="proxy" />
+    </>;
+}
+
+function SigningField({ signing, valueKey, name, description, required, onChange }: {
+    signing: any; valueKey: string; name: string; description: string; required: boolean; onChange: () => void;
+}) {
+    const [value, setValue] = useState(signing[valueKey] || "");
+
+    return <>
+        <h4 className="text-md font-semibold mb-2">
+            {name}
+        </h4>
+
+        <h5 className="text-sm mb-2">
+            {description}
+        </h5>
+
+        <form className="mb-4" onSubmit={e => e.preventDefault()}>
+            <input
+                type="text"
+                value={value}
+                onChange={e => {
+                    const value = e.target.value;
+                    setValue(value);
+                    signing[valueKey] = !required && value.trim().length === 0 ? null : value;
+                    onChange();
+                }}
+                className="w-full dark:bg-slate-900 bg-slate-50 p-2 rounded-lg"
+            />
+        </form>
+    </>;
+}
+
+// Checks that the required fields of the signing settings are set.
+function signingValid(signing: HTTPSigning | null) {
+    if (!signing) return true;
+    const required = signing.type === "HMAC" ?
+        [signing.key_template, signing.signature_header, signing.timestamp_header] :
+        [signing.access_key_id_template, signing.secret_access_key_template, signing.region_template, signing.service];
+    return required.every(v => v.trim().length > 0);
+}
+
+function SigningHandler({ handler, setOk }: { handler: any; setOk: (ok: boolean) => void }) {
+    if (handler.signing === undefined) handler.signing = null;
+    const [type, setType] = useState<string>(handler.signing ? handler.signing.type : "None");
+    const onChange = () => setOk(signingValid(handler.signing));
+
+    return <>
+        <form className="mb-4" onSubmit={e => e.preventDefault()}>
+            <select
+                value={type}
+                onChange={e => {
+                    const type = e.target.value;
+                    setType(type);
+                    if (type === "HMAC") {
+                        handler.signing = {
+                            type, key_template: "", signature_header: "X-Signature",
+                            timestamp_header: "X-Timestamp", encoding: "hex",
+                        };
+                    } else if (type === "SigV4") {
+                        handler.signing = {
+                            type, access_key_id_template: "", secret_access_key_template: "",
+                            session_token_template: null, region_template: "us-east-1", service: "s3",
+                            unsigned_payload: false,
+                        };
+                    } else {
+                        handler.signing = null;
+                    }
+                    onChange();
+                }}
+                className="dark:text-black w-full"
+            >
+                <option value="None">None</option>
+                <option value="HMAC">HMAC-SHA256</option>
+                <option value="SigV4">AWS Signature Version 4</option>
+            </select>
+        </form>
+
+        {type === "HMAC" && <div key="HMAC">
+            <SigningField
+                signing={handler.signing} valueKey="key_template" name="Key" required={true} onChange={onChange}
+                description="The HMAC key. This can use rewrites so it can come from the config."
+            />
+            <SigningField
+                signing={handler.signing} valueKey="signature_header" name="Signature Header" required={true}
+                onChange={onChange}
+                description="The header the signature is sent in. The signature covers the method, path with the query string, timestamp, and the SHA-256 of the body, each on a new line."
+            />
+            <SigningField
+                signing={handler.signing} valueKey="timestamp_header" name="Timestamp Header" required={true}
+                onChange={onChange} description="The header the UNIX timestamp used in the signature is sent in."
+            />
+            <form onSubmit={e => e.preventDefault()}>
+                <select
+                    defaultValue={handler.signing.encoding}
+                    onChange={e => { handler.signing.encoding = e.target.value; }}
+                    className="dark:text-black w-full"
+                >
+                    <option value="hex">Hexadecimal</option>
+                    <option value="base64">Base64</option>
+                </select>
+            </form>
+        </div>}
+
+        {type === "SigV4" && <div key="SigV4">
+            <SigningField
+                signing={handler.signing} valueKey="access_key_id_template" name="Access Key ID" required={true}
+                onChange={onChange} description="The access key ID. This can use rewrites."
+            />
+            <SigningField
+                signing={handler.signing} valueKey="secret_access_key_template" name="Secret Access Key"
+                required={true} onChange={onChange} description="The secret access key. This can use rewrites."
+            />
+            <SigningField
+                signing={handler.signing} valueKey="session_token_template" name="Session Token" required={false}
+                onChange={onChange} description="The session token for temporary credentials. This can use rewrites and is optional."
+            />
+            <SigningField
+                signing={handler.signing} valueKey="region_template" name="Region" required={true}
+                onChange={onChange} description="The region to sign for. This can use rewrites."
+            />
+            <SigningField
+                signing={handler.signing} valueKey="service" name="Service" required={true}
+                onChange={onChange} description="The service to sign for. This is s3 for S3 compatible APIs."
+            />
+            <label className="flex items-center align-middle">
+                <input
+                    type="checkbox" className="mr-1"
+                    defaultChecked={handler.signing.unsigned_payload}
+                    onChange={e => { handler.signing.unsigned_payload = e.target.checked; }}
+                />
+                Do not sign the body. This is faster for large files and supported by S3.
+            </label>
+        </div>}
+    </>;
+}
+
 function EncodedBodyHandler({ body, setOk }: { body: URLEncodedBody; setOk: (ok: boolean) => void }) {
-    if (!body.content) body.content = [{}, { name: "data", encoding_type: "b64" }];
+    if (!body.value) body.value = [{}, { name: "data", encoding_type: "b64" }];
 
     const kvRef = useRef(true);
-    const [name, setName] = useState(body.content[1].name);
-    const [encodingType, setEncodingType] = useState(body.content[1].encoding_type);
+    const [name, setName] = useState(body.value[1].name);
+    const [encodingType, setEncodingType] = useState(body.value[1].encoding_type);
 
     return <>
         <h4 className="text-md font-semibold mt-8 mb-4">
@@ -120,7 +549,7 @@ function EncodedBodyHandler({ body, setOk }: { body: URLEncodedBody; setOk: (ok:
             This is a list of query parameters that will be sent with the request.
         </h5>
 
-        {stringKvWithObject(body.content[0], ok => {
+        {stringKvWithObject(body.value[0], ok => {
             kvRef.current = ok;
             setOk(ok ? name.trim().length > 0 : false);
         })}
@@ -140,7 +569,7 @@ function EncodedBodyHandler({ body, setOk }: { body: URLEncodedBody; setOk: (ok:
                 onChange={e => {
                     const name = e.target.value;
                     setName(name);
-                    body.content[1].name = name;
+                    body.value[1].name = name;
                     setOk(kvRef.current && name.trim().length > 0);
                 }}
                 className="w-full dark:bg-slate-900 bg-slate-50 p-2 rounded-lg"
@@ -161,7 +590,7 @@ function EncodedBodyHandler({ body, setOk }: { body: URLEncodedBody; setOk: (ok:
                 onChange={e => {
                     const encodingType = e.target.value;
                     setEncodingType(encodingType as any);
-                    body.content[1].encoding_type = encodingType as any;
+                    body.value[1].encoding_type = encodingType as any;
                 }}
                 className="dark:text-black w-full"
             >
@@ -174,10 +603,10 @@ function EncodedBodyHandler({ body, setOk }: { body: URLEncodedBody; setOk: (ok:
 }
 
 function MultipartBodyHandler({ body, setOk }: { body: MultipartBody; setOk: (ok: boolean) => void }) {
-    if (!body.content) body.content = [{}, "data"];
+    if (!body.value) body.value = [{}, "data"];
 
     const kvRef = useRef(true);
-    const [name, setName] = useState(body.content[1]);
+    const [name, setName] = useState(body.value[1]);
 
     return <>
         <h4 className="text-md font-semibold mt-8 mb-4">
@@ -188,7 +617,7 @@ function MultipartBodyHandler({ body, setOk }: { body: MultipartBody; setOk: (ok
             This is a list of form parameters that will be sent with the request.
         </h5>
 
-        {stringKvWithObject(body.content[0], ok => {
+        {stringKvWithObject(body.value[0], ok => {
             kvRef.current = ok;
             setOk(ok ? name.trim().length > 0 : false);
         })}
@@ -208,7 +637,7 @@ function MultipartBodyHandler({ body, setOk }: { body: MultipartBody; setOk: (ok
                 onChange={e => {
                     const name = e.target.value;
                     setName(name);
-                    body.content[1] = name;
+                    body.value[1] = name;
                     setOk(kvRef.current && name.trim().length > 0);
                 }}
                 className="w-full dark:bg-slate-900 bg-slate-50 p-2 rounded-lg"
@@ -217,6 +646,163 @@ function MultipartBodyHandler({ body, setOk }: { body: MultipartBody; setOk: (ok
     </>;
 }
 
+function JSONBodyHandler({ body, setOk }: { body: JSONBody; setOk: (ok: boolean) => void }) {
+    if (!body.value) body.value = [{}, "file"];
+
+    const pathRef = useRef(body.value[1].trim().length > 0);
+    const templateRef = useRef(true);
+    const [template, setTemplate] = useState(JSON.stringify(body.value[0], null, 4));
+    const [path, setPath] = useState(body.value[1]);
+
+    return <>
+        <h4 className="text-md font-semibold mt-8 mb-4">
+            JSON Template
+        </h4>
+
+        <h5 className="text-sm mt-2 mb-4">
+            This is the JSON that will be sent with the request. Rewrites are applied to each string in it.
+        </h5>
+
+        <form onSubmit={e => e.preventDefault()}>
+            <textarea
+                value={template}
+                onChange={e => {
+                    const template = e.target.value;
+                    setTemplate(template);
+                    try {
+                        const parsed = JSON.parse(template);
+                        templateRef.current = typeof parsed === "object" && parsed !== null && !Array.isArray(parsed);
+                        if (templateRef.current) body.value[0] = parsed;
+                    } catch {
+                        templateRef.current = false;
+                    }
+                    setOk(templateRef.current && pathRef.current);
+                }}
+                className="w-full dark:bg-slate-900 bg-slate-50 p-2 rounded-lg font-mono"
+            />
+        </form>
+
+        <h4 className="text-md font-semibold mt-8 mb-4">
+            File Path
+        </h4>
+
+        <h5 className="text-sm mt-2 mb-4">
+            This is the dot separated path in the JSON to put the base64 encoded file at, such as <code>data.file</code>.
+        </h5>
+
+        <form onSubmit={e => e.preventDefault()}>
+            <input
+                type="text"
+                value={path}
+                onChange={e => {
+                    const path = e.target.value;
+                    setPath(path);
+                    body.value[1] = path;
+                    pathRef.current = path.trim().length > 0;
+                    setOk(templateRef.current && pathRef.current);
+                }}
+                className="w-full dark:bg-slate-900 bg-slate-50 p-2 rounded-lg"
+            />
+        </form>
+    </>;
+}
+
+function MultipartFileFieldEditor({ field, remove }: { field: MultipartFileField; remove: () => void }) {
+    const [name, setName] = useState(field.name);
+    const [kind, setKind] = useState(field.kind.type);
+    const [maxSize, setMaxSize] = useState(field.kind.type === "Thumbnail" ? field.kind.value : 256);
+
+    return <div className="flex items-center space-x-2 mb-2">
+        <form className="flex-grow" onSubmit={e => e.preventDefault()}>
+            <input
+                type="text"
+                value={name}
+                onChange={e => {
+                    setName(e.target.value);
+                    field.name = e.target.value;
+                }}
+                className="w-full dark:bg-slate-900 bg-slate-50 p-1 rounded-lg"
+            />
+        </form>
+
+        <form onSubmit={e => e.preventDefault()}>
+            <select
+                value={kind}
+                onChange={e => {
+                    const kind = e.target.value as MultipartFileField["kind"]["type"];
+                    setKind(kind);
+                    field.kind = kind === "Thumbnail" ? { type: kind, value: maxSize } : { type: "Original" };
+                }}
+                className="dark:text-black"
+            >
+                <option value="Original">File</option>
+                <option value="Thumbnail">Thumbnail</option>
+            </select>
+        </form>
+
+        {kind === "Thumbnail" && <form onSubmit={e => e.preventDefault()}>
+            <input
+                type="number"
+                min={1}
+                value={maxSize}
+                onChange={e => {
+                    const maxSize = Math.max(1, Math.floor(Number(e.target.value)) || 1);
+                    setMaxSize(maxSize);
+                    field.kind = { type: "Thumbnail", value: maxSize };
+                }}
+                className="w-24 dark:bg-slate-900 bg-slate-50 p-1 rounded-lg"
+            />
+        </form>}
+
+        <Button onClick={remove}>
+            Remove
+        </Button>
+    </div>;
+}
+
+function MultipartFilesBodyHandler({ body, setOk }: { body: MultipartFilesBody; setOk: (ok: boolean) => void }) {
+    if (!body.value) body.value = [{}, [{ name: "file", kind: { type: "Original" } }]];
+
+    const [generation, setGeneration] = useState(0);
+    const files = body.value[1];
+
+    return <>
+        <h4 className="text-md font-semibold mt-8 mb-4">
+            Other Form Parameters
+        </h4>
+
+        <h5 className="text-sm mt-2 mb-4">
+            This is a list of form parameters that will be sent with the request.
+        </h5>
+
+        {stringKvWithObject(body.value[0], setOk)}
+
+        <h4 className="text-md font-semibold mt-8 mb-4">
+            Files
+        </h4>
+
+        <h5 className="text-sm mt-2 mb-4">
+            These are the form fields files will be sent in. Thumbnails are PNG images which fit within the size specified.
+        </h5>
+
+        {files.map((field, i) => <MultipartFileFieldEditor
+            key={`${generation}-${i}`}
+            field={field}
+            remove={() => {
+                files.splice(i, 1);
+                setGeneration(generation + 1);
+            }}
+        />)}
+
+        <Button onClick={() => {
+            files.push({ name: "thumbnail", kind: { type: "Thumbnail", value: 256 } });
+            setGeneration(generation + 1);
+        }}>
+            Add File
+        </Button>
+    </>;
+}
+
 function HTTPBodyHandler({ body, setOk }: { body: HTTPBody; setOk: (ok: boolean) => void }) {
     const [type, setType] = useState(body.type);
 
@@ -228,9 +814,15 @@ function HTTPBodyHandler({ body, setOk }: { body: HTTPBody; setOk: (ok: boolean)
         case "URLEncoded":
             Component = EncodedBodyHandler;
             break;
-        case "Multipart":
+        case "MultipartForm":
             Component = MultipartBodyHandler;
             break;
+        case "JSON":
+            Component = JSONBodyHandler;
+            break;
+        case "MultipartFiles":
+            Component = MultipartFilesBodyHandler;
+            break;
     }
 
     return <>
@@ -246,7 +838,7 @@ function HTTPBodyHandler({ body, setOk }: { body: HTTPBody; setOk: (ok: boolean)
                 className="dark:text-black w-full"
             >
                 {
-                    ["Raw", "URL Encoded", "Multipart"].map(type => {
+                    ["Raw", "URL Encoded", "Multipart Form", "JSON", "Multipart Files"].map(type => {
                         const value = type.replace(" ", "");
                         return <option key={type} value={value}>{type}</option>;
                     })
@@ -268,15 +860,30 @@ export default function HTTPSetup({ setNextStep, config }: BuilderProps) {
         header_templates: {},
         body: { type: "Raw" },
         response_expr: 'json_path("url")',
+        delete_expr: null,
+        delete_request: null,
+        pre_steps: [],
+        upload_captures: {},
+        post_steps: [],
+        error_expr: null,
+        network: { connect_timeout: null, read_timeout: null, max_retries: 0, proxy: null },
+        signing: null,
     };
+    if (!config.handler.pre_steps) config.handler.pre_steps = [];
+    if (!config.handler.upload_captures) config.handler.upload_captures = {};
+    if (!config.handler.post_steps) config.handler.post_steps = [];
 
     const [configOk, setConfigOk] = useState(true);
     const [rewritesOk, setRewritesOk] = useState(true);
     const [urlTemplateOk, setUrlTemplateOk] = useState(true);
-    const [methodInput, setMethodInput] = useState(config.handler.method);
     const [headerTemplatesOk, setHeaderTemplatesOk] = useState(true);
     const [bodyOk, setBodyOk] = useState(true);
     const [responseExprOk, setResponseExprOk] = useState(true);
+    const [deleteRequestOk, setDeleteRequestOk] = useState(true);
+    const [preStepsOk, setPreStepsOk] = useState(true);
+    const [uploadCapturesOk, setUploadCapturesOk] = useState(true);
+    const [postStepsOk, setPostStepsOk] = useState(true);
+    const [signingOk, setSigningOk] = useState(true);
 
     return <>
         <p>
@@ -319,6 +926,19 @@ export default function HTTPSetup({ setNextStep, config }: BuilderProps) {
 
             <Divider />
 
+            <h2 className="text-lg font-semibold mb-4">
+                Steps Before Upload
+            </h2>
+
+            <h3 className="text-sm mb-4">
+                Defines requests made in order before the upload, such as getting a token or an upload URL.
+                Values captured from their responses can be used as rewrites in later requests.
+            </h3>
+
+            <StepsEditor steps={config.handler.pre_steps} setOk={setPreStepsOk} />
+
+            <Divider />
+
             <h2 className="text-lg font-semibold mb-4">
                 URL Template
             </h2>
@@ -343,25 +963,7 @@ export default function HTTPSetup({ setNextStep, config }: BuilderProps) {
                 Defines the method type for the HTTP request.
             </h3>
 
-            <form onSubmit={e => e.preventDefault()}>
-                {
-                    ["GET", "POST", "PUT", "PATCH"].map(method => (
-                        <label key={method} className="flex items-center space-x-2">
-                            <input
-                                type="radio"
-                                name="method"
-                                value={method}
-                                checked={methodInput === method}
-                                onChange={e => {
-                                    config.handler.method = method;
-                                    setMethodInput(method);
-                                }}
-                            />
-                            <span>{method}</span>
-                        </label>
-                    ))
-                }
-            </form>
+            <MethodSelector methods={["GET", "POST", "PUT", "PATCH"]} handler={config.handler} />
 
             <Divider />
 
@@ -392,6 +994,32 @@ export default function HTTPSetup({ setNextStep, config }: BuilderProps) {
 
             <Divider />
 
+            <h2 className="text-lg font-semibold mb-4">
+                Upload Captures
+            </h2>
+
+            <h3 className="text-sm mb-4">
+                Defines values captured from the upload response for the steps after the upload. Each key is
+                a rewrite which is set to the result of the expression.
+            </h3>
+
+            {stringKvWithObject(config.handler.upload_captures, setUploadCapturesOk)}
+
+            <Divider />
+
+            <h2 className="text-lg font-semibold mb-4">
+                Steps After Upload
+            </h2>
+
+            <h3 className="text-sm mb-4">
+                Defines requests made in order after the upload, such as finalizing it. If there are any, the
+                response expression is run against the response of the last one.
+            </h3>
+
+            <StepsEditor steps={config.handler.post_steps} setOk={setPostStepsOk} />
+
+            <Divider />
+
             <h2 className="text-lg font-semibold mb-4">
                 Response Expression
             </h2>
@@ -407,6 +1035,63 @@ export default function HTTPSetup({ setNextStep, config }: BuilderProps) {
                     valueKey="response_expr"
                 />
             </div>
+
+            <Divider />
+
+            <h2 className="text-lg font-semibold mb-4">
+                Error Expression
+            </h2>
+
+            <div className="text-sm mb-4">
+                <Description description={errorExprMd} />
+            </div>
+
+            <div className="mb-2">
+                <OptionalString handler={config.handler} valueKey="error_expr" />
+            </div>
+
+            <Divider />
+
+            <h2 className="text-lg font-semibold mb-4">
+                Network
+            </h2>
+
+            <h3 className="text-sm mb-4">
+                Defines the timeouts, retries, and proxy used for every request the uploader makes.
+            </h3>
+
+            <NetworkOptions handler={config.handler} />
+
+            <Divider />
+
+            <h2 className="text-lg font-semibold mb-4">
+                Signing
+            </h2>
+
+            <h3 className="text-sm mb-4">
+                Defines how the upload and delete requests are signed. The signature is made once the headers and
+                body are built. Steps are not signed.
+            </h3>
+
+            <SigningHandler handler={config.handler} setOk={setSigningOk} />
+
+            <Divider />
+
+            <h2 className="text-lg font-semibold mb-4">
+                Deletion
+            </h2>
+
+            <div className="text-sm mb-4">
+                <Description description={deleteExprMd} />
+            </div>
+
+            <div className="mb-4">
+                <OptionalString handler={config.handler} valueKey="delete_expr" />
+            </div>
+
+            <div className="mb-2">
+                <DeleteRequestHandler handler={config.handler} setOk={setDeleteRequestOk} />
+            </div>
         </div>
 
         <Button
@@ -415,7 +1100,8 @@ export default function HTTPSetup({ setNextStep, config }: BuilderProps) {
             disabled={
                 !configOk || !rewritesOk || !urlTemplateOk ||
                 !headerTemplatesOk || !bodyOk ||
-                !responseExprOk
+                !responseExprOk || !deleteRequestOk ||
+                !preStepsOk || !uploadCapturesOk || !postStepsOk || !signingOk
             }
         >
             Finish
@@ -9,7 +9,7 @@ const DEFAULT_PHP_CODE = `<?php
 // PHP is sandboxed to a specific directory, so a lot of the information
 // is accessed via environment variables pulled from the base application.
 
-// Any persistent storage should go into this folder.
+// Any persistent storage should go into this folder. Changes are only kept if the script succeeds.
 $data_folder = getenv("DATA_FOLDER");
 
 // The file that is being uploaded.
@@ -27,6 +27,7 @@ function PHPCode({ config }: { config: any }) {
     if (!config.handler) config.handler = {
         type: "php",
         code: DEFAULT_PHP_CODE,
+        limits: { timeout: 30, memory_limit: 128, data_quota: 50 },
     };
 
     // Mount Monaco.
@@ -39,6 +40,55 @@ function PHPCode({ config }: { config: any }) {
     />;
 }
 
+function Limit({ limits, valueKey, title, description }: {
+    limits: any; valueKey: string; title: string; description: string;
+}) {
+    const [value, setValue] = useState(String(limits[valueKey]));
+
+    return <div className="flex-col mr-4">
+        <h4 className="text-md font-semibold mb-1">
+            {title}
+        </h4>
+
+        <h5 className="text-sm mb-2">
+            {description}
+        </h5>
+
+        <form onSubmit={e => e.preventDefault()}>
+            <input
+                type="number"
+                min={0}
+                value={value}
+                onChange={e => {
+                    setValue(e.target.value);
+                    limits[valueKey] = Math.max(0, Math.floor(Number(e.target.value)) || 0);
+                }}
+                className="w-full dark:bg-slate-900 bg-slate-50 p-2 rounded-lg"
+            />
+        </form>
+    </div>;
+}
+
+function PHPLimits({ config }: { config: any }) {
+    if (!config.handler.limits) config.handler.limits = { timeout: 30, memory_limit: 128, data_quota: 50 };
+    const limits = config.handler.limits;
+
+    return <div className="flex mb-4">
+        <Limit
+            limits={limits} valueKey="timeout" title="Timeout"
+            description="The seconds the script can run for. 0 means no limit."
+        />
+        <Limit
+            limits={limits} valueKey="memory_limit" title="Memory Limit"
+            description="The megabytes of memory the script can use."
+        />
+        <Limit
+            limits={limits} valueKey="data_quota" title="Data Quota"
+            description="The megabytes the data folder can use. 0 means no limit."
+        />
+    </div>;
+}
+
 export default function PHPSetup({ setNextStep, config }: BuilderProps) {
     const [ok, setOk] = useState(true);
     const finalize = () => setNextStep(0);
@@ -60,6 +110,8 @@ export default function PHPSetup({ setNextStep, config }: BuilderProps) {
             </div>
         </div>
 
+        <PHPLimits config={config} />
+
         <Button
             color="primary"
             onClick={finalize}
@@ -0,0 +1,52 @@
+import { useCallback, useEffect, useId, useState } from "react";
+import { getConfigOption, setConfigOption } from "../../bridge/api";
+import Description from "../atoms/Description";
+
+// Gets the uploaders which are opted into reusing URLs.
+async function getDedupeUploaders(): Promise<string[]> {
+    const val = await getConfigOption("dedupe_uploaders");
+    return Array.isArray(val) ? val : [];
+}
+
+export default function DedupeToggle({ uploaderId }: { uploaderId: string }) {
+    const id = useId();
+    const [checked, setChecked] = useState(false);
+
+    useEffect(() => {
+        let cancelled = false;
+        getDedupeUploaders().then(uploaders => {
+            if (cancelled) return;
+            setChecked(uploaders.includes(uploaderId));
+        });
+        return () => { cancelled = true; };
+    }, [uploaderId]);
+
+    const cb = useCallback(async (checked: boolean) => {
+        setChecked(checked);
+        const uploaders = (await getDedupeUploaders()).filter(u => u !== uploaderId);
+        if (checked) uploaders.push(uploaderId);
+        await setConfigOption("dedupe_uploaders", uploaders).catch(e => {
+            setChecked(!checked);
+            throw e;
+        });
+    }, [uploaderId]);
+
+    return <form autoComplete="off" className="block" onSubmit={e => e.preventDefault()}>
+        <p className="mb-1 font-semibold">
+            Reuse URLs for Duplicates
+        </p>
+
+        <Description description="If the same file was already uploaded with this configuration, the existing URL is used instead of uploading it again." />
+
+        <label htmlFor={id} className="flex items-center align-middle">
+            <input
+                type="checkbox" className="mr-1"
+                checked={checked}
+                onChange={e => cb(e.target.checked)}
+                id={id}
+            />
+
+            Reuse URLs for Duplicates
+        </label>
+    </form>;
+}
@@ -7,6 +7,9 @@ import {
 import { addCaptureWatcher, removeCaptureWatcher } from "../../bridge/newCaptures";
 import { fileSystemProxy } from "../../bridge/proxy";
 import ErrorPage from "../atoms/ErrorPage";
+import Modal from "../atoms/Modal";
+import Button from "../atoms/Button";
+import Alert from "../atoms/Alert";
 import useDarkMode from "../../hooks/useDarkMode";
 import usePromise from "../../hooks/usePromise";
 
@@ -32,6 +35,25 @@ const supportedCaptureActions: {[key: string]: (id: string) => Promise<void>} =
     deleteCapture, openUrl, openFile, showInFolder, copyUrl,
 };
 
+// Defines a request to delete a capture which can also be deleted from the uploader.
+type RemoteDeleteRequest = { id: string; remove: () => void };
+
+// Defines the function called when a capture which can be deleted from the uploader is deleted. This is set
+// by the captures component so it can ask the user what to do.
+let remoteDeleteRequested: ((request: RemoteDeleteRequest) => void) | null = null;
+
+// Removes the capture root item which contains the element.
+function removeCaptureRoot(el: HTMLElement) {
+    let x: HTMLElement | null = el;
+    while (x) {
+        if (x.dataset.captureRoot) {
+            x.remove();
+            break;
+        }
+        x = x.parentElement;
+    }
+}
+
 // Handles when a capture form is submitted.
 function captureFormSubmit(event: Event) {
     event.preventDefault();
@@ -47,22 +69,19 @@ function captureFormSubmit(event: Event) {
         () => { throw new Error("unsupported action") }
     );
 
+    // If the capture can be deleted from the uploader, ask the user what they want to do first.
+    if (action === "deleteCapture" && form.dataset.remoteDeletable === "true" && remoteDeleteRequested) {
+        remoteDeleteRequested({ id: captureId, remove: () => removeCaptureRoot(form) });
+        return false;
+    }
+
     // Call the action function.
     actionFn(captureId).catch(error => {
         console.error("Capture action failed:", error);
     });
 
     // If the action is deleteCapture, remove the capture root item.
-    if (action === "deleteCapture") {
-        let x: HTMLElement = form;
-        while (x) {
-            if (x.dataset.captureRoot) {
-                x.remove();
-                break;
-            }
-            x = x.parentElement;
-        }
-    }
+    if (action === "deleteCapture") removeCaptureRoot(form);
 
     // Prevent standard form submission in some WebKit browsers.
     return false;
@@ -138,6 +157,50 @@ function CapturesHTML({ html }: { html: string }) {
     return <span dangerouslySetInnerHTML={{ __html: html }} ref={ref} />;
 }
 
+// Asks the user if the capture should be deleted from the uploader as well as MagicCap.
+function RemoteDeleteModal({ request, close }: { request: RemoteDeleteRequest | null; close: () => void }) {
+    const [error, setError] = useState<string | null>(null);
+    const [deleting, setDeleting] = useState(false);
+
+    const closeModal = () => {
+        setError(null);
+        close();
+    };
+    const del = (remote: boolean) => {
+        if (!request) return;
+        setDeleting(true);
+        deleteCapture(request.id, remote).then(() => {
+            request.remove();
+            closeModal();
+        }).catch(e => setError(e.message)).finally(() => setDeleting(false));
+    };
+
+    return <Modal title="Delete Capture" open={request !== null} onClose={closeModal}>
+        <div className="max-w-xl mt-4">
+            <p>
+                This capture can also be deleted from the uploader it was sent to. Do you want to delete
+                it from there as well?
+            </p>
+
+            {error && <Alert type="error" message={error} />}
+
+            <div className="flex mt-4">
+                <div className="flex-col mr-2">
+                    <Button color="danger" disabled={deleting} onClick={() => del(true)}>
+                        Delete Everywhere
+                    </Button>
+                </div>
+
+                <div className="flex-col">
+                    <Button disabled={deleting} onClick={() => del(false)}>
+                        Only Delete from MagicCap
+                    </Button>
+                </div>
+            </div>
+        </div>
+    </Modal>;
+}
+
 // This component should render exactly once after the HTML is set. The HTML generated from the
 // Rust takes over inside the function.
 export default function Captures() {
@@ -147,6 +210,13 @@ export default function Captures() {
     // Get the HTML.
     const [htmlOrError, state] = usePromise(() => getCapturesHtml(query), [query]);
 
+    // Handle requests to delete captures which can be deleted from the uploader.
+    const [remoteDelete, setRemoteDelete] = useState<RemoteDeleteRequest | null>(null);
+    useEffect(() => {
+        remoteDeleteRequested = setRemoteDelete;
+        return () => { remoteDeleteRequested = null; };
+    }, []);
+
     // Defines the captures state.
     const capturesState = (() => {
         // If we are loading, show a loading message.
@@ -170,5 +240,6 @@ export default function Captures() {
             />
         </form>
         {capturesState}
+        <RemoteDeleteModal request={remoteDelete} close={() => setRemoteDelete(null)} />
     </>;
 }
@@ -101,6 +101,19 @@ export default function General() {
 
         <Divider />
 
+        <Radio
+            dbKey="text_capture_source"
+            label="Text Upload Source"
+            description="Defines where the text comes from when uploading text. Using the clipboard is only supported on Linux."
+            defaultValue="region"
+            radioItems={[
+                ["region", "Scan the text in a region I select"],
+                ["clipboard", "Use the text in the clipboard"],
+            ]}
+        />
+
+        <Divider />
+
         <div id={startupLabelId}>
             <p className="mb-1 font-semibold">
                 Start MagicCap on system startup
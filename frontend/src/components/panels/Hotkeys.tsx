@@ -47,5 +47,13 @@ export default function Hotkeys() {
             label="Clipboard Capture"
             description="Defines the hotkey to capture the clipboard:"
         />
+
+        <Divider />
+
+        <Hotkey
+            dbKey="text_hotkey"
+            label="Upload Text"
+            description="Defines the hotkey to upload text from the source set in the general settings:"
+        />
     </Container>;
 }
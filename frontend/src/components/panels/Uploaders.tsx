@@ -7,7 +7,8 @@ import Header from "../atoms/Header";
 import { uploaderIdAtom } from "../../atoms";
 import {
     Uploader as UploaderType, ConfigOption, getUploaderConfigOptions, getUploaders, testUploader,
-    setConfigOption, getCustomUploaders, deleteCustomUploader,
+    setConfigOption, getCustomUploaders, getCustomUploaderErrors, deleteCustomUploader, UploaderTestError,
+    getCustomUploaderLog, clearCustomUploaderLog,
 } from "../../bridge/api";
 import Checkbox from "../atoms/config/Checkbox";
 import Divider from "../atoms/Divider";
@@ -19,7 +20,9 @@ import NumberInput from "../atoms/config/NumberInput";
 import Custom from "../atoms/config/Custom";
 import Embedded from "../atoms/config/Embedded";
 import Description from "../atoms/Description";
+import Modal from "../atoms/Modal";
 import CustomUploaderButtons from "../molecules/CustomUploaderButtons";
+import DedupeToggle from "../molecules/DedupeToggle";
 
 type UploaderProps = {
     uploader: UploaderType;
@@ -29,6 +32,7 @@ type UploaderProps = {
 
 type UploaderOptionsProps = UploaderProps & {
     config: { [key: string]: any };
+    errorField: string | null;
 };
 
 function optionSwitch(
@@ -102,7 +106,7 @@ function optionSwitch(
     }
 }
 
-function UploaderOptions({ uploader, uploaderId, config }: UploaderOptionsProps) {
+function UploaderOptions({ uploader, uploaderId, config, errorField }: UploaderOptionsProps) {
     // Handle the case where the uploader has no options.
     if (uploader.options.length === 0) return <>
         <p className="mt-2">
@@ -112,13 +116,41 @@ function UploaderOptions({ uploader, uploaderId, config }: UploaderOptionsProps)
     </>;
 
     return uploader.options.map(([key, option]) => {
+        // Highlight the option if the last test failed because of it.
         return <Fragment key={key}>
-            {optionSwitch(uploaderId, key, option, config)}
+            <div className={key === errorField ? "rounded-md ring-2 ring-red-500 p-2" : ""}>
+                {optionSwitch(uploaderId, key, option, config)}
+            </div>
             <Divider />
         </Fragment>;
     });
 }
 
+function UploaderLog({ uploaderId }: { uploaderId: string }) {
+    const [revision, setRevision] = useState(0);
+    const [log, promiseState] = usePromise(() => getCustomUploaderLog(uploaderId), [uploaderId, revision]);
+    if (promiseState !== "resolved") return <></>;
+
+    return <>
+        {
+            log === "" ? <p className="text-sm">
+                This uploader has not logged anything.
+            </p> : <pre className="text-xs max-h-[60vh] max-w-[70vw] overflow-auto whitespace-pre-wrap">
+                {log}
+            </pre>
+        }
+
+        <div className="flex mt-4">
+            <Button
+                color="danger"
+                onClick={() => clearCustomUploaderLog(uploaderId).then(() => setRevision(v => v + 1))}
+            >
+                Clear Log
+            </Button>
+        </div>
+    </>;
+}
+
 function Uploader({ uploader, uploaderId, custom, reload }: UploaderProps & { reload: () => void }) {
     const [config, promiseState] = usePromise(
         () => getUploaderConfigOptions(uploaderId), [uploaderId],
@@ -128,24 +160,29 @@ function Uploader({ uploader, uploaderId, custom, reload }: UploaderProps & { re
         message: string;
     } | null>(null);
 
+    const [errorField, setErrorField] = useState<string | null>(null);
+    const [logOpen, setLogOpen] = useState(false);
+
     const testCb = useCallback(() => {
         setAlert(null);
-        testUploader(uploaderId).catch(e => {
-            setAlert({
-                type: "error",
-                message: e.message,
-            });
-        }).then(() => {
+        setErrorField(null);
+        testUploader(uploaderId).then(() => {
             setAlert({
                 type: "success",
                 message: "The test was successful!",
             });
+        }, e => {
+            if (e instanceof UploaderTestError) setErrorField(e.field);
+            setAlert({
+                type: "error",
+                message: e.message,
+            });
         });
     }, [uploaderId]);
 
-    const defaultCb = useCallback(() => {
+    const setUploaderOption = useCallback((key: string, message: string) => {
         setAlert(null);
-        setConfigOption("uploader_type", uploaderId).catch(e => {
+        setConfigOption(key, uploaderId).catch(e => {
             setAlert({
                 type: "error",
                 message: e.message,
@@ -153,11 +190,16 @@ function Uploader({ uploader, uploaderId, custom, reload }: UploaderProps & { re
         }).then(() => {
             setAlert({
                 type: "success",
-                message: "The uploader has been set as the default!",
+                message,
             });
         });
     }, [uploaderId]);
 
+    const defaultCb = useCallback(
+        () => setUploaderOption("uploader_type", "The uploader has been set as the default!"),
+        [setUploaderOption],
+    );
+
     const deleteUploader = useCallback(() => {
         deleteCustomUploader(uploaderId).catch(e => {
             setAlert({
@@ -177,12 +219,25 @@ function Uploader({ uploader, uploaderId, custom, reload }: UploaderProps & { re
             <Alert type={alert.type} message={alert.message} />
         </div>}
 
+        {
+            (uploader.accepted_mime_types.length !== 0 || uploader.max_file_size !== null) && <p className="text-sm mb-4">
+                This uploader only accepts {
+                    uploader.accepted_mime_types.length === 0 ? "files" : uploader.accepted_mime_types.join(", ")
+                }{
+                    uploader.max_file_size !== null && ` up to ${(uploader.max_file_size / (1024 * 1024)).toFixed(1)} MB`
+                }. Captures it cannot take are sent to the fallback uploader.
+            </p>
+        }
+
         {promiseState === "resolved" && <>
             <UploaderOptions
                 uploaderId={uploaderId} uploader={uploader} config={config}
-                custom={custom}
+                custom={custom} errorField={errorField}
             />
 
+            <DedupeToggle uploaderId={uploaderId} />
+            <Divider />
+
             <div className="flex">
                 <div className="flex-col mr-2">
                     <Button
@@ -202,6 +257,61 @@ function Uploader({ uploader, uploaderId, custom, reload }: UploaderProps & { re
                     </Button>
                 </div>
 
+                <div className="flex-col mr-2">
+                    <Button
+                        color="default"
+                        onClick={() => setUploaderOption(
+                            "uploader_type_image", "The uploader will be used for images!",
+                        )}
+                    >
+                        Use for Images
+                    </Button>
+                </div>
+
+                <div className="flex-col mr-2">
+                    <Button
+                        color="default"
+                        onClick={() => setUploaderOption(
+                            "uploader_type_video", "The uploader will be used for videos!",
+                        )}
+                    >
+                        Use for Videos
+                    </Button>
+                </div>
+
+                <div className="flex-col mr-2">
+                    <Button
+                        color="default"
+                        onClick={() => setUploaderOption(
+                            "uploader_type_text", "The uploader will be used for text!",
+                        )}
+                    >
+                        Use for Text
+                    </Button>
+                </div>
+
+                <div className="flex-col mr-2">
+                    <Button
+                        color="default"
+                        onClick={() => setUploaderOption(
+                            "fallback_uploader_type", "The uploader has been set as the fallback!",
+                        )}
+                    >
+                        Set as Fallback
+                    </Button>
+                </div>
+
+                {
+                    custom && <div className="flex-col mr-2">
+                        <Button
+                            color="default"
+                            onClick={() => setLogOpen(true)}
+                        >
+                            View Log
+                        </Button>
+                    </div>
+                }
+
                 {
                     custom && <div className="flex-col">
                         <Button
@@ -213,6 +323,10 @@ function Uploader({ uploader, uploaderId, custom, reload }: UploaderProps & { re
                     </div>
                 }
             </div>
+
+            <Modal open={logOpen} onClose={() => setLogOpen(false)} title={`${uploader.name} Log`}>
+                <UploaderLog uploaderId={uploaderId} />
+            </Modal>
         </>}
     </Container>;
 }
@@ -243,6 +357,7 @@ export default function Uploaders() {
     const [uploaders, uploadersPromiseState] = usePromise(getUploaders, []);
     const [revision, setRevision] = useState(0);
     const [customUploaders, customUploadersPromiseState] = usePromise(getCustomUploaders, [revision]);
+    const [customUploaderErrors, customUploaderErrorsPromiseState] = usePromise(getCustomUploaderErrors, [revision]);
 
     // Check if the hash explicitly contains an uploader ID.
     useEffect(() => {
@@ -312,6 +427,11 @@ export default function Uploaders() {
                         }
                     }}
                 />}
+                {
+                    customUploaderErrorsPromiseState === "resolved" && Object.values(customUploaderErrors).map(
+                        message => <Alert type="error" message={message} key={message} />,
+                    )
+                }
                 <div className="mt-4">
                     <CustomUploaderButtons revise={() => setRevision((v) => v + 1)} />
                 </div>
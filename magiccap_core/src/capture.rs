@@ -1,22 +1,30 @@
 use crate::{
     clipboard_actions::{self, CaptureFile},
-    database,
+    database, dedupe,
     mainthread::main_thread_sync,
     notification, ocr,
     region_selector::open_region_selector,
     search_indexing,
     statics::run_thread,
-    uploaders,
+    upload_progress, upload_queue,
+    uploaders::{
+        self, CancellationToken, CaptureMetadata, ProgressCallback, UploadError, UploadSource,
+    },
     utils::get_filename,
     video_capture::start_recorder,
 };
 use image::{DynamicImage, RgbaImage};
-use std::{io::Cursor, path::PathBuf};
+use std::{
+    io::Cursor,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 use xcap::{Monitor, Window};
 
 // Handles writing captures to the filesystem, uploading them to the internet, and injecting them into the clipboard.
 // Also handles any errors within the process.
 fn post_capture_flow(
+    capture_type: &str,
     ext: &str,
     notification_content: &str,
     data: Vec<u8>,
@@ -99,16 +107,9 @@ fn post_capture_flow(
         fp_result = fp.to_str().unwrap().to_string();
     }
 
-    // Get the uploader type.
-    let uploader_type = match database::get_config_option("uploader_type") {
-        Some(type_or_null) => match type_or_null.as_str() {
-            Some(type_) => type_.to_string(),
-            None => "imgur".to_string(),
-        },
-
-        // Defaults to uploading to imgur.
-        None => "imgur".to_string(),
-    };
+    // Get the uploaders to send the capture to. These depend on the kind of file and how big it is.
+    let mime = mime_guess::from_ext(ext).first_or_octet_stream();
+    let uploader_types = uploaders::get_uploaders_for_file(mime.essence_str(), data.len() as u64);
 
     // Check if we should upload.
     let upload_capture = match database::get_config_option("upload_capture") {
@@ -118,25 +119,58 @@ fn post_capture_flow(
         None => false,
     };
 
-    // If uploading is on, upload the file.
+    // If uploading is on, upload the file to all of the destinations.
     let mut url_result: Option<String> = None;
     let mut capture_success = true;
+    let mut upload_results = Vec::new();
+    let mut source = None;
+    let mut content_hash = None;
     if upload_capture {
-        match uploaders::call_uploader(
-            &uploader_type,
-            Box::new(Cursor::new(data.clone())),
-            filename.as_str(),
-        ) {
-            Ok(u) => url_result = Some(u),
+        // Uploaders stream from the disk, so use the saved file or write the data to a temporary one.
+        let created = match save_capture {
+            true => UploadSource::from_path(&fp_result),
+            false => UploadSource::from_bytes(&data, &filename),
+        };
+        match created {
+            Ok(created) => {
+                let created = Arc::new(created);
+                content_hash = dedupe::hash_source(&created);
+                let job = Arc::new(UploadJob {
+                    filename: filename.clone(),
+                    source: created.clone(),
+                    content_hash: content_hash.clone(),
+                    metadata: CaptureMetadata {
+                        capture_id: None,
+                        capture_type: Some(capture_type.to_string()),
+                        file_path: match save_capture {
+                            true => Some(fp_result.clone()),
+                            false => None,
+                        },
+                    },
+                });
+                upload_results = upload_to_destinations(&uploader_types, &job);
+                source = Some(created);
+                match get_primary_upload(&uploader_types, &upload_results) {
+                    Some(i) => url_result = upload_results[i].as_ref().ok().map(|u| u.url.clone()),
+
+                    // This is a capture failure.
+                    None => capture_success = false,
+                }
+
+                // Notify the user of any errors but do not stop the flow for uploader errors.
+                let errors = upload_errors(&uploader_types, &upload_results);
+                if !errors.is_empty() {
+                    notification::send_dialog_message(&errors);
+                }
+            }
             Err(e) => {
-                // This is a capture failure.
+                // Nothing can be uploaded, so this is a capture failure. The user is told why, and the flow
+                // carries on so the capture still goes to the clipboard and is logged.
+                notification::send_dialog_message(&format!(
+                    "Failed to prepare the capture for uploading: {}",
+                    e
+                ));
                 capture_success = false;
-
-                // Log this as a capture failure.
-                database::insert_failed_capture(&filename, Some(&fp_result));
-
-                // Notify the user but do not stop the flow for uploader errors.
-                notification::send_dialog_message(&e);
             }
         }
     }
@@ -162,22 +196,282 @@ fn post_capture_flow(
         }),
     );
 
-    // If this capture was successful, push a notification and write to the database.
-    if capture_success {
-        // The order here matters. The notification can block forever on some systems.
-        let capture_id = database::insert_successful_capture(&filename, Some(&fp_result), url_str);
-        let filename_clone = filename.clone();
-        if let Some(thread_callback) = thread_callback {
-            run_thread(move || thread_callback(&filename_clone, capture_id));
+    // If this capture failed, log it as a capture failure and record the uploads.
+    if !capture_success {
+        if let Some(capture_id) = database::insert_failed_capture(&filename, Some(&fp_result)) {
+            record_uploads(
+                capture_id,
+                &uploader_types,
+                &upload_results,
+                None,
+                &filename,
+                match save_capture {
+                    true => Some(&fp_result),
+                    false => None,
+                },
+                source.as_deref(),
+            );
+            if let Some(content_hash) = &content_hash {
+                database::set_capture_content_hash(capture_id, content_hash);
+            }
         }
-        notification::send_notification(
-            notification_content,
-            url_str,
-            match save_capture {
-                true => Some(&fp_result),
+        return;
+    }
+
+    // The order here matters. The notification can block forever on some systems.
+    let capture_id = database::insert_successful_capture(&filename, Some(&fp_result), url_str);
+    record_uploads(
+        capture_id,
+        &uploader_types,
+        &upload_results,
+        get_primary_upload(&uploader_types, &upload_results),
+        &filename,
+        match save_capture {
+            true => Some(&fp_result),
+            false => None,
+        },
+        source.as_deref(),
+    );
+    if let Some(content_hash) = &content_hash {
+        database::set_capture_content_hash(capture_id, content_hash);
+    }
+    let filename_clone = filename.clone();
+    if let Some(thread_callback) = thread_callback {
+        run_thread(move || thread_callback(&filename_clone, capture_id));
+    }
+    notification::send_notification(
+        notification_content,
+        url_str,
+        match save_capture {
+            true => Some(&fp_result),
+            false => None,
+        },
+    );
+}
+
+// Defines the size after which the upload progress is shown in the tray. Smaller uploads finish too quickly.
+#[cfg(target_os = "linux")]
+const PROGRESS_ICON_THRESHOLD: u64 = 5 * 1024 * 1024;
+
+// Shows the combined progress of the uploads for a capture in the tray where it is supported.
+struct ProgressIcon {
+    #[cfg(target_os = "linux")]
+    icon: Option<crate::temp_icon::IconHandler>,
+    progress: Mutex<Vec<(u64, u64)>>,
+}
+
+impl ProgressIcon {
+    // Creates the icon. Cancelling from the icon cancels the token.
+    fn new(token: CancellationToken, uploads: usize, size: u64) -> Arc<Self> {
+        #[cfg(not(target_os = "linux"))]
+        let _ = (token, size);
+        Arc::new(Self {
+            #[cfg(target_os = "linux")]
+            icon: match size >= PROGRESS_ICON_THRESHOLD {
+                true => Some(crate::temp_icon::IconHandler::uploading(Box::new(move || token.cancel()))),
                 false => None,
             },
-        );
+            progress: Mutex::new(vec![(0, size); uploads]),
+        })
+    }
+
+    // Updates the progress of the upload at the index specified.
+    fn update(&self, index: usize, sent: u64, total: u64) {
+        let mut progress = self.progress.lock().unwrap();
+        progress[index] = (sent, total);
+        let (sent, total) = progress
+            .iter()
+            .fold((0, 0), |(s, t), (sent, total)| (s + sent, t + total));
+        drop(progress);
+        #[cfg(target_os = "linux")]
+        if let Some(icon) = &self.icon {
+            icon.progress(sent, total);
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (sent, total);
+    }
+}
+
+impl Drop for ProgressIcon {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        if let Some(icon) = &self.icon {
+            icon.remove();
+        }
+    }
+}
+
+// Defines a successful upload to one of the uploaders.
+struct UploadedFile {
+    url: String,
+
+    // Defines the token used to delete the file from the uploader. Reused URLs do not have one since the
+    // file belongs to the earlier capture.
+    deletion_token: Option<String>,
+
+    // Defines the thumbnail the uploader made if there is one.
+    thumbnail_url: Option<String>,
+}
+
+// Defines the capture being uploaded. This is shared between the upload threads.
+struct UploadJob {
+    filename: String,
+    source: Arc<UploadSource>,
+    content_hash: Option<String>,
+    metadata: CaptureMetadata,
+}
+
+// Uploads the capture to one uploader while tracking the progress. If the same content was already uploaded
+// and the uploader is opted in, the URL is reused instead.
+fn tracked_upload(
+    uploader_type: &str,
+    job: &UploadJob,
+    token: &CancellationToken,
+    icon: &Arc<ProgressIcon>,
+    index: usize,
+) -> Result<UploadedFile, UploadError> {
+    let content_hash = job.content_hash.as_deref();
+    if let Some(url) = content_hash.and_then(|h| dedupe::find_reusable_url(uploader_type, h)) {
+        return Ok(UploadedFile { url, deletion_token: None, thumbnail_url: None });
+    }
+
+    let icon = icon.clone();
+    let extra: ProgressCallback = Box::new(move |sent, total| icon.update(index, sent, total));
+    let (_tracked, ctx) = upload_progress::track(
+        uploader_type,
+        &job.filename,
+        job.source.size(),
+        token.clone(),
+        Some(extra),
+    );
+    let ctx = ctx.with_capture(job.metadata.clone());
+    let url = uploaders::call_uploader(uploader_type, &job.source, &job.filename, &ctx)?;
+    Ok(UploadedFile {
+        url,
+        deletion_token: ctx.take_deletion_token(),
+        thumbnail_url: ctx.take_thumbnail_url(),
+    })
+}
+
+// Uploads the capture to all of the uploaders in parallel. The results are in the same order as the uploaders.
+fn upload_to_destinations(
+    uploader_types: &[String],
+    job: &Arc<UploadJob>,
+) -> Vec<Result<UploadedFile, UploadError>> {
+    // Every upload shares a token so cancelling from the tray stops all of them.
+    let token = CancellationToken::new();
+    let icon = ProgressIcon::new(token.clone(), uploader_types.len(), job.source.size());
+
+    // If there is only one uploader, there is no need for any threads.
+    if uploader_types.len() == 1 {
+        return vec![tracked_upload(&uploader_types[0], job, &token, &icon, 0)];
+    }
+
+    // Run all of the uploads on scoped threads instead of run_thread. The capture flow already runs on a pool
+    // thread and has to wait for every upload to finish, so if the uploads were queued on the same pool, a few
+    // captures at once could fill every pool thread with waiting flows and the uploads would never start.
+    // Scoped threads can also borrow the job and the token, so nothing has to be cloned per upload.
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = uploader_types
+            .iter()
+            .enumerate()
+            .map(|(i, uploader_type)| {
+                let token = &token;
+                let icon = &icon;
+                scope.spawn(move || tracked_upload(uploader_type, job, token, icon, i))
+            })
+            .collect();
+
+        // Collect the results. If a thread panicked, the upload is treated as failed.
+        handles
+            .into_iter()
+            .map(|handle| match handle.join() {
+                Ok(result) => result,
+                Err(_) => Err(UploadError::io("The upload stopped unexpectedly.")),
+            })
+            .collect()
+    })
+}
+
+// Gets the index of the upload whose URL is used for the capture based on the primary upload policy.
+fn get_primary_upload(
+    uploader_types: &[String],
+    results: &[Result<UploadedFile, UploadError>],
+) -> Option<usize> {
+    let policy = database::get_config_option("upload_primary_policy");
+    match policy.as_ref().and_then(|v| v.as_str()) {
+        // Use the designated uploader. If it failed, the capture failed. If it is not one of the uploaders for
+        // this capture, use the first one that succeeded.
+        Some("designated") => {
+            let designated = database::get_config_option("upload_primary_uploader");
+            let designated = match designated.as_ref().and_then(|v| v.as_str()) {
+                Some(designated) => designated,
+                None => uploader_types[0].as_str(),
+            };
+            match uploader_types.iter().position(|u| u == designated) {
+                Some(i) => Some(i).filter(|i| results[*i].is_ok()),
+                None => results.iter().position(|r| r.is_ok()),
+            }
+        }
+
+        // By default, use the first uploader in the list that succeeded.
+        _ => results.iter().position(|r| r.is_ok()),
+    }
+}
+
+// Builds a message with all of the upload errors. Empty if there were none.
+fn upload_errors(
+    uploader_types: &[String],
+    results: &[Result<UploadedFile, UploadError>],
+) -> String {
+    let errors: Vec<(&String, &UploadError)> = uploader_types
+        .iter()
+        .zip(results.iter())
+        .filter_map(|(u, r)| r.as_ref().err().map(|e| (u, e)))
+        .collect();
+    match errors.len() {
+        0 => "".to_string(),
+        1 if uploader_types.len() == 1 => errors[0].1.to_string(),
+        _ => errors
+            .iter()
+            .map(|(u, e)| format!("{}: {}", u, e))
+            .collect::<Vec<String>>()
+            .join("\n"),
+    }
+}
+
+// Records the result of each upload for the capture and queues any failures to be retried. Failures are only
+// possible if there was a source to upload.
+fn record_uploads(
+    capture_id: i64,
+    uploader_types: &[String],
+    results: &[Result<UploadedFile, UploadError>],
+    primary: Option<usize>,
+    filename: &str,
+    file_path: Option<&str>,
+    source: Option<&UploadSource>,
+) {
+    for (i, (uploader_type, result)) in uploader_types.iter().zip(results.iter()).enumerate() {
+        let is_primary = primary == Some(i);
+        match result {
+            Ok(upload) => {
+                database::insert_capture_upload(
+                    capture_id, uploader_type, Some(&upload.url), None, is_primary,
+                    Some(&dedupe::config_hash(uploader_type)), upload.deletion_token.as_deref(),
+                );
+                if let Some(thumbnail_url) = &upload.thumbnail_url {
+                    database::set_capture_upload_thumbnail(capture_id, uploader_type, thumbnail_url);
+                }
+            }
+            Err(e) => {
+                database::insert_capture_upload(
+                    capture_id, uploader_type, None, Some(&e.to_string()), is_primary, None, None,
+                );
+                if let Some(source) = source {
+                    upload_queue::enqueue_or_notify(capture_id, uploader_type, filename, file_path, source, e);
+                }
+            }
+        }
     }
 }
 
@@ -217,6 +511,7 @@ pub fn region_capture() {
         .unwrap();
 
     post_capture_flow(
+        "region",
         "png",
         "Region capture successful.",
         data,
@@ -231,7 +526,7 @@ pub fn gif_capture() {
         None => return,
     };
     let data = start_recorder(true, monitor, region);
-    post_capture_flow("gif", "GIF capture successful.", data, None)
+    post_capture_flow("gif", "gif", "GIF capture successful.", data, None)
 }
 
 // Handle doing MP4 captures.
@@ -241,7 +536,7 @@ pub fn video_capture() {
         None => return,
     };
     let data = start_recorder(false, monitor, region);
-    post_capture_flow("mp4", "Video capture successful.", data, None)
+    post_capture_flow("video", "mp4", "Video capture successful.", data, None)
 }
 
 // Take a Pixbuf and turn it into a image.
@@ -289,6 +584,7 @@ pub fn clipboard_capture() {
             };
             let v_clone = v.clone();
             post_capture_flow(
+                "clipboard",
                 mime.subtype().as_str(),
                 "Clipboard capture successful.",
                 v,
@@ -299,6 +595,68 @@ pub fn clipboard_capture() {
     }
 }
 
+// Gets the text in the clipboard. The error is the message to show the user.
+#[cfg(target_os = "linux")]
+fn clipboard_text() -> Result<String, String> {
+    // TODO: wayland
+    let text = main_thread_sync(|| {
+        let clipboard = gtk::Clipboard::default(&gdk::Display::default().unwrap()).unwrap();
+        clipboard.wait_for_text().map(|t| t.to_string())
+    });
+    match text {
+        Some(text) if !text.trim().is_empty() => Ok(text),
+        _ => Err("There is no text in the clipboard to upload.".to_string()),
+    }
+}
+
+// Reading text from the clipboard is only supported on Linux.
+#[cfg(not(target_os = "linux"))]
+fn clipboard_text() -> Result<String, String> {
+    Err("Uploading text from the clipboard is only supported on Linux.".to_string())
+}
+
+// Scans the text in a region the user selects. Returns None if the user closed the selector, and the error is
+// the message to show the user.
+fn region_text() -> Option<Result<String, String>> {
+    let image = open_region_selector(true)?.image;
+    let text = ocr::scan_text(DynamicImage::ImageRgba8(image).to_rgb8());
+    match text.trim().is_empty() {
+        true => Some(Err("No text was found in the region.".to_string())),
+        false => Some(Ok(text)),
+    }
+}
+
+// Handles uploading text. The text_capture_source option decides if this uses the text in the clipboard or
+// scans the text in a region. Scanning a region is the default since it works on every platform.
+pub fn text_capture() {
+    let source = database::get_config_option("text_capture_source");
+    let result = match source.as_ref().and_then(|v| v.as_str()) {
+        Some("clipboard") => clipboard_text(),
+        _ => match region_text() {
+            Some(result) => result,
+            None => return,
+        },
+    };
+    let text = match result {
+        Ok(text) => text,
+        Err(message) => {
+            notification::send_notification(&message, None, None);
+            return;
+        }
+    };
+
+    let data = text.clone().into_bytes();
+    post_capture_flow(
+        "text",
+        "txt",
+        "Text upload successful.",
+        data,
+        Some(Box::new(move |filename, capture_id| {
+            search_indexing::insert_capture(capture_id, filename, text, Vec::new())
+        })),
+    )
+}
+
 // Handle doing fullscreen captures. This will capture all of the displays in order.
 pub fn fullscreen_capture() {
     let monitors = Monitor::all().unwrap();
@@ -360,6 +718,7 @@ pub fn fullscreen_capture() {
     // Handle the post capture flow.
     let windows = Window::all().unwrap();
     post_capture_flow(
+        "fullscreen",
         "png",
         "Fullscreen capture successful.",
         vec,
@@ -44,12 +44,56 @@ fn ensure_id_number(id: Option<&str>) -> Result<i64, APIError> {
     }
 }
 
-// Allows you to delete a capture.
-fn delete_capture(id: Option<&str>) -> Option<APIError> {
+// Deletes the uploads for a capture from the uploaders. URLs reused by other captures are left alone, and uploads
+// made with a different uploader config are not deleted since the token may not mean the same thing anymore.
+// Tokens which worked are cleared so trying again only deletes what is left.
+fn delete_remote_uploads(capture_id: i64) -> Option<APIError> {
+    let mut errors = Vec::new();
+    for upload in database::get_capture_uploads(capture_id) {
+        let deletion_token = match &upload.deletion_token {
+            Some(deletion_token) => deletion_token,
+            None => continue,
+        };
+        if let Some(url) = &upload.url {
+            if database::is_url_shared(capture_id, url) {
+                continue;
+            }
+        }
+        if upload.config_hash.as_deref() != Some(crate::dedupe::config_hash(&upload.uploader_id).as_str()) {
+            errors.push(format!(
+                "{}: The uploader config changed since the file was uploaded, so it cannot be deleted.",
+                upload.uploader_id
+            ));
+            continue;
+        }
+        match crate::uploaders::delete_remote(&upload.uploader_id, deletion_token) {
+            Ok(()) => database::clear_capture_upload_deletion_token(capture_id, &upload.uploader_id),
+            Err(e) => errors.push(format!("{}: {}", upload.uploader_id, e)),
+        }
+    }
+
+    // Keep the capture if anything failed so the user can try again.
+    match errors.is_empty() {
+        true => None,
+        false => Some(APIError {
+            message: format!("Failed to delete the capture from the uploader:\n{}", errors.join("\n")),
+            user_facing: true,
+        }),
+    }
+}
+
+// Allows you to delete a capture. If remote is set, the uploaded files are deleted from the uploaders first.
+fn delete_capture(id: Option<&str>, remote: bool) -> Option<APIError> {
     let id = match ensure_id_number(id) {
         Ok(id) => id,
         Err(err) => return Some(err),
     };
+    if remote {
+        if let Some(err) = delete_remote_uploads(id) {
+            return Some(err);
+        }
+    }
+    crate::upload_queue::cancel_for_capture(id);
     match database::delete_capture(id) {
         true => None,
         false => Some(APIError {
@@ -329,7 +373,25 @@ fn set_uploader_config_option(query: &serde_json::Value) -> Option<APIError> {
         }
     };
 
-    database::set_uploader_config_item(uploader_id, key, value);
+    // Validate the value against the option if the uploader defines it. Unset values are removed so the
+    // default is used.
+    let uploader = crate::uploaders::get_uploader(uploader_id);
+    let option = uploader
+        .as_ref()
+        .and_then(|u| u.options.iter().find(|(k, _)| k == key).map(|(_, o)| o));
+    match option {
+        Some(option) => match option.validate(key, value) {
+            Ok(Some(value)) => database::set_uploader_config_item(uploader_id, key, &value),
+            Ok(None) => database::delete_uploader_config_item(uploader_id, key),
+            Err(e) => {
+                return Some(APIError {
+                    message: e.to_string(),
+                    user_facing: true,
+                })
+            }
+        },
+        None => database::set_uploader_config_item(uploader_id, key, value),
+    }
 
     // No errors!
     None
@@ -482,7 +544,7 @@ fn get_uploaders() -> Result<serde_json::Value, APIError> {
     Ok(serde_json::Value::Object(serde_json::Map::from_iter(
         crate::uploaders::UPLOADERS
             .iter()
-            .map(|(k, v)| (k.to_string(), serde_json::to_value(v).unwrap())),
+            .map(|(k, v)| (k.to_string(), serde_json::to_value(v.as_ref()).unwrap())),
     )))
 }
 
@@ -491,10 +553,15 @@ fn get_custom_uploaders() -> Result<serde_json::Value, APIError> {
     Ok(serde_json::Value::Object(serde_json::Map::from_iter(
         crate::uploaders::get_custom_uploaders()
             .iter()
-            .map(|(k, v)| (k.to_string(), serde_json::to_value(v).unwrap())),
+            .map(|(k, v)| (k.to_string(), serde_json::to_value(v.as_ref()).unwrap())),
     )))
 }
 
+// Gets why each custom uploader which could not be loaded failed.
+fn get_custom_uploader_errors() -> Result<serde_json::Value, APIError> {
+    Ok(serde_json::to_value(crate::uploaders::get_custom_uploader_errors()).unwrap())
+}
+
 // Get the build date.
 fn build_date() -> String {
     let build_timestamp = env!("BUILD_TIMESTAMP");
@@ -536,17 +603,25 @@ fn get_build_info(key: Option<&str>) -> Result<serde_json::Value, APIError> {
 
 // Inserts a custom uploader.
 fn insert_custom_uploader(query: &serde_json::Value) -> Option<APIError> {
-    // Get the uploader and attempt to deserialize it.
-    let uploader: crate::uploaders::custom::CustomUploader =
-        match serde_json::from_value(query["uploader"].clone()) {
-            Ok(v) => v,
-            Err(e) => {
-                return Some(APIError {
-                    message: format!("The uploader is not valid: {}", e),
-                    user_facing: true,
-                })
-            }
-        };
+    // Get the uploader, migrate it to the latest version, and attempt to deserialize it.
+    let uploader = match crate::uploaders::custom::migrate_uploader(query["uploader"].clone()) {
+        Ok((v, _)) => v,
+        Err(e) => {
+            return Some(APIError {
+                message: format!("The uploader is not valid: {}", e),
+                user_facing: true,
+            })
+        }
+    };
+    let uploader: crate::uploaders::custom::CustomUploader = match serde_json::from_value(uploader) {
+        Ok(v) => v,
+        Err(e) => {
+            return Some(APIError {
+                message: format!("The uploader is not valid: {}", e),
+                user_facing: true,
+            })
+        }
+    };
 
     // Check if replace is set.
     let replace = match query.get("replace") {
@@ -573,12 +648,182 @@ fn insert_custom_uploader(query: &serde_json::Value) -> Option<APIError> {
                 message: e,
                 user_facing: true,
             },
+            crate::uploaders::CustomUploaderInsertError::InvalidName(e) => APIError {
+                message: e,
+                user_facing: true,
+            },
         };
         return Some(dispatched_err);
     }
     None
 }
 
+// Imports a ShareX .sxcu file as a custom uploader.
+fn import_sxcu(query: &serde_json::Value) -> Result<serde_json::Value, APIError> {
+    // Get the contents of the file.
+    let data = match query_find(query, "data") {
+        Some(data) => data,
+        None => {
+            return Err(APIError {
+                message: "The data is required.".to_string(),
+                user_facing: true,
+            })
+        }
+    };
+
+    // Convert the file into a custom uploader.
+    let uploader = match crate::uploaders::custom::sxcu_to_uploader(data) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(APIError {
+                message: e,
+                user_facing: true,
+            })
+        }
+    };
+    let serialized = serde_json::to_value(&uploader).unwrap();
+
+    // Insert it the same way as any other custom uploader.
+    match insert_custom_uploader(&serde_json::json!({
+        "uploader": serialized,
+        "replace": query.get("replace").cloned().unwrap_or(serde_json::Value::Bool(false)),
+    })) {
+        Some(err) => Err(err),
+        None => Ok(serialized),
+    }
+}
+
+// Exports a custom uploader to a bundle that can be shared.
+fn export_custom_uploader(query: &serde_json::Value) -> Result<serde_json::Value, APIError> {
+    // Get the uploader ID.
+    let id = match query_find(query, "id") {
+        Some(id) => id.to_string(),
+        None => {
+            return Err(APIError {
+                message: "The id is required.".to_string(),
+                user_facing: true,
+            })
+        }
+    };
+
+    // Get if the PHP data folder should be included and if the bundle should be signed.
+    let include_data = query
+        .get("include_data")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let sign = query.get("sign").and_then(|v| v.as_bool()).unwrap_or(false);
+
+    // Ask the user where to save the bundle.
+    let file_name = format!("{}.mcbundle", id);
+    let fp = main_thread_sync(move || {
+        let res = native_dialog::FileDialog::new()
+            .set_filename(&file_name)
+            .add_filter("MagicCap Uploader Bundle", &["mcbundle"])
+            .show_save_single_file();
+        match res {
+            Ok(Some(path)) => Some(path),
+            Ok(None) => None,
+            Err(e) => {
+                eprintln!("Failed to open save dialog: {}", e);
+                None
+            }
+        }
+    });
+    let fp = match fp {
+        Some(fp) => fp,
+        None => return Ok(serde_json::Value::Null),
+    };
+
+    // Write the bundle and return the signer.
+    match crate::uploaders::custom::export_bundle(&id, include_data, sign, &fp) {
+        Ok(signer) => Ok(serde_json::json!({
+            "path": fp.to_string_lossy(),
+            "signer": signer,
+        })),
+        Err(e) => Err(APIError {
+            message: e,
+            user_facing: true,
+        }),
+    }
+}
+
+// Imports a custom uploader bundle. The signer is returned so it can be shown to the user.
+fn import_custom_uploader_bundle(query: &serde_json::Value) -> Result<serde_json::Value, APIError> {
+    // Ask the user for the bundle.
+    let fp = main_thread_sync(|| {
+        let res = native_dialog::FileDialog::new()
+            .add_filter("MagicCap Uploader Bundle", &["mcbundle"])
+            .show_open_single_file();
+        match res {
+            Ok(Some(path)) => Some(path),
+            Ok(None) => None,
+            Err(e) => {
+                eprintln!("Failed to open file dialog: {}", e);
+                None
+            }
+        }
+    });
+    let fp = match fp {
+        Some(fp) => fp,
+        None => return Ok(serde_json::Value::Null),
+    };
+
+    // Read the bundle. This fails if the signature does not match.
+    let bundle = match crate::uploaders::custom::read_bundle(&fp) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(APIError {
+                message: e,
+                user_facing: true,
+            })
+        }
+    };
+    let serialized = serde_json::to_value(&bundle.uploader).unwrap();
+
+    // Insert it the same way as any other custom uploader.
+    if let Some(err) = insert_custom_uploader(&serde_json::json!({
+        "uploader": serialized,
+        "replace": query.get("replace").cloned().unwrap_or(serde_json::Value::Bool(false)),
+    })) {
+        return Err(err);
+    }
+
+    // Write the PHP data folder. The uploader is keyed by its name.
+    if let Err(e) = bundle.write_data(&bundle.uploader.name) {
+        return Err(APIError {
+            message: format!("The uploader was imported but its data could not be written: {}", e),
+            user_facing: true,
+        });
+    }
+
+    Ok(serde_json::json!({
+        "uploader": serialized,
+        "signer": bundle.signer,
+        "untrusted_signer": bundle.untrusted_signer,
+        "has_data": bundle.has_data(),
+    }))
+}
+
+// Trusts the public key specified to sign bundles.
+fn trust_bundle_signer(key: Option<&str>) -> Option<APIError> {
+    let key = match key {
+        Some(key) => key,
+        None => {
+            return Some(APIError {
+                message: "The key is required.".to_string(),
+                user_facing: true,
+            })
+        }
+    };
+    match crate::uploaders::custom::trust_signer(key) {
+        Ok(_) => None,
+        Err(e) => Some(APIError {
+            message: e,
+            user_facing: true,
+        }),
+    }
+}
+
 // Deletes a custom uploader if it exists.
 fn delete_custom_uploader(id: Option<&str>) -> Option<APIError> {
     let id = match id {
@@ -595,6 +840,33 @@ fn delete_custom_uploader(id: Option<&str>) -> Option<APIError> {
     None
 }
 
+// Gets the log the PHP script for a custom uploader wrote.
+fn get_custom_uploader_log(id: Option<&str>) -> Result<serde_json::Value, APIError> {
+    match id {
+        Some(id) => Ok(serde_json::Value::String(crate::uploaders::custom::read_php_log(id))),
+        None => Err(APIError {
+            message: "The id is required.".to_string(),
+            user_facing: true,
+        }),
+    }
+}
+
+// Clears the log for a custom uploader.
+fn clear_custom_uploader_log(id: Option<&str>) -> Option<APIError> {
+    let id = match id {
+        Some(id) => id,
+        None => {
+            return Some(APIError {
+                message: "The id is required.".to_string(),
+                user_facing: true,
+            })
+        }
+    };
+
+    crate::uploaders::custom::clear_php_log(id);
+    None
+}
+
 // Defines the global hotkey being captured.
 pub static GLOBAL_HOTKEY: Mutex<Option<HotkeyCapture>> = Mutex::new(None);
 
@@ -614,25 +886,35 @@ fn stop_hotkey_capture() -> Option<APIError> {
 
 static UPLOAD_TEST_IMAGE: &[u8] = include_bytes!("./upload_test_image.png");
 
-// Allows you to test a uploader.
-fn test_uploader(id: Option<&str>) -> Option<APIError> {
+// Allows you to test a uploader. Returns null on success, or the error and a message for the user on failure
+// so the frontend can highlight the option that is wrong.
+fn test_uploader(id: Option<&str>) -> Result<serde_json::Value, APIError> {
     let id = match id {
         Some(id) => id,
         None => {
-            return Some(APIError {
+            return Err(APIError {
                 message: "The id is required.".to_string(),
                 user_facing: true,
             })
         }
     };
 
-    let reader = Box::new(std::io::Cursor::new(UPLOAD_TEST_IMAGE));
-    match crate::uploaders::call_uploader(id, reader, "test.png") {
-        Ok(_) => None,
-        Err(e) => Some(APIError {
-            message: format!("The uploader failed: {}", e),
-            user_facing: true,
-        }),
+    let source = match crate::uploaders::UploadSource::from_bytes(UPLOAD_TEST_IMAGE, "test.png") {
+        Ok(source) => source,
+        Err(e) => {
+            return Err(APIError {
+                message: e.to_string(),
+                user_facing: true,
+            })
+        }
+    };
+    let ctx = crate::uploaders::UploadContext::background();
+    match crate::uploaders::call_uploader(id, &source, "test.png", &ctx) {
+        Ok(_) => Ok(serde_json::Value::Null),
+        Err(e) => Ok(serde_json::json!({
+            "error": e,
+            "message": format!("The uploader failed: {}", e),
+        })),
     }
 }
 
@@ -765,6 +1047,69 @@ fn load_config() -> Option<APIError> {
     None
 }
 
+// Gets the result of every upload for a capture.
+fn get_capture_uploads(id: Option<&str>) -> Result<serde_json::Value, APIError> {
+    let id = match ensure_id_number(id) {
+        Ok(id) => id,
+        Err(err) => return Err(err),
+    };
+
+    Ok(serde_json::to_value(database::get_capture_uploads(id)).unwrap())
+}
+
+// Lists the uploads which are queued to be retried.
+fn list_upload_queue() -> Result<serde_json::Value, APIError> {
+    Ok(serde_json::to_value(database::get_queued_uploads()).unwrap())
+}
+
+// Retries a queued upload now. Returns the URL if it was successful.
+fn retry_upload(id: Option<&str>) -> Result<serde_json::Value, APIError> {
+    let id = match ensure_id_number(id) {
+        Ok(id) => id,
+        Err(err) => return Err(err),
+    };
+
+    match crate::upload_queue::retry(id) {
+        Ok(url) => Ok(serde_json::Value::String(url)),
+        Err(e) => Err(APIError {
+            message: format!("The upload failed: {}", e),
+            user_facing: true,
+        }),
+    }
+}
+
+// Cancels a queued upload.
+fn cancel_upload(id: Option<&str>) -> Option<APIError> {
+    let id = match ensure_id_number(id) {
+        Ok(id) => id,
+        Err(err) => return Some(err),
+    };
+
+    match crate::upload_queue::cancel(id) {
+        true => None,
+        false => Some(APIError {
+            message: "The queued upload does not exist.".to_string(),
+            user_facing: true,
+        }),
+    }
+}
+
+// Cancels an upload which is running.
+fn cancel_active_upload(id: Option<&str>) -> Option<APIError> {
+    let id = match ensure_id_number(id) {
+        Ok(id) => id,
+        Err(err) => return Some(err),
+    };
+
+    match crate::upload_progress::cancel(id as u64) {
+        true => None,
+        false => Some(APIError {
+            message: "The upload is not running.".to_string(),
+            user_facing: true,
+        }),
+    }
+}
+
 // Routes the API call to the correct function.
 fn route_api_call(
     api_type: &str,
@@ -772,7 +1117,10 @@ fn route_api_call(
 ) -> Result<serde_json::Value, APIError> {
     match api_type {
         // Handle deleting a capture.
-        "delete_capture" => err_only(delete_capture(query_find(query, "id"))),
+        "delete_capture" => err_only(delete_capture(
+            query_find(query, "id"),
+            query.get("remote").and_then(|v| v.as_bool()).unwrap_or(false),
+        )),
 
         // Opens a capture URL.
         "open_url" => err_only(open_url(query_find(query, "id"))),
@@ -818,15 +1166,45 @@ fn route_api_call(
         // Gets all the custom uploaders.
         "get_custom_uploaders" => get_custom_uploaders(),
 
+        // Gets why any custom uploaders could not be loaded.
+        "get_custom_uploader_errors" => get_custom_uploader_errors(),
+
         // Gets build information from the internal data.
         "get_build_info" => get_build_info(query_find(query, "key")),
 
         // Inserts a custom uploader.
         "insert_custom_uploader" => err_only(insert_custom_uploader(query)),
 
+        // Imports a ShareX custom uploader.
+        "import_sxcu" => import_sxcu(query),
+
+        // Exports a custom uploader to a bundle.
+        "export_custom_uploader" => export_custom_uploader(query),
+
+        // Imports a custom uploader bundle.
+        "import_custom_uploader_bundle" => import_custom_uploader_bundle(query),
+
+        // Gets the public key used to sign exported bundles.
+        "get_bundle_signing_key" => match crate::uploaders::custom::signing_public_key() {
+            Ok(key) => Ok(serde_json::Value::String(key)),
+            Err(e) => Err(APIError {
+                message: e,
+                user_facing: true,
+            }),
+        },
+
+        // Trusts the public key specified to sign bundles.
+        "trust_bundle_signer" => err_only(trust_bundle_signer(query_find(query, "key"))),
+
         // Deletes a custom uploader if it exists.
         "delete_custom_uploader" => err_only(delete_custom_uploader(query_find(query, "id"))),
 
+        // Gets the log for a custom uploader.
+        "get_custom_uploader_log" => get_custom_uploader_log(query_find(query, "id")),
+
+        // Clears the log for a custom uploader.
+        "clear_custom_uploader_log" => err_only(clear_custom_uploader_log(query_find(query, "id"))),
+
         // Starts the hotkey capture.
         "start_hotkey_capture" => err_only(start_hotkey_capture()),
 
@@ -834,7 +1212,7 @@ fn route_api_call(
         "stop_hotkey_capture" => err_only(stop_hotkey_capture()),
 
         // Allows you to test a uploader.
-        "test_uploader" => err_only(test_uploader(query_find(query, "id"))),
+        "test_uploader" => test_uploader(query_find(query, "id")),
 
         // Opens a save dialog.
         "save_dialog" => err_only(save_dialog(query)),
@@ -851,6 +1229,21 @@ fn route_api_call(
         // Loads the configuration.
         "load_config" => err_only(load_config()),
 
+        // Gets the uploads for a capture.
+        "get_capture_uploads" => get_capture_uploads(query_find(query, "id")),
+
+        // Lists the upload queue.
+        "list_upload_queue" => list_upload_queue(),
+
+        // Retries a queued upload.
+        "retry_upload" => retry_upload(query_find(query, "id")),
+
+        // Cancels a queued upload.
+        "cancel_upload" => err_only(cancel_upload(query_find(query, "id"))),
+
+        // Cancels an upload which is running.
+        "cancel_active_upload" => err_only(cancel_active_upload(query_find(query, "id"))),
+
         // Catch all unknown API types.
         _ => Err(APIError {
             message: "Unknown API type".to_string(),
@@ -1,3 +1,4 @@
+use super::fs_proxy::icon_for_filename;
 use crate::{
     database::{get_captures, get_many_captures, Capture},
     search_indexing,
@@ -16,6 +17,18 @@ fn generate_info(capture: &Capture) -> Box<div<String>> {
         a11y_capture_info = "capture succeeded ";
     }
 
+    // Mark captures which reused the URL of an earlier upload.
+    let duplicate_label = match capture.duplicate {
+        true => " (duplicate)",
+        false => "",
+    };
+
+    // Lets the frontend offer to delete the file from the uploader too.
+    let remote_deletable = match capture.remote_deletable {
+        true => "true",
+        false => "false",
+    };
+
     // tbh this macro is a big hack and I don't like how it interacts with VS Code. The fact
     // I can't collapse DOM nodes is not dyslexia friendly. Ah well.
     html!(
@@ -23,6 +36,7 @@ fn generate_info(capture: &Capture) -> Box<div<String>> {
             <p class="text-sm block" tabindex="0">
                 <span class="sr-only">{text!(a11y_capture_info)}</span>
                 {text!(&capture.filename)}
+                <span class="opacity-75">{text!(duplicate_label)}</span>
             </p>
 
             <div class="hide-first-when-hovered text-sm">
@@ -84,6 +98,7 @@ fn generate_info(capture: &Capture) -> Box<div<String>> {
                             <form
                                 data-action="deleteCapture" method="post"
                                 autocomplete="off" aria-relevant="all" aria-live="assertive"
+                                data-remote-deletable=remote_deletable
                             >
                                 <input type="hidden" name="capture_id" value=&cap_id_str />
                                 <button class="cursor-default" type="submit" aria-label="Delete Capture">
@@ -108,6 +123,15 @@ pub fn generate_dom_node(capture: Capture) -> Box<div<String>> {
         }
     };
 
+    // If the capture was not saved, show the thumbnail from the uploader if it made one, or an icon for files
+    // which are not images.
+    let placeholder = "magiccap-internal://frontend-dist/placeholder.png".to_string();
+    let preview_src = match (&capture.file_path, &capture.thumbnail_url) {
+        (None, Some(thumbnail_url)) => thumbnail_url.clone(),
+        (None, None) => icon_for_filename(&capture.filename).unwrap_or(placeholder),
+        _ => placeholder,
+    };
+
     html!(
         <div data-capture-root="1" class="flex-col m-2 shadow-md">
             <div class="block w-48 h-24 relative rounded-lg overflow-hidden">
@@ -116,7 +140,7 @@ pub fn generate_dom_node(capture: Capture) -> Box<div<String>> {
                 </div>
                 <img
                     class="object-cover w-full h-full rounded-lg absolute"
-                    src="magiccap-internal://frontend-dist/placeholder.png"
+                    src=preview_src
                     alt=""
                     loading="lazy"
                     data-filesystem-path=fp
@@ -2,18 +2,111 @@ use base64::Engine;
 use image::GenericImageView;
 use std::{
     fs,
-    io::{BufReader, Cursor},
+    io::{BufReader, Cursor, Read},
 };
 
+// Defines the extensions which are previewed as images.
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "bmp", "tiff", "webp"];
+
+// Defines the extensions which are previewed as text.
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "log", "json", "csv", "xml", "yml", "yaml", "toml", "ini", "html", "css", "js", "ts", "rs",
+    "py", "sh",
+];
+
+// Defines how much of a text file is read for the preview.
+const TEXT_PREVIEW_BYTES: u64 = 2048;
+
+// Escapes text so it can go in a SVG.
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// Turns a SVG into a data URI.
+fn svg_data_uri(svg: String) -> Vec<u8> {
+    format!(
+        "data:image/svg+xml;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(svg)
+    )
+    .into_bytes()
+}
+
+// Makes a preview with the first few lines of a text file.
+fn proxy_text(fp: &str) -> Result<Vec<u8>, String> {
+    let file = match fs::File::open(fp) {
+        Ok(x) => x,
+        Err(err) => return Err(format!("Error opening file: {}", err)),
+    };
+    let mut buf = Vec::new();
+    if let Err(err) = file.take(TEXT_PREVIEW_BYTES).read_to_end(&mut buf) {
+        return Err(format!("Error reading file: {}", err));
+    }
+    let text = String::from_utf8_lossy(&buf);
+
+    // Put each line in the SVG, cutting off long lines.
+    let lines: String = text
+        .lines()
+        .take(8)
+        .enumerate()
+        .map(|(i, line)| {
+            let line: String = line.chars().take(48).collect();
+            format!(
+                "<text x=\"8\" y=\"{}\" xml:space=\"preserve\">{}</text>",
+                20 + i * 22,
+                escape_xml(&line)
+            )
+        })
+        .collect();
+    Ok(svg_data_uri(format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"384\" height=\"192\">\
+<rect width=\"100%\" height=\"100%\" fill=\"#f8fafc\"/>\
+<g font-family=\"monospace\" font-size=\"14\" fill=\"#1e293b\">{}</g></svg>",
+        lines
+    )))
+}
+
+// Makes a preview showing the extension for files which cannot be previewed.
+fn proxy_icon(ext: &str) -> Vec<u8> {
+    let label: String = ext.chars().take(6).collect::<String>().to_uppercase();
+    svg_data_uri(format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"384\" height=\"192\">\
+<rect width=\"100%\" height=\"100%\" fill=\"#475569\"/>\
+<text x=\"50%\" y=\"50%\" dominant-baseline=\"middle\" text-anchor=\"middle\" \
+font-family=\"sans-serif\" font-size=\"48\" fill=\"#f8fafc\">{}</text></svg>",
+        escape_xml(&label)
+    ))
+}
+
+// Gets the icon preview for a file which is not on the disk. Returns None for images since the placeholder
+// suits them better.
+pub fn icon_for_filename(filename: &str) -> Option<String> {
+    let ext = match filename.rsplit_once('.') {
+        Some((_, v)) => v.to_lowercase(),
+        None => "file".to_string(),
+    };
+    match IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+        true => None,
+        false => String::from_utf8(proxy_icon(&ext)).ok(),
+    }
+}
+
 pub fn proxy_fp(fp: &str) -> Result<Vec<u8>, String> {
-    // Check if the extension is allowed.
-    let ext = match fp.split('.').last() {
-        Some(v) => v,
-        None => return Err("File has no extension.".to_string()),
+    // Get the extension to work out how to preview the file.
+    let ext = match fp.rsplit_once('.') {
+        Some((_, v)) if !v.contains(['/', '\\']) => v.to_lowercase(),
+        _ => return Ok(proxy_icon("file")),
     };
-    if !["png", "jpg", "jpeg", "gif", "bmp", "tiff", "webp"].contains(&ext) {
-        return Err("File extension is not allowed.".to_string());
+    if TEXT_EXTENSIONS.contains(&ext.as_str()) {
+        return proxy_text(fp);
+    }
+    if !IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+        return Ok(proxy_icon(&ext));
     }
+    let ext = ext.as_str();
     let mime = "image/".to_string() + ext;
 
     // Check the file is under 5MB.
@@ -322,6 +322,28 @@ pub fn update_webview_with_capture(capture_id: i64) {
     }
 }
 
+// Handles sending upload progress to the webview if present. The progress is JSON.
+#[cfg(target_os = "macos")]
+pub fn send_upload_progress(progress_json: String) {
+    use objc::{msg_send, sel, sel_impl};
+
+    let progress_base64 = base64::engine::general_purpose::STANDARD.encode(&progress_json);
+
+    match app().delegate.webview.write().unwrap().as_ref() {
+        Some(webview) => {
+            let webview = &webview.delegate.as_ref().unwrap().content;
+            webview.objc.with_mut(|obj| unsafe {
+                let nsstr = NSString::new(&format!(
+                    "window.bridgeResponse(-3, '{}');", // see persistentHandlers in frontend/src/bridge/implementation.ts
+                    progress_base64
+                ));
+                let _: () = msg_send![obj, evaluateJavaScript: nsstr completionHandler: nil];
+            });
+        }
+        None => {}
+    }
+}
+
 // Handles updating the webview with a capture on Windows.
 #[cfg(target_os = "windows")]
 pub fn update_webview_with_capture(capture_id: i64) {
@@ -354,6 +376,31 @@ pub fn update_webview_with_capture(capture_id: i64) {
     });
 }
 
+// Handles sending upload progress to the webview on Windows. The progress is JSON.
+#[cfg(target_os = "windows")]
+pub fn send_upload_progress(progress_json: String) {
+    use crate::windows_shared::app;
+
+    let progress_base64 = base64::engine::general_purpose::STANDARD.encode(&progress_json);
+
+    crate::mainthread::main_thread_async(move || {
+        match app().wv_controller.as_mut() {
+            Some(wv) => {
+                let wv = wv.0.get_webview().unwrap();
+                wv.execute_script(
+                    &format!(
+                        "window.bridgeResponse(-3, '{}');", // see persistentHandlers in frontend/src/bridge/implementation.ts
+                        progress_base64
+                    ),
+                    |_| Ok(()),
+                )
+                .unwrap();
+            }
+            None => {}
+        }
+    });
+}
+
 // Process the webview controller.
 #[cfg(target_os = "windows")]
 fn process_webview_controller(
@@ -766,3 +813,27 @@ pub fn update_webview_with_capture(capture_id: i64) {
         }
     });
 }
+
+// Handles sending upload progress to the webview if present. The progress is JSON.
+#[cfg(target_os = "linux")]
+pub fn send_upload_progress(progress_json: String) {
+    use crate::{linux_shared::app, mainthread::main_thread_async};
+    use webkit2gtk::WebViewExt;
+
+    let progress_base64 = base64::engine::general_purpose::STANDARD.encode(&progress_json);
+
+    // Since we need the main thread on Linux, we push a async main thread task here.
+    main_thread_async(move || {
+        let read_ref = app().webview.read().unwrap();
+        if let Some(webview) = read_ref.as_ref() {
+            webview.value.run_javascript(
+                &format!(
+                    "window.bridgeResponse(-3, '{}');", // see persistentHandlers in frontend/src/bridge/implementation.ts
+                    progress_base64
+                ),
+                None::<&gio::Cancellable>,
+                |_| {},
+            );
+        }
+    });
+}
@@ -362,6 +362,9 @@ pub fn load_data(fp: String) -> Option<String> {
                 filename: header.filename,
                 url: header.url,
                 file_path: Some(local_capture_path.to_str().unwrap().to_string()),
+                duplicate: false,
+                remote_deletable: false,
+                thumbnail_url: None,
             });
         } else {
             // This capture was not saved to the disk.
@@ -372,6 +375,9 @@ pub fn load_data(fp: String) -> Option<String> {
                 filename: header.filename,
                 url: header.url,
                 file_path: None,
+                duplicate: false,
+                remote_deletable: false,
+                thumbnail_url: None,
             });
         }
     }
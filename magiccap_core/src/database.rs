@@ -221,8 +221,30 @@ pub struct Capture {
     pub filename: String,
     pub file_path: Option<String>,
     pub url: Option<String>,
+
+    // Defines if an earlier capture had the same content and the URL was reused.
+    pub duplicate: bool,
+
+    // Defines if any of the uploads can be deleted from the uploader.
+    pub remote_deletable: bool,
+
+    // Defines the thumbnail the primary uploader made for the capture if there is one.
+    pub thumbnail_url: Option<String>,
 }
 
+// Defines the columns selected for a capture. A capture is a duplicate if an earlier one has the same content
+// hash and URL, which only happens when the URL was reused.
+const CAPTURE_COLUMNS: &str = "id, created_at, success, filename, file_path, url, EXISTS (
+    SELECT 1 FROM captures earlier WHERE earlier.content_hash = captures.content_hash
+    AND earlier.id < captures.id AND earlier.url = captures.url
+) AS duplicate, EXISTS (
+    SELECT 1 FROM capture_uploads WHERE capture_uploads.capture_id = captures.id
+    AND capture_uploads.deletion_token IS NOT NULL
+) AS remote_deletable, (
+    SELECT thumbnail_url FROM capture_uploads WHERE capture_uploads.capture_id = captures.id
+    AND capture_uploads.is_primary = 1
+) AS thumbnail_url";
+
 // Read a row into a capture.
 fn read_capture(stmt: &sqlite::Statement) -> Capture {
     Capture {
@@ -232,6 +254,9 @@ fn read_capture(stmt: &sqlite::Statement) -> Capture {
         filename: stmt.read::<String, _>("filename").unwrap(),
         file_path: stmt.read::<Option<String>, _>("file_path").unwrap(),
         url: stmt.read::<Option<String>, _>("url").unwrap(),
+        duplicate: stmt.read::<i64, _>("duplicate").unwrap() == 1,
+        remote_deletable: stmt.read::<i64, _>("remote_deletable").unwrap() == 1,
+        thumbnail_url: stmt.read::<Option<String>, _>("thumbnail_url").unwrap(),
     }
 }
 
@@ -243,9 +268,7 @@ pub fn get_capture(id: i64) -> Option<Capture> {
 
     // Prepare the statement.
     let mut stmt = database
-        .prepare(
-            "SELECT id, created_at, success, filename, file_path, url FROM captures WHERE id = ?",
-        )
+        .prepare(format!("SELECT {} FROM captures WHERE id = ?", CAPTURE_COLUMNS))
         .unwrap();
 
     // Execute the statement.
@@ -276,8 +299,8 @@ pub fn get_many_captures(capture_ids: Vec<i64>) -> Vec<Capture> {
         .collect::<Vec<String>>()
         .join(", ");
     let query = format!(
-        "SELECT id, created_at, success, filename, file_path, url FROM captures WHERE id IN ({})",
-        ids
+        "SELECT {} FROM captures WHERE id IN ({})",
+        CAPTURE_COLUMNS, ids
     );
 
     // Execute the statement.
@@ -299,7 +322,7 @@ pub fn get_captures() -> Vec<Capture> {
 
     // Prepare the statement.
     let mut stmt = database
-        .prepare("SELECT id, created_at, success, filename, file_path, url FROM captures ORDER BY created_at DESC")
+        .prepare(format!("SELECT {} FROM captures ORDER BY created_at DESC", CAPTURE_COLUMNS))
         .unwrap();
 
     // Execute the statement.
@@ -312,8 +335,8 @@ pub fn get_captures() -> Vec<Capture> {
     captures
 }
 
-// Inserts a failed capture into the database.
-pub fn insert_failed_capture(filename: &str, file_path: Option<&str>) {
+// Inserts a failed capture into the database. Returns the ID if it was inserted.
+pub fn insert_failed_capture(filename: &str, file_path: Option<&str>) -> Option<i64> {
     // Acquire the database lock.
     let database_opt = DATABASE.read().unwrap();
     let database = database_opt.borrow().as_ref().unwrap();
@@ -333,7 +356,9 @@ pub fn insert_failed_capture(filename: &str, file_path: Option<&str>) {
     if let Ok(State::Row) = stmt.next() {
         let id: i64 = stmt.read(0).unwrap();
         config::update_webview_with_capture(id);
+        return Some(id);
     }
+    None
 }
 
 // Inserts a successful capture into the database.
@@ -366,6 +391,440 @@ pub fn insert_successful_capture(
     }
 }
 
+// Marks a capture as successfully uploaded with the URL specified.
+pub fn set_capture_uploaded(id: i64, url: &str) {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare("UPDATE captures SET success = 1, url = ? WHERE id = ?")
+        .unwrap();
+
+    // Create the binds.
+    stmt.bind((1, url)).unwrap();
+    stmt.bind((2, id)).unwrap();
+
+    // Execute the statement.
+    stmt.next().unwrap();
+}
+
+// Sets the hash of the content of a capture. This is used to find uploads of the same file.
+pub fn set_capture_content_hash(id: i64, content_hash: &str) {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare("UPDATE captures SET content_hash = ? WHERE id = ?")
+        .unwrap();
+
+    // Create the binds.
+    stmt.bind((1, content_hash)).unwrap();
+    stmt.bind((2, id)).unwrap();
+
+    // Execute the statement.
+    stmt.next().unwrap();
+}
+
+// Finds the URL of a previous successful upload of the same content to the same uploader with the same config.
+pub fn find_uploaded_url(content_hash: &str, uploader_id: &str, config_hash: &str) -> Option<String> {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare(
+            "SELECT capture_uploads.url FROM capture_uploads
+            INNER JOIN captures ON captures.id = capture_uploads.capture_id
+            WHERE captures.content_hash = ? AND capture_uploads.uploader_id = ?
+            AND capture_uploads.config_hash = ? AND capture_uploads.url IS NOT NULL
+            ORDER BY capture_uploads.created_at DESC LIMIT 1",
+        )
+        .unwrap();
+
+    // Create the binds.
+    stmt.bind((1, content_hash)).unwrap();
+    stmt.bind((2, uploader_id)).unwrap();
+    stmt.bind((3, config_hash)).unwrap();
+
+    // Execute the statement.
+    if let Ok(State::Row) = stmt.next() {
+        return stmt.read::<Option<String>, _>(0).unwrap();
+    }
+    None
+}
+
+#[derive(serde::Serialize)]
+pub struct CaptureUpload {
+    pub capture_id: i64,
+    pub uploader_id: String,
+    pub created_at: String,
+    pub url: Option<String>,
+    pub error: Option<String>,
+    pub primary: bool,
+
+    // Defines the token used to delete the file from the uploader. This is kept out of the frontend.
+    #[serde(skip)]
+    pub deletion_token: Option<String>,
+
+    // Defines the hash of the uploader config when the file was uploaded. The deletion token is only used
+    // with the same config.
+    #[serde(skip)]
+    pub config_hash: Option<String>,
+
+    pub thumbnail_url: Option<String>,
+}
+
+// Records the result of uploading a capture to a uploader. The config hash is the uploader config at the time
+// of the upload, which is used to decide if the URL can be reused. The deletion token is set if the uploader
+// can delete the file later.
+pub fn insert_capture_upload(
+    capture_id: i64,
+    uploader_id: &str,
+    url: Option<&str>,
+    error: Option<&str>,
+    primary: bool,
+    config_hash: Option<&str>,
+    deletion_token: Option<&str>,
+) {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare("INSERT OR REPLACE INTO capture_uploads (capture_id, uploader_id, url, error, is_primary, config_hash, deletion_token) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .unwrap();
+
+    // Create the binds.
+    stmt.bind((1, capture_id)).unwrap();
+    stmt.bind((2, uploader_id)).unwrap();
+    stmt.bind((3, url)).unwrap();
+    stmt.bind((4, error)).unwrap();
+    stmt.bind((5, primary as i64)).unwrap();
+    stmt.bind((6, config_hash)).unwrap();
+    stmt.bind((7, deletion_token)).unwrap();
+
+    // Execute the statement.
+    stmt.next().unwrap();
+}
+
+// Sets the URL and deletion token for a capture upload which previously failed. The config hash is replaced
+// with the hash of the config the retry used.
+pub fn set_capture_upload_url(
+    capture_id: i64, uploader_id: &str, url: &str, config_hash: &str, deletion_token: Option<&str>,
+) {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare("UPDATE capture_uploads SET url = ?, error = NULL, config_hash = ?, deletion_token = ? WHERE capture_id = ? AND uploader_id = ?")
+        .unwrap();
+
+    // Create the binds.
+    stmt.bind((1, url)).unwrap();
+    stmt.bind((2, config_hash)).unwrap();
+    stmt.bind((3, deletion_token)).unwrap();
+    stmt.bind((4, capture_id)).unwrap();
+    stmt.bind((5, uploader_id)).unwrap();
+
+    // Execute the statement.
+    stmt.next().unwrap();
+}
+
+// Removes the deletion token from a capture upload once the file was deleted from the uploader.
+pub fn clear_capture_upload_deletion_token(capture_id: i64, uploader_id: &str) {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare("UPDATE capture_uploads SET deletion_token = NULL WHERE capture_id = ? AND uploader_id = ?")
+        .unwrap();
+
+    // Create the binds.
+    stmt.bind((1, capture_id)).unwrap();
+    stmt.bind((2, uploader_id)).unwrap();
+
+    // Execute the statement.
+    stmt.next().unwrap();
+}
+
+// Checks if another capture uses the URL. This happens when the URL was reused for a duplicate, in which case
+// deleting the file would break the other capture.
+pub fn is_url_shared(capture_id: i64, url: &str) -> bool {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare("SELECT 1 FROM capture_uploads WHERE url = ? AND capture_id != ? LIMIT 1")
+        .unwrap();
+
+    // Execute the statement.
+    stmt.bind((1, url)).unwrap();
+    stmt.bind((2, capture_id)).unwrap();
+    matches!(stmt.next(), Ok(State::Row))
+}
+
+// Sets the URL to the thumbnail the uploader made for a capture upload.
+pub fn set_capture_upload_thumbnail(capture_id: i64, uploader_id: &str, thumbnail_url: &str) {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare("UPDATE capture_uploads SET thumbnail_url = ? WHERE capture_id = ? AND uploader_id = ?")
+        .unwrap();
+
+    // Create the binds.
+    stmt.bind((1, thumbnail_url)).unwrap();
+    stmt.bind((2, capture_id)).unwrap();
+    stmt.bind((3, uploader_id)).unwrap();
+
+    // Execute the statement.
+    stmt.next().unwrap();
+}
+
+// Gets all of the uploads for a capture.
+pub fn get_capture_uploads(capture_id: i64) -> Vec<CaptureUpload> {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare("SELECT capture_id, uploader_id, created_at, url, error, is_primary, deletion_token, config_hash, thumbnail_url FROM capture_uploads WHERE capture_id = ? ORDER BY is_primary DESC, created_at")
+        .unwrap();
+
+    // Execute the statement.
+    stmt.bind((1, capture_id)).unwrap();
+    let mut uploads = Vec::new();
+    while let Ok(State::Row) = stmt.next() {
+        uploads.push(CaptureUpload {
+            capture_id: stmt.read::<i64, _>("capture_id").unwrap(),
+            uploader_id: stmt.read::<String, _>("uploader_id").unwrap(),
+            created_at: stmt.read::<String, _>("created_at").unwrap(),
+            url: stmt.read::<Option<String>, _>("url").unwrap(),
+            error: stmt.read::<Option<String>, _>("error").unwrap(),
+            primary: stmt.read::<i64, _>("is_primary").unwrap() == 1,
+            deletion_token: stmt.read::<Option<String>, _>("deletion_token").unwrap(),
+            config_hash: stmt.read::<Option<String>, _>("config_hash").unwrap(),
+            thumbnail_url: stmt.read::<Option<String>, _>("thumbnail_url").unwrap(),
+        });
+    }
+
+    // Return the uploads.
+    uploads
+}
+
+#[derive(serde::Serialize)]
+pub struct QueuedUpload {
+    pub id: i64,
+    pub capture_id: i64,
+    pub created_at: String,
+    pub uploader_id: String,
+    pub filename: String,
+    pub file_path: String,
+    pub owned_file: bool,
+    pub attempts: i64,
+    pub last_error: String,
+    pub next_attempt_at: Option<i64>,
+}
+
+// Read a row into a queued upload.
+fn read_queued_upload(stmt: &sqlite::Statement) -> QueuedUpload {
+    QueuedUpload {
+        id: stmt.read::<i64, _>("id").unwrap(),
+        capture_id: stmt.read::<i64, _>("capture_id").unwrap(),
+        created_at: stmt.read::<String, _>("created_at").unwrap(),
+        uploader_id: stmt.read::<String, _>("uploader_id").unwrap(),
+        filename: stmt.read::<String, _>("filename").unwrap(),
+        file_path: stmt.read::<String, _>("file_path").unwrap(),
+        owned_file: stmt.read::<i64, _>("owned_file").unwrap() == 1,
+        attempts: stmt.read::<i64, _>("attempts").unwrap(),
+        last_error: stmt.read::<String, _>("last_error").unwrap(),
+        next_attempt_at: stmt.read::<Option<i64>, _>("next_attempt_at").unwrap(),
+    }
+}
+
+// Defines the columns selected for queued uploads.
+const QUEUED_UPLOAD_COLUMNS: &str = "id, capture_id, created_at, uploader_id, filename, file_path, owned_file, attempts, last_error, next_attempt_at";
+
+// Inserts an upload into the upload queue. Returns the ID of the queued upload.
+pub fn insert_queued_upload(
+    capture_id: i64,
+    uploader_id: &str,
+    filename: &str,
+    file_path: &str,
+    owned_file: bool,
+    last_error: &str,
+    next_attempt_at: i64,
+) -> Result<i64, String> {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = match database.prepare(
+        "INSERT INTO upload_queue (capture_id, uploader_id, filename, file_path, owned_file, attempts, last_error, next_attempt_at) VALUES (?, ?, ?, ?, ?, 1, ?, ?) RETURNING id",
+    ) {
+        Ok(stmt) => stmt,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    // Create the binds.
+    let binds = stmt
+        .bind((1, capture_id))
+        .and_then(|_| stmt.bind((2, uploader_id)))
+        .and_then(|_| stmt.bind((3, filename)))
+        .and_then(|_| stmt.bind((4, file_path)))
+        .and_then(|_| stmt.bind((5, owned_file as i64)))
+        .and_then(|_| stmt.bind((6, last_error)))
+        .and_then(|_| stmt.bind((7, next_attempt_at)));
+    if let Err(e) = binds {
+        return Err(e.to_string());
+    }
+
+    // Execute the statement.
+    match stmt.next() {
+        Ok(State::Row) => stmt.read(0).map_err(|e| e.to_string()),
+        Ok(State::Done) => Err("The database did not return the ID of the queued upload.".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Gets the number of queued uploads using the file path specified.
+pub fn count_queued_uploads_for_file(file_path: &str) -> i64 {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare("SELECT COUNT(*) FROM upload_queue WHERE file_path = ?")
+        .unwrap();
+
+    // Execute the statement.
+    stmt.bind((1, file_path)).unwrap();
+    match stmt.next() {
+        Ok(State::Row) => stmt.read(0).unwrap(),
+        _ => 0,
+    }
+}
+
+// Gets a single queued upload from the database.
+pub fn get_queued_upload(id: i64) -> Option<QueuedUpload> {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare(format!("SELECT {} FROM upload_queue WHERE id = ?", QUEUED_UPLOAD_COLUMNS))
+        .unwrap();
+
+    // Execute the statement.
+    stmt.bind((1, id)).unwrap();
+    if let Ok(State::Row) = stmt.next() {
+        return Some(read_queued_upload(&stmt));
+    }
+
+    // Return none.
+    None
+}
+
+// Gets all the queued uploads from the database.
+pub fn get_queued_uploads() -> Vec<QueuedUpload> {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare(format!("SELECT {} FROM upload_queue ORDER BY created_at DESC", QUEUED_UPLOAD_COLUMNS))
+        .unwrap();
+
+    // Execute the statement.
+    let mut uploads = Vec::new();
+    while let Ok(State::Row) = stmt.next() {
+        uploads.push(read_queued_upload(&stmt));
+    }
+
+    // Return the queued uploads.
+    uploads
+}
+
+// Gets the queued uploads which are due to be retried at the timestamp specified.
+pub fn get_due_queued_uploads(now: i64) -> Vec<QueuedUpload> {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare(format!(
+            "SELECT {} FROM upload_queue WHERE next_attempt_at IS NOT NULL AND next_attempt_at <= ? ORDER BY next_attempt_at",
+            QUEUED_UPLOAD_COLUMNS,
+        ))
+        .unwrap();
+
+    // Execute the statement.
+    stmt.bind((1, now)).unwrap();
+    let mut uploads = Vec::new();
+    while let Ok(State::Row) = stmt.next() {
+        uploads.push(read_queued_upload(&stmt));
+    }
+
+    // Return the queued uploads.
+    uploads
+}
+
+// Records a failed attempt for a queued upload. A next attempt of None means it will not be retried automatically.
+pub fn set_queued_upload_failed(id: i64, last_error: &str, next_attempt_at: Option<i64>) {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare("UPDATE upload_queue SET attempts = attempts + 1, last_error = ?, next_attempt_at = ? WHERE id = ?")
+        .unwrap();
+
+    // Create the binds.
+    stmt.bind((1, last_error)).unwrap();
+    stmt.bind((2, next_attempt_at)).unwrap();
+    stmt.bind((3, id)).unwrap();
+
+    // Execute the statement.
+    stmt.next().unwrap();
+}
+
+// Deletes a queued upload from the database.
+pub fn delete_queued_upload(id: i64) {
+    // Acquire the database lock.
+    let database_opt = DATABASE.read().unwrap();
+    let database = database_opt.borrow().as_ref().unwrap();
+
+    // Prepare the statement.
+    let mut stmt = database
+        .prepare("DELETE FROM upload_queue WHERE id = ?")
+        .unwrap();
+
+    // Execute the statement.
+    stmt.bind((1, id)).unwrap();
+    stmt.next().unwrap();
+}
+
 // Deletes a capture from the database. Returns true if a capture was deleted or false if it never existed.
 pub fn delete_capture(id: i64) -> bool {
     // Drop from the search index.
@@ -375,6 +834,13 @@ pub fn delete_capture(id: i64) -> bool {
     let database_opt = DATABASE.read().unwrap();
     let database = database_opt.borrow().as_ref().unwrap();
 
+    // Delete the uploads for the capture.
+    let mut stmt = database
+        .prepare("DELETE FROM capture_uploads WHERE capture_id = ?")
+        .unwrap();
+    stmt.bind((1, id)).unwrap();
+    stmt.next().unwrap();
+
     // Prepare the statement.
     let mut stmt = database
         .prepare("DELETE FROM captures WHERE id = ?")
@@ -385,6 +851,20 @@ pub fn delete_capture(id: i64) -> bool {
     stmt.next().unwrap() == State::Done
 }
 
+// Adds a column to a table if it is not already there. SQLite does not support IF NOT EXISTS for columns.
+fn add_column_if_missing(database: &ConnectionThreadSafe, table: &str, column: &str, definition: &str) {
+    let mut stmt = database
+        .prepare(format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?", table))
+        .unwrap();
+    stmt.bind((1, column)).unwrap();
+    if let Ok(State::Row) = stmt.next() {
+        return;
+    }
+    database
+        .execute(format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+        .unwrap();
+}
+
 // Does the database migrations.
 fn do_migrations() {
     // Acquire the database lock.
@@ -415,8 +895,46 @@ fn do_migrations() {
             url TEXT
         );
         CREATE INDEX IF NOT EXISTS captures_created_at_reversed ON captures (created_at DESC);
+
+        CREATE TABLE IF NOT EXISTS upload_queue (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            capture_id INTEGER NOT NULL,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            uploader_id TEXT NOT NULL,
+            filename TEXT NOT NULL,
+            file_path TEXT NOT NULL,
+            owned_file INTEGER NOT NULL,
+            attempts INTEGER NOT NULL,
+            last_error TEXT NOT NULL,
+            next_attempt_at INTEGER
+        );
+        CREATE INDEX IF NOT EXISTS upload_queue_next_attempt_at ON upload_queue (next_attempt_at);
+        CREATE INDEX IF NOT EXISTS upload_queue_capture_id ON upload_queue (capture_id);
+
+        CREATE TABLE IF NOT EXISTS capture_uploads (
+            capture_id INTEGER NOT NULL,
+            uploader_id TEXT NOT NULL,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            url TEXT,
+            error TEXT,
+            is_primary INTEGER NOT NULL,
+            PRIMARY KEY (capture_id, uploader_id)
+        );
     ";
     database.execute(stmts).unwrap();
+
+    // Add the columns used to find duplicate uploads.
+    add_column_if_missing(database, "captures", "content_hash", "TEXT");
+    add_column_if_missing(database, "capture_uploads", "config_hash", "TEXT");
+    database
+        .execute("CREATE INDEX IF NOT EXISTS captures_content_hash ON captures (content_hash);")
+        .unwrap();
+
+    // Add the column used to delete uploads from the uploader.
+    add_column_if_missing(database, "capture_uploads", "deletion_token", "TEXT");
+
+    // Add the column for thumbnails made by the uploader.
+    add_column_if_missing(database, "capture_uploads", "thumbnail_url", "TEXT");
 }
 
 // Connects to the database.
@@ -435,6 +953,11 @@ pub fn connect() {
     do_migrations();
 }
 
+// Checks if the database is connected.
+pub fn is_connected() -> bool {
+    DATABASE.read().unwrap().is_some()
+}
+
 // Disconnects from the database.
 pub fn disconnect() {
     // Lock the database.
@@ -455,8 +978,11 @@ pub fn wipe_all() {
         DELETE FROM uploader_config_items;
         DELETE FROM config;
         DELETE FROM captures;
+        DELETE FROM upload_queue;
+        DELETE FROM capture_uploads;
     ";
     database.execute(stmts).unwrap();
+    crate::upload_queue::remove_all_files();
 
     // Handle the bulk changes.
     on_bulk_changes();
@@ -477,8 +1003,11 @@ pub fn rewrite(
         DELETE FROM uploader_config_items;
         DELETE FROM config;
         DELETE FROM captures;
+        DELETE FROM upload_queue;
+        DELETE FROM capture_uploads;
     ";
     database.execute(stmts).unwrap();
+    crate::upload_queue::remove_all_files();
 
     // Run the insert statements.
     for (name, value) in config_items {
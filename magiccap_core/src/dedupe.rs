@@ -0,0 +1,50 @@
+use crate::{database, uploaders::UploadSource};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+// Checks if the user opted into reusing URLs for the uploader specified.
+pub fn is_enabled(uploader_id: &str) -> bool {
+    match database::get_config_option("dedupe_uploaders") {
+        Some(serde_json::Value::Array(uploaders)) => {
+            uploaders.iter().any(|v| v.as_str() == Some(uploader_id))
+        }
+        _ => false,
+    }
+}
+
+// Turns a finished hash into a hex string.
+fn to_hex(hasher: Sha256) -> String {
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Hashes the content of the source. Returns None if the file could not be read.
+pub fn hash_source(source: &UploadSource) -> Option<String> {
+    let mut file = source.open().ok()?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).ok()?;
+    Some(to_hex(hasher))
+}
+
+// Hashes the config for the uploader. For custom uploaders, the definition is included so that editing the
+// uploader is treated as a config change. The keys are sorted so the hash is stable.
+pub fn config_hash(uploader_id: &str) -> String {
+    let items: BTreeMap<String, serde_json::Value> =
+        database::get_uploader_config_items(uploader_id).into_iter().collect();
+    let definition = database::get_config_option(&("custom_uploader_".to_string() + uploader_id));
+    let hashed = serde_json::json!({
+        "config": items,
+        "definition": definition,
+    });
+    let mut hasher = Sha256::new();
+    hasher.update(hashed.to_string().as_bytes());
+    to_hex(hasher)
+}
+
+// Finds a URL which can be reused instead of uploading the content again. Returns None if the uploader is not
+// opted in or the content was not uploaded with the current config.
+pub fn find_reusable_url(uploader_id: &str, content_hash: &str) -> Option<String> {
+    if !is_enabled(uploader_id) {
+        return None;
+    }
+    database::find_uploaded_url(content_hash, uploader_id, &config_hash(uploader_id))
+}
@@ -33,6 +33,7 @@ fn magiccap_id_hit(id: &str) {
         "gif_hotkey" => capture::gif_capture(),
         "video_hotkey" => capture::video_capture(),
         "clipboard_hotkey" => capture::clipboard_capture(),
+        "text_hotkey" => capture::text_capture(),
         _ => {}
     })
 }
@@ -43,6 +44,7 @@ static DATABASE_KEYS: &[&str] = &[
     "gif_hotkey",
     "video_hotkey",
     "clipboard_hotkey",
+    "text_hotkey",
 ];
 
 impl HotkeyWrapper {
@@ -7,6 +7,7 @@ mod config;
 mod data_dump;
 mod database;
 mod database_hooks;
+mod dedupe;
 mod hotkeys;
 mod mainthread;
 mod notification;
@@ -18,6 +19,8 @@ mod statics;
 mod temp_icon;
 mod tray;
 mod unload;
+mod upload_progress;
+mod upload_queue;
 mod uploaders;
 mod utils;
 mod video_capture;
@@ -35,6 +38,13 @@ mod framebufferd;
 #[cfg(target_os = "windows")]
 mod windows_shared;
 
+// Exposes the upload streaming to the integration tests in the tests folder. This is not part of the API.
+#[doc(hidden)]
+pub mod test_support {
+    pub use crate::uploaders::multipart::multipart_stream;
+    pub use crate::uploaders::UploadSource;
+}
+
 #[cfg(target_os = "macos")]
 #[no_mangle]
 pub unsafe extern "C" fn application_init() {
@@ -75,6 +75,10 @@ pub fn application_init() {
             crate::temp_icon::icond();
             return;
         }
+        if val == "upload" {
+            crate::temp_icon::upload_icond();
+            return;
+        }
     }
 
     // Create the shared application box.
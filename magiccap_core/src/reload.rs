@@ -16,6 +16,9 @@ pub fn application_reload() {
     // Load the tray.
     load_tray();
 
+    // Start retrying any queued uploads.
+    crate::upload_queue::start_worker();
+
     // Setup glfw instance for region selector.
     crate::region_selector::setup_glfw_instance_for_region_selector();
 
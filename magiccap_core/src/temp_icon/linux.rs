@@ -5,7 +5,7 @@ use std::{
     io::{BufRead, Read, Write},
     process,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU8, Ordering},
         Mutex,
     },
     thread,
@@ -96,19 +96,75 @@ pub fn icond() {
     gtk::main();
 }
 
+// Updates the tooltip with the upload progress.
+fn upload_progress_update(percent: u8) {
+    // Get the tray sender.
+    let sent = unsafe { TRAY_SENDER.as_mut().unwrap() };
+
+    // Update the tooltip.
+    let _ = sent
+        .value
+        .set_tooltip(Some(format!("MagicCap Uploading ({}%)", percent)));
+}
+
+// On Linux, define a temporary icon demon that shows upload progress and lets the user cancel the upload.
+pub fn upload_icond() {
+    // Defines the tray.
+    let menu = Box::new(Menu::new());
+    menu.append(&MenuItem::new("Cancel Upload", true, None))
+        .unwrap();
+    let tray = TrayIconBuilder::new()
+        .with_tooltip("MagicCap Uploading")
+        .with_icon(COG_ICON.clone())
+        .with_menu(menu)
+        .build()
+        .unwrap();
+
+    // We leak the box in a fake sender so that it is 'static since this process is short lived anyway.
+    unsafe {
+        TRAY_SENDER = Some(Box::leak(Box::new(FakeSend { value: tray })));
+    }
+
+    // Defines a thread to handle stdio. Progress is sent as 'p' followed by a byte with the percentage.
+    thread::spawn(move || {
+        loop {
+            let mut buffer = [0; 2];
+            if std::io::stdin().read_exact(&mut buffer).is_err() {
+                return;
+            }
+            if buffer[0] == b'p' {
+                let percent = buffer[1];
+                glib::idle_add_once(move || upload_progress_update(percent));
+            }
+        }
+    });
+
+    // Handle the menu event loop. This will be a click event, so tell the parent to cancel.
+    MenuEvent::set_event_handler(Some(move |_| {
+        println!("MAGICCAP_CANCEL");
+    }));
+
+    // Call gtk::main.
+    gtk::main();
+}
+
 // Defines the icon handler. When the icon is dropped, it will be removed from the tray.
 pub struct IconHandler {
     process: Mutex<process::Child>,
+    last_percent: AtomicU8,
 }
 
-// Build a child process of ourselves but with the MAGICCAP_INTERNAL_TEMP_ICON env var set.
-fn create_icond(cb: Box<dyn FnOnce() + Send>) -> process::Child {
+// Build a child process of ourselves but with the MAGICCAP_INTERNAL_TEMP_ICON env var set to the mode. The
+// callback is called when the child writes the trigger to the stdout.
+fn create_icond(
+    mode: &str, trigger: &'static str, cb: Box<dyn FnOnce() + Send>,
+) -> process::Child {
     // Get the current executable path.
     let path = std::env::current_exe().unwrap();
 
     // Create the process.
     let mut proc = process::Command::new(path)
-        .env("MAGICCAP_INTERNAL_TEMP_ICON", "1")
+        .env("MAGICCAP_INTERNAL_TEMP_ICON", mode)
         .stdout(process::Stdio::piped())
         .stdin(process::Stdio::piped())
         .spawn()
@@ -125,8 +181,13 @@ fn create_icond(cb: Box<dyn FnOnce() + Send>) -> process::Child {
                 Err(_) => return,
             };
 
-            // If the buffer contains "MAGICCAP_PROCESSING", call the callback and return.
-            if buffer.contains("MAGICCAP_PROCESSING") {
+            // If the buffer is empty, the process has exited.
+            if buffer.is_empty() {
+                return;
+            }
+
+            // If the buffer contains the trigger, call the callback and return.
+            if buffer.contains(trigger) {
                 cb();
                 return;
             }
@@ -143,10 +204,34 @@ impl IconHandler {
     // will NOT be ran in the main thread in all cases.
     pub fn new(stop_callback: Box<dyn FnOnce() + Send>) -> Self {
         Self {
-            process: Mutex::new(create_icond(stop_callback)),
+            process: Mutex::new(create_icond("1", "MAGICCAP_PROCESSING", stop_callback)),
+            last_percent: AtomicU8::new(u8::MAX),
         }
     }
 
+    // Create a version of the icon handler that shows upload progress. Note that the cancel callback
+    // will NOT be ran in the main thread in all cases.
+    pub fn uploading(cancel_callback: Box<dyn FnOnce() + Send>) -> Self {
+        Self {
+            process: Mutex::new(create_icond("upload", "MAGICCAP_CANCEL", cancel_callback)),
+            last_percent: AtomicU8::new(u8::MAX),
+        }
+    }
+
+    // Updates the upload progress shown by the icon. Only changes in the percentage are sent to the child so
+    // it is not flooded with updates for every read.
+    pub fn progress(&self, sent: u64, total: u64) {
+        let percent = match total {
+            0 => 100,
+            _ => (sent.saturating_mul(100) / total).min(100) as u8,
+        };
+        if self.last_percent.swap(percent, Ordering::Relaxed) == percent {
+            return;
+        }
+        let mut proc = self.process.lock().unwrap();
+        let _ = proc.stdin.as_mut().unwrap().write_all(&[b'p', percent]);
+    }
+
     // Changes the icon to the cog icon.
     pub fn processing(&self) {
         let mut proc = self.process.lock().unwrap();
@@ -154,7 +239,7 @@ impl IconHandler {
     }
 
     // Remove the icon from the tray.
-    pub fn remove(&mut self) {
+    pub fn remove(&self) {
         let mut proc = self.process.lock().unwrap();
         let _ = proc.kill();
     }
@@ -4,9 +4,9 @@ mod linux;
 mod other;
 mod shared;
 
-// On Linux, re-export the icond function.
+// On Linux, re-export the icond functions.
 #[cfg(target_os = "linux")]
-pub use linux::icond;
+pub use linux::{icond, upload_icond};
 
 // Re-export the icon handler.
 #[cfg(target_os = "linux")]
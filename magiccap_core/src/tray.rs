@@ -1,19 +1,58 @@
 use crate::clipboard_actions;
 use crate::database;
+use crate::dedupe;
 use crate::mainthread::main_thread_async;
 use crate::notification;
 use crate::statics::run_thread;
+use crate::upload_progress;
+use crate::upload_queue;
 use crate::uploaders;
 
 // TODO: update tray on Linux
 
 // Defines the safe call which is wrapped.
 fn safely_upload(name: &str, path: &str) {
-    // Create a reader for the path.
-    let reader: Box<dyn std::io::Read + Send + Sync> = Box::new(std::fs::File::open(path).unwrap());
+    // Create the upload source for the path.
+    let source = match uploaders::UploadSource::from_path(path) {
+        Ok(source) => source,
+        Err(e) => return notification::send_dialog_message(&e.to_string()),
+    };
 
+    // Send the file to a fallback uploader if the one chosen cannot take it. If the type cannot be read, the
+    // chosen uploader is kept so the upload fails with the reason.
     let filename = path.split(path::MAIN_SEPARATOR).last().unwrap();
-    match uploaders::call_uploader(&name, reader, filename) {
+    let name = match uploaders::guess_mime_type(filename, &source) {
+        Ok(mime) => uploaders::get_uploader_for_file(name, mime.essence_str(), source.size()),
+        Err(_) => name.to_string(),
+    };
+    let name = name.as_str();
+
+    // Reuse the URL if this file was already uploaded and the uploader is opted in.
+    let content_hash = dedupe::hash_source(&source);
+    let mut deletion_token = None;
+    let mut thumbnail_url = None;
+    let result = match content_hash.as_deref().and_then(|h| dedupe::find_reusable_url(name, h)) {
+        Some(url) => Ok(url),
+        None => {
+            let (_tracked, ctx) = upload_progress::track(
+                name,
+                filename,
+                source.size(),
+                uploaders::CancellationToken::new(),
+                None,
+            );
+            let ctx = ctx.with_capture(uploaders::CaptureMetadata {
+                capture_id: None,
+                capture_type: Some("file".to_string()),
+                file_path: Some(path.to_string()),
+            });
+            let result = uploaders::call_uploader(name, &source, filename, &ctx);
+            deletion_token = ctx.take_deletion_token();
+            thumbnail_url = ctx.take_thumbnail_url();
+            result
+        }
+    };
+    match result {
         Ok(url) => {
             // Write a successful "capture".
             notification::send_notification(
@@ -21,15 +60,33 @@ fn safely_upload(name: &str, path: &str) {
                 Some(&url),
                 None,
             );
-            database::insert_successful_capture(filename, Some(path), Some(&url));
+            let capture_id = database::insert_successful_capture(filename, Some(path), Some(&url));
+            database::insert_capture_upload(
+                capture_id, name, Some(&url), None, true, Some(&dedupe::config_hash(name)),
+                deletion_token.as_deref(),
+            );
+            if let Some(thumbnail_url) = &thumbnail_url {
+                database::set_capture_upload_thumbnail(capture_id, name, thumbnail_url);
+            }
+            if let Some(content_hash) = &content_hash {
+                database::set_capture_content_hash(capture_id, content_hash);
+            }
 
             // Handle the clipboard flow.
             clipboard_actions::handle_clipboard_action(Some(path), Some(&url), None);
         }
         Err(e) => {
-            // Write a failed "capture".
-            database::insert_failed_capture(filename, Some(path));
-            notification::send_dialog_message(&e);
+            // Write a failed "capture" and queue it to be retried.
+            if let Some(capture_id) = database::insert_failed_capture(filename, Some(path)) {
+                database::insert_capture_upload(
+                    capture_id, name, None, Some(&e.to_string()), true, None, None,
+                );
+                if let Some(content_hash) = &content_hash {
+                    database::set_capture_content_hash(capture_id, content_hash);
+                }
+                upload_queue::enqueue_or_notify(capture_id, name, filename, Some(path), &source, &e);
+            }
+            notification::send_dialog_message(&e.to_string());
         }
     };
 }
@@ -100,6 +157,7 @@ extern "C" fn capture_type_clicked(type_: c_int) {
             2 => crate::capture::gif_capture(),
             3 => crate::capture::video_capture(),
             4 => crate::capture::clipboard_capture(),
+            5 => crate::capture::text_capture(),
             _ => panic!("Unknown capture type."),
         }
     });
@@ -127,24 +185,15 @@ fn tray_main_thread() {
         };
     }
 
-    // Get the default uploader.
-    let default_uploader = match database::get_config_option("uploader_type") {
-        // If the default option is set, use it.
-        Some(option_json) => {
-            let cpy = option_json.clone();
-            cpy.to_string()
-        }
-
-        // imgur is the default uploader within the application if none is set.
-        None => "imgur".to_owned(),
-    };
+    // Get the default uploaders.
+    let default_uploaders = uploaders::get_default_uploaders();
 
     // Create all the uploader items.
     let mut uploader_items: Vec<macos::UploaderItem> =
         Vec::with_capacity(uploaders::UPLOADERS.len());
     for (id, uploader) in uploaders::UPLOADERS.iter() {
-        // Check if this is the default uploader.
-        let is_default = id == &default_uploader;
+        // Check if this is a default uploader.
+        let is_default = default_uploaders.contains(id);
 
         // Get all of the configuration options for this uploader from the database.
         let db_options = database::get_uploader_config_items(id.as_str());
@@ -203,6 +252,10 @@ fn tray_main_thread() {
             .as_ptr(),
             type_: 4,
         },
+        macos::CaptureType {
+            name: Box::leak(Box::new(std::ffi::CString::new("Upload Text").unwrap())).as_ptr(),
+            type_: 5,
+        },
     ];
 
     // Create the tray.
@@ -322,11 +375,10 @@ fn create_menu() -> muda::Menu {
 
     // Add the uploaders to a submenu.
     let uploaders_menu = Submenu::new("Upload to...", true);
+    let default_uploaders = uploaders::get_default_uploaders();
     for (id, uploader) in uploaders::UPLOADERS.iter() {
-        // Check if this is the default uploader.
-        let is_default = id
-            == &database::get_config_option("uploader_type")
-                .unwrap_or_else(|| serde_json::Value::String("imgur".to_owned()));
+        // Check if this is a default uploader.
+        let is_default = default_uploaders.contains(id);
 
         // Get all of the configuration options for this uploader from the database.
         let db_options = database::get_uploader_config_items(id.as_str());
@@ -388,6 +440,11 @@ fn create_menu() -> muda::Menu {
             true,
             Box::new(|| run_thread(crate::capture::clipboard_capture))
         ),
+        menu_item!(
+            "Upload Text",
+            true,
+            Box::new(|| run_thread(crate::capture::text_capture))
+        ),
         separator!(),
         &uploaders_menu,
         separator!(),
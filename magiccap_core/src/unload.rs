@@ -1,4 +1,4 @@
-use crate::{database, hotkeys, search_indexing, statics};
+use crate::{database, hotkeys, search_indexing, statics, upload_queue};
 
 pub fn application_unload() {
     // Drop all hotkeys.
@@ -7,8 +7,8 @@ pub fn application_unload() {
     // Activate the kill switch.
     statics::KILL_SWITCH.store(true, std::sync::atomic::Ordering::Relaxed);
 
-    // Disconnect the database.
-    database::disconnect();
+    // Disconnect the database once no queued upload is writing its result.
+    upload_queue::without_writes(database::disconnect);
 
     // Destroy the thread pool.
     statics::kill_thread_pool();
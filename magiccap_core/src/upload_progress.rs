@@ -0,0 +1,114 @@
+use crate::uploaders::{CancellationToken, ProgressCallback, UploadContext};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+// Defines the ID of the next tracked upload.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// Defines the cancellation tokens for uploads that are running.
+static ACTIVE_UPLOADS: Lazy<Mutex<HashMap<u64, CancellationToken>>> = Lazy::new(Default::default);
+
+// Defines the progress event sent to the config frontend.
+#[derive(Serialize)]
+struct ProgressEvent<'a> {
+    id: u64,
+    uploader_id: &'a str,
+    filename: &'a str,
+    sent: u64,
+    total: u64,
+    done: bool,
+}
+
+// Sends a progress event to the config frontend.
+fn send_event(event: &ProgressEvent) {
+    crate::config::send_upload_progress(serde_json::to_string(event).unwrap());
+}
+
+// Defines an upload which is being tracked. When this is dropped, the upload is removed and the frontend is
+// told it is done.
+pub struct TrackedUpload {
+    id: u64,
+    uploader_id: String,
+    filename: String,
+    total: u64,
+}
+
+impl Drop for TrackedUpload {
+    fn drop(&mut self) {
+        ACTIVE_UPLOADS.lock().unwrap().remove(&self.id);
+        send_event(&ProgressEvent {
+            id: self.id,
+            uploader_id: &self.uploader_id,
+            filename: &self.filename,
+            sent: self.total,
+            total: self.total,
+            done: true,
+        });
+    }
+}
+
+// Starts tracking an upload. Progress is sent to the config frontend and the extra callback if there is one,
+// and the upload can be cancelled from the frontend or with the token. Keep the TrackedUpload alive until the
+// upload is finished.
+pub fn track(
+    uploader_id: &str,
+    filename: &str,
+    total: u64,
+    token: CancellationToken,
+    extra: Option<ProgressCallback>,
+) -> (TrackedUpload, UploadContext) {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    ACTIVE_UPLOADS.lock().unwrap().insert(id, token.clone());
+
+    // Only send an event when the percentage changes so the webview is not flooded.
+    let last_percent = AtomicU64::new(u64::MAX);
+    let uploader_id_cpy = uploader_id.to_string();
+    let filename_cpy = filename.to_string();
+    let progress: ProgressCallback = Box::new(move |sent, total| {
+        if let Some(extra) = &extra {
+            extra(sent, total);
+        }
+        let percent = match total {
+            0 => 100,
+            _ => sent.saturating_mul(100) / total,
+        };
+        if last_percent.swap(percent, Ordering::Relaxed) != percent {
+            send_event(&ProgressEvent {
+                id,
+                uploader_id: &uploader_id_cpy,
+                filename: &filename_cpy,
+                sent,
+                total,
+                done: false,
+            });
+        }
+    });
+
+    (
+        TrackedUpload {
+            id,
+            uploader_id: uploader_id.to_string(),
+            filename: filename.to_string(),
+            total,
+        },
+        UploadContext::new(token, Some(progress)),
+    )
+}
+
+// Cancels a running upload. Returns false if it is not running.
+pub fn cancel(id: u64) -> bool {
+    match ACTIVE_UPLOADS.lock().unwrap().get(&id) {
+        Some(token) => {
+            token.cancel();
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,372 @@
+use crate::{
+    config,
+    database::{self, QueuedUpload},
+    dedupe,
+    notification,
+    statics::{run_thread, CONFIG_FOLDER, KILL_SWITCH},
+    upload_progress,
+    uploaders::{self, CancellationToken, CaptureMetadata, UploadError, UploadSource},
+};
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+// Defines the base delay in seconds before the first retry. This doubles on every attempt.
+const BASE_DELAY_SECS: i64 = 30;
+
+// Defines the longest we will wait between retries.
+const MAX_DELAY_SECS: i64 = 6 * 60 * 60;
+
+// Defines the number of attempts before we stop retrying automatically.
+const MAX_ATTEMPTS: i64 = 10;
+
+// Defines how often the worker checks for uploads that are due.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// Defines if the worker is running.
+static WORKER_RUNNING: AtomicBool = AtomicBool::new(false);
+
+// Defines the cancellation tokens of the queued uploads being attempted. The worker and a manual retry skip
+// anything in here so the same item is never uploaded twice at the same time.
+static IN_FLIGHT: Lazy<Mutex<HashMap<i64, CancellationToken>>> = Lazy::new(Default::default);
+
+// Makes sure a queued upload is not removed while the result of an attempt is written. This is only held around
+// database changes and never across an upload, so cancelling is never blocked by the network.
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+// Defines a claim on a queued upload which is being attempted. Dropping it releases the claim.
+struct Claim {
+    id: i64,
+    token: CancellationToken,
+}
+
+impl Claim {
+    // Claims the queued upload. Returns None if it is already being attempted.
+    fn new(id: i64) -> Option<Self> {
+        let mut in_flight = IN_FLIGHT.lock().unwrap();
+        if in_flight.contains_key(&id) {
+            return None;
+        }
+        let token = CancellationToken::new();
+        in_flight.insert(id, token.clone());
+        Some(Self { id, token })
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        IN_FLIGHT.lock().unwrap().remove(&self.id);
+    }
+}
+
+// Cancels the attempt of the queued upload if one is running.
+fn cancel_in_flight(id: i64) {
+    if let Some(token) = IN_FLIGHT.lock().unwrap().get(&id) {
+        token.cancel();
+    }
+}
+
+// Gets the current unix timestamp.
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+// Gets when the next attempt should be made after the number of attempts specified. None means give up.
+fn next_attempt_at(attempts: i64) -> Option<i64> {
+    if attempts >= MAX_ATTEMPTS {
+        return None;
+    }
+    let delay = BASE_DELAY_SECS
+        .saturating_mul(1 << (attempts - 1).clamp(0, 30))
+        .min(MAX_DELAY_SECS);
+    Some(now() + delay)
+}
+
+// Gets the folder used to hold copies of captures which were not saved to the filesystem.
+fn queue_folder() -> PathBuf {
+    CONFIG_FOLDER.join("upload_queue")
+}
+
+// Removes the file for the queued upload if we made it and no other queued upload still needs it.
+fn remove_owned_file(item: &QueuedUpload) {
+    if item.owned_file && database::count_queued_uploads_for_file(&item.file_path) == 0 {
+        let _ = std::fs::remove_file(&item.file_path);
+    }
+}
+
+// Gets the name of the file holding the copy for a queued upload. The uploader is included so uploads of the
+// same capture to different uploaders do not share a file.
+fn owned_file_name(capture_id: i64, uploader_id: &str, filename: &str) -> String {
+    let uploader_id: String = uploader_id
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    format!("{}_{}_{}", capture_id, uploader_id, filename)
+}
+
+// Removes all of the files made for queued uploads. Used when the database is wiped or rewritten.
+pub fn remove_all_files() {
+    for token in IN_FLIGHT.lock().unwrap().values() {
+        token.cancel();
+    }
+    let _ = std::fs::remove_dir_all(queue_folder());
+}
+
+// Runs the function while no queued upload is writing its result. Used to disconnect the database when
+// MagicCap closes, since attempts which finish after the kill switch is set do not write anything.
+pub fn without_writes<F: FnOnce()>(f: F) {
+    let _guard = STATE_LOCK.lock().unwrap();
+    f();
+}
+
+// Queues a failed upload to be retried. If the capture was not saved to the filesystem, the source is
+// copied into the config folder so it survives restarts. Errors which retrying will not fix are not queued.
+fn enqueue(
+    capture_id: i64,
+    uploader_id: &str,
+    filename: &str,
+    file_path: Option<&str>,
+    source: &UploadSource,
+    error: &UploadError,
+) -> Result<(), String> {
+    if !error.is_retryable() {
+        return Ok(());
+    }
+
+    let (file_path, owned_file) = match file_path {
+        Some(fp) if !fp.is_empty() => (fp.to_string(), false),
+        _ => {
+            let folder = queue_folder();
+            if let Err(e) = std::fs::create_dir_all(&folder) {
+                return Err(format!("Failed to create the queue folder: {}", e));
+            }
+            let fp = folder.join(owned_file_name(capture_id, uploader_id, filename));
+            let fp_str = match fp.to_str() {
+                Some(fp_str) => fp_str.to_string(),
+                None => return Err("The path of the queue folder is not valid UTF-8.".to_string()),
+            };
+            if let Err(e) = std::fs::copy(source.path(), &fp) {
+                return Err(format!("Failed to write the queued file: {}", e));
+            }
+            (fp_str, true)
+        }
+    };
+
+    // The database is disconnected under the lock when MagicCap closes, so failures while it is closing are
+    // still queued if the database is there.
+    let guard = STATE_LOCK.lock().unwrap();
+    let res = match database::is_connected() {
+        true => database::insert_queued_upload(
+            capture_id,
+            uploader_id,
+            filename,
+            &file_path,
+            owned_file,
+            &error.to_string(),
+            next_attempt_at(1).unwrap(),
+        ),
+        false => Err("MagicCap is closing.".to_string()),
+    };
+    drop(guard);
+    match res {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            if owned_file {
+                let _ = std::fs::remove_file(&file_path);
+            }
+            Err(format!("Failed to add the upload to the queue: {}", e))
+        }
+    }
+}
+
+// Queues a failed upload to be retried and tells the user if it could not be queued.
+pub fn enqueue_or_notify(
+    capture_id: i64,
+    uploader_id: &str,
+    filename: &str,
+    file_path: Option<&str>,
+    source: &UploadSource,
+    error: &UploadError,
+) {
+    if let Err(e) = enqueue(capture_id, uploader_id, filename, file_path, source, error) {
+        let message = format!("The upload to {} could not be queued for retry: {}", uploader_id, e);
+        run_thread(move || notification::send_notification(&message, None, None));
+    }
+}
+
+// Attempts the queued upload and updates the database with the result. Errors which need the user to change
+// something stop the automatic retries.
+fn attempt_upload(item: &QueuedUpload, claim: &Claim) -> Result<String, UploadError> {
+    // Call the uploader with the file. This is tracked so it can be cancelled from the frontend.
+    let result = match UploadSource::from_path(&item.file_path) {
+        Ok(source) => {
+            let (_tracked, ctx) = upload_progress::track(
+                &item.uploader_id,
+                &item.filename,
+                source.size(),
+                claim.token.clone(),
+                None,
+            );
+
+            // Owned files are temporary copies, so only give the uploader the path if it is the saved capture.
+            let ctx = ctx.with_capture(CaptureMetadata {
+                capture_id: Some(item.capture_id),
+                capture_type: None,
+                file_path: match item.owned_file {
+                    true => None,
+                    false => Some(item.file_path.clone()),
+                },
+            });
+            uploaders::call_uploader(&item.uploader_id, &source, &item.filename, &ctx)
+                .map(|url| (url, ctx.take_deletion_token(), ctx.take_thumbnail_url()))
+        }
+        Err(e) => Err(e),
+    };
+
+    // If MagicCap is closing, the database may already be disconnected. Leave the queued upload as it is so it
+    // is tried again the next time MagicCap starts. This is checked under the lock since unloading disconnects
+    // the database while holding it.
+    let guard = STATE_LOCK.lock().unwrap();
+    if KILL_SWITCH.load(Ordering::Relaxed) {
+        return Err(UploadError::ShuttingDown);
+    }
+
+    // If the queued upload was cancelled during the attempt, do not write anything. The file is removed here
+    // since it could not be removed while the uploader had it open.
+    if database::get_queued_upload(item.id).is_none() {
+        drop(guard);
+        remove_owned_file(item);
+        return Err(UploadError::Cancelled);
+    }
+
+    // Handle the result.
+    match result {
+        Ok((url, deletion_token, thumbnail_url)) => {
+            // Only set the URL on the capture if no other upload for it already succeeded.
+            database::set_capture_upload_url(
+                item.capture_id, &item.uploader_id, &url, &dedupe::config_hash(&item.uploader_id),
+                deletion_token.as_deref(),
+            );
+            if let Some(thumbnail_url) = &thumbnail_url {
+                database::set_capture_upload_thumbnail(item.capture_id, &item.uploader_id, thumbnail_url);
+            }
+            if let Some(capture) = database::get_capture(item.capture_id) {
+                if !capture.success {
+                    database::set_capture_uploaded(item.capture_id, &url);
+                }
+            }
+            database::delete_queued_upload(item.id);
+            drop(guard);
+            remove_owned_file(item);
+            config::update_webview_with_capture(item.capture_id);
+            Ok(url)
+        }
+        Err(e) => {
+            let next_attempt = match e.is_retryable() {
+                true => next_attempt_at(item.attempts + 1),
+                false => None,
+            };
+            database::set_queued_upload_failed(item.id, &e.to_string(), next_attempt);
+            Err(e)
+        }
+    }
+}
+
+// Processes all of the uploads which are due.
+fn process_due_uploads() {
+    for item in database::get_due_queued_uploads(now()) {
+        // Stop if the application is unloading.
+        if KILL_SWITCH.load(Ordering::Relaxed) {
+            return;
+        }
+
+        // Skip the item if a manual retry is already attempting it.
+        let claim = match Claim::new(item.id) {
+            Some(claim) => claim,
+            None => continue,
+        };
+
+        // Let the user know if the retry worked. This is in a thread since notifications can block.
+        if let Ok(url) = attempt_upload(&item, &claim) {
+            let filename = item.filename.clone();
+            run_thread(move || {
+                notification::send_notification(
+                    &format!("The queued upload of {} was successful.", filename),
+                    Some(&url),
+                    None,
+                )
+            });
+        }
+    }
+}
+
+// Starts the background worker which retries queued uploads. Does nothing if it is already running.
+pub fn start_worker() {
+    if WORKER_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    // This is a dedicated thread rather than the pool since it lives for the lifetime of the application.
+    std::thread::spawn(|| {
+        while !KILL_SWITCH.load(Ordering::Relaxed) {
+            process_due_uploads();
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        WORKER_RUNNING.store(false, Ordering::SeqCst);
+    });
+}
+
+// Retries a queued upload immediately. Returns the URL on success.
+pub fn retry(id: i64) -> Result<String, String> {
+    let claim = match Claim::new(id) {
+        Some(claim) => claim,
+        None => return Err("The queued upload is already being attempted.".to_string()),
+    };
+    let item = match database::get_queued_upload(id) {
+        Some(item) => item,
+        None => return Err("The queued upload does not exist.".to_string()),
+    };
+    attempt_upload(&item, &claim).map_err(|e| e.to_string())
+}
+
+// Cancels a queued upload. Returns false if it never existed.
+pub fn cancel(id: i64) -> bool {
+    let guard = STATE_LOCK.lock().unwrap();
+    match database::get_queued_upload(id) {
+        Some(item) => {
+            database::delete_queued_upload(item.id);
+            drop(guard);
+            cancel_in_flight(item.id);
+            remove_owned_file(&item);
+            true
+        }
+        None => false,
+    }
+}
+
+// Cancels any queued uploads for the capture specified. Used when the capture is deleted.
+pub fn cancel_for_capture(capture_id: i64) {
+    let guard = STATE_LOCK.lock().unwrap();
+    let items: Vec<QueuedUpload> = database::get_queued_uploads()
+        .into_iter()
+        .filter(|item| item.capture_id == capture_id)
+        .collect();
+    for item in &items {
+        database::delete_queued_upload(item.id);
+    }
+    drop(guard);
+    for item in &items {
+        cancel_in_flight(item.id);
+        remove_owned_file(item);
+    }
+}
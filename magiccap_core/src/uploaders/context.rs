@@ -0,0 +1,153 @@
+use super::error::{UploadError, CANCELLED_MESSAGE};
+use std::{
+    io::{Error, ErrorKind, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+// Defines a token which can be shared between threads to cancel an upload.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    // Creates a token which is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Cancels anything using the token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    // Checks if the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+// Defines what is known about the capture being uploaded. Uploads which are not from a capture, or happen
+// before the capture is saved in the database, leave the parts that are not known unset.
+#[derive(Clone, Default)]
+pub struct CaptureMetadata {
+    pub capture_id: Option<i64>,
+    pub capture_type: Option<String>,
+    pub file_path: Option<String>,
+}
+
+// Defines the function called with the bytes sent and the total bytes.
+pub type ProgressCallback = Box<dyn Fn(u64, u64) + Send + Sync>;
+
+// Defines the context passed to every uploader. Uploaders should report progress as they send data and
+// stop as soon as they can when the upload is cancelled. Uploaders which can delete files also set the
+// token needed to delete the file here, and uploaders which make a thumbnail set the URL to it.
+pub struct UploadContext {
+    token: CancellationToken,
+    progress: Option<ProgressCallback>,
+    capture: CaptureMetadata,
+    deletion_token: Mutex<Option<String>>,
+    thumbnail_url: Mutex<Option<String>>,
+}
+
+impl UploadContext {
+    // Creates a context with the cancellation token and optional progress callback specified.
+    pub fn new(token: CancellationToken, progress: Option<ProgressCallback>) -> Self {
+        Self {
+            token,
+            progress,
+            capture: CaptureMetadata::default(),
+            deletion_token: Mutex::new(None),
+            thumbnail_url: Mutex::new(None),
+        }
+    }
+
+    // Sets the metadata for the capture being uploaded.
+    pub fn with_capture(mut self, capture: CaptureMetadata) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    // Gets the metadata for the capture being uploaded.
+    pub fn capture(&self) -> &CaptureMetadata {
+        &self.capture
+    }
+
+    // Creates a context which nothing is watching.
+    pub fn background() -> Self {
+        Self::new(CancellationToken::new(), None)
+    }
+
+    // Checks if the upload was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    // Returns an error if the upload was cancelled. Useful before starting slow work.
+    pub fn check_cancelled(&self) -> Result<(), UploadError> {
+        match self.is_cancelled() {
+            true => Err(UploadError::Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    // Reports the progress of the upload.
+    pub fn report_progress(&self, sent: u64, total: u64) {
+        if let Some(progress) = &self.progress {
+            progress(sent, total);
+        }
+    }
+
+    // Sets the token which is passed to the uploaders delete handler to delete the uploaded file.
+    pub fn set_deletion_token(&self, deletion_token: impl Into<String>) {
+        *self.deletion_token.lock().unwrap() = Some(deletion_token.into());
+    }
+
+    // Takes the deletion token set by the uploader if there is one.
+    pub fn take_deletion_token(&self) -> Option<String> {
+        self.deletion_token.lock().unwrap().take()
+    }
+
+    // Sets the URL to a thumbnail of the uploaded file.
+    pub fn set_thumbnail_url(&self, thumbnail_url: impl Into<String>) {
+        *self.thumbnail_url.lock().unwrap() = Some(thumbnail_url.into());
+    }
+
+    // Takes the thumbnail URL set by the uploader if there is one.
+    pub fn take_thumbnail_url(&self) -> Option<String> {
+        self.thumbnail_url.lock().unwrap().take()
+    }
+
+    // Wraps a reader so progress is reported as it is read and reads fail once the upload is cancelled.
+    pub fn progress_reader<R: Read>(&self, reader: R, total: u64) -> ProgressReader<'_, R> {
+        self.report_progress(0, total);
+        ProgressReader {
+            inner: reader,
+            ctx: self,
+            sent: 0,
+            total,
+        }
+    }
+}
+
+// Defines a reader which reports progress to the upload context.
+pub struct ProgressReader<'a, R: Read> {
+    inner: R,
+    ctx: &'a UploadContext,
+    sent: u64,
+    total: u64,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Don't use ErrorKind::Interrupted here since most consumers retry on it.
+        if self.ctx.is_cancelled() {
+            return Err(Error::new(ErrorKind::Other, CANCELLED_MESSAGE));
+        }
+        let n = self.inner.read(buf)?;
+        self.sent += n as u64;
+        self.ctx.report_progress(self.sent.min(self.total), self.total);
+        Ok(n)
+    }
+}
@@ -0,0 +1,550 @@
+use super::{config_structure::check_uploader_name, CustomUploader};
+use crate::{database, statics::CONFIG_FOLDER};
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+// Defines the paths used inside of the bundle.
+const UPLOADER_PATH: &str = "uploader.json";
+const ICON_PATH_PREFIX: &str = "icon.";
+const DATA_PATH_PREFIX: &str = "data/";
+const SIGNATURE_PATH: &str = "signature.json";
+
+// Defines the file in the config folder holding the seed for the signing key of this install. This is kept out
+// of the database so it cannot be read with the config API or end up in a data dump.
+const SIGNING_KEY_FILE: &str = "bundle_signing_key";
+
+// Defines the config key the signing key was held in by older versions. It is moved into the file.
+const LEGACY_SIGNING_KEY_CONFIG: &str = "bundle_signing_key";
+
+// Defines the config key holding the hex encoded public keys the user trusts to sign bundles.
+const TRUSTED_SIGNERS_CONFIG: &str = "bundle_trusted_signers";
+
+// Defines the signature which is placed at the end of the bundle.
+#[derive(Serialize, Deserialize)]
+struct BundleSignature {
+    public_key: String,
+    signature: String,
+}
+
+// Defines a bundle which was read from the disk.
+pub struct UploaderBundle {
+    pub uploader: CustomUploader,
+
+    // Defines the hex encoded public key of the signer if the bundle was signed by a trusted key.
+    pub signer: Option<String>,
+
+    // Defines the hex encoded public key of the signer if the bundle was signed by a key which is not trusted.
+    // This is only a claim, so the bundle should be treated as unsigned.
+    pub untrusted_signer: Option<String>,
+
+    // Defines the files in the PHP data folder.
+    data: Vec<(PathBuf, Vec<u8>)>,
+}
+
+// Turns bytes into lowercase hex.
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Turns lowercase or uppercase hex into bytes.
+fn from_hex(data: &str) -> Option<Vec<u8>> {
+    if data.len() % 2 != 0 {
+        return None;
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(data.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// Hashes the entries of the bundle. The entries are sorted and length prefixed so the digest does not
+// depend on the order they are stored in the tarball.
+fn digest_entries(entries: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut sorted: Vec<&(String, Vec<u8>)> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut hasher = Sha256::new();
+    for (path, data) in sorted {
+        hasher.update((path.len() as u64).to_le_bytes());
+        hasher.update(path.as_bytes());
+        hasher.update((data.len() as u64).to_le_bytes());
+        hasher.update(data);
+    }
+    hasher.finalize().to_vec()
+}
+
+// Decodes a base64 encoded seed for a signing key.
+fn decode_seed(encoded: &str) -> Option<[u8; 32]> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(encoded.trim()).ok()?;
+    <[u8; 32]>::try_from(bytes.as_slice()).ok()
+}
+
+// Writes the seed to the signing key file. The file is only readable by the current user.
+fn write_seed(fp: &Path, seed: &[u8; 32]) -> Result<(), String> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = match options.open(fp) {
+        Ok(f) => f,
+        Err(e) => return Err(format!("Failed to create the signing key file: {}", e)),
+    };
+    match file.write_all(base64::engine::general_purpose::STANDARD.encode(seed).as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write the signing key file: {}", e)),
+    }
+}
+
+// Gets the signing key for this install, generating it the first time.
+fn signing_key() -> Result<SigningKey, String> {
+    let fp = CONFIG_FOLDER.join(SIGNING_KEY_FILE);
+    match std::fs::read_to_string(&fp) {
+        Ok(encoded) => match decode_seed(&encoded) {
+            Some(seed) => return Ok(SigningKey::from_bytes(&seed)),
+            None => return Err("The signing key file is corrupt.".to_string()),
+        },
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(format!("Failed to read the signing key file: {}", e))
+        }
+        Err(_) => {}
+    }
+
+    // Move the key out of the database if an older version made it there, otherwise make a new one.
+    let legacy = database::get_config_option(LEGACY_SIGNING_KEY_CONFIG)
+        .and_then(|v| v.as_str().and_then(decode_seed));
+    let seed = legacy.unwrap_or_else(rand::random);
+    write_seed(&fp, &seed)?;
+    database::delete_config_option(LEGACY_SIGNING_KEY_CONFIG);
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+// Gets the hex encoded public key used to sign bundles exported from this install.
+pub fn signing_public_key() -> Result<String, String> {
+    Ok(to_hex(signing_key()?.verifying_key().as_bytes()))
+}
+
+// Gets the hex encoded public keys the user trusts to sign bundles. The key of this install is always trusted.
+fn trusted_signers() -> Vec<String> {
+    let mut signers: Vec<String> = match database::get_config_option(TRUSTED_SIGNERS_CONFIG) {
+        Some(serde_json::Value::Array(keys)) => keys
+            .iter()
+            .filter_map(|v| v.as_str().map(|s| s.to_lowercase()))
+            .collect(),
+        _ => Vec::new(),
+    };
+    if let Ok(own_key) = signing_public_key() {
+        signers.push(own_key);
+    }
+    signers
+}
+
+// Adds a hex encoded public key to the keys trusted to sign bundles.
+pub fn trust_signer(public_key: &str) -> Result<(), String> {
+    let public_key = public_key.trim().to_lowercase();
+    let valid = from_hex(&public_key)
+        .and_then(|b| <[u8; 32]>::try_from(b.as_slice()).ok())
+        .map_or(false, |b| VerifyingKey::from_bytes(&b).is_ok());
+    if !valid {
+        return Err("The public key is not valid.".to_string());
+    }
+    let mut keys = match database::get_config_option(TRUSTED_SIGNERS_CONFIG) {
+        Some(serde_json::Value::Array(keys)) => keys,
+        _ => Vec::new(),
+    };
+    if !keys.iter().any(|v| v.as_str().map(|s| s.to_lowercase()) == Some(public_key.clone())) {
+        keys.push(serde_json::Value::String(public_key));
+        database::set_config_option(TRUSTED_SIGNERS_CONFIG, &serde_json::Value::Array(keys));
+    }
+    Ok(())
+}
+
+// Splits a data URI icon into its extension and bytes. Returns None if it is not a data URI.
+fn decode_icon(encoded_icon: &str) -> Option<(String, Vec<u8>)> {
+    let rest = encoded_icon.strip_prefix("data:")?;
+    let (mime, data) = rest.split_once(";base64,")?;
+    let ext = match mime_guess::get_mime_extensions_str(mime) {
+        Some(exts) if !exts.is_empty() => exts[0].to_string(),
+        _ => return None,
+    };
+    let data = base64::engine::general_purpose::STANDARD.decode(data).ok()?;
+    Some((ext, data))
+}
+
+// Gets the folder used by the PHP uploader for persistent data. The key is checked so it cannot point outside of
+// the custom uploaders folder.
+fn data_folder(uploader_key: &str) -> Result<PathBuf, String> {
+    check_uploader_name(uploader_key)?;
+    Ok(CONFIG_FOLDER.join("custom_uploaders").join(uploader_key))
+}
+
+// Reads all of the files in the data folder as bundle entries.
+fn read_data_folder(folder: &Path) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut entries = Vec::new();
+    if !folder.exists() {
+        return Ok(entries);
+    }
+    for entry in walkdir::WalkDir::new(folder) {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(e) => return Err(e.to_string()),
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(folder).unwrap();
+        let relative = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+        let data = match std::fs::read(entry.path()) {
+            Ok(d) => d,
+            Err(e) => return Err(e.to_string()),
+        };
+        entries.push((format!("{}{}", DATA_PATH_PREFIX, relative), data));
+    }
+    Ok(entries)
+}
+
+// Appends a file to the tarball.
+fn append_file<W: Write>(
+    builder: &mut tar::Builder<W>, path: &str, data: &[u8],
+) -> Result<(), String> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    match builder.append_data(&mut header, path, data) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Exports the custom uploader with the key specified into a bundle at the path specified. Returns the
+// public key of the signer if it was signed.
+pub fn export_bundle(
+    uploader_key: &str, include_data: bool, sign: bool, fp: &Path,
+) -> Result<Option<String>, String> {
+    // Get the custom uploader.
+    let uploader = match database::get_config_option(&("custom_uploader_".to_string() + uploader_key)) {
+        Some(v) => v,
+        None => return Err("The custom uploader does not exist.".to_string()),
+    };
+    let (uploader, _) = super::migrate_uploader(uploader)?;
+    let mut uploader: CustomUploader = match serde_json::from_value(uploader) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("The custom uploader is not valid: {}", e)),
+    };
+
+    // Build the entries. The icon is stored as its own file so the bundle is easy to inspect.
+    let mut entries = Vec::new();
+    if let Some((ext, data)) = decode_icon(&uploader.encoded_icon) {
+        entries.push((format!("{}{}", ICON_PATH_PREFIX, ext), data));
+        uploader.encoded_icon = String::new();
+    }
+    let uploader_json = match serde_json::to_vec_pretty(&uploader) {
+        Ok(v) => v,
+        Err(e) => return Err(e.to_string()),
+    };
+    entries.insert(0, (UPLOADER_PATH.to_string(), uploader_json));
+    if include_data {
+        entries.extend(read_data_folder(&data_folder(uploader_key)?)?);
+    }
+
+    // Sign the entries if requested.
+    let mut signer = None;
+    if sign {
+        let key = signing_key()?;
+        let signature = key.sign(&digest_entries(&entries));
+        let public_key = to_hex(key.verifying_key().as_bytes());
+        let signature = BundleSignature {
+            public_key: public_key.clone(),
+            signature: to_hex(&signature.to_bytes()),
+        };
+        entries.push((SIGNATURE_PATH.to_string(), serde_json::to_vec_pretty(&signature).unwrap()));
+        signer = Some(public_key);
+    }
+
+    // Write the tarball.
+    let file = match std::fs::File::create(fp) {
+        Ok(f) => f,
+        Err(e) => return Err(e.to_string()),
+    };
+    let compressor = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    let mut builder = tar::Builder::new(compressor);
+    for (path, data) in &entries {
+        append_file(&mut builder, path, data)?;
+    }
+    match builder.into_inner() {
+        Ok(compressor) => match compressor.finish() {
+            Ok(_) => Ok(signer),
+            Err(e) => Err(e.to_string()),
+        },
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Turns a data path inside of the bundle into a relative path, making sure it cannot escape the folder.
+fn safe_data_path(path: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(&path[DATA_PATH_PREFIX.len()..]);
+    for component in relative.components() {
+        match component {
+            Component::Normal(_) => (),
+            _ => return Err(format!("The bundle contains an unsafe path: {}", path)),
+        }
+    }
+    Ok(relative.to_path_buf())
+}
+
+// Checks the signature of the bundle against the trusted keys. Returns the public key of the signer and if it
+// is trusted. The key in the signature is only used when no trusted key matches, so an untrusted signature can
+// still be reported and a tampered bundle is still rejected.
+fn verify_signature(
+    entries: &[(String, Vec<u8>)],
+    signature: &[u8],
+    trusted_signers: &[String],
+) -> Result<(String, bool), String> {
+    let signature: BundleSignature = match serde_json::from_slice(signature) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("The bundle signature is not valid: {}", e)),
+    };
+    let sig = match from_hex(&signature.signature)
+        .and_then(|b| <[u8; 64]>::try_from(b.as_slice()).ok())
+    {
+        Some(b) => Signature::from_bytes(&b),
+        None => return Err("The bundle signature is malformed.".to_string()),
+    };
+    let parse_key = |hex: &str| {
+        from_hex(hex)
+            .and_then(|b| <[u8; 32]>::try_from(b.as_slice()).ok())
+            .and_then(|b| VerifyingKey::from_bytes(&b).ok())
+    };
+    let digest = digest_entries(entries);
+
+    // Check the trusted keys first.
+    for trusted in trusted_signers {
+        if let Some(key) = parse_key(trusted) {
+            if key.verify(&digest, &sig).is_ok() {
+                return Ok((trusted.to_lowercase(), true));
+            }
+        }
+    }
+
+    // Check the key the bundle claims to be signed by.
+    let public_key = match parse_key(&signature.public_key) {
+        Some(key) => key,
+        None => return Err("The bundle signature is malformed.".to_string()),
+    };
+    match public_key.verify(&digest, &sig) {
+        Ok(_) => Ok((signature.public_key.to_lowercase(), false)),
+        Err(_) => Err("The bundle signature does not match its contents. It may have been tampered with.".to_string()),
+    }
+}
+
+// Reads a bundle from the path specified. If it is signed, the signature must be valid.
+pub fn read_bundle(fp: &Path) -> Result<UploaderBundle, String> {
+    // Read all of the entries in the tarball.
+    let file = match std::fs::File::open(fp) {
+        Ok(f) => f,
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    let mut entries = Vec::new();
+    let mut signature = None;
+    let tar_entries = match archive.entries() {
+        Ok(e) => e,
+        Err(e) => return Err(format!("The bundle is not valid: {}", e)),
+    };
+    for entry in tar_entries {
+        let mut entry = match entry {
+            Ok(e) => e,
+            Err(e) => return Err(format!("The bundle is not valid: {}", e)),
+        };
+        if entry.header().entry_type() != tar::EntryType::Regular {
+            continue;
+        }
+        let path = match entry.path() {
+            Ok(p) => p.to_string_lossy().to_string(),
+            Err(e) => return Err(e.to_string()),
+        };
+        let mut data = Vec::new();
+        if let Err(e) = entry.read_to_end(&mut data) {
+            return Err(e.to_string());
+        }
+        if path == SIGNATURE_PATH {
+            signature = Some(data);
+        } else {
+            entries.push((path, data));
+        }
+    }
+
+    // Check the signature before trusting anything else.
+    let (signer, untrusted_signer) = match signature {
+        Some(signature) => match verify_signature(&entries, &signature, &trusted_signers())? {
+            (key, true) => (Some(key), None),
+            (key, false) => (None, Some(key)),
+        },
+        None => (None, None),
+    };
+
+    // Sort the entries into their parts.
+    let mut uploader = None;
+    let mut icon = None;
+    let mut data = Vec::new();
+    for (path, contents) in entries {
+        if path == UPLOADER_PATH {
+            uploader = Some(contents);
+        } else if let Some(ext) = path.strip_prefix(ICON_PATH_PREFIX) {
+            let mime = mime_guess::from_ext(ext).first_or_octet_stream();
+            icon = Some(format!(
+                "data:{};base64,{}",
+                mime,
+                base64::engine::general_purpose::STANDARD.encode(contents),
+            ));
+        } else if path.starts_with(DATA_PATH_PREFIX) {
+            data.push((safe_data_path(&path)?, contents));
+        } else {
+            return Err(format!("The bundle contains an unknown file: {}", path));
+        }
+    }
+
+    // Deserialize the uploader and put the icon back.
+    let uploader = match uploader {
+        Some(v) => v,
+        None => return Err("The bundle does not contain a custom uploader.".to_string()),
+    };
+    let uploader: serde_json::Value = match serde_json::from_slice(&uploader) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("The custom uploader in the bundle is not valid: {}", e)),
+    };
+    let (uploader, _) = super::migrate_uploader(uploader)?;
+    let mut uploader: CustomUploader = match serde_json::from_value(uploader) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("The custom uploader in the bundle is not valid: {}", e)),
+    };
+    if let Some(icon) = icon {
+        uploader.encoded_icon = icon;
+    }
+
+    Ok(UploaderBundle {
+        uploader,
+        signer,
+        untrusted_signer,
+        data,
+    })
+}
+
+impl UploaderBundle {
+    // Defines if the bundle contains any PHP data.
+    pub fn has_data(&self) -> bool {
+        !self.data.is_empty()
+    }
+
+    // Writes the PHP data into the folder for the uploader key specified.
+    pub fn write_data(&self, uploader_key: &str) -> Result<(), String> {
+        let folder = data_folder(uploader_key)?;
+        for (relative, contents) in &self.data {
+            let fp = folder.join(relative);
+            if let Some(parent) = fp.parent() {
+                if let Err(e) = std::fs::create_dir_all(parent) {
+                    return Err(e.to_string());
+                }
+            }
+            if let Err(e) = std::fs::write(&fp, contents) {
+                return Err(e.to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Signs the entries with the key like an export does.
+    fn sign(key: &SigningKey, entries: &[(String, Vec<u8>)]) -> Vec<u8> {
+        let signature = BundleSignature {
+            public_key: to_hex(key.verifying_key().as_bytes()),
+            signature: to_hex(&key.sign(&digest_entries(entries)).to_bytes()),
+        };
+        serde_json::to_vec(&signature).unwrap()
+    }
+
+    fn entries() -> Vec<(String, Vec<u8>)> {
+        vec![
+            (UPLOADER_PATH.to_string(), b"{}".to_vec()),
+            ("data/state.json".to_string(), b"[]".to_vec()),
+        ]
+    }
+
+    #[test]
+    fn rejects_unsafe_uploader_names() {
+        for name in ["", ".", "..", "../x", "a/b", "a\\b", "/abs", "x/.."] {
+            assert!(check_uploader_name(name).is_err(), "{:?} should be rejected", name);
+        }
+        for name in ["imgur", "sxcu.net", "My Uploader", "..hidden"] {
+            assert!(check_uploader_name(name).is_ok(), "{:?} should be allowed", name);
+        }
+        assert!(data_folder("../../etc").is_err());
+    }
+
+    #[test]
+    fn rejects_unsafe_data_paths() {
+        assert!(safe_data_path("data/../../x").is_err());
+        assert!(safe_data_path("data//abs").is_err());
+        assert_eq!(safe_data_path("data/a/b.json").unwrap(), PathBuf::from("a/b.json"));
+    }
+
+    #[test]
+    fn only_trusts_signatures_by_trusted_keys() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let public_key = to_hex(key.verifying_key().as_bytes());
+        let signature = sign(&key, &entries());
+
+        // A valid signature by a key which is not trusted is only reported as a claim.
+        let other = to_hex(SigningKey::from_bytes(&[8; 32]).verifying_key().as_bytes());
+        assert_eq!(
+            verify_signature(&entries(), &signature, &[other]).unwrap(),
+            (public_key.clone(), false)
+        );
+        assert_eq!(
+            verify_signature(&entries(), &signature, &[public_key.to_uppercase()]).unwrap(),
+            (public_key, true)
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_bundles() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let public_key = to_hex(key.verifying_key().as_bytes());
+        let signature = sign(&key, &entries());
+        let mut tampered = entries();
+        tampered[1].1 = b"[1]".to_vec();
+        assert!(verify_signature(&tampered, &signature, &[public_key.clone()]).is_err());
+        assert!(verify_signature(&tampered, &signature, &[]).is_err());
+
+        // Claiming a trusted key does not help if the signature was made by another key.
+        let forged = BundleSignature {
+            public_key,
+            signature: to_hex(&SigningKey::from_bytes(&[9; 32]).sign(&digest_entries(&entries())).to_bytes()),
+        };
+        let forged = serde_json::to_vec(&forged).unwrap();
+        assert!(verify_signature(&entries(), &forged, &[]).is_err());
+    }
+
+    #[test]
+    fn digest_does_not_depend_on_order() {
+        let mut reversed = entries();
+        reversed.reverse();
+        assert_eq!(digest_entries(&entries()), digest_entries(&reversed));
+    }
+}
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 
 // Handles removing Embedded items from the custom uploader config.
 // This is because these are meant for internal use only.
+#[derive(Default)]
 pub struct CustomUploaderConfig(Vec<(String, crate::uploaders::ConfigOption)>);
 
 impl Serialize for CustomUploaderConfig {
@@ -60,24 +61,23 @@ pub trait IntoUploader {
     fn into_uploader(
         self,
         id: String,
-    ) -> Box<
-        dyn Fn(
-                &str,
-                HashMap<String, serde_json::Value>,
-                Box<dyn std::io::Read + Send + Sync>,
-            ) -> Result<String, String>
-            + Send
-            + Sync,
-    >;
+    ) -> crate::uploaders::UploadHandler;
+
+    // Gets the handler used to delete uploaded files. Returns None if the uploader cannot delete files.
+    fn delete_handler(&self) -> Option<crate::uploaders::DeleteHandler> {
+        None
+    }
 }
 
 // Defines the HTTP method type.
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub enum Method {
     GET,
     POST,
     PUT,
     PATCH,
+    DELETE,
+    HEAD,
 }
 
 // Turns the method into a str.
@@ -88,12 +88,32 @@ impl Method {
             Method::POST => "POST",
             Method::PUT => "PUT",
             Method::PATCH => "PATCH",
+            Method::DELETE => "DELETE",
+            Method::HEAD => "HEAD",
         }
     }
 }
 
+// Defines the network settings used for every request the HTTP uploader makes. Timeouts are in seconds.
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct HTTPNetworkOptions {
+    #[serde(default)]
+    pub connect_timeout: Option<u64>,
+    #[serde(default)]
+    pub read_timeout: Option<u64>,
+
+    // Defines how many times a request is tried again when the server responds with a 5xx or 429 status.
+    #[serde(default)]
+    pub max_retries: u32,
+
+    // Defines the proxy URL, such as socks5://localhost:1080. This can use rewrites so it can come from the
+    // config, and it is ignored if it is empty.
+    #[serde(default)]
+    pub proxy: Option<String>,
+}
+
 // Defines the HTTP uploader config.
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct HTTPUploaderConfig {
     pub rewrites: HashMap<String, super::HTTPRewrite>,
     pub url_template: String,
@@ -101,6 +121,73 @@ pub struct HTTPUploaderConfig {
     pub header_templates: HashMap<String, String>,
     pub body: super::HTTPBody,
     pub response_expr: String,
+
+    // Defines the expression run on the upload response to get the deletion token. If this is not set,
+    // the uploader cannot delete files.
+    #[serde(default)]
+    pub delete_expr: Option<String>,
+
+    // Defines the request made to delete a file. If this is not set, the deletion token is treated as a URL
+    // which deletes the file when it is requested, which is what ShareX deletion URLs do. That URL must be on
+    // the same host as the upload URL and is not signed.
+    #[serde(default)]
+    pub delete_request: Option<HTTPDeleteRequest>,
+
+    // Defines the requests made in order before the upload, such as getting a token or an upload slot.
+    #[serde(default)]
+    pub pre_steps: Vec<HTTPStep>,
+
+    // Defines the values captured from the upload response for the requests after it. The key is the rewrite
+    // the value is stored in and the value is the expression.
+    #[serde(default)]
+    pub upload_captures: HashMap<String, String>,
+
+    // Defines the requests made in order after the upload, such as finalizing it. If there are any, the
+    // response and delete expressions are run against the response of the last one.
+    #[serde(default)]
+    pub post_steps: Vec<HTTPStep>,
+
+    // Defines the expression run on responses with an error status to get the message from the server. If
+    // this is not set or fails, the body is shown.
+    #[serde(default)]
+    pub error_expr: Option<String>,
+
+    #[serde(default)]
+    pub network: HTTPNetworkOptions,
+
+    // Defines how the upload and delete requests are signed. The signature covers the final request, so it is
+    // made after the headers and body are built. Steps are not signed.
+    #[serde(default)]
+    pub signing: Option<super::signing::HTTPSigning>,
+}
+
+// Defines the body for a step. Text bodies and form values can use rewrites.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "type", content = "value")]
+pub enum HTTPStepBody {
+    Empty,
+    Text(String),
+    URLEncoded(HashMap<String, String>),
+}
+
+// Defines a request made before or after the upload. Each capture runs an expression against the response
+// and stores the result in the rewrite with the key, so later requests can use it in their URL, headers,
+// and body.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct HTTPStep {
+    pub url_template: String,
+    pub method: Method,
+    pub header_templates: HashMap<String, String>,
+    pub body: HTTPStepBody,
+    pub captures: HashMap<String, String>,
+}
+
+// Defines the request made to delete a file. The deletion token is available with the DeletionToken rewrite.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct HTTPDeleteRequest {
+    pub url_template: String,
+    pub method: Method,
+    pub header_templates: HashMap<String, String>,
 }
 
 // Implements the IntoUploader trait for HTTPUploaderConfig.
@@ -108,41 +195,64 @@ impl IntoUploader for HTTPUploaderConfig {
     fn into_uploader(
         self,
         _: String,
-    ) -> Box<
-        dyn Fn(
-                &str,
-                HashMap<String, serde_json::Value>,
-                Box<dyn std::io::Read + Send + Sync>,
-            ) -> Result<String, String>
-            + Send
-            + Sync,
-    > {
-        return Box::new(move |filename, config, reader| {
-            let (mime_type, reader) =
-                match crate::uploaders::mime::guess_mime_type(filename, reader) {
-                    Ok(v) => v,
-                    Err(e) => return Err(e.to_string()),
-                };
-            super::http::http(
-                filename,
-                mime_type.essence_str(),
-                self.rewrites.clone(),
-                &self.url_template,
-                self.method.as_str(),
-                self.header_templates.clone(),
-                self.body.clone(),
-                config.clone(),
-                reader,
-                &self.response_expr,
-            )
+    ) -> crate::uploaders::UploadHandler {
+        return Box::new(move |filename, config, source, ctx| {
+            let mime_type = crate::uploaders::mime::guess_mime_type(filename, source)?;
+            super::http::http(&self, filename, mime_type.essence_str(), config, source, ctx)
         });
     }
+
+    fn delete_handler(&self) -> Option<crate::uploaders::DeleteHandler> {
+        // The token comes from the delete expression, so we cannot delete without it.
+        self.delete_expr.as_ref()?;
+        let handler = self.clone();
+        Some(Box::new(move |deletion_token: &str, config: HashMap<String, serde_json::Value>| {
+            super::http::http_delete(&handler, deletion_token, &config)
+        }))
+    }
+}
+
+// Defines the limits the PHP script runs with. The timeout is in seconds and the sizes are in megabytes. A
+// data quota of 0 means the data folder can be any size.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct PHPLimits {
+    #[serde(default = "default_php_timeout")]
+    pub timeout: u64,
+    #[serde(default = "default_php_memory_limit")]
+    pub memory_limit: u64,
+    #[serde(default = "default_php_data_quota")]
+    pub data_quota: u64,
+}
+
+fn default_php_timeout() -> u64 {
+    30
+}
+
+fn default_php_memory_limit() -> u64 {
+    128
+}
+
+fn default_php_data_quota() -> u64 {
+    50
+}
+
+impl Default for PHPLimits {
+    fn default() -> Self {
+        Self {
+            timeout: default_php_timeout(),
+            memory_limit: default_php_memory_limit(),
+            data_quota: default_php_data_quota(),
+        }
+    }
 }
 
 // Defines the PHP uploader config.
 #[derive(Deserialize, Serialize)]
 pub struct PHPUploaderConfig {
     pub code: String,
+
+    #[serde(default)]
+    pub limits: PHPLimits,
 }
 
 // Implements the IntoUploader trait for PHPUploaderConfig.
@@ -150,17 +260,11 @@ impl IntoUploader for PHPUploaderConfig {
     fn into_uploader(
         self,
         id: String,
-    ) -> Box<
-        dyn Fn(
-                &str,
-                HashMap<String, serde_json::Value>,
-                Box<dyn std::io::Read + Send + Sync>,
-            ) -> Result<String, String>
-            + Send
-            + Sync,
-    > {
-        return Box::new(move |filename, config, reader| {
-            super::php::php(&id, &self.code, config, filename, reader)
+    ) -> crate::uploaders::UploadHandler {
+        return Box::new(move |filename, config, source, ctx| {
+            // PHP does not report progress, so the best we can do is check for cancellation before starting.
+            ctx.check_cancelled()?;
+            super::php::php(&id, &self, config, filename, source, ctx)
         });
     }
 }
@@ -178,27 +282,48 @@ impl IntoUploader for CustomUploaderHandler {
     fn into_uploader(
         self,
         id: String,
-    ) -> Box<
-        dyn Fn(
-                &str,
-                HashMap<String, serde_json::Value>,
-                Box<dyn std::io::Read + Send + Sync>,
-            ) -> Result<String, String>
-            + Send
-            + Sync,
-    > {
+    ) -> crate::uploaders::UploadHandler {
         match self {
             CustomUploaderHandler::HTTP(v) => v.into_uploader(id),
             CustomUploaderHandler::PHP(v) => v.into_uploader(id),
         }
     }
+
+    fn delete_handler(&self) -> Option<crate::uploaders::DeleteHandler> {
+        match self {
+            CustomUploaderHandler::HTTP(v) => v.delete_handler(),
+            CustomUploaderHandler::PHP(v) => v.delete_handler(),
+        }
+    }
 }
 
-// Currently the only version is V1.
-#[derive(Deserialize, Serialize)]
+// Defines the versions of the custom uploader schema. V2 adds the metadata and makes the HTTP steps part of the
+// schema. V1 uploaders are migrated to the latest version before they are loaded.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "lowercase")]
 pub enum UploaderVersions {
     V1,
+    V2,
+}
+
+// Defines the latest version of the custom uploader schema.
+pub const LATEST_UPLOADER_VERSION: UploaderVersions = UploaderVersions::V2;
+
+// Defines the metadata about a custom uploader. The MIME types can end in /* to match any subtype, and an
+// empty list means any file is supported. The max file size is in bytes. The minimum app version is only for
+// people reading the uploader since the core does not know the version of the app it is bundled in.
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct CustomUploaderMetadata {
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub homepage: Option<String>,
+    #[serde(default)]
+    pub min_app_version: Option<String>,
+    #[serde(default)]
+    pub supported_mime_types: Vec<String>,
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
 }
 
 // Defines the main custom uploader struct.
@@ -211,4 +336,24 @@ pub struct CustomUploader {
     pub encoded_icon: String,
     pub config: CustomUploaderConfig,
     pub handler: CustomUploaderHandler,
+
+    #[serde(default)]
+    pub metadata: CustomUploaderMetadata,
+}
+
+// Checks the name of a custom uploader. The name is used as a folder name for the PHP data, so it must be a
+// single normal path component.
+pub fn check_uploader_name(name: &str) -> Result<(), String> {
+    let mut components = std::path::Path::new(name).components();
+    let single = matches!(
+        (components.next(), components.next()),
+        (Some(std::path::Component::Normal(c)), None) if c == name
+    );
+    match single && !name.contains(['/', '\\']) {
+        true => Ok(()),
+        false => Err(format!(
+            "The uploader name {:?} is not valid. It cannot be empty, . or .., or contain slashes.",
+            name
+        )),
+    }
 }
@@ -1,9 +1,25 @@
-use super::http_expr_functions::add_default_functions;
+use super::{
+    config_structure::{HTTPNetworkOptions, HTTPStep, HTTPStepBody, HTTPUploaderConfig},
+    http_expr_functions::{add_default_functions, add_file_functions, FileInfo},
+    signing::{sign_request, Payload},
+};
+use crate::uploaders::{
+    multipart::{
+        multipart_stream, multipart_stream_files, MultipartBody, MultipartContent, MultipartFile,
+    },
+    UploadContext, UploadError, UploadSource,
+};
 use base64::{engine::general_purpose, Engine};
 use evalexpr::{ContextWithMutableFunctions, EvalexprError, Value};
 use mime::Mime;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, str::FromStr, sync::Arc};
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 #[derive(Deserialize, Serialize, Clone)]
 #[serde(tag = "type", content = "value")]
@@ -12,6 +28,9 @@ pub enum HTTPRewrite {
     Static(String),
     Filename,
     MIME,
+
+    // The deletion token. This is only set when deleting a file and is empty otherwise.
+    DeletionToken,
 }
 
 fn rewrite_processor(
@@ -20,6 +39,7 @@ fn rewrite_processor(
     filename: &str,
     mime_type: &str,
     config: &HashMap<String, serde_json::Value>,
+    deletion_token: &str,
 ) -> String {
     let mut value = value.to_string();
     for (key, rewrite) in rewrites {
@@ -34,6 +54,7 @@ fn rewrite_processor(
             HTTPRewrite::Static(s) => s.clone(),
             HTTPRewrite::Filename => filename.to_string(),
             HTTPRewrite::MIME => mime_type.to_string(),
+            HTTPRewrite::DeletionToken => deletion_token.to_string(),
         };
         value = value.replace(key, replacement.as_str());
     }
@@ -54,220 +75,898 @@ pub struct URLEncodingOpts {
     pub encoding_type: URLEncodingType,
 }
 
+// Defines what is sent in a file field of a multipart form.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "type", content = "value")]
+pub enum MultipartFileKind {
+    // The file that is being uploaded.
+    Original,
+
+    // A PNG thumbnail of the image that fits within the size specified.
+    Thumbnail(u32),
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct MultipartFileField {
+    pub name: String,
+    pub kind: MultipartFileKind,
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 #[serde(tag = "type", content = "value")]
 pub enum HTTPBody {
     Raw,
     URLEncoded(HashMap<String, String>, URLEncodingOpts),
     MultipartForm(HashMap<String, String>, String),
+
+    // A JSON template and the dot separated path to put the base64 encoded file at.
+    JSON(serde_json::Value, String),
+
+    // A multipart form with several file fields.
+    MultipartFiles(HashMap<String, String>, Vec<MultipartFileField>),
 }
 
-fn consume_all_reader(mut reader: Box<dyn std::io::Read + Send + Sync>) -> Result<Vec<u8>, String> {
+// Rewrites each string in the JSON template.
+fn rewrite_json(
+    rewrites: &HashMap<String, HTTPRewrite>,
+    value: &serde_json::Value,
+    filename: &str,
+    mime_type: &str,
+    config: &HashMap<String, serde_json::Value>,
+) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(s) => serde_json::Value::String(
+            rewrite_processor(rewrites, s, filename, mime_type, config, ""),
+        ),
+        serde_json::Value::Array(items) => serde_json::Value::Array(
+            items
+                .iter()
+                .map(|v| rewrite_json(rewrites, v, filename, mime_type, config))
+                .collect(),
+        ),
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), rewrite_json(rewrites, v, filename, mime_type, config)))
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+// Inserts the value at the dot separated path, making any objects on the way that do not exist.
+fn insert_json_path(
+    root: &mut serde_json::Value,
+    path: &str,
+    value: serde_json::Value,
+) -> Result<(), UploadError> {
+    let mut current = root;
+    let parts: Vec<&str> = path.split('.').collect();
+    for (i, part) in parts.iter().enumerate() {
+        let map = match current {
+            serde_json::Value::Object(map) => map,
+            _ => {
+                return Err(UploadError::io(format!(
+                    "The JSON path {} goes through something which is not an object.",
+                    path
+                )))
+            }
+        };
+        if i == parts.len() - 1 {
+            map.insert(part.to_string(), value);
+            return Ok(());
+        }
+        current = map
+            .entry(part.to_string())
+            .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+    }
+    Ok(())
+}
+
+// Makes a PNG thumbnail of the image which fits within the size specified.
+fn make_thumbnail(source: &UploadSource, max_size: u32) -> Result<Vec<u8>, UploadError> {
+    let img = match image::load_from_memory(&source.read_all()?) {
+        Ok(img) => img,
+        Err(e) => return Err(UploadError::io(format!("Failed to make the thumbnail: {}", e))),
+    };
+    let mut data = Vec::new();
+    match img
+        .thumbnail(max_size, max_size)
+        .write_to(&mut std::io::Cursor::new(&mut data), image::ImageFormat::Png)
+    {
+        Ok(_) => Ok(data),
+        Err(e) => Err(UploadError::io(format!("Failed to make the thumbnail: {}", e))),
+    }
+}
+
+fn consume_all_reader(mut reader: Box<dyn std::io::Read + Send + Sync>) -> Result<Vec<u8>, UploadError> {
     let mut buffer = Vec::new();
     match reader.read_to_end(&mut buffer) {
         Ok(_) => Ok(buffer),
-        Err(e) => Err(e.to_string()),
+        Err(e) => Err(UploadError::network(e.to_string())),
     }
 }
 
-fn process_http_response(
-    template: &str,
+// Rewrites the values of the extra form fields. The fields are sorted so the body is stable.
+fn rewrite_fields(
+    rewrites: &HashMap<String, HTTPRewrite>,
+    fields: &HashMap<String, String>,
     filename: &str,
     mime_type: &str,
-    rewrites: HashMap<String, HTTPRewrite>,
-    http_response: ureq::Response,
-    config: HashMap<String, serde_json::Value>,
-) -> Result<String, String> {
-    // Get the expr map.
-    let mut expr_map = evalexpr::HashMapContext::new();
-    let filename_str = filename.to_string();
-    let mime_str = mime_type.to_string();
-    let rewrites_arc = Arc::new(rewrites);
-    let config_arc = Arc::new(config);
-    expr_map
-        .set_function(
-            "get_rewrite".to_string(),
-            evalexpr::Function::new(move |arg| {
-                let rewrites_ref = rewrites_arc.clone();
-                let str = match arg.as_string() {
-                    Ok(s) => s,
-                    Err(e) => return Err(e),
-                };
-                let rewrite = match rewrites_ref.get(str.as_str()) {
-                    Some(r) => r,
-                    None => {
-                        return Err(EvalexprError::CustomMessage(format!(
-                            "The rewrite {} does not exist.",
-                            str
-                        )))
-                    }
-                };
-                match rewrite {
-                    HTTPRewrite::Config(s) => match config_arc.get(s) {
-                        Some(v) => match v {
-                            serde_json::Value::String(s) => Ok(Value::String(s.to_string())),
-                            _ => Ok(Value::String(v.to_string())),
+    config: &HashMap<String, serde_json::Value>,
+) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = fields
+        .iter()
+        .map(|(k, v)| (k.clone(), rewrite_processor(rewrites, v, filename, mime_type, config, "")))
+        .collect();
+    fields.sort();
+    fields
+}
+
+// Defines a response which expressions can be evaluated against.
+struct ExprResponse {
+    context: evalexpr::HashMapContext,
+    status: u16,
+    body: Arc<Vec<u8>>,
+}
+
+impl ExprResponse {
+    // Reads the response and builds the expression context for it.
+    fn new(
+        file: &FileInfo,
+        rewrites: &HashMap<String, HTTPRewrite>,
+        http_response: ureq::Response,
+        config: &HashMap<String, serde_json::Value>,
+    ) -> Result<Self, UploadError> {
+        // Get the expr map.
+        let mut expr_map = evalexpr::HashMapContext::new();
+        let filename_str = file.filename.clone();
+        let mime_str = file.mime_type.clone();
+        let rewrites_arc = Arc::new(rewrites.clone());
+        let config_arc = Arc::new(config.clone());
+        expr_map
+            .set_function(
+                "get_rewrite".to_string(),
+                evalexpr::Function::new(move |arg| {
+                    let rewrites_ref = rewrites_arc.clone();
+                    let str = match arg.as_string() {
+                        Ok(s) => s,
+                        Err(e) => return Err(e),
+                    };
+                    let rewrite = match rewrites_ref.get(str.as_str()) {
+                        Some(r) => r,
+                        None => {
+                            return Err(EvalexprError::CustomMessage(format!(
+                                "The rewrite {} does not exist.",
+                                str
+                            )))
+                        }
+                    };
+                    match rewrite {
+                        HTTPRewrite::Config(s) => match config_arc.get(s) {
+                            Some(v) => match v {
+                                serde_json::Value::String(s) => Ok(Value::String(s.to_string())),
+                                _ => Ok(Value::String(v.to_string())),
+                            },
+                            None => Ok(Value::String("".to_string())),
                         },
+                        HTTPRewrite::Static(s) => Ok(Value::String(s.to_string())),
+                        HTTPRewrite::Filename => Ok(Value::String(filename_str.clone())),
+                        HTTPRewrite::MIME => Ok(Value::String(mime_str.clone())),
+                        HTTPRewrite::DeletionToken => Ok(Value::String("".to_string())),
+                    }
+                }),
+            )
+            .unwrap();
+        let status = http_response.status();
+        expr_map
+            .set_function(
+                "get_status".to_string(),
+                evalexpr::Function::new(move |_| Ok(Value::Int(status as i64))),
+            )
+            .unwrap();
+
+        // Header names are case insensitive, so store them lowercased.
+        let mut headers = HashMap::new();
+        for key in http_response.headers_names() {
+            headers.insert(
+                key.to_lowercase(),
+                http_response.header(key.as_str()).unwrap().to_string(),
+            );
+        }
+        expr_map
+            .set_function(
+                "get_header".to_string(),
+                evalexpr::Function::new(move |arg| {
+                    let key = match arg.as_string() {
+                        Ok(s) => s,
+                        Err(e) => return Err(e),
+                    };
+                    match headers.get(key.to_lowercase().as_str()) {
+                        Some(v) => Ok(Value::String(v.to_string())),
                         None => Ok(Value::String("".to_string())),
-                    },
-                    HTTPRewrite::Static(s) => Ok(Value::String(s.to_string())),
-                    HTTPRewrite::Filename => Ok(Value::String(filename_str.clone())),
-                    HTTPRewrite::MIME => Ok(Value::String(mime_str.clone())),
-                }
+                    }
+                }),
+            )
+            .unwrap();
+        let body_arc = match consume_all_reader(http_response.into_reader()) {
+            Ok(v) => Arc::new(v),
+            Err(e) => return Err(e),
+        };
+        add_default_functions(&mut expr_map, body_arc.clone());
+        add_file_functions(&mut expr_map, file);
+        Ok(ExprResponse {
+            context: expr_map,
+            status,
+            body: body_arc,
+        })
+    }
+
+    // Evaluates the expression to a string. If this fails, the response was not what the uploader expected, so
+    // the error has the body.
+    fn eval_string(&self, expr: &str) -> Result<String, UploadError> {
+        match evalexpr::eval_with_context(expr, &self.context) {
+            Ok(Value::String(s)) => Ok(s),
+            _ => Err(UploadError::RemoteRejected {
+                status: self.status,
+                body: String::from_utf8_lossy(&self.body).to_string(),
             }),
-        )
-        .unwrap();
-    let mut headers = HashMap::new();
-    for key in http_response.headers_names() {
-        let key_cpy = key.clone();
-        headers.insert(
-            key_cpy,
-            http_response.header(key.as_str()).unwrap().to_string(),
-        );
-    }
-    expr_map
-        .set_function(
-            "get_header".to_string(),
-            evalexpr::Function::new(move |arg| {
-                let key = match arg.as_string() {
-                    Ok(s) => s,
-                    Err(e) => return Err(e),
-                };
-                match headers.get(key.as_str()) {
-                    Some(v) => Ok(Value::String(v.to_string())),
-                    None => Ok(Value::String("".to_string())),
+        }
+    }
+
+    // Turns a response with an error status into an error. The error expression is used to get the message
+    // from the body if it is set and works.
+    fn into_error(self, error_expr: Option<&str>) -> UploadError {
+        let body = match error_expr.and_then(|expr| self.eval_string(expr).ok()) {
+            Some(message) => message,
+            None => String::from_utf8_lossy(&self.body).to_string(),
+        };
+        UploadError::from_status(self.status, body)
+    }
+}
+
+// Defines the result of sending a request. Error statuses are kept so the body can be read.
+type SendResult = Result<ureq::Response, ureq::Error>;
+
+// Defines the longest we will wait before trying a request again.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+// Gets how long the server asked us to wait from the Retry-After header. This can be seconds or a date.
+fn retry_after(response: &ureq::Response) -> Option<Duration> {
+    let value = response.header("retry-after")?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()
+}
+
+// Waits for the duration specified, stopping early if the upload is cancelled.
+fn cancellable_sleep(duration: Duration, ctx: &UploadContext) -> Result<(), UploadError> {
+    let end = Instant::now() + duration;
+    loop {
+        ctx.check_cancelled()?;
+        let now = Instant::now();
+        if now >= end {
+            return Ok(());
+        }
+        std::thread::sleep((end - now).min(Duration::from_millis(100)));
+    }
+}
+
+// Defines the agent, the file, and the settings shared by every request the uploader makes.
+struct HTTPClient<'a> {
+    agent: ureq::Agent,
+    file: FileInfo,
+    max_retries: u32,
+    error_expr: Option<&'a str>,
+}
+
+impl<'a> HTTPClient<'a> {
+    // Builds the client for the uploader. The proxy can use rewrites so it can come from the config.
+    fn new(
+        handler: &'a HTTPUploaderConfig,
+        file: FileInfo,
+        rewrites: &HashMap<String, HTTPRewrite>,
+        config: &HashMap<String, serde_json::Value>,
+    ) -> Result<Self, UploadError> {
+        let network: &HTTPNetworkOptions = &handler.network;
+        let mut builder = ureq::AgentBuilder::new();
+        if let Some(secs) = network.connect_timeout {
+            builder = builder.timeout_connect(Duration::from_secs(secs));
+        }
+        if let Some(secs) = network.read_timeout {
+            builder = builder.timeout_read(Duration::from_secs(secs));
+        }
+        if let Some(proxy) = &network.proxy {
+            let proxy = rewrite_processor(rewrites, proxy, "", "", config, "");
+            if !proxy.trim().is_empty() {
+                match ureq::Proxy::new(proxy.trim()) {
+                    Ok(proxy) => builder = builder.proxy(proxy),
+                    Err(e) => {
+                        return Err(UploadError::Config {
+                            field: None,
+                            message: format!("The proxy {} is not valid: {}", proxy, e),
+                        })
+                    }
                 }
-            }),
-        )
-        .unwrap();
-    let body_arc = match consume_all_reader(http_response.into_reader()) {
-        Ok(v) => Arc::new(v),
-        Err(e) => return Err(e),
-    };
-    add_default_functions(&mut expr_map, body_arc);
+            }
+        }
+        Ok(HTTPClient {
+            agent: builder.build(),
+            file,
+            max_retries: network.max_retries,
+            error_expr: handler.error_expr.as_deref(),
+        })
+    }
+
+    // Sends a request, trying it again if the server responds with a 5xx or 429 status. The request is built
+    // for each attempt since the body has to be read again.
+    fn send(
+        &self,
+        ctx: &UploadContext,
+        mut send: impl FnMut(&ureq::Agent) -> Result<SendResult, UploadError>,
+    ) -> Result<SendResult, UploadError> {
+        let mut attempt = 0;
+        loop {
+            let result = send(&self.agent)?;
+            let delay = match &result {
+                Err(ureq::Error::Status(status, response))
+                    if attempt < self.max_retries && (*status >= 500 || *status == 429) =>
+                {
+                    // Back off exponentially if the server did not say how long to wait.
+                    retry_after(response).unwrap_or_else(|| Duration::from_secs(1 << attempt.min(5)))
+                }
+                _ => return Ok(result),
+            };
+            attempt += 1;
+            cancellable_sleep(delay.min(MAX_RETRY_DELAY), ctx)?;
+        }
+    }
 
-    // Call the template.
-    match evalexpr::eval_with_context(template, &expr_map) {
-        Ok(v) => match v.as_string() {
-            Ok(s) => Ok(s),
-            Err(e) => Err(e.to_string()),
-        },
-        Err(e) => Err(e.to_string()),
+    // Reads the response so expressions can be evaluated against it. If the server responded with an error
+    // status, this is turned into an error with the message from the error expression.
+    fn read_response(
+        &self,
+        result: SendResult,
+        rewrites: &HashMap<String, HTTPRewrite>,
+        config: &HashMap<String, serde_json::Value>,
+        ctx: &UploadContext,
+    ) -> Result<ExprResponse, UploadError> {
+        match result {
+            Ok(response) => ExprResponse::new(&self.file, rewrites, response, config),
+
+            // If the upload was cancelled, the error is from the reader stopping.
+            Err(_) if ctx.is_cancelled() => Err(UploadError::Cancelled),
+            Err(ureq::Error::Status(_, response)) => {
+                let response = ExprResponse::new(&self.file, rewrites, response, config)?;
+                Err(response.into_error(self.error_expr))
+            }
+            Err(e) => Err(e.into()),
+        }
     }
 }
 
+// Sends a request in the chain and captures the values from its response into the rewrites so that later
+// requests can use them. Returns the response so the caller can evaluate expressions against it.
+fn run_step(
+    step: &HTTPStep,
+    client: &HTTPClient,
+    rewrites: &mut HashMap<String, HTTPRewrite>,
+    filename: &str,
+    mime_type: &str,
+    config: &HashMap<String, serde_json::Value>,
+    ctx: &UploadContext,
+) -> Result<ExprResponse, UploadError> {
+    ctx.check_cancelled()?;
+
+    // Build and send the request.
+    let url = rewrite_processor(rewrites, &step.url_template, filename, mime_type, config, "");
+    let result = client.send(ctx, |agent| {
+        let mut req = agent.request(step.method.as_str(), url.as_str());
+        for (key, value) in &step.header_templates {
+            req = req.set(
+                rewrite_processor(rewrites, key, filename, mime_type, config, "").as_str(),
+                rewrite_processor(rewrites, value, filename, mime_type, config, "").as_str(),
+            );
+        }
+
+        // Send the body.
+        Ok(match &step.body {
+            HTTPStepBody::Empty => req.call(),
+            HTTPStepBody::Text(template) => req.send_string(
+                rewrite_processor(rewrites, template, filename, mime_type, config, "").as_str(),
+            ),
+            HTTPStepBody::URLEncoded(fields) => {
+                // Sort the fields so the body is stable.
+                let mut form: Vec<(String, String)> = fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), rewrite_processor(rewrites, v, filename, mime_type, config, "")))
+                    .collect();
+                form.sort();
+                req.send_form(&form.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect::<Vec<_>>())
+            }
+        })
+    })?;
+    let response = client.read_response(result, rewrites, config, ctx)?;
+
+    // Capture the values for later requests.
+    for (key, expr) in &step.captures {
+        let value = response.eval_string(expr)?;
+        rewrites.insert(key.clone(), HTTPRewrite::Static(value));
+    }
+    Ok(response)
+}
+
+// Signs the request if the uploader has signing set up. This is called once the headers and body are built.
+fn maybe_sign(
+    handler: &HTTPUploaderConfig,
+    req: ureq::Request,
+    rewrite: &dyn Fn(&str) -> String,
+    payload: Payload,
+) -> Result<ureq::Request, UploadError> {
+    match &handler.signing {
+        Some(signing) => sign_request(req, signing, rewrite, payload),
+        None => Ok(req),
+    }
+}
+
+// Sends a multipart form. If the request is signed, the form is read into memory first since the signature
+// covers the body.
+fn send_multipart(
+    handler: &HTTPUploaderConfig,
+    req: ureq::Request,
+    rewrite: &dyn Fn(&str) -> String,
+    mut multipart: MultipartBody,
+    ctx: &UploadContext,
+) -> Result<SendResult, UploadError> {
+    let req = req
+        .set("content-type", &multipart.content_type)
+        .set("content-length", &multipart.content_length.to_string());
+    if handler.signing.is_none() {
+        return Ok(req.send(ctx.progress_reader(multipart.reader, multipart.content_length)));
+    }
+    let mut data = Vec::with_capacity(multipart.content_length as usize);
+    multipart.reader.read_to_end(&mut data)?;
+    let req = maybe_sign(handler, req, rewrite, Payload::Bytes(&data))?;
+    Ok(req.send(ctx.progress_reader(Cursor::new(data), multipart.content_length)))
+}
+
 pub fn http(
+    handler: &HTTPUploaderConfig,
     filename: &str,
     mime_type: &str,
-    rewrites: HashMap<String, HTTPRewrite>,
-    url_template: &str,
-    method: &str,
-    header_templates: HashMap<String, String>,
-    body: HTTPBody,
     config: HashMap<String, serde_json::Value>,
-    mut reader: Box<dyn std::io::Read + Send + Sync>,
-    response: &str,
-) -> Result<String, String> {
+    source: &UploadSource,
+    ctx: &UploadContext,
+) -> Result<String, UploadError> {
+    // Run the steps before the upload. The values they capture are added to the rewrites.
+    let mut rewrites = handler.rewrites.clone();
+    let file = FileInfo::new(filename, mime_type, source);
+    let client = HTTPClient::new(handler, file, &rewrites, &config)?;
+    for step in &handler.pre_steps {
+        run_step(step, &client, &mut rewrites, filename, mime_type, &config, ctx)?;
+    }
+
     // Rewrite the URL.
-    let url = rewrite_processor(&rewrites, url_template, filename, mime_type, &config);
-
-    // Build the request.
-    let mut req = ureq::agent().request(method, url.as_str());
-    for (key, value) in header_templates {
-        // Rewrite the header value.
-        let key_rewritten =
-            rewrite_processor(&rewrites, key.as_str(), filename, mime_type, &config);
-        let value_rewritten =
-            rewrite_processor(&rewrites, value.as_str(), filename, mime_type, &config);
-
-        // Add the header.
-        req = req.set(key_rewritten.as_str(), value_rewritten.as_str());
-    }
-
-    // Add the body and make the call.
-    let result = match body {
-        HTTPBody::Raw => {
-            if !req.has("content-type") {
-                // Set the content type if it is not set.
-                req = req.set("content-type", mime_type);
-            }
-            req.send(reader)
+    let url = rewrite_processor(&rewrites, &handler.url_template, filename, mime_type, &config, "");
+
+    // Send the upload. This is built for each attempt since the file has to be read again.
+    let method = handler.method.as_str();
+    let rewrite = |value: &str| rewrite_processor(&rewrites, value, filename, mime_type, &config, "");
+    let result = client.send(ctx, |agent| {
+        // Build the request.
+        let mut req = agent.request(method, url.as_str());
+        for (key, value) in &handler.header_templates {
+            // Rewrite the header value.
+            let key_rewritten =
+                rewrite_processor(&rewrites, key.as_str(), filename, mime_type, &config, "");
+            let value_rewritten =
+                rewrite_processor(&rewrites, value.as_str(), filename, mime_type, &config, "");
+
+            // Add the header.
+            req = req.set(key_rewritten.as_str(), value_rewritten.as_str());
         }
-        HTTPBody::MultipartForm(other_items, body_name) => {
-            // Build the multipart form.
-            let mut multipart_builder = ureq_multipart::MultipartBuilder::new();
-            for (key, value) in other_items {
-                multipart_builder = multipart_builder
-                    .add_text(key.as_str(), value.as_str())
-                    .unwrap();
+
+        // Add the body and make the call.
+        Ok(match handler.body.clone() {
+            HTTPBody::Raw => {
+                if !req.has("content-type") {
+                    // Set the content type if it is not set.
+                    req = req.set("content-type", mime_type);
+                }
+
+                // Stream the file from the disk.
+                let req = req.set("content-length", &source.size().to_string());
+                let req = maybe_sign(handler, req, &rewrite, Payload::Source(source))?;
+                let file = source.open()?;
+                req.send(ctx.progress_reader(file, source.size()))
             }
-            multipart_builder = match multipart_builder.add_stream(
-                &mut reader,
-                body_name.as_str(),
-                Some(filename),
-                Some(Mime::from_str(mime_type).unwrap()),
-            ) {
-                Ok(m) => m,
-                Err(e) => return Err(e.to_string()),
-            };
+            HTTPBody::MultipartForm(other_items, body_name) => {
+                // Build the multipart form. Sort the fields so the body is stable.
+                let fields = rewrite_fields(&rewrites, &other_items, filename, mime_type, &config);
+                let multipart = multipart_stream(
+                    fields.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+                    body_name.as_str(),
+                    filename,
+                    &Mime::from_str(mime_type).unwrap(),
+                    source,
+                )?;
 
-            // Finish the multipart data.
-            let (content_type, multipart) = match multipart_builder.finish() {
-                Ok(m) => m,
-                Err(err) => return Err(err.to_string()),
-            };
-            req = req.set("content-type", &content_type);
+                // Send the request. This streams the file from the disk.
+                send_multipart(handler, req, &rewrite, multipart, ctx)?
+            }
+            HTTPBody::MultipartFiles(other_items, file_fields) => {
+                // Build the files. Thumbnails are made in memory and the original is streamed from the disk.
+                let mime = Mime::from_str(mime_type).unwrap();
+                let thumbnail_mime = mime::IMAGE_PNG;
+                let thumbnail_filename = format!("thumbnail_{}.png", filename);
+                let mut files = Vec::new();
+                for file_field in &file_fields {
+                    files.push(match file_field.kind {
+                        MultipartFileKind::Original => MultipartFile {
+                            field: &file_field.name,
+                            filename,
+                            mime: &mime,
+                            content: MultipartContent::Source(source),
+                        },
+                        MultipartFileKind::Thumbnail(max_size) => MultipartFile {
+                            field: &file_field.name,
+                            filename: &thumbnail_filename,
+                            mime: &thumbnail_mime,
+                            content: MultipartContent::Bytes(make_thumbnail(source, max_size)?),
+                        },
+                    });
+                    ctx.check_cancelled()?;
+                }
 
-            // Send the request.
-            req.send_bytes(&multipart)
-        }
-        HTTPBody::URLEncoded(other_items, field) => {
-            // Get the body.
-            let body = match consume_all_reader(reader) {
-                Ok(v) => v,
-                Err(e) => return Err(e),
-            };
+                // Build the multipart form and send it.
+                let fields = rewrite_fields(&rewrites, &other_items, filename, mime_type, &config);
+                let multipart = multipart_stream_files(
+                    fields.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+                    files,
+                )?;
+                send_multipart(handler, req, &rewrite, multipart, ctx)?
+            }
+            HTTPBody::JSON(template, path) => {
+                // Get the body. This has to be in memory since it is encoded into the JSON.
+                let body = source.read_all()?;
+                ctx.check_cancelled()?;
 
-            // Get the query items.
-            let mut query: Vec<(String, String)> =
-                other_items.into_iter().collect::<Vec<(String, String)>>();
+                // Build the JSON and put the file in it.
+                let mut json = rewrite_json(&rewrites, &template, filename, mime_type, &config);
+                insert_json_path(
+                    &mut json,
+                    &path,
+                    serde_json::Value::String(general_purpose::STANDARD.encode(body)),
+                )?;
 
-            // Figure out how to handle the body.
-            let key = field.name;
-            let value = match field.encoding_type {
-                URLEncodingType::Hex => body
-                    .iter()
-                    .map(|b| format!("{:02x}", b))
-                    .collect::<String>(),
-                URLEncodingType::B64URL => general_purpose::URL_SAFE.encode(body),
-                URLEncodingType::B64 => general_purpose::STANDARD.encode(body),
-            };
+                // Set the content type if it is not set and send the request.
+                if !req.has("content-type") {
+                    req = req.set("content-type", "application/json");
+                }
+                let json = json.to_string();
+                maybe_sign(handler, req, &rewrite, Payload::Bytes(json.as_bytes()))?.send_string(&json)
+            }
+            HTTPBody::URLEncoded(other_items, field) => {
+                // Get the body. This has to be in memory since it is encoded into the form.
+                let body = source.read_all()?;
+                ctx.check_cancelled()?;
 
-            // Add the body to the query and then sort.
-            query.push((key, value));
-            query.sort_by(|a, b| a.0.cmp(&b.0));
-
-            // Send the request.
-            if method == "GET" {
-                req.query_pairs(query.iter().map(|(k, v)| (k.as_str(), v.as_str())))
-                    .send_string("")
-            } else {
-                // Build the query string.
-                let query_string = query
-                    .iter()
-                    .map(|(k, v)| format!("{}={}", urlencoding::encode(k), urlencoding::encode(v)))
-                    .collect::<Vec<String>>()
-                    .join("&");
+                // Get the query items.
+                let mut query = rewrite_fields(&rewrites, &other_items, filename, mime_type, &config);
+
+                // Figure out how to handle the body.
+                let key = field.name;
+                let value = match field.encoding_type {
+                    URLEncodingType::Hex => body
+                        .iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect::<String>(),
+                    URLEncodingType::B64URL => general_purpose::URL_SAFE.encode(body),
+                    URLEncodingType::B64 => general_purpose::STANDARD.encode(body),
+                };
+
+                // Add the body to the query and then sort.
+                query.push((key, value));
+                query.sort_by(|a, b| a.0.cmp(&b.0));
 
                 // Send the request.
-                req.send_string(query_string.as_str())
+                if method == "GET" {
+                    let req = req.query_pairs(query.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+                    maybe_sign(handler, req, &rewrite, Payload::Bytes(b""))?.send_string("")
+                } else {
+                    // Build the query string.
+                    let query_string = query
+                        .iter()
+                        .map(|(k, v)| format!("{}={}", urlencoding::encode(k), urlencoding::encode(v)))
+                        .collect::<Vec<String>>()
+                        .join("&");
+
+                    // Send the request.
+                    maybe_sign(handler, req, &rewrite, Payload::Bytes(query_string.as_bytes()))?
+                        .send_string(query_string.as_str())
+                }
+            }
+        })
+    })?;
+
+    // Handle the result.
+    let mut response = client.read_response(result, &rewrites, &config, ctx)?;
+    for (key, expr) in &handler.upload_captures {
+        let value = response.eval_string(expr)?;
+        rewrites.insert(key.clone(), HTTPRewrite::Static(value));
+    }
+
+    // Run the steps after the upload. The expressions are evaluated against the last response.
+    for step in &handler.post_steps {
+        response = run_step(step, &client, &mut rewrites, filename, mime_type, &config, ctx)?;
+    }
+
+    // Get the URL and the deletion token. The file was uploaded, so a token that cannot be found is not
+    // an error.
+    let url = response.eval_string(&handler.response_expr)?;
+    if let Some(delete_expr) = &handler.delete_expr {
+        if let Ok(deletion_token) = response.eval_string(delete_expr) {
+            if !deletion_token.is_empty() {
+                ctx.set_deletion_token(deletion_token);
             }
         }
+    }
+    Ok(url)
+}
+
+// Gets the host of a HTTP or HTTPS URL in lowercase.
+fn http_host(url: &str) -> Option<String> {
+    let uri = uriparse::URI::try_from(url.trim()).ok()?;
+    match uri.scheme().as_str() {
+        "http" | "https" => Some(uri.host()?.to_string().to_lowercase()),
+        _ => None,
+    }
+}
+
+// Deletes a file uploaded with a HTTP uploader. If there is no delete request, the deletion token is a URL
+// which deletes the file when it is requested. That URL came from the server, so it is only requested if it
+// is on the same host as the upload, and it is never signed so the credentials are not sent anywhere else.
+pub fn http_delete(
+    handler: &HTTPUploaderConfig,
+    deletion_token: &str,
+    config: &HashMap<String, serde_json::Value>,
+) -> Result<(), UploadError> {
+    let rewrites = &handler.rewrites;
+    let client = HTTPClient::new(handler, FileInfo::default(), rewrites, config)?;
+    let ctx = UploadContext::background();
+    let rewrite = |value: &str| rewrite_processor(rewrites, value, "", "", config, deletion_token);
+    let result = match &handler.delete_request {
+        Some(delete_request) => {
+            // Build the request from the templates.
+            let url = rewrite(&delete_request.url_template);
+            client.send(&ctx, |agent| {
+                let mut req = agent.request(delete_request.method.as_str(), url.as_str());
+                for (key, value) in &delete_request.header_templates {
+                    req = req.set(rewrite(key).as_str(), rewrite(value).as_str());
+                }
+                Ok(maybe_sign(handler, req, &rewrite, Payload::Bytes(b""))?.call())
+            })?
+        }
+        None => {
+            let upload_host = http_host(&rewrite(&handler.url_template));
+            if upload_host.is_none() || http_host(deletion_token) != upload_host {
+                return Err(UploadError::local(
+                    "The deletion URL is not on the same host as the upload, so it was not requested.",
+                ));
+            }
+            client.send(&ctx, |agent| Ok(agent.get(deletion_token).call()))?
+        }
     };
+    client.read_response(result, rewrites, config, &ctx)?;
+    Ok(())
+}
 
-    // Handle the result.
-    match result {
-        Ok(v) => process_http_response(response, filename, mime_type, rewrites, v, config),
-        Err(e) => Err(e.to_string()),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uploaders::mock_server::{config, MockServer, Response};
+    use serde_json::json;
+
+    // Builds the handler from the JSON the uploader builder saves. The upload URL is on the server.
+    fn handler(server: &MockServer, value: serde_json::Value) -> HTTPUploaderConfig {
+        let mut handler = json!({
+            "rewrites": {"{api_key}": {"type": "Config", "value": "api_key"}},
+            "url_template": format!("{}/upload", server.url()),
+            "method": "POST",
+            "header_templates": {},
+            "body": {"type": "Raw"},
+            "response_expr": "json_path(\"url\")",
+        });
+        handler.as_object_mut().unwrap().extend(value.as_object().unwrap().clone());
+        serde_json::from_value(handler).unwrap()
+    }
+
+    // Uploads a small text file with the handler and returns the URL and deletion token.
+    fn upload(handler: &HTTPUploaderConfig) -> (Result<String, UploadError>, Option<String>) {
+        let source = UploadSource::from_bytes(b"hello", "a.txt").unwrap();
+        let ctx = UploadContext::background();
+        let result = http(handler, "a.txt", "text/plain", config(json!({"api_key": "k3y"})), &source, &ctx);
+        (result, ctx.take_deletion_token())
+    }
+
+    #[test]
+    fn uploads_multipart_forms_with_rewrites() {
+        let server = MockServer::start(|_| Response::json(json!({"url": "https://i.example.com/a.txt", "del": "t0k"})));
+        let handler = handler(&server, json!({
+            "header_templates": {"Authorization": "Bearer {api_key}"},
+            "body": {"type": "MultipartForm", "value": [{"key": "{api_key}"}, "file"]},
+            "delete_expr": "json_path(\"del\")",
+        }));
+        let (result, deletion_token) = upload(&handler);
+        assert_eq!(result.unwrap(), "https://i.example.com/a.txt");
+        assert_eq!(deletion_token.as_deref(), Some("t0k"));
+
+        let req = &server.requests()[0];
+        assert_eq!((req.method.as_str(), req.path.as_str()), ("POST", "/upload"));
+        assert_eq!(req.header("Authorization"), Some("Bearer k3y"));
+        let body = req.body_str();
+        assert!(body.contains("name=\"key\"\r\n\r\nk3y\r\n"), "{}", body);
+        assert!(body.contains("name=\"file\"; filename=\"a.txt\"\r\nContent-Type: text/plain\r\n\r\nhello\r\n"), "{}", body);
+    }
+
+    #[test]
+    fn streams_raw_bodies() {
+        let server = MockServer::start(|_| Response::json(json!({"url": "https://i.example.com/a.txt"})));
+        let handler = handler(&server, json!({"method": "PUT"}));
+        assert_eq!(upload(&handler).0.unwrap(), "https://i.example.com/a.txt");
+        let req = &server.requests()[0];
+        assert_eq!(req.method, "PUT");
+        assert_eq!(req.body, b"hello");
+        assert_eq!(req.header("Content-Type"), Some("text/plain"));
+    }
+
+    #[test]
+    fn puts_the_file_in_json_bodies() {
+        let server = MockServer::start(|_| Response::json(json!({"url": "https://i.example.com/a.txt"})));
+        let handler = handler(&server, json!({
+            "body": {"type": "JSON", "value": [{"key": "{api_key}", "file": {}}, "file.data"]},
+        }));
+        upload(&handler).0.unwrap();
+        let req = &server.requests()[0];
+        assert_eq!(req.header("Content-Type"), Some("application/json"));
+        let body: serde_json::Value = serde_json::from_slice(&req.body).unwrap();
+        assert_eq!(body, json!({"key": "k3y", "file": {"data": "aGVsbG8="}}));
+    }
+
+    #[test]
+    fn sends_url_encoded_get_requests() {
+        let server = MockServer::start(|_| Response::json(json!({"url": "https://i.example.com/a.txt"})));
+        let handler = handler(&server, json!({
+            "method": "GET",
+            "body": {"type": "URLEncoded", "value": [{"key": "{api_key}"}, {"name": "data", "encoding_type": "hex"}]},
+        }));
+        upload(&handler).0.unwrap();
+        let req = &server.requests()[0];
+        assert_eq!(req.method, "GET");
+        assert_eq!(req.query, "data=68656c6c6f&key=k3y");
+    }
+
+    #[test]
+    fn runs_steps_and_passes_captures_along() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/slot" => Response::json(json!({"slot": "s1"})),
+            "/upload/s1" => Response::json(json!({"id": "f1"})),
+            "/finish/f1" => Response::json(json!({"url": "https://i.example.com/f1"})),
+            _ => Response::new(404),
+        });
+        let handler = handler(&server, json!({
+            "url_template": format!("{}/upload/{{slot}}", server.url()),
+            "pre_steps": [{
+                "url_template": format!("{}/slot", server.url()),
+                "method": "POST",
+                "header_templates": {"X-Key": "{api_key}"},
+                "body": {"type": "Text", "value": "size please"},
+                "captures": {"{slot}": "json_path(\"slot\")"},
+            }],
+            "upload_captures": {"{id}": "json_path(\"id\")"},
+            "post_steps": [{
+                "url_template": format!("{}/finish/{{id}}", server.url()),
+                "method": "POST",
+                "header_templates": {},
+                "body": {"type": "Empty"},
+                "captures": {},
+            }],
+        }));
+        assert_eq!(upload(&handler).0.unwrap(), "https://i.example.com/f1");
+        let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(paths, vec!["/slot", "/upload/s1", "/finish/f1"]);
+        assert_eq!(server.requests()[0].header("X-Key"), Some("k3y"));
+        assert_eq!(server.requests()[0].body_str(), "size please");
+    }
+
+    #[test]
+    fn retries_server_errors() {
+        // Fail the first attempt.
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let server = MockServer::start(move |_| {
+            match attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => Response::new(503).header("Retry-After", "0"),
+                _ => Response::json(json!({"url": "https://i.example.com/a.txt"})),
+            }
+        });
+        let handler = handler(&server, json!({"network": {"max_retries": 2}}));
+        assert_eq!(upload(&handler).0.unwrap(), "https://i.example.com/a.txt");
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].body, b"hello");
+    }
+
+    #[test]
+    fn uses_the_error_expression() {
+        let server = MockServer::start(|_| Response::new(400).body(r#"{"error": "The file is too big."}"#));
+        let handler = handler(&server, json!({"error_expr": "json_path(\"error\")"}));
+        match upload(&handler).0 {
+            Err(UploadError::RemoteRejected { status, body }) => {
+                assert_eq!((status, body.as_str()), (400, "The file is too big."))
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn reports_responses_without_a_url() {
+        let server = MockServer::start(|_| Response::new(200).body("<html>maintenance</html>"));
+        let handler = handler(&server, json!({}));
+        assert!(matches!(upload(&handler).0, Err(UploadError::RemoteRejected { status: 200, .. })));
+    }
+
+    #[test]
+    fn deletes_with_the_delete_request() {
+        let server = MockServer::start(|_| Response::new(204));
+        let handler = handler(&server, json!({
+            "rewrites": {
+                "{api_key}": {"type": "Config", "value": "api_key"},
+                "{token}": {"type": "DeletionToken"},
+            },
+            "delete_expr": "json_path(\"del\")",
+            "delete_request": {
+                "url_template": format!("{}/files/{{token}}", server.url()),
+                "method": "DELETE",
+                "header_templates": {"Authorization": "Bearer {api_key}"},
+            },
+        }));
+        http_delete(&handler, "t0k", &config(json!({"api_key": "k3y"}))).unwrap();
+        let req = &server.requests()[0];
+        assert_eq!((req.method.as_str(), req.path.as_str()), ("DELETE", "/files/t0k"));
+        assert_eq!(req.header("Authorization"), Some("Bearer k3y"));
+    }
+
+    #[test]
+    fn requests_deletion_urls_on_the_upload_host_without_signing() {
+        let server = MockServer::start(|_| Response::new(200));
+        let handler = handler(&server, json!({
+            "delete_expr": "json_path(\"del\")",
+            "signing": {
+                "type": "HMAC",
+                "key_template": "{api_key}",
+                "signature_header": "X-Signature",
+                "timestamp_header": "X-Timestamp",
+                "encoding": "hex",
+            },
+        }));
+        let cfg = config(json!({"api_key": "k3y"}));
+        http_delete(&handler, &format!("{}/delete/t0k", server.url()), &cfg).unwrap();
+        let req = &server.requests()[0];
+        assert_eq!((req.method.as_str(), req.path.as_str()), ("GET", "/delete/t0k"));
+        assert_eq!(req.header("X-Signature"), None);
+    }
+
+    #[test]
+    fn refuses_deletion_urls_on_other_hosts() {
+        let server = MockServer::start(|_| Response::new(200));
+        let other = MockServer::start(|_| Response::new(200));
+        let handler = handler(&server, json!({"delete_expr": "json_path(\"del\")"}));
+        let cfg = config(json!({}));
+        let other_url = format!("http://localhost:{}/delete/t0k", other.host().rsplit(':').next().unwrap());
+        assert!(http_delete(&handler, &other_url, &cfg).is_err());
+        assert!(http_delete(&handler, "file:///etc/passwd", &cfg).is_err());
+        assert!(other.requests().is_empty());
+        assert!(server.requests().is_empty());
     }
 }
@@ -1,5 +1,9 @@
+use crate::uploaders::UploadSource;
 use base64::Engine;
 use evalexpr::ContextWithMutableFunctions;
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
 
 macro_rules! func {
@@ -10,6 +14,124 @@ macro_rules! func {
     };
 }
 
+// Defines the file being uploaded so expressions can use it. This is empty when a file is being deleted.
+#[derive(Clone, Default)]
+pub struct FileInfo {
+    pub filename: String,
+    pub mime_type: String,
+    pub size: u64,
+    pub dimensions: Option<(u32, u32)>,
+}
+
+impl FileInfo {
+    // Gets the information for the file. The dimensions are only read from the header of images.
+    pub fn new(filename: &str, mime_type: &str, source: &UploadSource) -> Self {
+        let dimensions = match mime_type.starts_with("image/") {
+            true => image::image_dimensions(source.path()).ok(),
+            false => None,
+        };
+        FileInfo {
+            filename: filename.to_string(),
+            mime_type: mime_type.to_string(),
+            size: source.size(),
+            dimensions,
+        }
+    }
+}
+
+// Creates an error with the message specified.
+fn custom_err(message: impl Into<String>) -> evalexpr::EvalexprError {
+    evalexpr::EvalexprError::CustomMessage(message.into())
+}
+
+// Turns a value into a string. Strings are used as is rather than quoted.
+fn value_to_string(value: &evalexpr::Value) -> String {
+    match value {
+        evalexpr::Value::String(s) => s.clone(),
+        evalexpr::Value::Empty => "".to_string(),
+        v => v.to_string(),
+    }
+}
+
+// Gets the arguments as a list. A single argument is a list of one.
+fn args_list(arg: &evalexpr::Value) -> Vec<evalexpr::Value> {
+    match arg {
+        evalexpr::Value::Tuple(args) => args.clone(),
+        evalexpr::Value::Empty => Vec::new(),
+        v => vec![v.clone()],
+    }
+}
+
+// Encodes the bytes as lowercase hex.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Encodes a digest with the encoding name the user gave. The default is hex.
+fn encode_digest(
+    bytes: &[u8],
+    encoding: Option<&evalexpr::Value>,
+) -> Result<String, evalexpr::EvalexprError> {
+    let encoding = match encoding {
+        Some(v) => v.as_string()?,
+        None => "hex".to_string(),
+    };
+    match encoding.as_str() {
+        "hex" => Ok(hex(bytes)),
+        "base64" => Ok(base64::engine::general_purpose::STANDARD.encode(bytes)),
+        "base64url" => Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)),
+        e => Err(custom_err(format!("The encoding {} is not supported.", e))),
+    }
+}
+
+// Fills in each {} in the template with the arguments in order. {{ and }} are literal braces.
+fn format_template(
+    template: &str,
+    args: &[evalexpr::Value],
+) -> Result<String, evalexpr::EvalexprError> {
+    let mut result = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                result.push(c);
+                chars.next();
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                match args.next() {
+                    Some(v) => result += &value_to_string(v),
+                    None => return Err(custom_err("There are more {} in the template than arguments.")),
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
+// Selects the first element matching the CSS selector in the HTML. If an attribute is given, its value is
+// returned, otherwise the text of the element is.
+fn select_html(html: &str, selector: &str, attribute: Option<&str>) -> Result<String, String> {
+    let selector = match scraper::Selector::parse(selector) {
+        Ok(s) => s,
+        Err(e) => return Err(format!("The selector is not valid: {}", e)),
+    };
+    let document = scraper::Html::parse_document(html);
+    let element = match document.select(&selector).next() {
+        Some(e) => e,
+        None => return Err("The selector did not match anything.".to_string()),
+    };
+    match attribute {
+        Some(attribute) => match element.value().attr(attribute) {
+            Some(v) => Ok(v.to_string()),
+            None => Err(format!("The element does not have the attribute {}.", attribute)),
+        },
+        None => Ok(element.text().collect::<String>().trim().to_string()),
+    }
+}
+
 fn serde2evalexpr(value: serde_json::Value) -> Result<evalexpr::Value, evalexpr::EvalexprError> {
     match value {
         serde_json::Value::Null => Ok(evalexpr::Value::Empty),
@@ -165,11 +287,11 @@ pub fn add_default_functions(expr_map: &mut evalexpr::HashMapContext, body_arc:
             }
         };
 
-        // Figure out what to return.
+        // Figure out what to return. Node sets are unordered, so sort them into the order they are in the body.
         match value {
             sxd_xpath::Value::Nodeset(nodes) => {
                 let mut vec = Vec::new();
-                for node in nodes {
+                for node in nodes.document_order() {
                     vec.push(evalexpr::Value::String(node.string_value()));
                 }
                 Ok(evalexpr::Value::Tuple(vec))
@@ -219,6 +341,71 @@ pub fn add_default_functions(expr_map: &mut evalexpr::HashMapContext, body_arc:
         ))
     });
 
+    // Add regex_match. The group can either be a index or a name.
+    let ar = body_arc.clone();
+    func!(expr_map, "regex_match", move |arg| {
+        // Get the pattern and group.
+        let args = match arg.as_fixed_len_tuple(2) {
+            Ok(a) => a,
+            Err(e) => return Err(e),
+        };
+        let pattern = match args[0].as_string() {
+            Ok(s) => s,
+            Err(e) => return Err(e),
+        };
+        let re = match regex::Regex::new(&pattern) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(evalexpr::EvalexprError::CustomMessage(format!(
+                    "Failed to compile the regex: {}.",
+                    e
+                )))
+            }
+        };
+
+        // Process the body as a string and find the match.
+        let body = String::from_utf8_lossy(&ar);
+        let captures = match re.captures(&body) {
+            Some(c) => c,
+            None => {
+                return Err(evalexpr::EvalexprError::CustomMessage(
+                    "The regex did not match the body.".to_string(),
+                ))
+            }
+        };
+        let group = match &args[1] {
+            evalexpr::Value::Int(i) if *i >= 0 => captures.get(*i as usize),
+            evalexpr::Value::String(s) => captures.name(s),
+            _ => {
+                return Err(evalexpr::EvalexprError::CustomMessage(
+                    "The regex group must be a positive integer or a string.".to_string(),
+                ))
+            }
+        };
+        match group {
+            Some(m) => Ok(evalexpr::Value::String(m.as_str().to_string())),
+            None => Err(evalexpr::EvalexprError::CustomMessage(
+                "The regex group did not match.".to_string(),
+            )),
+        }
+    });
+
+    // Add html_select. The second argument is the attribute to get, otherwise the text is returned.
+    let ar = body_arc.clone();
+    func!(expr_map, "html_select", move |arg| {
+        let args = args_list(arg);
+        let (selector, attribute) = match args.as_slice() {
+            [selector] => (selector.as_string()?, None),
+            [selector, attribute] => (selector.as_string()?, Some(attribute.as_string()?)),
+            _ => return Err(custom_err("html_select takes a selector and an optional attribute.")),
+        };
+        let body = String::from_utf8_lossy(&ar);
+        match select_html(&body, &selector, attribute.as_deref()) {
+            Ok(v) => Ok(evalexpr::Value::String(v)),
+            Err(e) => Err(custom_err(e)),
+        }
+    });
+
     // Add string_body.
     func!(expr_map, "string_body", move |_| {
         let body_arc = body_arc.clone();
@@ -234,6 +421,88 @@ pub fn add_default_functions(expr_map: &mut evalexpr::HashMapContext, body_arc:
         Ok(evalexpr::Value::String(body.to_string()))
     });
 
+    // Add format. Each {} in the template is replaced with the next argument.
+    func!(expr_map, "format", |arg| {
+        let args = args_list(arg);
+        let template = match args.first() {
+            Some(t) => t.as_string()?,
+            None => return Err(custom_err("format takes a template and the arguments for it.")),
+        };
+        Ok(evalexpr::Value::String(format_template(&template, &args[1..])?))
+    });
+
+    // Add concat.
+    func!(expr_map, "concat", |arg| {
+        Ok(evalexpr::Value::String(
+            args_list(arg).iter().map(value_to_string).collect(),
+        ))
+    });
+
+    // Add hex_encode.
+    func!(expr_map, "hex_encode", |arg| {
+        Ok(evalexpr::Value::String(hex(arg.as_string()?.as_bytes())))
+    });
+
+    // Add sha256. The optional second argument is the encoding of the digest.
+    func!(expr_map, "sha256", |arg| {
+        let args = args_list(arg);
+        let value = match args.first() {
+            Some(v) => value_to_string(v),
+            None => return Err(custom_err("sha256 takes a value and an optional encoding.")),
+        };
+        let digest = Sha256::digest(value.as_bytes());
+        Ok(evalexpr::Value::String(encode_digest(&digest, args.get(1))?))
+    });
+
+    // Add hmac_sha256. The optional third argument is the encoding of the signature.
+    func!(expr_map, "hmac_sha256", |arg| {
+        let args = args_list(arg);
+        if args.len() < 2 || args.len() > 3 {
+            return Err(custom_err("hmac_sha256 takes a key, a message, and an optional encoding."));
+        }
+        let mut mac = match Hmac::<Sha256>::new_from_slice(value_to_string(&args[0]).as_bytes()) {
+            Ok(m) => m,
+            Err(e) => return Err(custom_err(format!("The HMAC key is not valid: {}.", e))),
+        };
+        mac.update(value_to_string(&args[1]).as_bytes());
+        let signature = mac.finalize().into_bytes();
+        Ok(evalexpr::Value::String(encode_digest(&signature, args.get(2))?))
+    });
+
+    // Add now. This is the UNIX timestamp in seconds.
+    func!(expr_map, "now", |_| Ok(evalexpr::Value::Int(chrono::Utc::now().timestamp())));
+
+    // Add format_date. This formats a UNIX timestamp in UTC with strftime syntax.
+    func!(expr_map, "format_date", |arg| {
+        let args = arg.as_fixed_len_tuple(2)?;
+        let timestamp = args[0].as_int()?;
+        let format = args[1].as_string()?;
+        let date = match chrono::DateTime::from_timestamp(timestamp, 0) {
+            Some(d) => d,
+            None => return Err(custom_err(format!("The timestamp {} is out of range.", timestamp))),
+        };
+        let mut formatted = String::new();
+        match std::fmt::write(&mut formatted, format_args!("{}", date.format(&format))) {
+            Ok(_) => Ok(evalexpr::Value::String(formatted)),
+            Err(_) => Err(custom_err(format!("The date format {} is not valid.", format))),
+        }
+    });
+
+    // Add random_string. This is alphanumeric.
+    func!(expr_map, "random_string", |arg| {
+        let length = arg.as_int()?;
+        if !(0..=4096).contains(&length) {
+            return Err(custom_err("The length must be between 0 and 4096."));
+        }
+        Ok(evalexpr::Value::String(
+            rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(length as usize)
+                .map(char::from)
+                .collect(),
+        ))
+    });
+
     // Add url_encode.
     func!(expr_map, "url_encode", |arg| {
         let str = match arg.as_string() {
@@ -245,3 +514,252 @@ pub fn add_default_functions(expr_map: &mut evalexpr::HashMapContext, body_arc:
         ))
     });
 }
+
+// Adds the functions to get information about the file being uploaded.
+pub fn add_file_functions(expr_map: &mut evalexpr::HashMapContext, file: &FileInfo) {
+    // Add filename.
+    let filename = file.filename.clone();
+    func!(expr_map, "filename", move |_| Ok(evalexpr::Value::String(filename.clone())));
+
+    // Add file_size.
+    let size = file.size as i64;
+    func!(expr_map, "file_size", move |_| Ok(evalexpr::Value::Int(size)));
+
+    // Add file_metadata. Width and height are empty if the file is not an image.
+    let file = file.clone();
+    func!(expr_map, "file_metadata", move |arg| {
+        let key = arg.as_string()?;
+        Ok(match key.as_str() {
+            "filename" => evalexpr::Value::String(file.filename.clone()),
+            "extension" => evalexpr::Value::String(match file.filename.rsplit_once('.') {
+                Some((_, ext)) => ext.to_string(),
+                None => "".to_string(),
+            }),
+            "mime_type" => evalexpr::Value::String(file.mime_type.clone()),
+            "size" => evalexpr::Value::Int(file.size as i64),
+            "width" => match file.dimensions {
+                Some((w, _)) => evalexpr::Value::Int(w as i64),
+                None => evalexpr::Value::Empty,
+            },
+            "height" => match file.dimensions {
+                Some((_, h)) => evalexpr::Value::Int(h as i64),
+                None => evalexpr::Value::Empty,
+            },
+            _ => return Err(custom_err(format!("The metadata key {} does not exist.", key))),
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evalexpr::{EvalexprError, Value};
+
+    // Evaluates the expression against the body with the functions added. The file is a 640x480 PNG.
+    fn eval(body: &str, expr: &str) -> Result<Value, EvalexprError> {
+        let mut expr_map = evalexpr::HashMapContext::new();
+        add_default_functions(&mut expr_map, Arc::new(body.as_bytes().to_vec()));
+        add_file_functions(
+            &mut expr_map,
+            &FileInfo {
+                filename: "shot.png".to_string(),
+                mime_type: "image/png".to_string(),
+                size: 1234,
+                dimensions: Some((640, 480)),
+            },
+        );
+        evalexpr::eval_with_context(expr, &expr_map)
+    }
+
+    // Evaluates the expression and expects a string.
+    fn eval_str(body: &str, expr: &str) -> String {
+        match eval(body, expr) {
+            Ok(Value::String(s)) => s,
+            res => panic!("{} returned {:?}", expr, res),
+        }
+    }
+
+    fn str_value(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn json_path() {
+        let body = r#"{"files": [{"url": "https://i.example.com/a.png", "size": 12, "ratio": 0.5}], "ok": true}"#;
+        assert_eq!(eval_str(body, r#"json_path("files.0.url")"#), "https://i.example.com/a.png");
+        assert_eq!(eval(body, r#"json_path("files.0.size")"#).unwrap(), Value::Int(12));
+        assert_eq!(eval(body, r#"json_path("files.0.ratio")"#).unwrap(), Value::Float(0.5));
+        assert_eq!(eval(body, r#"json_path("ok")"#).unwrap(), Value::Boolean(true));
+        assert_eq!(eval(r#"["a", "b"]"#, "json_path(1)").unwrap(), str_value("b"));
+        assert_eq!(
+            eval(r#"["a", null]"#, "json_path()").unwrap(),
+            Value::Tuple(vec![str_value("a"), Value::Empty])
+        );
+        assert!(eval(body, r#"json_path("files.1.url")"#).is_err());
+        assert!(eval(body, r#"json_path("missing")"#).is_err());
+        assert!(eval("not json", r#"json_path("url")"#).is_err());
+        assert!(eval(r#"["a"]"#, "json_path(-1)").is_err());
+    }
+
+    #[test]
+    fn xml_path() {
+        let body = "<files><file url=\"https://i.example.com/a.png\"/><file url=\"https://i.example.com/b.png\"/></files>";
+        assert_eq!(
+            eval(body, r#"xml_path("/files/file/@url")"#).unwrap(),
+            Value::Tuple(vec![str_value("https://i.example.com/a.png"), str_value("https://i.example.com/b.png")])
+        );
+        assert_eq!(eval_str(body, r#"xml_path("string(/files/file[2]/@url)")"#), "https://i.example.com/b.png");
+        assert_eq!(eval(body, r#"xml_path("count(/files/file)")"#).unwrap(), Value::Float(2.0));
+        assert_eq!(eval(body, r#"xml_path("count(/files/file) = 2")"#).unwrap(), Value::Boolean(true));
+        assert!(eval("<open>", r#"xml_path("/open")"#).is_err());
+        assert!(eval(body, r#"xml_path("///")"#).is_err());
+    }
+
+    #[test]
+    fn base64_encode_and_decode() {
+        assert_eq!(eval_str("", r#"base64_encode("hello?")"#), "aGVsbG8/");
+        assert_eq!(eval_str("", r#"base64_decode("aGVsbG8/")"#), "hello?");
+        assert!(eval("", r#"base64_decode("not base64!")"#).is_err());
+        assert!(eval("", r#"base64_decode("/w==")"#).is_err());
+        assert!(eval("", "base64_encode(1)").is_err());
+    }
+
+    #[test]
+    fn regex_match() {
+        let body = "Uploaded to https://i.example.com/abc.png in 2s";
+        assert_eq!(eval_str(body, r#"regex_match("https://\\S+", 0)"#), "https://i.example.com/abc.png");
+        assert_eq!(eval_str(body, r#"regex_match("example.com/(\\w+)", 1)"#), "abc");
+        assert_eq!(eval_str(body, r#"regex_match("in (?P<secs>\\d+)s", "secs")"#), "2");
+        assert!(eval(body, r#"regex_match("nothing", 0)"#).is_err());
+        assert!(eval(body, r#"regex_match("(", 0)"#).is_err());
+        assert!(eval(body, r#"regex_match("in", 1)"#).is_err());
+        assert!(eval(body, r#"regex_match("in", -1)"#).is_err());
+    }
+
+    #[test]
+    fn html_select() {
+        let body = "<html><body><a class=\"link\" href=\"https://i.example.com/a.png\"> Image </a></body></html>";
+        assert_eq!(eval_str(body, r#"html_select("a.link")"#), "Image");
+        assert_eq!(eval_str(body, r#"html_select("a.link", "href")"#), "https://i.example.com/a.png");
+        assert!(eval(body, r#"html_select("a.link", "title")"#).is_err());
+        assert!(eval(body, r#"html_select("img")"#).is_err());
+        assert!(eval(body, r#"html_select("<<")"#).is_err());
+        assert!(eval(body, "html_select()").is_err());
+    }
+
+    #[test]
+    fn string_body() {
+        assert_eq!(eval_str("https://i.example.com/a.png", "string_body()"), "https://i.example.com/a.png");
+        let mut expr_map = evalexpr::HashMapContext::new();
+        add_default_functions(&mut expr_map, Arc::new(vec![0xff, 0xfe]));
+        assert!(evalexpr::eval_with_context("string_body()", &expr_map).is_err());
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(eval_str("", r#"format("{}/{}.png?w={}", "https://i.example.com", "abc", 640)"#), "https://i.example.com/abc.png?w=640");
+        assert_eq!(eval_str("", r#"format("{{}}{}", "a")"#), "{}a");
+        assert_eq!(eval_str("", r#"format("plain")"#), "plain");
+        assert!(eval("", r#"format("{}{}", "a")"#).is_err());
+        assert!(eval("", "format()").is_err());
+    }
+
+    #[test]
+    fn concat() {
+        assert_eq!(eval_str("", r#"concat("a", 1, "b", 2.5)"#), "a1b2.5");
+        assert_eq!(eval_str("", r#"concat("a")"#), "a");
+    }
+
+    #[test]
+    fn hex_encode() {
+        assert_eq!(eval_str("", r#"hex_encode("hi!")"#), "686921");
+        assert!(eval("", "hex_encode(1)").is_err());
+    }
+
+    #[test]
+    fn sha256() {
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(eval_str("", r#"sha256("abc")"#), abc);
+        assert_eq!(eval_str("", r#"sha256("abc", "hex")"#), abc);
+        assert_eq!(eval_str("", r#"sha256("abc", "base64")"#), "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=");
+        assert_eq!(eval_str("", r#"sha256("abc", "base64url")"#), "ungWv48Bz-pBQUDeXa4iI7ADYaOWF3qctBD_YfIAFa0");
+        assert!(eval("", r#"sha256("abc", "binary")"#).is_err());
+        assert!(eval("", "sha256()").is_err());
+    }
+
+    #[test]
+    fn hmac_sha256() {
+        // This is test case 2 from RFC 4231.
+        assert_eq!(
+            eval_str("", r#"hmac_sha256("Jefe", "what do ya want for nothing?")"#),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            eval_str("", r#"hmac_sha256("Jefe", "what do ya want for nothing?", "base64")"#),
+            "W9zBRr9gdU5qBCQmCJV1x1oAPwidJzmDnexYuWTsOEM="
+        );
+        assert!(eval("", r#"hmac_sha256("Jefe")"#).is_err());
+        assert!(eval("", r#"hmac_sha256("Jefe", "a", "hex", "b")"#).is_err());
+    }
+
+    #[test]
+    fn now() {
+        let before = chrono::Utc::now().timestamp();
+        let now = eval("", "now()").unwrap().as_int().unwrap();
+        assert!(now >= before && now <= chrono::Utc::now().timestamp());
+    }
+
+    #[test]
+    fn format_date() {
+        assert_eq!(eval_str("", r#"format_date(1700000000, "%Y-%m-%d %H:%M:%S")"#), "2023-11-14 22:13:20");
+        assert!(eval("", r#"format_date(1700000000, "%Q")"#).is_err());
+        assert!(eval("", r#"format_date(9223372036854775807, "%Y")"#).is_err());
+        assert!(eval("", "format_date(1700000000)").is_err());
+    }
+
+    #[test]
+    fn random_string() {
+        let s = eval_str("", "random_string(32)");
+        assert_eq!(s.len(), 32);
+        assert!(s.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(s, eval_str("", "random_string(32)"));
+        assert_eq!(eval_str("", "random_string(0)"), "");
+        assert!(eval("", "random_string(4097)").is_err());
+        assert!(eval("", "random_string(-1)").is_err());
+    }
+
+    #[test]
+    fn url_encode() {
+        assert_eq!(eval_str("", r#"url_encode("a b/c?d=é")"#), "a%20b%2Fc%3Fd%3D%C3%A9");
+        assert!(eval("", "url_encode(1)").is_err());
+    }
+
+    #[test]
+    fn file_functions() {
+        assert_eq!(eval_str("", "filename()"), "shot.png");
+        assert_eq!(eval("", "file_size()").unwrap(), Value::Int(1234));
+        assert_eq!(eval_str("", r#"file_metadata("filename")"#), "shot.png");
+        assert_eq!(eval_str("", r#"file_metadata("extension")"#), "png");
+        assert_eq!(eval_str("", r#"file_metadata("mime_type")"#), "image/png");
+        assert_eq!(eval("", r#"file_metadata("size")"#).unwrap(), Value::Int(1234));
+        assert_eq!(eval("", r#"file_metadata("width")"#).unwrap(), Value::Int(640));
+        assert_eq!(eval("", r#"file_metadata("height")"#).unwrap(), Value::Int(480));
+        assert!(eval("", r#"file_metadata("owner")"#).is_err());
+
+        // Files which are not images have no dimensions, and files without a dot have no extension.
+        let mut expr_map = evalexpr::HashMapContext::new();
+        add_file_functions(
+            &mut expr_map,
+            &FileInfo {
+                filename: "notes".to_string(),
+                mime_type: "text/plain".to_string(),
+                size: 0,
+                dimensions: None,
+            },
+        );
+        let eval = |expr| evalexpr::eval_with_context(expr, &expr_map).unwrap();
+        assert_eq!(eval(r#"file_metadata("extension")"#), str_value(""));
+        assert_eq!(eval(r#"file_metadata("width")"#), Value::Empty);
+        assert_eq!(eval(r#"file_metadata("height")"#), Value::Empty);
+    }
+}
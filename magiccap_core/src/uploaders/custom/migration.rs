@@ -0,0 +1,33 @@
+use super::config_structure::{UploaderVersions, LATEST_UPLOADER_VERSION};
+
+// Migrates a V1 uploader to V2. The metadata is new, so it is added empty. The HTTP steps were accepted by V1
+// as optional fields, so they do not need to change.
+fn v1_to_v2(uploader: &mut serde_json::Map<String, serde_json::Value>) {
+    uploader
+        .entry("metadata")
+        .or_insert_with(|| serde_json::json!({}));
+}
+
+// Migrates a serialized custom uploader to the latest version. Returns the uploader and if anything changed.
+pub fn migrate_uploader(mut uploader: serde_json::Value) -> Result<(serde_json::Value, bool), String> {
+    let obj = match uploader.as_object_mut() {
+        Some(v) => v,
+        None => return Err("The custom uploader is not an object.".to_string()),
+    };
+    let mut version: UploaderVersions = match obj.get("version") {
+        Some(v) => match serde_json::from_value(v.clone()) {
+            Ok(v) => v,
+            Err(_) => return Err(format!("The custom uploader version {} is not supported.", v)),
+        },
+        None => return Err("The custom uploader does not have a version.".to_string()),
+    };
+
+    // Run each migration in order. New migrations go after the last one.
+    let migrated = version != LATEST_UPLOADER_VERSION;
+    if version == UploaderVersions::V1 {
+        v1_to_v2(obj);
+        version = UploaderVersions::V2;
+    }
+    obj.insert("version".to_string(), serde_json::to_value(version).unwrap());
+    Ok((uploader, migrated))
+}
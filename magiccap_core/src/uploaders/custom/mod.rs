@@ -1,12 +1,26 @@
 // Exports all the logic for HTTP based custom uploaders.
 mod http_expr_functions;
 mod http;
+mod signing;
 pub use http::{HTTPBody, HTTPRewrite};
 
 // Loads the logic for PHP based custom uploaders.
 mod php_bootstrapping;
 mod php;
+pub use php::{clear_php_log, read_php_log};
 
 // Exports the JSON structure for custom uploaders.
 mod config_structure;
-pub use config_structure::{CustomUploader, IntoUploader};
+pub use config_structure::{check_uploader_name, CustomUploader, IntoUploader};
+
+// Exports the migrations between custom uploader versions.
+mod migration;
+pub use migration::migrate_uploader;
+
+// Exports the importer for ShareX custom uploaders.
+mod sxcu;
+pub use sxcu::sxcu_to_uploader;
+
+// Exports the shareable bundle format for custom uploaders.
+mod bundle;
+pub use bundle::{export_bundle, read_bundle, signing_public_key, trust_signer, UploaderBundle};
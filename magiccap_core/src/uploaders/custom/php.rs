@@ -1,6 +1,18 @@
-use std::{collections::HashMap, path::PathBuf};
-use crate::statics::CONFIG_FOLDER;
-use super::php_bootstrapping::{setup_php_binary, validate_php_metadata};
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+use crate::{statics::CONFIG_FOLDER, uploaders::{UploadContext, UploadError, UploadSource}};
+use super::{
+    config_structure::{PHPLimits, PHPUploaderConfig},
+    php_bootstrapping::{setup_php_binary, validate_php_metadata},
+};
+
+// Defines the largest a log can get before the oldest half is dropped.
+const MAX_LOG_SIZE: usize = 256 * 1024;
 
 fn random_string() -> String {
     use rand::Rng;
@@ -13,58 +25,70 @@ fn random_string() -> String {
 }
 
 // Handle generating the PHP ini file.
-fn php_ini_gen(temp_folder: &PathBuf) -> String {
+fn php_ini_gen(temp_folder: &PathBuf, limits: &PHPLimits) -> String {
     return "disable_functions=exec,passthru,shell_exec,system,proc_open,popen,curl_exec,curl_multi_exec,parse_ini_file,show_source
 allow_url_include=off
-open_basedir=".to_string() + temp_folder.to_str().unwrap() + "/content";
+memory_limit=".to_string() + &limits.memory_limit.to_string() + "M
+max_execution_time=" + &limits.timeout.to_string() + "
+open_basedir=" + temp_folder.to_str().unwrap() + "/content";
 }
 
-// Handles copying the data from the perma_data_folder to the data_folder. Returns an error if one occurs.
-async fn copy_data(perma_data_folder: &str, data_folder: &PathBuf) -> Result<(), String> {
-    use tokio::fs;
-
-    // Get the directory contents.
-    let mut dir = match fs::read_dir(perma_data_folder).await {
-        Ok(d) => d,
+// Handles copying a folder and everything in it. Returns an error if one occurs.
+fn copy_dir(from: &Path, to: &Path) -> Result<(), String> {
+    // Create the folder we are copying to.
+    match std::fs::create_dir_all(to) {
+        Ok(_) => (),
         Err(e) => return Err(e.to_string()),
     };
 
     // Iterate over the directory contents.
-    while let Some(entry) = dir.next_entry().await.map_err(|e| e.to_string())? {
-        // Get the entry path.
-        let entry_path = entry.path();
-
-        // Get the metadata.
-        let metadata = match fs::metadata(&entry_path).await {
-            Ok(m) => m,
+    let dir = match std::fs::read_dir(from) {
+        Ok(d) => d,
+        Err(e) => return Err(e.to_string()),
+    };
+    for entry in dir {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(e) => return Err(e.to_string()),
+        };
+        let file_type = match entry.file_type() {
+            Ok(t) => t,
             Err(e) => return Err(e.to_string()),
         };
 
-        // If the entry is a directory, create it.
-        if metadata.is_dir() {
-            match fs::create_dir(data_folder.join(entry.file_name())).await {
+        // Copy folders recursively and files directly. Symlinks are skipped so the script cannot use them to
+        // get outside of the sandbox.
+        let target = to.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_file() {
+            match std::fs::copy(entry.path(), &target) {
                 Ok(_) => (),
                 Err(e) => return Err(e.to_string()),
             };
-            continue;
         }
-
-        // If the entry is a file, copy it.
-        match fs::copy(&entry_path, data_folder.join(entry.file_name())).await {
-            Ok(_) => (),
-            Err(e) => return Err(e.to_string()),
-        };
     }
 
     // Return no error.
     return Ok(());
 }
 
+// Gets the total size of the files in a folder.
+fn dir_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
 // Handle building the PHP temporary folder.
 fn build_php_temp_folder(
-    perma_data_folder: &str, php_code: &str, filename: &str,
+    perma_data_folder: &Path, handler: &PHPUploaderConfig, filename: &str,
     config: HashMap<String, serde_json::Value>,
-    mut reader: Box<dyn std::io::Read + Send + Sync>,
+    source: &UploadSource,
 ) -> Result<PathBuf, String> {
     // Create the temporary folder.
     let temp_folder = std::env::temp_dir().join(
@@ -75,118 +99,223 @@ fn build_php_temp_folder(
         Err(e) => return Err(e.to_string()),
     };
 
+    // Do the rest of the setup, removing the folder if anything fails.
+    let result = fill_php_temp_folder(&temp_folder, perma_data_folder, handler, filename, config, source);
+    if let Err(e) = result {
+        let _ = std::fs::remove_dir_all(&temp_folder);
+        return Err(e);
+    }
+
+    // Return the temporary folder.
+    return Ok(temp_folder);
+}
+
+// Writes the script, ini file, config, data, and screenshot to the PHP temporary folder.
+fn fill_php_temp_folder(
+    temp_folder: &PathBuf, perma_data_folder: &Path, handler: &PHPUploaderConfig, filename: &str,
+    config: HashMap<String, serde_json::Value>,
+    source: &UploadSource,
+) -> Result<(), String> {
     // Create the PHP script.
-    let php_script = temp_folder.clone().join("script.php");
-    match std::fs::write(&php_script, php_code) {
+    let php_script = temp_folder.join("script.php");
+    match std::fs::write(&php_script, &handler.code) {
         Ok(_) => (),
-        Err(e) => {
-            let _ = std::fs::remove_dir_all(&temp_folder);
-            return Err(e.to_string());
-        },
+        Err(e) => return Err(e.to_string()),
     };
 
     // Create the PHP ini file.
     let php_ini = temp_folder.join("php.ini");
-    match std::fs::write(&php_ini, php_ini_gen(&temp_folder)) {
+    match std::fs::write(&php_ini, php_ini_gen(temp_folder, &handler.limits)) {
         Ok(_) => (),
-        Err(e) => {
-            let _ = std::fs::remove_dir_all(&temp_folder);
-            return Err(e.to_string());
-        },
+        Err(e) => return Err(e.to_string()),
     };
 
-    // Create the content and data folders.
+    // Create the content folder.
     let content_folder = temp_folder.join("content");
     match std::fs::create_dir(&content_folder) {
         Ok(_) => (),
-        Err(e) => {
-            let _ = std::fs::remove_dir_all(&temp_folder);
-            return Err(e.to_string());
-        },
+        Err(e) => return Err(e.to_string()),
     };
 
     // Write config.json to the content folder.
     let config_json = content_folder.join("config.json");
     match std::fs::write(&config_json, serde_json::to_string(&config).unwrap()) {
         Ok(_) => (),
-        Err(e) => {
-            let _ = std::fs::remove_dir_all(&temp_folder);
-            return Err(e.to_string());
-        },
+        Err(e) => return Err(e.to_string()),
     };
 
-    // Try to copy the permanent data folder to the temporary data folder.
-    let data_folder = content_folder.clone().join("data");
-    tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .unwrap()
-        .block_on(copy_data(perma_data_folder, &data_folder))?;
+    // Copy the permanent data folder to the temporary data folder. The script works on the copy, so nothing
+    // it does is kept unless it succeeds.
+    copy_dir(perma_data_folder, &content_folder.join("data"))?;
 
-    // Create the screenshot file.
-    let screenshot_path = content_folder.join(filename);
-    let mut file = match std::fs::File::create(&screenshot_path) {
-        Ok(f) => f,
+    // Copy the screenshot file.
+    match std::fs::copy(source.path(), content_folder.join(filename)) {
+        Ok(_) => (),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    // Return no error.
+    return Ok(());
+}
+
+// Replaces the permanent data folder with the data the script left. The data is copied next to the permanent
+// folder first so the swap is two renames, and the old data is put back if the second rename fails.
+fn commit_data(data_dir: &Path, perma_data_folder: &Path, data_quota: u64) -> Result<(), String> {
+    // Make sure the data is within the quota.
+    let size = dir_size(data_dir);
+    if data_quota > 0 && size > data_quota * 1024 * 1024 {
+        return Err(format!(
+            "The data folder is {:.1} MB which is over the {} MB quota, so the changes were not saved.",
+            size as f64 / (1024.0 * 1024.0), data_quota,
+        ));
+    }
+
+    // Copy the data next to the permanent folder.
+    let parent = perma_data_folder.parent().unwrap();
+    let name = perma_data_folder.file_name().unwrap().to_string_lossy();
+    let staging_folder = parent.join(format!(".{}.staging", name));
+    let old_folder = parent.join(format!(".{}.old", name));
+    let _ = std::fs::remove_dir_all(&staging_folder);
+    let _ = std::fs::remove_dir_all(&old_folder);
+    if let Err(e) = copy_dir(data_dir, &staging_folder) {
+        let _ = std::fs::remove_dir_all(&staging_folder);
+        return Err(e);
+    }
+
+    // Swap the folders.
+    match std::fs::rename(perma_data_folder, &old_folder) {
+        Ok(_) => (),
         Err(e) => {
-            let _ = std::fs::remove_dir_all(&temp_folder);
+            let _ = std::fs::remove_dir_all(&staging_folder);
             return Err(e.to_string());
         },
     };
-    match std::io::copy(&mut reader, &mut file) {
+    match std::fs::rename(&staging_folder, perma_data_folder) {
         Ok(_) => (),
         Err(e) => {
-            let _ = std::fs::remove_dir_all(&temp_folder);
+            let _ = std::fs::rename(&old_folder, perma_data_folder);
+            let _ = std::fs::remove_dir_all(&staging_folder);
             return Err(e.to_string());
         },
     };
-
-    // Return the temporary folder.
-    return Ok(temp_folder);
+    let _ = std::fs::remove_dir_all(&old_folder);
+    return Ok(());
 }
 
-// Handles cleaning up the PHP temporary folder. There being a String means an error occurred.
-fn php_cleanup(temp_folder: PathBuf, perma_data_folder: PathBuf) -> Option<String> {
-    let data_dir = temp_folder.join("content").join("data");
-    if data_dir.exists() {
-        // Try removing the perma_data_folder.
-        match std::fs::remove_dir_all(&perma_data_folder) {
-            Ok(_) => (),
-            Err(e) => return Some(e.to_string()),
-        };
+// Gets the path to the log for a uploader.
+fn log_path(uploader_id: &str) -> PathBuf {
+    CONFIG_FOLDER.join("custom_uploader_logs").join(uploader_id.to_string() + ".log")
+}
 
-        // Move the data_dir to the perma_data_folder.
-        match std::fs::rename(data_dir, perma_data_folder) {
-            Ok(_) => (),
-            Err(e) => return Some(e.to_string()),
-        };
+// Adds an entry to the log for a uploader. The oldest half of the log is dropped if it gets too big.
+fn append_log(uploader_id: &str, message: &str) {
+    let path = log_path(uploader_id);
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
     }
-    let _ = std::fs::remove_dir_all(&temp_folder);
-    return None;
+    let mut log = std::fs::read_to_string(&path).unwrap_or_default();
+    log += &format!(
+        "[{}] {}\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), message.trim_end(),
+    );
+    if log.len() > MAX_LOG_SIZE {
+        let mut cut = log.len() - MAX_LOG_SIZE / 2;
+        while !log.is_char_boundary(cut) {
+            cut += 1;
+        }
+        log = log[cut..].to_string();
+    }
+    if let Err(e) = std::fs::write(&path, log) {
+        eprintln!("Failed to write the log for {}: {}", uploader_id, e);
+    }
+}
+
+// Reads the log for a uploader. This is blank if nothing has been logged.
+pub fn read_php_log(uploader_id: &str) -> String {
+    std::fs::read_to_string(log_path(uploader_id)).unwrap_or_default()
+}
+
+// Clears the log for a uploader.
+pub fn clear_php_log(uploader_id: &str) {
+    let _ = std::fs::remove_file(log_path(uploader_id));
+}
+
+// Runs the PHP binary until it exits, it times out, or the upload is cancelled. Returns the exit status, the
+// stdout, and the stderr.
+fn run_php(
+    mut cmd: Command, timeout: u64, ctx: &UploadContext,
+) -> Result<(std::process::ExitStatus, Vec<u8>, Vec<u8>), UploadError> {
+    let mut process = match cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn() {
+        Ok(p) => p,
+        Err(e) => return Err(UploadError::io(format!("Failed to start PHP: {}", e))),
+    };
+
+    // Read the stdout and stderr in threads so the pipes do not fill up.
+    let mut stdout = process.stdout.take().unwrap();
+    let stdout_thread = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stdout.read_to_end(&mut buf);
+        buf
+    });
+    let mut stderr = process.stderr.take().unwrap();
+    let stderr_thread = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stderr.read_to_end(&mut buf);
+        buf
+    });
+
+    // Wait for the process, killing it if it runs for too long or the upload is cancelled.
+    let started = Instant::now();
+    let status = loop {
+        if ctx.is_cancelled() {
+            let _ = process.kill();
+            let _ = process.wait();
+            return Err(UploadError::Cancelled);
+        }
+        if timeout > 0 && started.elapsed() >= Duration::from_secs(timeout) {
+            let _ = process.kill();
+            let _ = process.wait();
+            return Err(UploadError::io(format!(
+                "The PHP script did not finish within {} seconds.", timeout,
+            )));
+        }
+        match process.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) => std::thread::sleep(Duration::from_millis(50)),
+            Err(e) => {
+                let _ = process.kill();
+                return Err(UploadError::io(e.to_string()));
+            },
+        }
+    };
+    let stdout = stdout_thread.join().unwrap_or_default();
+    let stderr = stderr_thread.join().unwrap_or_default();
+    return Ok((status, stdout, stderr));
 }
 
 // Handle the function that does the PHP upload.
 pub fn php(
-    uploader_id: &str, php_code: &str,
+    uploader_id: &str, handler: &PHPUploaderConfig,
     config: HashMap<String, serde_json::Value>,
-    filename: &str, reader: Box<dyn std::io::Read + Send + Sync>,
-) -> Result<String, String> {
+    filename: &str, source: &UploadSource, ctx: &UploadContext,
+) -> Result<String, UploadError> {
     // Get the PHP binary path.
     let php_path = CONFIG_FOLDER.join("php");
     let php_path = match php_path.metadata() {
         Ok(_) => {
             match validate_php_metadata(php_path) {
                 Ok(s) => s,
-                Err(e) => return Err(e),
+                Err(e) => return Err(UploadError::io(e)),
             }
         },
         Err(e) => {
             if e.kind() == std::io::ErrorKind::NotFound {
                 match setup_php_binary(php_path) {
                     Ok(s) => s,
-                    Err(e) => return Err(e),
+                    Err(e) => return Err(UploadError::io(e)),
                 }
             } else {
-                return Err(e.to_string());
+                return Err(UploadError::io(e.to_string()));
             }
         }
     };
@@ -198,16 +327,16 @@ pub fn php(
     if !perma_data_folder.exists() {
         match std::fs::create_dir_all(&perma_data_folder) {
             Ok(_) => (),
-            Err(e) => return Err(e.to_string()),
+            Err(e) => return Err(UploadError::io(e.to_string())),
         }
     }
 
     // Build the PHP temporary folder.
     let temp_folder = match build_php_temp_folder(
-        perma_data_folder.to_str().unwrap(), php_code, filename, config, reader,
+        &perma_data_folder, handler, filename, config, source,
     ) {
         Ok(p) => p,
-        Err(e) => return Err(e),
+        Err(e) => return Err(UploadError::io(e)),
     };
 
     // Defines all of the paths we now know.
@@ -218,45 +347,70 @@ pub fn php(
     let screenshot_path = content_folder.join(filename);
     let config_json = content_folder.join("config.json");
 
-    // Execute the PHP script and get the output.
-    let output = std::process::Command::new(php_path)
-        .arg("-c")
+    // Execute the PHP script and get the output. DATA_FOLDER is what the builder template uses, so both are set.
+    let mut cmd = Command::new(php_path);
+    cmd.arg("-c")
         .arg(php_ini)
         .arg(php_script)
-        .env("DATA_DIR", data_folder)
+        .env("DATA_DIR", &data_folder)
+        .env("DATA_FOLDER", &data_folder)
         .env("SCREENSHOT_PATH", screenshot_path)
-        .env("CONFIG_JSON_PATH", config_json)
-        .output();
+        .env("CONFIG_JSON_PATH", config_json);
+    let output = run_php(cmd, handler.limits.timeout, ctx);
 
-    // In all cases, do the cleanup.
-    if let Some(e) = php_cleanup(temp_folder, perma_data_folder) {
-        return Err(e);
-    }
+    // Get the URL and save the data if the script ran successfully.
+    let result = handle_php_output(uploader_id, output, &data_folder, &perma_data_folder, &handler.limits);
 
+    // In all cases, remove the temporary folder.
+    let _ = std::fs::remove_dir_all(&temp_folder);
+    return result;
+}
+
+// Handles the output of the PHP script. The stderr is logged and the data is only saved if the script worked.
+fn handle_php_output(
+    uploader_id: &str,
+    output: Result<(std::process::ExitStatus, Vec<u8>, Vec<u8>), UploadError>,
+    data_folder: &Path, perma_data_folder: &Path, limits: &PHPLimits,
+) -> Result<String, UploadError> {
     // Unwrap the result.
-    let output = match output {
+    let (status, stdout, stderr) = match output {
         Ok(o) => o,
-        Err(e) => return Err(e.to_string()),
+        Err(e) => {
+            append_log(uploader_id, &e.to_string());
+            return Err(e);
+        },
     };
+    let stderr = String::from_utf8_lossy(&stderr).to_string();
+    let mut log = format!("The PHP script exited with {}.", status);
+    if !stderr.trim().is_empty() {
+        log += "\n";
+        log += stderr.trim_end();
+    }
+    append_log(uploader_id, &log);
 
     // Check if the PHP script ran successfully.
-    if !output.status.success() {
-        return Err(format!(
-            "The PHP script failed with the following output: {}",
-            String::from_utf8_lossy(&output.stderr),
-        ));
+    if !status.success() {
+        return Err(UploadError::io(format!(
+            "The PHP script failed with the following output: {}", stderr,
+        )));
     }
 
     // Get the output as a string and ensure it is a valid URL.
-    let output_str = match String::from_utf8(output.stdout) {
+    let output_str = match String::from_utf8(stdout) {
         Ok(s) => s,
-        Err(e) => return Err(e.to_string()),
+        Err(e) => return Err(UploadError::io(e.to_string())),
     };
     let url = match uriparse::URI::try_from(output_str.trim()) {
-        Ok(u) => u,
-        Err(e) => return Err(e.to_string()),
+        Ok(u) => u.to_string(),
+        Err(e) => return Err(UploadError::io(e.to_string())),
     };
 
+    // Save the data. The file is already uploaded at this point, so a failure is logged rather than failing
+    // the upload.
+    if let Err(e) = commit_data(data_folder, perma_data_folder, limits.data_quota) {
+        append_log(uploader_id, &format!("Failed to save the data folder: {}", e));
+    }
+
     // Return the URL.
-    Ok(url.to_string())
+    Ok(url)
 }
@@ -0,0 +1,466 @@
+use crate::uploaders::{UploadError, UploadSource};
+use base64::{engine::general_purpose, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Defines the hash AWS uses for the payload when it is not signed.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+// Defines how a HMAC signature is encoded in the header.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureEncoding {
+    Hex,
+    Base64,
+}
+
+// Defines how requests are signed. The templates can use rewrites so the keys can come from the config.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum HTTPSigning {
+    // Signs "METHOD\nPATH\nTIMESTAMP\nBODY_SHA256" with HMAC-SHA256. The path includes the query string and the
+    // timestamp is the UNIX timestamp in seconds, which is sent in the timestamp header.
+    HMAC {
+        key_template: String,
+        signature_header: String,
+        timestamp_header: String,
+        encoding: SignatureEncoding,
+    },
+
+    // Signs the request with AWS Signature Version 4. If unsigned_payload is set, the body is not hashed, which
+    // S3 allows and is faster for large files.
+    SigV4 {
+        access_key_id_template: String,
+        secret_access_key_template: String,
+        #[serde(default)]
+        session_token_template: Option<String>,
+        region_template: String,
+        service: String,
+        #[serde(default)]
+        unsigned_payload: bool,
+    },
+}
+
+// Defines the body of the request being signed.
+pub enum Payload<'a> {
+    Bytes(&'a [u8]),
+    Source(&'a UploadSource),
+}
+
+impl Payload<'_> {
+    // Hashes the payload with SHA-256 as lowercase hex. Sources are streamed from the disk.
+    fn sha256_hex(&self) -> Result<String, UploadError> {
+        match self {
+            Payload::Bytes(data) => Ok(hex(&Sha256::digest(data))),
+            Payload::Source(source) => match crate::dedupe::hash_source(source) {
+                Some(hash) => Ok(hash),
+                None => Err(UploadError::io("Failed to read the file to sign the request.")),
+            },
+        }
+    }
+}
+
+// Encodes the bytes as lowercase hex.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Computes HMAC-SHA256 of the data with the key.
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    // HMAC accepts keys of any length, so this cannot fail.
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+// Encodes a value the way SigV4 wants. Only the unreserved characters from RFC 3986 are left alone.
+fn aws_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(b as char),
+            _ => encoded += &format!("%{:02X}", b),
+        }
+    }
+    encoded
+}
+
+// Gets the URL the request will be sent to.
+fn request_url(req: &ureq::Request) -> Result<ureq::RequestUrl, UploadError> {
+    match req.request_url() {
+        Ok(url) => Ok(url),
+        Err(e) => Err(UploadError::Config {
+            field: None,
+            message: format!("The URL is not valid: {}", e),
+        }),
+    }
+}
+
+// Builds the SigV4 canonical request. The headers must already be lowercase and sorted.
+fn sigv4_canonical_request(
+    method: &str,
+    path: &str,
+    query: &[(String, String)],
+    headers: &[(String, String)],
+    payload_hash: &str,
+) -> String {
+    // Sort the query by the encoded key and then value.
+    let mut query: Vec<(String, String)> = query
+        .iter()
+        .map(|(k, v)| (aws_encode(k), aws_encode(v)))
+        .collect();
+    query.sort();
+    let canonical_query = query
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<String>>()
+        .join("&");
+
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(k, v)| format!("{}:{}\n", k, v.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(k, _)| k.as_str())
+        .collect::<Vec<&str>>()
+        .join(";");
+    format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        if path.is_empty() { "/" } else { path },
+        canonical_query,
+        canonical_headers,
+        signed_headers,
+        payload_hash,
+    )
+}
+
+// Computes the SigV4 signature for the canonical request. The date is in the yyyymmddThhmmssZ format.
+fn sigv4_signature(
+    secret_access_key: &str,
+    amz_date: &str,
+    region: &str,
+    service: &str,
+    canonical_request: &str,
+) -> String {
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes())),
+    );
+    let k_date = hmac_sha256(format!("AWS4{}", secret_access_key).as_bytes(), date.as_bytes());
+    let k_region = hmac_sha256(&k_date, region.as_bytes());
+    let k_service = hmac_sha256(&k_region, service.as_bytes());
+    let k_signing = hmac_sha256(&k_service, b"aws4_request");
+    hex(&hmac_sha256(&k_signing, string_to_sign.as_bytes()))
+}
+
+// Signs the request. The rewrite function fills in the templates. This should be called after every other
+// header is set since the signature covers them.
+pub fn sign_request(
+    mut req: ureq::Request,
+    signing: &HTTPSigning,
+    rewrite: &dyn Fn(&str) -> String,
+    payload: Payload,
+) -> Result<ureq::Request, UploadError> {
+    let url = request_url(&req)?;
+    let url = url.as_url();
+    match signing {
+        HTTPSigning::HMAC {
+            key_template,
+            signature_header,
+            timestamp_header,
+            encoding,
+        } => {
+            // Build the path with the query string.
+            let path = match url.query() {
+                Some(query) => format!("{}?{}", url.path(), query),
+                None => url.path().to_string(),
+            };
+
+            // Sign it.
+            let timestamp = chrono::Utc::now().timestamp();
+            let string_to_sign = format!(
+                "{}\n{}\n{}\n{}",
+                req.method(),
+                path,
+                timestamp,
+                payload.sha256_hex()?,
+            );
+            let signature = hmac_sha256(rewrite(key_template).as_bytes(), string_to_sign.as_bytes());
+            let signature = match encoding {
+                SignatureEncoding::Hex => hex(&signature),
+                SignatureEncoding::Base64 => general_purpose::STANDARD.encode(signature),
+            };
+            Ok(req
+                .set(timestamp_header, &timestamp.to_string())
+                .set(signature_header, &signature))
+        }
+        HTTPSigning::SigV4 {
+            access_key_id_template,
+            secret_access_key_template,
+            session_token_template,
+            region_template,
+            service,
+            unsigned_payload,
+        } => {
+            // Set the headers AWS needs.
+            let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+            let payload_hash = match unsigned_payload {
+                true => UNSIGNED_PAYLOAD.to_string(),
+                false => payload.sha256_hex()?,
+            };
+            req = req
+                .set("x-amz-date", &amz_date)
+                .set("x-amz-content-sha256", &payload_hash);
+            if let Some(session_token_template) = session_token_template {
+                let session_token = rewrite(session_token_template);
+                if !session_token.is_empty() {
+                    req = req.set("x-amz-security-token", &session_token);
+                }
+            }
+
+            // Get the headers to sign. This is the host, the content type, and any AWS headers.
+            let host = match (url.host_str(), url.port()) {
+                (Some(host), Some(port)) => format!("{}:{}", host, port),
+                (Some(host), None) => host.to_string(),
+                (None, _) => {
+                    return Err(UploadError::Config {
+                        field: None,
+                        message: "The URL does not have a host to sign.".to_string(),
+                    })
+                }
+            };
+            let mut headers = vec![("host".to_string(), host)];
+            for name in req.header_names() {
+                let lower = name.to_lowercase();
+                if lower == "content-type" || lower.starts_with("x-amz-") {
+                    headers.push((lower, req.header(&name).unwrap_or("").to_string()));
+                }
+            }
+            headers.sort();
+
+            // Sign the request.
+            let query: Vec<(String, String)> = url
+                .query_pairs()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            let canonical_request =
+                sigv4_canonical_request(req.method(), url.path(), &query, &headers, &payload_hash);
+            let region = rewrite(region_template);
+            let signature = sigv4_signature(
+                &rewrite(secret_access_key_template),
+                &amz_date,
+                &region,
+                service,
+                &canonical_request,
+            );
+            let signed_headers = headers
+                .iter()
+                .map(|(k, _)| k.as_str())
+                .collect::<Vec<&str>>()
+                .join(";");
+            let authorization = format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}/{}/{}/aws4_request, SignedHeaders={}, Signature={}",
+                rewrite(access_key_id_template),
+                &amz_date[..8],
+                region,
+                service,
+                signed_headers,
+                signature,
+            );
+            Ok(req.set("authorization", &authorization))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Defines the credentials and date used by the AWS SigV4 test suite.
+    const SECRET_ACCESS_KEY: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+    const AMZ_DATE: &str = "20150830T123600Z";
+
+    // Defines the hash of an empty payload.
+    const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    fn pairs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+        items.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        // Test case 1.
+        assert_eq!(
+            hex(&hmac_sha256(&[0x0b; 20], b"Hi There")),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+
+        // Test case 2.
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+
+        // Test case 6, where the key is longer than the block size.
+        assert_eq!(
+            hex(&hmac_sha256(&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First")),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn sigv4_get_vanilla() {
+        let headers = pairs(&[("host", "example.amazonaws.com"), ("x-amz-date", AMZ_DATE)]);
+        let canonical_request = sigv4_canonical_request("GET", "/", &[], &headers, EMPTY_SHA256);
+        assert_eq!(
+            canonical_request,
+            format!(
+                "GET\n/\n\nhost:example.amazonaws.com\nx-amz-date:{}\n\nhost;x-amz-date\n{}",
+                AMZ_DATE, EMPTY_SHA256
+            )
+        );
+        assert_eq!(
+            sigv4_signature(SECRET_ACCESS_KEY, AMZ_DATE, "us-east-1", "service", &canonical_request),
+            "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn sigv4_get_vanilla_query_order_key_case() {
+        let headers = pairs(&[("host", "example.amazonaws.com"), ("x-amz-date", AMZ_DATE)]);
+        let query = pairs(&[("Param2", "value2"), ("Param1", "value1")]);
+        let canonical_request = sigv4_canonical_request("GET", "/", &query, &headers, EMPTY_SHA256);
+        assert_eq!(canonical_request.lines().nth(2), Some("Param1=value1&Param2=value2"));
+        assert_eq!(
+            sigv4_signature(SECRET_ACCESS_KEY, AMZ_DATE, "us-east-1", "service", &canonical_request),
+            "b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"
+        );
+    }
+
+    #[test]
+    fn sigv4_get_vanilla_query_unreserved() {
+        let unreserved = "-._~0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+        let headers = pairs(&[("host", "example.amazonaws.com"), ("x-amz-date", AMZ_DATE)]);
+        let query = pairs(&[(unreserved, unreserved)]);
+        let canonical_request = sigv4_canonical_request("GET", "/", &query, &headers, EMPTY_SHA256);
+        assert_eq!(
+            sigv4_signature(SECRET_ACCESS_KEY, AMZ_DATE, "us-east-1", "service", &canonical_request),
+            "9c3e54bfcdf0b19771a7f523ee5669cdf59bc7cc0884027167c21bb143a40197"
+        );
+    }
+
+    #[test]
+    fn sigv4_get_vanilla_utf8_query() {
+        let headers = pairs(&[("host", "example.amazonaws.com"), ("x-amz-date", AMZ_DATE)]);
+        let query = pairs(&[("ሴ", "bar")]);
+        let canonical_request = sigv4_canonical_request("GET", "/", &query, &headers, EMPTY_SHA256);
+        assert_eq!(canonical_request.lines().nth(2), Some("%E1%88%B4=bar"));
+        assert_eq!(
+            sigv4_signature(SECRET_ACCESS_KEY, AMZ_DATE, "us-east-1", "service", &canonical_request),
+            "2cdec8eed098649ff3a119c94853b13c643bcf08f8b0a1d91e12c9027818dd04"
+        );
+    }
+
+    #[test]
+    fn sigv4_post_vanilla() {
+        let headers = pairs(&[("host", "example.amazonaws.com"), ("x-amz-date", AMZ_DATE)]);
+        let canonical_request = sigv4_canonical_request("POST", "/", &[], &headers, EMPTY_SHA256);
+        assert_eq!(
+            sigv4_signature(SECRET_ACCESS_KEY, AMZ_DATE, "us-east-1", "service", &canonical_request),
+            "5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b"
+        );
+    }
+
+    #[test]
+    fn sigv4_iam_list_users() {
+        // This is the example from the AWS documentation for signing a request to IAM.
+        let headers = pairs(&[
+            ("content-type", "application/x-www-form-urlencoded; charset=utf-8"),
+            ("host", "iam.amazonaws.com"),
+            ("x-amz-date", AMZ_DATE),
+        ]);
+        let query = pairs(&[("Action", "ListUsers"), ("Version", "2010-05-08")]);
+        let canonical_request = sigv4_canonical_request("GET", "/", &query, &headers, EMPTY_SHA256);
+        assert_eq!(
+            hex(&Sha256::digest(canonical_request.as_bytes())),
+            "f536975d06c0309214f805bb90ccff089219ecd68b2577efef23edd43b7e1a59"
+        );
+        assert_eq!(
+            sigv4_signature(SECRET_ACCESS_KEY, AMZ_DATE, "us-east-1", "iam", &canonical_request),
+            "5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn signs_requests_with_hmac() {
+        let signing = HTTPSigning::HMAC {
+            key_template: "{key}".to_string(),
+            signature_header: "X-Signature".to_string(),
+            timestamp_header: "X-Timestamp".to_string(),
+            encoding: SignatureEncoding::Base64,
+        };
+        let rewrite = |value: &str| value.replace("{key}", "secret");
+        let req = ureq::put("https://example.com/upload/a.png?album=1");
+        let req = sign_request(req, &signing, &rewrite, Payload::Bytes(b"hello")).unwrap();
+
+        // Check the signature against one made from the timestamp that was sent.
+        let timestamp = req.header("X-Timestamp").unwrap();
+        let string_to_sign = format!(
+            "PUT\n/upload/a.png?album=1\n{}\n{}",
+            timestamp,
+            hex(&Sha256::digest(b"hello"))
+        );
+        assert_eq!(
+            req.header("X-Signature").unwrap(),
+            general_purpose::STANDARD.encode(hmac_sha256(b"secret", string_to_sign.as_bytes()))
+        );
+    }
+
+    #[test]
+    fn signs_requests_with_sigv4() {
+        let signing = HTTPSigning::SigV4 {
+            access_key_id_template: "AKIDEXAMPLE".to_string(),
+            secret_access_key_template: "{secret}".to_string(),
+            session_token_template: Some("token".to_string()),
+            region_template: "us-east-1".to_string(),
+            service: "s3".to_string(),
+            unsigned_payload: true,
+        };
+        let rewrite = |value: &str| value.replace("{secret}", SECRET_ACCESS_KEY);
+        let req = ureq::put("https://bucket.s3.amazonaws.com:8443/a%20b.png?x-id=PutObject")
+            .set("Content-Type", "image/png")
+            .set("Cache-Control", "max-age=60");
+        let req = sign_request(req, &signing, &rewrite, Payload::Bytes(b"hello")).unwrap();
+        assert_eq!(req.header("x-amz-content-sha256"), Some(UNSIGNED_PAYLOAD));
+        assert_eq!(req.header("x-amz-security-token"), Some("token"));
+
+        // Check the authorization against one made from the date that was sent. Headers which are not the
+        // host, the content type, or AWS headers are not signed.
+        let amz_date = req.header("x-amz-date").unwrap();
+        let headers = pairs(&[
+            ("content-type", "image/png"),
+            ("host", "bucket.s3.amazonaws.com:8443"),
+            ("x-amz-content-sha256", UNSIGNED_PAYLOAD),
+            ("x-amz-date", amz_date),
+            ("x-amz-security-token", "token"),
+        ]);
+        let canonical_request = sigv4_canonical_request(
+            "PUT", "/a%20b.png", &pairs(&[("x-id", "PutObject")]), &headers, UNSIGNED_PAYLOAD,
+        );
+        assert_eq!(
+            req.header("authorization").unwrap(),
+            format!(
+                "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/{}/us-east-1/s3/aws4_request, SignedHeaders=content-type;host;x-amz-content-sha256;x-amz-date;x-amz-security-token, Signature={}",
+                &amz_date[..8],
+                sigv4_signature(SECRET_ACCESS_KEY, amz_date, "us-east-1", "s3", &canonical_request),
+            )
+        );
+    }
+}
@@ -0,0 +1,659 @@
+use super::{
+    config_structure::{
+        CustomUploaderConfig, CustomUploaderHandler, CustomUploaderMetadata, HTTPNetworkOptions,
+        HTTPUploaderConfig, Method, UploaderVersions,
+    },
+    CustomUploader, HTTPBody, HTTPRewrite,
+};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+
+// Deserializes a value which can be null. ShareX writes null for sections which are not used, so null is
+// treated as the default.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+// Defines the parts of a ShareX .sxcu file that we care about. Older files use RequestType instead of
+// RequestMethod and $syntax$ instead of {syntax}.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SXCU {
+    name: Option<String>,
+    destination_type: Option<String>,
+    request_method: Option<String>,
+    request_type: Option<String>,
+    #[serde(rename = "RequestURL")]
+    request_url: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    parameters: HashMap<String, String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    headers: HashMap<String, String>,
+    body: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    arguments: HashMap<String, String>,
+    file_form_name: Option<String>,
+    #[serde(rename = "URL")]
+    url: Option<String>,
+    #[serde(rename = "DeletionURL")]
+    deletion_url: Option<String>,
+    error_message: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    regex_list: Vec<String>,
+}
+
+// Defines a part of a ShareX template.
+enum TemplatePart {
+    Literal(String),
+    Syntax(String, Option<String>),
+}
+
+// Splits a {name:arg} syntax into its name and argument.
+fn split_syntax(inner: &str) -> TemplatePart {
+    match inner.split_once(':') {
+        Some((name, arg)) => TemplatePart::Syntax(name.to_lowercase(), Some(arg.to_string())),
+        None => TemplatePart::Syntax(inner.to_lowercase(), None),
+    }
+}
+
+// Defines the names which are valid in the legacy $name$ syntax.
+const LEGACY_NAMES: &[&str] = &[
+    "json", "xml", "regex", "header", "response", "filename", "random", "input",
+];
+
+// Parses a ShareX template into literals and syntax.
+fn parse_template(template: &str) -> Result<Vec<TemplatePart>, String> {
+    let chars: Vec<char> = template.chars().collect();
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            // Handle escaped characters. Other backslashes are kept since they are common in regexes.
+            '\\' if i + 1 < chars.len() && ['{', '}', '$', '|'].contains(&chars[i + 1]) => {
+                literal.push(chars[i + 1]);
+                i += 2;
+            }
+
+            // Handle the current {name:arg} syntax. These can be nested, so track the depth.
+            '{' => {
+                let mut depth = 0;
+                let mut end = None;
+                for (j, c) in chars.iter().enumerate().skip(i) {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                end = Some(j);
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                let end = match end {
+                    Some(end) => end,
+                    None => return Err(format!("The template {} has an unclosed bracket.", template)),
+                };
+                if !literal.is_empty() {
+                    parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(split_syntax(&chars[i + 1..end].iter().collect::<String>()));
+                i = end + 1;
+            }
+
+            // Handle the legacy $name:arg$ syntax. Only treat it as syntax if the name is known since
+            // dollar signs are common in URLs.
+            '$' => {
+                let rest: String = chars[i + 1..].iter().collect();
+                let end = rest.find('$');
+                let inner = end.map(|end| &rest[..end]);
+                let known = inner.map_or(false, |inner| {
+                    let name = inner.split(':').next().unwrap().to_lowercase();
+                    LEGACY_NAMES.contains(&name.as_str())
+                });
+                if !known {
+                    literal.push('$');
+                    i += 1;
+                    continue;
+                }
+                let inner = inner.unwrap();
+                if !literal.is_empty() {
+                    parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(split_syntax(inner));
+                i += inner.chars().count() + 2;
+            }
+
+            c => {
+                literal.push(c);
+                i += 1;
+            }
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(TemplatePart::Literal(literal));
+    }
+    Ok(parts)
+}
+
+// Turns a string into an evalexpr string literal.
+fn expr_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Turns a ShareX JSON path into the dot separated path json_path expects.
+fn json_path_arg(path: &str) -> String {
+    let path = path.trim_start_matches('$').trim_start_matches('.');
+    path.replace("['", ".")
+        .replace("']", "")
+        .replace('[', ".")
+        .replace(']', "")
+        .trim_start_matches('.')
+        .to_string()
+}
+
+// Turns a regex group into an evalexpr value. Numbers are group indexes and anything else is a group name.
+fn regex_group_arg(group: &str) -> String {
+    match group.trim().parse::<i64>() {
+        Ok(i) => i.to_string(),
+        Err(_) => expr_string(group.trim()),
+    }
+}
+
+// Turns a ShareX regex syntax into a regex_match call. The legacy syntax references the RegexList by a
+// 1-based index.
+fn regex_expr(arg: &str, regex_list: &[String]) -> Result<String, String> {
+    // Handle the current pattern|group syntax.
+    if let Some((pattern, group)) = arg.rsplit_once('|') {
+        return Ok(format!(
+            "regex_match({}, {})",
+            expr_string(pattern),
+            regex_group_arg(group),
+        ));
+    }
+
+    // Handle the legacy index,group syntax.
+    let (index, group) = match arg.split_once(',') {
+        Some((index, group)) => (index, group),
+        None => (arg, "0"),
+    };
+    let pattern = match index.trim().parse::<usize>() {
+        Ok(i) if i >= 1 && i <= regex_list.len() => &regex_list[i - 1],
+        _ => match regex_list.is_empty() {
+            // If there is no list, the argument is the pattern itself.
+            true => return Ok(format!("regex_match({}, 1)", expr_string(arg))),
+            false => return Err(format!("The regex {} is not in the regex list.", index)),
+        },
+    };
+    Ok(format!(
+        "regex_match({}, {})",
+        expr_string(pattern),
+        regex_group_arg(group),
+    ))
+}
+
+// Converts the ShareX URL template into a response expression.
+fn response_expr(template: &str, regex_list: &[String]) -> Result<String, String> {
+    // ShareX uses the response body as the URL if none is set.
+    if template.is_empty() {
+        return Ok("string_body()".to_string());
+    }
+
+    let mut exprs = Vec::new();
+    for part in parse_template(template)? {
+        let expr = match part {
+            TemplatePart::Literal(s) => expr_string(&s),
+            TemplatePart::Syntax(name, arg) => match (name.as_str(), arg) {
+                ("json", Some(arg)) => {
+                    format!("str::from(json_path({}))", expr_string(&json_path_arg(&arg)))
+                }
+                ("xml", Some(arg)) => {
+                    let path = match arg.starts_with("string(") {
+                        true => arg,
+                        false => format!("string({})", arg),
+                    };
+                    format!("str::from(xml_path({}))", expr_string(&path))
+                }
+                ("regex", Some(arg)) => regex_expr(&arg, regex_list)?,
+                ("header", Some(arg)) => format!("get_header({})", expr_string(&arg)),
+                ("response", None) => "string_body()".to_string(),
+                ("filename", None) => "get_rewrite(\"{filename}\")".to_string(),
+                (name, _) => {
+                    return Err(format!(
+                        "The {} syntax in the URL is not supported by MagicCap.",
+                        name
+                    ))
+                }
+            },
+        };
+        exprs.push(expr);
+    }
+    Ok(exprs.join(" + "))
+}
+
+// Converts a ShareX request template into a MagicCap template. The filename is the only dynamic value
+// supported in requests, and is handled with a rewrite.
+fn request_template(template: &str) -> Result<String, String> {
+    let mut result = String::new();
+    for part in parse_template(template)? {
+        match part {
+            TemplatePart::Literal(s) => result += &s,
+            TemplatePart::Syntax(name, None) if name == "filename" => result += "{filename}",
+            TemplatePart::Syntax(name, _) => {
+                return Err(format!(
+                    "The {} syntax in the request is not supported by MagicCap.",
+                    name
+                ))
+            }
+        }
+    }
+    Ok(result)
+}
+
+// Turns the name into something that is valid as a uploader ID.
+fn name_to_id(name: &str) -> String {
+    let id: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let id = id.trim_matches('_').to_string();
+    match id.is_empty() {
+        true => "sharex".to_string(),
+        false => format!("sharex_{}", id).chars().take(64).collect(),
+    }
+}
+
+// Converts the contents of a ShareX .sxcu file into a custom uploader.
+pub fn sxcu_to_uploader(data: &str) -> Result<CustomUploader, String> {
+    // Parse the file. Some files start with a byte order mark.
+    let sxcu: SXCU = match serde_json::from_str(data.trim_start_matches('\u{feff}')) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("The file is not a valid ShareX custom uploader: {}", e)),
+    };
+
+    // Make sure this is a uploader which handles files.
+    if let Some(destination_type) = &sxcu.destination_type {
+        if !destination_type.contains("ImageUploader") && !destination_type.contains("FileUploader") {
+            return Err("The ShareX uploader does not upload images or files.".to_string());
+        }
+    }
+
+    // Get the method.
+    let method = match sxcu
+        .request_method
+        .as_ref()
+        .or(sxcu.request_type.as_ref())
+        .map(|m| m.to_uppercase())
+        .as_deref()
+    {
+        None | Some("POST") => Method::POST,
+        Some("GET") => Method::GET,
+        Some("PUT") => Method::PUT,
+        Some("PATCH") => Method::PATCH,
+        Some(m) => return Err(format!("The request method {} is not supported.", m)),
+    };
+
+    // Build the URL with the parameters. Sort them so the output is stable.
+    let mut url_template = request_template(&sxcu.request_url)?;
+    let mut parameters: Vec<(&String, &String)> = sxcu.parameters.iter().collect();
+    parameters.sort();
+    for (i, (key, value)) in parameters.into_iter().enumerate() {
+        url_template.push(if i == 0 && !url_template.contains('?') { '?' } else { '&' });
+        url_template += &urlencoding::encode(key);
+        url_template.push('=');
+        url_template += &request_template(value)?.replace(' ', "%20");
+    }
+
+    // Build the headers.
+    let mut header_templates = HashMap::with_capacity(sxcu.headers.len());
+    for (key, value) in sxcu.headers {
+        header_templates.insert(key, request_template(&value)?);
+    }
+
+    // Build the body. Older files without a body type are always multipart.
+    let body = match sxcu.body.as_deref().unwrap_or("MultipartFormData") {
+        // The arguments can use the filename since rewrites are applied to the text fields of multipart forms.
+        "MultipartFormData" => {
+            let mut arguments = HashMap::with_capacity(sxcu.arguments.len());
+            for (key, value) in sxcu.arguments {
+                arguments.insert(key, request_template(&value)?);
+            }
+            HTTPBody::MultipartForm(
+                arguments,
+                sxcu.file_form_name.unwrap_or_else(|| "file".to_string()),
+            )
+        }
+        "Binary" => HTTPBody::Raw,
+        b => {
+            return Err(format!(
+                "The {} body type is not supported for file uploads.",
+                b
+            ))
+        }
+    };
+
+    // Build the response expression.
+    let url_expr = response_expr(sxcu.url.as_deref().unwrap_or(""), &sxcu.regex_list)?;
+
+    // ShareX deletion URLs delete the file when they are requested, so they are used as the deletion token.
+    let delete_expr = match sxcu.deletion_url.as_deref() {
+        Some(deletion_url) if !deletion_url.is_empty() => {
+            Some(response_expr(deletion_url, &sxcu.regex_list)?)
+        }
+        _ => None,
+    };
+
+    // Build the expression for the error message.
+    let error_expr = match sxcu.error_message.as_deref() {
+        Some(error_message) if !error_message.is_empty() => {
+            Some(response_expr(error_message, &sxcu.regex_list)?)
+        }
+        _ => None,
+    };
+
+    // Create the rewrites. The filename is the only one ShareX files can use.
+    let mut rewrites = HashMap::new();
+    rewrites.insert("{filename}".to_string(), HTTPRewrite::Filename);
+
+    // Return the custom uploader.
+    let name = sxcu.name.unwrap_or_else(|| "ShareX Uploader".to_string());
+    Ok(CustomUploader {
+        version: UploaderVersions::V2,
+        id: name_to_id(&name),
+        description: format!("Imported from the ShareX uploader {}.", name),
+        name,
+        encoded_icon: "/icons/http.png".to_string(),
+        config: CustomUploaderConfig::default(),
+        handler: CustomUploaderHandler::HTTP(HTTPUploaderConfig {
+            rewrites,
+            url_template,
+            method,
+            header_templates,
+            body,
+            response_expr: url_expr,
+            delete_expr,
+            delete_request: None,
+            pre_steps: Vec::new(),
+            upload_captures: HashMap::new(),
+            post_steps: Vec::new(),
+            error_expr,
+            network: HTTPNetworkOptions::default(),
+            signing: None,
+        }),
+        metadata: CustomUploaderMetadata::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::http_expr_functions::add_default_functions;
+    use super::*;
+    use evalexpr::ContextWithMutableFunctions;
+    use std::sync::Arc;
+
+    // Defines the sxcu.net uploader as it is downloaded from the site.
+    const SXCU_NET: &str = r#"{
+        "Version": "14.1.0",
+        "Name": "sxcu.net",
+        "DestinationType": "ImageUploader, TextUploader, FileUploader",
+        "RequestMethod": "POST",
+        "RequestURL": "https://sxcu.net/api/files/create",
+        "Body": "MultipartFormData",
+        "Arguments": {"noembed": "", "title": "{filename}"},
+        "FileFormName": "file",
+        "URL": "{json:url}",
+        "ThumbnailURL": "{json:thumb}",
+        "DeletionURL": "{json:del_url}",
+        "ErrorMessage": "{json:error}"
+    }"#;
+
+    // Defines a catbox.moe uploader with a byte order mark, which some editors add.
+    const CATBOX: &str = "\u{feff}{
+        \"Version\": \"13.1.0\",
+        \"Name\": \"Catbox\",
+        \"DestinationType\": \"ImageUploader, FileUploader\",
+        \"RequestMethod\": \"POST\",
+        \"RequestURL\": \"https://catbox.moe/user/api.php\",
+        \"Body\": \"MultipartFormData\",
+        \"Arguments\": {\"reqtype\": \"fileupload\"},
+        \"FileFormName\": \"fileToUpload\"
+    }";
+
+    // Defines a legacy uguu.se uploader using RequestType and the $syntax$.
+    const UGUU_LEGACY: &str = r#"{
+        "Name": "Uguu",
+        "DestinationType": "ImageUploader, FileUploader",
+        "RequestType": "POST",
+        "RequestURL": "https://uguu.se/upload.php",
+        "FileFormName": "files[]",
+        "ResponseType": "Text",
+        "URL": "$json:files[0].url$"
+    }"#;
+
+    // Defines a Zipline uploader which writes null for the sections it does not use.
+    const ZIPLINE: &str = r#"{
+        "Version": "15.0.0",
+        "Name": "Zipline",
+        "DestinationType": "ImageUploader, FileUploader",
+        "RequestMethod": "POST",
+        "RequestURL": "https://zipline.example.com/api/upload",
+        "Parameters": null,
+        "Headers": {"Authorization": "secret", "Format": "RANDOM"},
+        "Body": "MultipartFormData",
+        "Arguments": null,
+        "FileFormName": "file",
+        "RegexList": null,
+        "URL": "{json:files[0]}"
+    }"#;
+
+    // Defines a legacy uploader which gets the URL with the regex list.
+    const REGEX_LEGACY: &str = r#"{
+        "Name": "Regex Host",
+        "RequestType": "POST",
+        "RequestURL": "https://regex.example.com/upload",
+        "FileFormName": "image",
+        "RegexList": ["href=\"(https://[^\"]+)\""],
+        "URL": "$regex:1,1$"
+    }"#;
+
+    // Defines an uploader which returns XML.
+    const XML_HOST: &str = r#"{
+        "Name": "XML Host",
+        "RequestMethod": "POST",
+        "RequestURL": "https://xml.example.com/upload",
+        "Parameters": {"key": "abc 123", "name": "{filename}"},
+        "Body": "MultipartFormData",
+        "FileFormName": "file",
+        "URL": "{xml:/upload/url}"
+    }"#;
+
+    // Defines a binary uploader which returns the URL in a header.
+    const BINARY_PUT: &str = r#"{
+        "Name": "Binary Put",
+        "DestinationType": "FileUploader",
+        "RequestMethod": "PUT",
+        "RequestURL": "https://put.example.com/{filename}",
+        "Body": "Binary",
+        "URL": "{header:Location}"
+    }"#;
+
+    // Converts the uploader to JSON and back, which is how it is stored.
+    fn round_trip(uploader: &CustomUploader) -> CustomUploader {
+        let json = serde_json::to_string(uploader).unwrap();
+        let parsed: CustomUploader = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            serde_json::to_value(&parsed).unwrap(),
+            serde_json::to_value(uploader).unwrap()
+        );
+        parsed
+    }
+
+    // Gets the HTTP config of the uploader.
+    fn http_config(uploader: &CustomUploader) -> &HTTPUploaderConfig {
+        match &uploader.handler {
+            CustomUploaderHandler::HTTP(config) => config,
+            _ => panic!("the uploader is not a HTTP uploader"),
+        }
+    }
+
+    // Evaluates the expression against a response like the HTTP uploader does. The only header is Location
+    // and the filename is cat.png.
+    fn eval(expr: &str, body: &str) -> String {
+        let mut context = evalexpr::HashMapContext::new();
+        add_default_functions(&mut context, Arc::new(body.as_bytes().to_vec()));
+        context
+            .set_function(
+                "get_header".to_string(),
+                evalexpr::Function::new(|arg| {
+                    Ok(evalexpr::Value::String(match arg.as_string()?.to_lowercase().as_str() {
+                        "location" => "https://put.example.com/f/cat.png".to_string(),
+                        _ => "".to_string(),
+                    }))
+                }),
+            )
+            .unwrap();
+        context
+            .set_function(
+                "get_rewrite".to_string(),
+                evalexpr::Function::new(|_| Ok(evalexpr::Value::String("cat.png".to_string()))),
+            )
+            .unwrap();
+        match evalexpr::eval_with_context(expr, &context).unwrap() {
+            evalexpr::Value::String(s) => s,
+            v => panic!("the expression returned {:?}", v),
+        }
+    }
+
+    #[test]
+    fn imports_sxcu_net() {
+        let uploader = round_trip(&sxcu_to_uploader(SXCU_NET).unwrap());
+        assert_eq!(uploader.id, "sharex_sxcu_net");
+        assert_eq!(uploader.name, "sxcu.net");
+        let config = http_config(&uploader);
+        assert!(matches!(config.method, Method::POST));
+        assert_eq!(config.url_template, "https://sxcu.net/api/files/create");
+        match &config.body {
+            HTTPBody::MultipartForm(arguments, file_field) => {
+                assert_eq!(file_field, "file");
+                assert_eq!(arguments["noembed"], "");
+                assert_eq!(arguments["title"], "{filename}");
+            }
+            _ => panic!("the body is not a multipart form"),
+        }
+        assert!(config.rewrites.contains_key("{filename}"));
+
+        let body = r#"{"url": "https://sxcu.net/abc", "del_url": "https://sxcu.net/api/files/delete/abc/token"}"#;
+        assert_eq!(eval(&config.response_expr, body), "https://sxcu.net/abc");
+        assert_eq!(
+            eval(config.delete_expr.as_ref().unwrap(), body),
+            "https://sxcu.net/api/files/delete/abc/token"
+        );
+        assert_eq!(eval(config.error_expr.as_ref().unwrap(), r#"{"error": "Too big"}"#), "Too big");
+    }
+
+    #[test]
+    fn imports_catbox_with_a_byte_order_mark() {
+        let uploader = round_trip(&sxcu_to_uploader(CATBOX).unwrap());
+        let config = http_config(&uploader);
+        match &config.body {
+            HTTPBody::MultipartForm(arguments, file_field) => {
+                assert_eq!(file_field, "fileToUpload");
+                assert_eq!(arguments["reqtype"], "fileupload");
+            }
+            _ => panic!("the body is not a multipart form"),
+        }
+
+        // Catbox has no URL, so the body is the URL.
+        assert_eq!(config.response_expr, "string_body()");
+        let body = "https://files.catbox.moe/abc.png";
+        assert_eq!(eval(&config.response_expr, body), body);
+        assert!(config.delete_expr.is_none());
+    }
+
+    #[test]
+    fn imports_legacy_uguu() {
+        let uploader = round_trip(&sxcu_to_uploader(UGUU_LEGACY).unwrap());
+        let config = http_config(&uploader);
+        assert!(matches!(config.method, Method::POST));
+        match &config.body {
+            HTTPBody::MultipartForm(_, file_field) => assert_eq!(file_field, "files[]"),
+            _ => panic!("the body is not a multipart form"),
+        }
+        let body = r#"{"success": true, "files": [{"url": "https://a.uguu.se/abc.png"}]}"#;
+        assert_eq!(eval(&config.response_expr, body), "https://a.uguu.se/abc.png");
+    }
+
+    #[test]
+    fn imports_zipline_with_null_sections() {
+        let uploader = round_trip(&sxcu_to_uploader(ZIPLINE).unwrap());
+        let config = http_config(&uploader);
+        assert_eq!(config.url_template, "https://zipline.example.com/api/upload");
+        assert_eq!(config.header_templates["Authorization"], "secret");
+        assert_eq!(config.header_templates["Format"], "RANDOM");
+        match &config.body {
+            HTTPBody::MultipartForm(arguments, _) => assert!(arguments.is_empty()),
+            _ => panic!("the body is not a multipart form"),
+        }
+        let body = r#"{"files": ["https://zipline.example.com/u/abc.png"]}"#;
+        assert_eq!(eval(&config.response_expr, body), "https://zipline.example.com/u/abc.png");
+    }
+
+    #[test]
+    fn imports_legacy_regex_list() {
+        let uploader = round_trip(&sxcu_to_uploader(REGEX_LEGACY).unwrap());
+        let config = http_config(&uploader);
+        let body = r#"<a href="https://regex.example.com/i/abc.png">Your image</a>"#;
+        assert_eq!(eval(&config.response_expr, body), "https://regex.example.com/i/abc.png");
+    }
+
+    #[test]
+    fn imports_xml_with_parameters() {
+        let uploader = round_trip(&sxcu_to_uploader(XML_HOST).unwrap());
+        let config = http_config(&uploader);
+        assert_eq!(
+            config.url_template,
+            "https://xml.example.com/upload?key=abc%20123&name={filename}"
+        );
+        let body = "<upload><url>https://xml.example.com/abc.png</url></upload>";
+        assert_eq!(eval(&config.response_expr, body), "https://xml.example.com/abc.png");
+    }
+
+    #[test]
+    fn imports_binary_put_with_header_url() {
+        let uploader = round_trip(&sxcu_to_uploader(BINARY_PUT).unwrap());
+        let config = http_config(&uploader);
+        assert!(matches!(config.method, Method::PUT));
+        assert!(matches!(config.body, HTTPBody::Raw));
+        assert_eq!(config.url_template, "https://put.example.com/{filename}");
+        assert_eq!(eval(&config.response_expr, ""), "https://put.example.com/f/cat.png");
+    }
+
+    #[test]
+    fn rejects_unsupported_uploaders() {
+        let text = r#"{"Name": "Text", "DestinationType": "TextUploader", "RequestURL": "https://example.com"}"#;
+        assert!(sxcu_to_uploader(text).is_err());
+        let url = r#"{"Name": "Random", "RequestURL": "https://example.com/{random:a|b}"}"#;
+        assert!(sxcu_to_uploader(url).is_err());
+        let body = r#"{"Name": "Form", "RequestURL": "https://example.com", "Body": "FormURLEncoded"}"#;
+        assert!(sxcu_to_uploader(body).is_err());
+        assert!(sxcu_to_uploader("not json").is_err());
+    }
+
+    #[test]
+    fn parses_templates() {
+        assert_eq!(json_path_arg("$.files[0].url"), "files.0.url");
+        assert_eq!(json_path_arg("data['link']"), "data.link");
+        assert_eq!(request_template("a\\{b\\}$c").unwrap(), "a{b}$c");
+        assert_eq!(request_template("$filename$.png").unwrap(), "{filename}.png");
+        assert!(request_template("{unclosed").is_err());
+        assert_eq!(name_to_id("!!!"), "sharex");
+        assert_eq!(name_to_id("My Host"), "sharex_my_host");
+    }
+}
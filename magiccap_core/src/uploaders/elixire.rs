@@ -1,43 +1,48 @@
-use super::{mime::guess_mime_type, ConfigOption, Uploader};
+use super::{
+    mime::guess_mime_type, multipart::multipart_stream, read_json_response,
+    validation::{config_str, required_str},
+    ConfigOption, UploadContext, UploadError, UploadSource, Uploader,
+};
 use std::collections::HashMap;
-use ureq_multipart::MultipartBuilder;
 use uriparse::URI;
 
+// Defines the elixire instance used when one is not set.
+const DEFAULT_API_URL: &str = "https://elixi.re";
+
+// Gets the base URL of the elixire instance without a trailing slash.
+fn api_url(config: &HashMap<String, serde_json::Value>) -> &str {
+    config_str(config, "api_url")
+        .unwrap_or(DEFAULT_API_URL)
+        .trim_end_matches('/')
+}
+
 fn elixire_support_upload(
     filename: &str,
     config: HashMap<String, serde_json::Value>,
-    reader: Box<dyn std::io::Read + Send + Sync>,
-) -> Result<String, String> {
+    source: &UploadSource,
+    ctx: &UploadContext,
+) -> Result<String, UploadError> {
     // Guess the MIME type.
-    let (mime, mut reader) = match guess_mime_type(filename, reader) {
-        Ok(v) => v,
-        Err(e) => return Err(e.to_string()),
-    };
+    let mime = guess_mime_type(filename, source)?;
 
-    // Build the multipart data from the reader.
-    let multipart =
-        MultipartBuilder::new().add_stream(&mut reader, "f", Some(filename), Some(mime));
-
-    // Check that the stream was consumed okay.
-    let multipart = match multipart {
-        Ok(m) => m,
-        Err(err) => return Err(err.to_string()),
-    };
-
-    // Finish the multipart data.
-    let (content_type, multipart) = match multipart.finish() {
-        Ok(m) => m,
-        Err(err) => return Err(err.to_string()),
-    };
+    // Build the multipart body. This streams the file from the disk.
+    let multipart = multipart_stream(Vec::<(&str, &str)>::new(), "f", filename, &mime, source)?;
 
     // Add the required values to the URL.
-    let mut url = URI::try_from("https://elixi.re/api/upload").unwrap();
+    let upload_url = format!("{}/api/upload", api_url(&config));
+    let mut url = match URI::try_from(upload_url.as_str()) {
+        Ok(url) => url,
+        Err(e) => return Err(UploadError::config("api_url", format!("The API URL is not valid: {}", e))),
+    };
     let mut query: String;
     if let Some(domain_config_json) = config.get("domain_config") {
         // Ensure it is an array if it exists and is 3 values in length.
         let arr = domain_config_json.as_array().unwrap();
         if arr.len() != 3 {
-            return Err("The domain configuration must be an array of 3 values.".to_string());
+            return Err(UploadError::config(
+                "domain_config",
+                "The domain configuration must be an array of 3 values.",
+            ));
         }
 
         // Get the subdomain (string | null), domain (string), and if it should allow subdomains (bool).
@@ -64,32 +69,64 @@ fn elixire_support_upload(
 
     // Send the request to the server.
     let resp = ureq::post(url.to_string().as_str())
-        .set("Authorization", &config["token"].as_str().unwrap())
-        .set("Content-Type", &content_type)
-        .send_bytes(&multipart);
+        .set("Authorization", required_str(&config, "token")?)
+        .set("Content-Type", &multipart.content_type)
+        .set("Content-Length", &multipart.content_length.to_string())
+        .send(ctx.progress_reader(multipart.reader, multipart.content_length));
 
     // Handle the result.
     match resp {
         Ok(resp) => {
-            let json: serde_json::Value = match resp.into_json() {
-                Ok(json) => json,
-                Err(err) => return Err(err.to_string()),
-            };
+            let status = resp.status();
+            let json = read_json_response(resp)?;
             match json["url"].as_str() {
-                Some(link) => Ok(link.to_string()),
-                None => Err("The response did not contain a link.".to_string()),
+                Some(link) => {
+                    if let Some(shortname) = json["shortname"].as_str() {
+                        ctx.set_deletion_token(shortname);
+                    }
+                    Ok(link.to_string())
+                }
+                None => Err(UploadError::RemoteRejected { status, body: json.to_string() }),
             }
         }
-        Err(err) => Err(err.to_string()),
+        Err(_) if ctx.is_cancelled() => Err(UploadError::Cancelled),
+        Err(err) => Err(err.into()),
     }
 }
 
+// Deletes the file with the shortname from elixire.
+fn elixire_support_delete(
+    shortname: &str,
+    config: HashMap<String, serde_json::Value>,
+) -> Result<(), UploadError> {
+    ureq::delete(&format!(
+        "{}/api/files/{}",
+        api_url(&config),
+        urlencoding::encode(shortname)
+    ))
+        .set("Authorization", required_str(&config, "token")?)
+        .call()?;
+    Ok(())
+}
+
 pub fn elixire_support() -> Uploader {
     Uploader {
         name: "elixi.re".to_string(),
         description: "elixire is the future".to_string(),
         icon_path: "/icons/elixire.svg".to_string(),
         options: vec![
+            (
+                "api_url".to_string(),
+                ConfigOption::String {
+                    name: "API URL".to_string(),
+                    description: "The base URL of the elixire instance. Change this to use a self-hosted instance.".to_string(),
+                    default: Some(DEFAULT_API_URL.to_string()),
+                    required: false,
+                    password: false,
+                    regex: Some("^https?://".to_string()),
+                    validation_error_message: Some("The API URL must start with http:// or https://.".to_string()),
+                },
+            ),
             (
                 "domain_config".to_string(),
                 ConfigOption::Embedded {
@@ -103,7 +140,7 @@ pub fn elixire_support() -> Uploader {
                 "token".to_string(),
                 ConfigOption::String {
                     name: "Token".to_string(),
-                    description: "The token to use for the elixi.re API.".to_string(),
+                    description: "The token to use for the elixire API.".to_string(),
                     default: None,
                     required: true,
                     password: true,
@@ -113,5 +150,92 @@ pub fn elixire_support() -> Uploader {
             ),
         ],
         upload: Box::new(elixire_support_upload),
+        delete: Some(Box::new(elixire_support_delete)),
+        accepted_mime_types: Vec::new(),
+        max_file_size: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uploaders::mock_server::{config, MockServer, Response};
+    use serde_json::json;
+
+    // Uploads a small PNG to the server with the config specified.
+    fn upload(config: HashMap<String, serde_json::Value>) -> (Result<String, UploadError>, Option<String>) {
+        let source = UploadSource::from_bytes(b"\x89PNG\r\n\x1a\nimage", "a.png").unwrap();
+        let ctx = UploadContext::background();
+        let result = elixire_support_upload("a.png", config, &source, &ctx);
+        (result, ctx.take_deletion_token())
+    }
+
+    #[test]
+    fn uploads_to_the_domain_and_deletes() {
+        let server = MockServer::start(|req| match req.method.as_str() {
+            "POST" => Response::json(json!({"url": "https://i.example.com/abc.png", "shortname": "abc"})),
+            _ => Response::new(204),
+        });
+        let config = config(json!({
+            "api_url": format!("{}/", server.url()),
+            "token": "secret",
+            "domain_config": ["shots", "example.com", true],
+        }));
+        let (result, deletion_token) = upload(config.clone());
+        assert_eq!(result.unwrap(), "https://i.example.com/abc.png");
+        assert_eq!(deletion_token.as_deref(), Some("abc"));
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/api/upload");
+        assert_eq!(requests[0].query, "domain=example.com&subdomain=shots");
+        assert_eq!(requests[0].header("Authorization"), Some("secret"));
+        assert!(requests[0].header("Content-Type").unwrap().starts_with("multipart/form-data; boundary="));
+        let body = requests[0].body_str();
+        assert!(body.contains("name=\"f\"; filename=\"a.png\""), "{}", body);
+        assert!(body.contains("Content-Type: image/png"), "{}", body);
+
+        elixire_support_delete("abc", config).unwrap();
+        let requests = server.requests();
+        assert_eq!(requests[1].method, "DELETE");
+        assert_eq!(requests[1].path, "/api/files/abc");
+        assert_eq!(requests[1].header("Authorization"), Some("secret"));
+    }
+
+    #[test]
+    fn leaves_out_the_subdomain_when_not_allowed() {
+        let server = MockServer::start(|_| Response::json(json!({"url": "https://example.com/abc.png"})));
+        let (result, deletion_token) = upload(config(json!({
+            "api_url": server.url(),
+            "token": "secret",
+            "domain_config": ["shots", "example.com", false],
+        })));
+        assert_eq!(result.unwrap(), "https://example.com/abc.png");
+        assert_eq!(deletion_token, None);
+        assert_eq!(server.requests()[0].query, "domain=example.com");
+    }
+
+    #[test]
+    fn rejected_tokens_are_auth_errors() {
+        let server = MockServer::start(|_| Response::new(401).body("bad token"));
+        let (result, _) = upload(config(json!({"api_url": server.url(), "token": "wrong"})));
+        assert!(matches!(result, Err(UploadError::Auth { .. })));
+    }
+
+    #[test]
+    fn responses_without_a_url_are_rejected() {
+        let server = MockServer::start(|_| Response::json(json!({"error": "quota"})));
+        let (result, _) = upload(config(json!({"api_url": server.url(), "token": "secret"})));
+        assert!(matches!(result, Err(UploadError::RemoteRejected { status: 200, .. })));
+    }
+
+    #[test]
+    fn missing_tokens_are_config_errors() {
+        let server = MockServer::start(|_| Response::new(500));
+        let (result, _) = upload(config(json!({"api_url": server.url()})));
+        match result {
+            Err(UploadError::Config { field, .. }) => assert_eq!(field.as_deref(), Some("token")),
+            other => panic!("expected a config error, got {:?}", other),
+        }
+        assert!(server.requests().is_empty());
     }
 }
@@ -0,0 +1,210 @@
+use serde::Serialize;
+use std::fmt;
+
+// Defines the message used whenever an upload is cancelled.
+pub const CANCELLED_MESSAGE: &str = "The upload was cancelled.";
+
+// Defines the longest part of a response body we will show to the user.
+const MAX_BODY_DISPLAY_LEN: usize = 200;
+
+// Defines an error from an uploader. The variant is used to decide if the upload should be retried and the
+// field lets the frontend highlight the option that is wrong.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UploadError {
+    // The configuration is missing or invalid. The field is the option key if we know which one it is.
+    Config {
+        field: Option<String>,
+        message: String,
+    },
+
+    // The remote rejected the credentials.
+    Auth { message: String },
+
+    // The remote could not be reached or the connection failed part way through.
+    Network { message: String },
+
+    // The remote responded but rejected the upload.
+    RemoteRejected { status: u16, body: String },
+
+    // The upload was cancelled.
+    Cancelled,
+
+    // MagicCap is closing, so the upload was not started.
+    ShuttingDown,
+
+    // Reading the file or running something locally failed.
+    Io { message: String },
+
+    // Something locally failed in a way that will happen again, like the file being missing or a command
+    // exiting with an error.
+    Local { message: String },
+}
+
+impl UploadError {
+    // Creates a configuration error for the field specified.
+    pub fn config(field: &str, message: impl Into<String>) -> Self {
+        UploadError::Config {
+            field: Some(field.to_string()),
+            message: message.into(),
+        }
+    }
+
+    // Creates an authentication error.
+    pub fn auth(message: impl Into<String>) -> Self {
+        UploadError::Auth {
+            message: message.into(),
+        }
+    }
+
+    // Creates a network error.
+    pub fn network(message: impl Into<String>) -> Self {
+        UploadError::Network {
+            message: message.into(),
+        }
+    }
+
+    // Creates an I/O error.
+    pub fn io(message: impl Into<String>) -> Self {
+        UploadError::Io {
+            message: message.into(),
+        }
+    }
+
+    // Creates an error for a local failure which retrying will not fix.
+    pub fn local(message: impl Into<String>) -> Self {
+        UploadError::Local {
+            message: message.into(),
+        }
+    }
+
+    // Creates the right error for a HTTP status. 401 and 403 are treated as authentication errors.
+    pub fn from_status(status: u16, body: String) -> Self {
+        match status {
+            401 | 403 => UploadError::Auth {
+                message: format!("The server responded with {}: {}", status, truncate(&body)),
+            },
+            _ => UploadError::RemoteRejected { status, body },
+        }
+    }
+
+    // Defines if trying the upload again later could succeed. Configuration, authentication, and local errors
+    // need the user to change something first, and cancelled uploads were stopped on purpose. Uploads stopped
+    // by MagicCap closing are retried the next time it starts.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            UploadError::Config { .. }
+            | UploadError::Auth { .. }
+            | UploadError::Local { .. }
+            | UploadError::Cancelled => false,
+            UploadError::RemoteRejected { status, .. } => {
+                *status >= 500 || *status == 408 || *status == 429
+            }
+            UploadError::Network { .. } | UploadError::Io { .. } | UploadError::ShuttingDown => true,
+        }
+    }
+}
+
+// Truncates a response body so it is reasonable to show in a dialog.
+fn truncate(body: &str) -> String {
+    let body = body.trim();
+    match body.char_indices().nth(MAX_BODY_DISPLAY_LEN) {
+        Some((i, _)) => format!("{}...", &body[..i]),
+        None => body.to_string(),
+    }
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::Config { message, .. } => write!(f, "{}", message),
+            UploadError::Auth { message } => {
+                write!(f, "The uploader could not authenticate: {}", message)
+            }
+            UploadError::Network { message } => {
+                write!(f, "Failed to connect to the server: {}", message)
+            }
+            UploadError::RemoteRejected { status, body } if *status < 400 => write!(
+                f,
+                "The server responded with something MagicCap did not understand: {}",
+                truncate(body),
+            ),
+            UploadError::RemoteRejected { status, body } => match body.trim().is_empty() {
+                true => write!(f, "The server rejected the upload with the status {}.", status),
+                false => write!(
+                    f,
+                    "The server rejected the upload with the status {}: {}",
+                    status,
+                    truncate(body),
+                ),
+            },
+            UploadError::Cancelled => write!(f, "{}", CANCELLED_MESSAGE),
+            UploadError::ShuttingDown => write!(f, "The upload was stopped since MagicCap is closing."),
+            UploadError::Io { message } | UploadError::Local { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+// Reads a JSON response from a successful request. If the body is not JSON, it is treated as a rejection so the
+// user can see what the server said.
+pub fn read_json_response(resp: ureq::Response) -> Result<serde_json::Value, UploadError> {
+    let status = resp.status();
+    let body = match resp.into_string() {
+        Ok(body) => body,
+        Err(err) => return Err(UploadError::network(err.to_string())),
+    };
+    match serde_json::from_str(&body) {
+        Ok(json) => Ok(json),
+        Err(_) => Err(UploadError::RemoteRejected { status, body }),
+    }
+}
+
+impl From<std::io::Error> for UploadError {
+    fn from(err: std::io::Error) -> Self {
+        UploadError::io(err.to_string())
+    }
+}
+
+impl From<ureq::Error> for UploadError {
+    fn from(err: ureq::Error) -> Self {
+        match err {
+            ureq::Error::Status(status, resp) => {
+                UploadError::from_status(status, resp.into_string().unwrap_or_default())
+            }
+            ureq::Error::Transport(transport) => UploadError::network(transport.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uploaders::UploadSource;
+
+    #[test]
+    fn retries_temporary_failures() {
+        assert!(UploadError::network("timed out").is_retryable());
+        assert!(UploadError::io("disk busy").is_retryable());
+        assert!(UploadError::from_status(503, String::new()).is_retryable());
+        assert!(UploadError::from_status(429, String::new()).is_retryable());
+        assert!(UploadError::ShuttingDown.is_retryable());
+    }
+
+    #[test]
+    fn does_not_retry_permanent_failures() {
+        assert!(!UploadError::Cancelled.is_retryable());
+        assert!(!UploadError::local("exit status 1").is_retryable());
+        assert!(!UploadError::config("url", "missing").is_retryable());
+        assert!(!UploadError::from_status(401, String::new()).is_retryable());
+        assert!(!UploadError::from_status(413, String::new()).is_retryable());
+    }
+
+    #[test]
+    fn missing_files_are_not_retried() {
+        let path = std::env::temp_dir().join("magiccap_missing_upload_source.png");
+        match UploadSource::from_path(&path) {
+            Err(e) => assert!(matches!(e, UploadError::Local { .. }), "{:?}", e),
+            Ok(_) => panic!("the file should not exist"),
+        }
+    }
+}
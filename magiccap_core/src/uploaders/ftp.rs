@@ -1,75 +1,85 @@
 use super::{
     utils::{DOMAIN_OR_IP_REGEX, URL_FTP_REWRITE_DESCRIPTION},
-    ConfigOption, Uploader,
+    validation::{config_str, required_str},
+    ConfigOption, UploadContext, UploadError, UploadSource, Uploader,
 };
 use std::collections::HashMap;
 use suppaftp::{native_tls::TlsConnector, types::FileType, NativeTlsConnector, NativeTlsFtpStream};
 
-// Defines the function to upload a screenshot using FTP.
-fn ftp_support_upload(
-    filename: &str,
-    config: HashMap<String, serde_json::Value>,
-    mut reader: Box<dyn std::io::Read + Send + Sync>,
-) -> Result<String, String> {
-    // Handle the hostname.
-    let mut hostname = config.get("hostname").unwrap().to_string();
-    if let Some(port) = config.get("port") {
-        hostname += ":";
-        hostname += &port.to_string();
-    }
-
+// Connects and logs into the FTP server. The hostname includes the port.
+fn ftp_connect(
+    config: &HashMap<String, serde_json::Value>,
+    hostname: &str,
+) -> Result<NativeTlsFtpStream, UploadError> {
     // Create the FTP stream.
-    let use_ssl = match config.get("ssl_enabled") {
-        Some(ssl_enabled) => ssl_enabled.as_bool().unwrap(),
-        None => false,
-    };
-    let mut ftp_stream = match NativeTlsFtpStream::connect(&hostname) {
+    let use_ssl = config.get("ssl_enabled").and_then(|v| v.as_bool()).unwrap_or(false);
+    let mut ftp_stream = match NativeTlsFtpStream::connect(hostname) {
         Ok(c) => c,
         Err(err) => {
-            return Err(format!("Failed to connect to the FTP server: {}", err));
+            return Err(UploadError::network(err.to_string()));
         }
     };
     if use_ssl {
         let ctx = match TlsConnector::new() {
             Ok(tls) => tls,
             Err(err) => {
-                return Err(format!("Failed to setup TLS stream: {}", err));
+                return Err(UploadError::network(format!("Failed to setup TLS stream: {}", err)));
             }
         };
-        ftp_stream = match ftp_stream.into_secure(NativeTlsConnector::from(ctx), &hostname) {
+        ftp_stream = match ftp_stream.into_secure(NativeTlsConnector::from(ctx), hostname) {
             Ok(s) => s,
             Err(err) => {
-                return Err(format!("Failed to setup TLS stream: {}", err));
+                return Err(UploadError::network(format!("Failed to setup TLS stream: {}", err)));
             }
         };
     }
 
     // Handle the login.
-    let username = match config.get("username") {
-        Some(username) => username.as_str().unwrap(),
-        None => "anonymous",
-    };
-    let password = match config.get("password") {
-        Some(password) => password.as_str().unwrap(),
-        None => "anonymous",
-    };
+    let username = config_str(config, "username").unwrap_or("anonymous");
+    let password = config_str(config, "password").unwrap_or("anonymous");
     if let Err(err) = ftp_stream.login(username, password) {
-        return Err(format!("Failed to login to the FTP server: {}", err));
+        return Err(UploadError::auth(err.to_string()));
     }
+    Ok(ftp_stream)
+}
+
+// Gets the hostname with the port if it is set.
+fn ftp_hostname(config: &HashMap<String, serde_json::Value>) -> Result<String, UploadError> {
+    let mut hostname = required_str(config, "hostname")?.to_string();
+    if let Some(port) = config.get("port") {
+        hostname += ":";
+        hostname += &port.to_string();
+    }
+    Ok(hostname)
+}
+
+// Defines the function to upload a screenshot using FTP.
+fn ftp_support_upload(
+    filename: &str,
+    config: HashMap<String, serde_json::Value>,
+    source: &UploadSource,
+    ctx: &UploadContext,
+) -> Result<String, UploadError> {
+    // Open the file first so we do not connect if it is missing.
+    let mut file = ctx.progress_reader(source.open()?, source.size());
+
+    // Connect to the server. The config is validated before the upload starts, so the types are right.
+    let hostname = ftp_hostname(&config)?;
+    let mut ftp_stream = ftp_connect(&config, &hostname)?;
 
     // Set to a binary transfer.
     if let Err(err) = ftp_stream.transfer_type(FileType::Binary) {
-        return Err(format!(
+        return Err(UploadError::network(format!(
             "Failed to set the transfer type to binary: {}",
             err
-        ));
+        )));
     }
 
     // If path is set, change the directory.
     let path_var: String;
-    if let Some(path) = config.get("path") {
+    if let Some(path) = config_str(&config, "path") {
         // Add a slash to the path if it does not exist.
-        let path = if path.as_str().unwrap().ends_with("/") {
+        let path = if path.ends_with("/") {
             path_var = path.to_string();
             path.to_string()
         } else {
@@ -79,36 +89,44 @@ fn ftp_support_upload(
 
         // Change the directory.
         if let Err(err) = ftp_stream.cwd(&path) {
-            return Err(format!(
-                "Failed to change the directory to {}: {}",
-                path, err
+            return Err(UploadError::config(
+                "path",
+                format!("Failed to change the directory to {}: {}", path, err),
             ));
         }
     } else {
         path_var = "".to_string();
     }
 
-    // Put the file.
-    if let Err(err) = ftp_stream.put_file(filename, &mut reader) {
-        return Err(format!(
+    // Put the file. If this was cancelled part way through, the reader errors and the transfer is aborted.
+    if let Err(err) = ftp_stream.put_file(filename, &mut file) {
+        if ctx.is_cancelled() {
+            let _ = ftp_stream.quit();
+            return Err(UploadError::Cancelled);
+        }
+        return Err(UploadError::network(format!(
             "Failed to upload the file to the FTP server: {}",
             err
-        ));
+        )));
     }
 
     // Close the connection.
     if let Err(err) = ftp_stream.quit() {
-        return Err(format!(
+        return Err(UploadError::network(format!(
             "Failed to close the connection to the FTP server: {}",
             err
-        ));
+        )));
+    }
+
+    // The remote path is used to delete the file later.
+    match path_var.is_empty() {
+        true => ctx.set_deletion_token(filename),
+        false => ctx.set_deletion_token(format!("{}/{}", path_var.trim_end_matches('/'), filename)),
     }
 
     // Process the URL rewrite.
-    let url_rewrite = match config.get("url_rewrite") {
-        Some(url_rewrite) => url_rewrite.as_str().unwrap(),
-        None => "https://$hostname$folder_path/$filename",
-    };
+    let url_rewrite = config_str(&config, "url_rewrite")
+        .unwrap_or("https://$hostname$folder_path/$filename");
     let hostname_pre_port = hostname.split(":").next().unwrap();
     let url = url_rewrite
         .replace("$hostname", hostname_pre_port)
@@ -119,6 +137,24 @@ fn ftp_support_upload(
     Ok(url)
 }
 
+// Deletes the file at the remote path from the FTP server.
+fn ftp_support_delete(
+    remote_path: &str,
+    config: HashMap<String, serde_json::Value>,
+) -> Result<(), UploadError> {
+    let hostname = ftp_hostname(&config)?;
+    let mut ftp_stream = ftp_connect(&config, &hostname)?;
+    if let Err(err) = ftp_stream.rm(remote_path) {
+        let _ = ftp_stream.quit();
+        return Err(UploadError::network(format!(
+            "Failed to delete {} from the FTP server: {}",
+            remote_path, err
+        )));
+    }
+    let _ = ftp_stream.quit();
+    Ok(())
+}
+
 // Defines the config structure for FTP.
 pub fn ftp_support() -> Uploader {
     Uploader {
@@ -211,5 +247,104 @@ pub fn ftp_support() -> Uploader {
             ),
         ],
         upload: Box::new(ftp_support_upload),
+        delete: Some(Box::new(ftp_support_delete)),
+        accepted_mime_types: Vec::new(),
+        max_file_size: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uploaders::{
+        mock_ftp_server::{MockFtpServer, PASSWORD, USERNAME},
+        mock_server::config,
+        validate_config,
+    };
+    use serde_json::json;
+
+    // Validates the config like a real upload and adds the server and the login.
+    fn ftp_config(server: &MockFtpServer, extra: serde_json::Value) -> HashMap<String, serde_json::Value> {
+        let mut config = config(json!({
+            "hostname": "127.0.0.1",
+            "port": server.port(),
+            "username": USERNAME,
+            "password": PASSWORD,
+        }));
+        config.extend(extra.as_object().unwrap().clone());
+        validate_config(&ftp_support().options, config).unwrap()
+    }
+
+    // Uploads a small file with the config specified.
+    fn upload(config: HashMap<String, serde_json::Value>) -> (Result<String, UploadError>, Option<String>) {
+        let source = UploadSource::from_bytes(b"ftp file", "a b.txt").unwrap();
+        let ctx = UploadContext::background();
+        let result = ftp_support_upload("a b.txt", config, &source, &ctx);
+        (result, ctx.take_deletion_token())
+    }
+
+    #[test]
+    fn uploads_into_the_folder_and_deletes() {
+        let server = MockFtpServer::start(&["/shots"]);
+        let config = ftp_config(&server, json!({"path": "/shots"}));
+        let (result, deletion_token) = upload(config.clone());
+        assert_eq!(result.unwrap(), "https://127.0.0.1/shots/a%20b.txt");
+        assert_eq!(deletion_token.as_deref(), Some("/shots/a b.txt"));
+        assert_eq!(server.file("/shots/a b.txt").unwrap(), b"ftp file");
+
+        let commands = server.commands();
+        assert_eq!(
+            commands,
+            vec![
+                format!("USER {}", USERNAME),
+                "PASS ****".to_string(),
+                "TYPE I".to_string(),
+                "CWD /shots/".to_string(),
+                "PASV".to_string(),
+                "STOR a b.txt".to_string(),
+                "QUIT".to_string(),
+            ]
+        );
+
+        // The deletion token removes the file on a new connection.
+        ftp_support_delete(&deletion_token.unwrap(), config).unwrap();
+        assert_eq!(server.file("/shots/a b.txt"), None);
+    }
+
+    #[test]
+    fn uploads_to_the_root_with_a_url_rewrite() {
+        let server = MockFtpServer::start(&[]);
+        let (result, deletion_token) = upload(ftp_config(
+            &server,
+            json!({"url_rewrite": "https://cdn.example.com/$filename"}),
+        ));
+        assert_eq!(result.unwrap(), "https://cdn.example.com/a%20b.txt");
+        assert_eq!(deletion_token.as_deref(), Some("a b.txt"));
+        assert_eq!(server.file("/a b.txt").unwrap(), b"ftp file");
+    }
+
+    #[test]
+    fn rejected_logins_are_auth_errors() {
+        let server = MockFtpServer::start(&[]);
+        let (result, _) = upload(ftp_config(&server, json!({"password": "wrong"})));
+        assert!(matches!(result, Err(UploadError::Auth { .. })));
+        assert_eq!(server.file("/a b.txt"), None);
+    }
+
+    #[test]
+    fn missing_folders_are_config_errors() {
+        let server = MockFtpServer::start(&[]);
+        let (result, _) = upload(ftp_config(&server, json!({"path": "/missing"})));
+        match result {
+            Err(UploadError::Config { field, .. }) => assert_eq!(field.as_deref(), Some("path")),
+            other => panic!("expected a config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn deleting_a_missing_file_fails() {
+        let server = MockFtpServer::start(&[]);
+        let err = ftp_support_delete("gone.txt", ftp_config(&server, json!({}))).unwrap_err();
+        assert!(err.to_string().contains("gone.txt"), "{}", err);
     }
 }
@@ -1,36 +1,435 @@
-use super::Uploader;
+use super::{
+    mime::guess_mime_type, multipart::multipart_stream, read_json_response,
+    validation::config_str, ConfigOption, UploadContext, UploadError, UploadSource, Uploader,
+};
+use crate::database;
+use serde_json::Value;
 use std::collections::HashMap;
 
-fn imgur_support_upload(
-    _: &str,
-    _: HashMap<String, serde_json::Value>,
-    reader: Box<dyn std::io::Read + Send + Sync>,
-) -> Result<String, String> {
-    let resp = ureq::post("https://api.imgur.com/3/image")
-        .set("Authorization", "Client-ID 7be846b9a91ad50")
-        .send(reader);
+// Defines the API used when one is not set.
+const DEFAULT_API_URL: &str = "https://api.imgur.com";
+
+// Defines the client ID used when the user does not set their own.
+const DEFAULT_CLIENT_ID: &str = "7be846b9a91ad50";
+
+// Defines the largest image imgur accepts.
+const MAX_FILE_SIZE: u64 = 20 * 1024 * 1024;
+
+// Gets the base URL of the API without a trailing slash.
+fn api_url(config: &HashMap<String, Value>) -> &str {
+    config_str(config, "api_url")
+        .unwrap_or(DEFAULT_API_URL)
+        .trim_end_matches('/')
+}
+
+// Gets the authorization header. Account uploads use the access token and anonymous uploads use the client ID.
+fn authorization(config: &HashMap<String, Value>) -> String {
+    match config_str(config, "access_token") {
+        Some(token) => format!("Bearer {}", token),
+        None => format!("Client-ID {}", client_id(config)),
+    }
+}
+
+// Gets the client ID. The MagicCap client ID is used if the user does not set their own.
+fn client_id(config: &HashMap<String, Value>) -> &str {
+    config_str(config, "client_id").unwrap_or(DEFAULT_CLIENT_ID)
+}
 
+// Gets a option needed to refresh the access token.
+fn refresh_option<'a>(config: &'a HashMap<String, Value>, key: &str) -> Result<&'a str, UploadError> {
+    match config_str(config, key) {
+        Some(s) => Ok(s),
+        None => Err(UploadError::config(
+            key,
+            format!("The {} is needed to refresh the access token.", key.replace('_', " ")),
+        )),
+    }
+}
+
+// Defines the function used to save a token which was refreshed. It is given the option key and the value.
+type SaveToken<'a> = &'a dyn Fn(&str, &Value);
+
+// Saves a refreshed token to the imgur config in the database.
+fn save_to_database(key: &str, value: &Value) {
+    database::set_uploader_config_item("imgur", key, value);
+}
+
+// Gets a new access token with the refresh token. The new tokens are saved so the next upload uses them.
+fn refresh_access_token(config: &mut HashMap<String, Value>, save: SaveToken) -> Result<(), UploadError> {
+    // Send the refresh request.
+    let resp = ureq::post(&format!("{}/oauth2/token", api_url(config))).send_form(&[
+        ("refresh_token", refresh_option(config, "refresh_token")?),
+        ("client_id", client_id(config)),
+        ("client_secret", refresh_option(config, "client_secret")?),
+        ("grant_type", "refresh_token"),
+    ]);
+    let resp = match resp {
+        Ok(resp) => resp,
+        Err(ureq::Error::Status(status, resp)) => {
+            return Err(UploadError::auth(format!(
+                "Failed to refresh the access token ({}): {}",
+                status,
+                resp.into_string().unwrap_or_default()
+            )))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let status = resp.status();
+    let json = read_json_response(resp)?;
+
+    // Save the tokens. imgur can also give us a new refresh token.
+    let access_token = match json["access_token"].as_str() {
+        Some(token) => Value::String(token.to_string()),
+        None => return Err(UploadError::RemoteRejected { status, body: json.to_string() }),
+    };
+    save("access_token", &access_token);
+    config.insert("access_token".to_string(), access_token);
+    if let Some(token) = json["refresh_token"].as_str() {
+        let refresh_token = Value::String(token.to_string());
+        save("refresh_token", &refresh_token);
+        config.insert("refresh_token".to_string(), refresh_token);
+    }
+    Ok(())
+}
+
+// Defines if the access token can be refreshed if imgur rejects it.
+fn can_refresh(config: &HashMap<String, Value>) -> bool {
+    config_str(config, "refresh_token").is_some()
+}
+
+// Sends the image to imgur.
+fn send_upload(
+    filename: &str,
+    config: &HashMap<String, Value>,
+    source: &UploadSource,
+    ctx: &UploadContext,
+) -> Result<ureq::Response, UploadError> {
+    // Build the multipart body with the album if one is set.
+    let mime = guess_mime_type(filename, source)?;
+    let fields = match config_str(config, "album") {
+        Some(album) => vec![("album", album)],
+        None => vec![],
+    };
+    let multipart = multipart_stream(fields, "image", filename, &mime, source)?;
+
+    let resp = ureq::post(&format!("{}/3/image", api_url(config)))
+        .set("Authorization", &authorization(config))
+        .set("Content-Type", &multipart.content_type)
+        .set("Content-Length", &multipart.content_length.to_string())
+        .send(ctx.progress_reader(multipart.reader, multipart.content_length));
     match resp {
-        Ok(resp) => {
-            let json: serde_json::Value = match resp.into_json() {
-                Ok(json) => json,
-                Err(err) => return Err(err.to_string()),
-            };
-            match json["data"]["link"].as_str() {
-                Some(link) => Ok(link.to_string()),
-                None => Err("The response did not contain a link.".to_string()),
+        Ok(resp) => Ok(resp),
+        Err(_) if ctx.is_cancelled() => Err(UploadError::Cancelled),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Uploads the image to imgur. Refreshed tokens are given to the save function.
+fn upload(
+    filename: &str,
+    mut config: HashMap<String, Value>,
+    source: &UploadSource,
+    ctx: &UploadContext,
+    save: SaveToken,
+) -> Result<String, UploadError> {
+    // If there is only a refresh token, get a access token first.
+    if config_str(&config, "access_token").is_none() && can_refresh(&config) {
+        refresh_access_token(&mut config, save)?;
+    }
+
+    // Upload the image. If the access token expired, refresh it and try again.
+    let resp = match send_upload(filename, &config, source, ctx) {
+        Err(UploadError::Auth { .. }) if can_refresh(&config) => {
+            refresh_access_token(&mut config, save)?;
+            send_upload(filename, &config, source, ctx)?
+        }
+        resp => resp?,
+    };
+
+    let status = resp.status();
+    let json = read_json_response(resp)?;
+    match json["data"]["link"].as_str() {
+        Some(link) => {
+            // Anonymous uploads can only be deleted with the delete hash.
+            if let Some(deletehash) = json["data"]["deletehash"].as_str() {
+                ctx.set_deletion_token(deletehash);
             }
+            Ok(link.to_string())
         }
-        Err(err) => Err(err.to_string()),
+        None => Err(UploadError::RemoteRejected { status, body: json.to_string() }),
+    }
+}
+
+// Sends the delete request for the delete hash.
+fn send_delete(deletehash: &str, config: &HashMap<String, Value>) -> Result<(), UploadError> {
+    ureq::delete(&format!(
+        "{}/3/image/{}",
+        api_url(config),
+        urlencoding::encode(deletehash)
+    ))
+    .set("Authorization", &authorization(config))
+    .call()?;
+    Ok(())
+}
+
+fn imgur_support_upload(
+    filename: &str,
+    config: HashMap<String, Value>,
+    source: &UploadSource,
+    ctx: &UploadContext,
+) -> Result<String, UploadError> {
+    upload(filename, config, source, ctx, &save_to_database)
+}
+
+// Deletes the image from imgur with the delete hash. Refreshed tokens are given to the save function.
+fn delete(deletehash: &str, mut config: HashMap<String, Value>, save: SaveToken) -> Result<(), UploadError> {
+    match send_delete(deletehash, &config) {
+        Err(UploadError::Auth { .. }) if can_refresh(&config) => {
+            refresh_access_token(&mut config, save)?;
+            send_delete(deletehash, &config)
+        }
+        res => res,
+    }
+}
+
+fn imgur_support_delete(deletehash: &str, config: HashMap<String, Value>) -> Result<(), UploadError> {
+    delete(deletehash, config, &save_to_database)
+}
+
+// Creates a optional string option.
+fn string_option(name: &str, description: &str, default: Option<&str>, password: bool) -> ConfigOption {
+    ConfigOption::String {
+        name: name.to_string(),
+        description: description.to_string(),
+        default: default.map(|s| s.to_string()),
+        required: false,
+        password,
+        regex: None,
+        validation_error_message: None,
     }
 }
 
 pub fn imgur_support() -> Uploader {
     Uploader {
         name: "imgur".to_string(),
-        description: "Uploads the image to imgur.".to_string(),
+        description: "Uploads the image to imgur or a imgur compatible server.".to_string(),
         icon_path: "/icons/imgur.svg".to_string(),
-        options: vec![],
+        options: vec![
+            (
+                "api_url".to_string(),
+                string_option(
+                    "API URL",
+                    "The base URL of the API. Change this to use a imgur compatible server.",
+                    Some(DEFAULT_API_URL),
+                    false,
+                ),
+            ),
+            (
+                "client_id".to_string(),
+                string_option(
+                    "Client ID",
+                    "The client ID of your imgur application. If unset, the MagicCap client ID is used.",
+                    None,
+                    false,
+                ),
+            ),
+            (
+                "client_secret".to_string(),
+                string_option(
+                    "Client Secret",
+                    "The client secret of your imgur application. This is needed to refresh the access token.",
+                    None,
+                    true,
+                ),
+            ),
+            (
+                "access_token".to_string(),
+                string_option(
+                    "Access Token",
+                    "The OAuth2 access token to upload to your account. If unset, images are uploaded anonymously.",
+                    None,
+                    true,
+                ),
+            ),
+            (
+                "refresh_token".to_string(),
+                string_option(
+                    "Refresh Token",
+                    "The OAuth2 refresh token. If set, the access token is refreshed and saved when it expires.",
+                    None,
+                    true,
+                ),
+            ),
+            (
+                "album".to_string(),
+                string_option(
+                    "Album",
+                    "The album to add the image to. This is the album ID for account uploads or the album delete hash for anonymous uploads.",
+                    None,
+                    false,
+                ),
+            ),
+        ],
         upload: Box::new(imgur_support_upload),
+        delete: Some(Box::new(imgur_support_delete)),
+        accepted_mime_types: vec!["image/*".to_string()],
+        max_file_size: Some(MAX_FILE_SIZE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uploaders::mock_server::{config, MockServer, Response};
+    use serde_json::json;
+    use std::cell::RefCell;
+
+    // Defines the result of a test upload.
+    struct TestUpload {
+        result: Result<String, UploadError>,
+        deletion_token: Option<String>,
+        saved: HashMap<String, Value>,
+    }
+
+    // Uploads a small PNG to the server with the config specified. Saved tokens are kept instead of going to
+    // the database.
+    fn test_upload(config: HashMap<String, Value>) -> TestUpload {
+        let source = UploadSource::from_bytes(b"\x89PNG\r\n\x1a\nimage", "a.png").unwrap();
+        let ctx = UploadContext::background();
+        let saved = RefCell::new(HashMap::new());
+        let save = |key: &str, value: &Value| {
+            saved.borrow_mut().insert(key.to_string(), value.clone());
+        };
+        let result = upload("a.png", config, &source, &ctx, &save);
+        TestUpload {
+            result,
+            deletion_token: ctx.take_deletion_token(),
+            saved: saved.into_inner(),
+        }
+    }
+
+    // Answers an upload with the link and the delete hash.
+    fn uploaded() -> Response {
+        Response::json(json!({"data": {"link": "https://i.imgur.com/abc.png", "deletehash": "del123"}}))
+    }
+
+    #[test]
+    fn uploads_anonymously_and_deletes() {
+        let server = MockServer::start(|req| match req.method.as_str() {
+            "POST" => uploaded(),
+            _ => Response::json(json!({"success": true})),
+        });
+        let config = config(json!({"api_url": server.url(), "album": "album1"}));
+        let upload = test_upload(config.clone());
+        assert_eq!(upload.result.unwrap(), "https://i.imgur.com/abc.png");
+        assert_eq!(upload.deletion_token.as_deref(), Some("del123"));
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/3/image");
+        assert_eq!(
+            requests[0].header("Authorization"),
+            Some(format!("Client-ID {}", DEFAULT_CLIENT_ID).as_str())
+        );
+        let body = requests[0].body_str();
+        assert!(body.contains("name=\"album\"\r\n\r\nalbum1"), "{}", body);
+        assert!(body.contains("name=\"image\"; filename=\"a.png\""), "{}", body);
+
+        delete("del123", config, &|_, _| panic!("nothing should be saved")).unwrap();
+        let requests = server.requests();
+        assert_eq!(requests[1].method, "DELETE");
+        assert_eq!(requests[1].path, "/3/image/del123");
+    }
+
+    #[test]
+    fn uploads_to_the_account() {
+        let server = MockServer::start(|_| uploaded());
+        let upload = test_upload(config(json!({
+            "api_url": server.url(),
+            "client_id": "mine",
+            "access_token": "token1",
+        })));
+        assert_eq!(upload.result.unwrap(), "https://i.imgur.com/abc.png");
+        assert_eq!(server.requests()[0].header("Authorization"), Some("Bearer token1"));
+    }
+
+    #[test]
+    fn refreshes_expired_access_tokens() {
+        let server = MockServer::start(|req| match (req.method.as_str(), req.path.as_str()) {
+            ("POST", "/oauth2/token") => Response::json(json!({"access_token": "new", "refresh_token": "refresh2"})),
+            _ if req.header("Authorization") == Some("Bearer new") => uploaded(),
+            _ => Response::new(401),
+        });
+        let upload = test_upload(config(json!({
+            "api_url": server.url(),
+            "client_id": "mine",
+            "client_secret": "shh",
+            "access_token": "old",
+            "refresh_token": "refresh1",
+        })));
+        assert_eq!(upload.result.unwrap(), "https://i.imgur.com/abc.png");
+
+        let requests = server.requests();
+        let calls: Vec<&str> = requests.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(calls, vec!["/3/image", "/oauth2/token", "/3/image"]);
+        let form = requests[1].body_str();
+        assert!(form.contains("refresh_token=refresh1"), "{}", form);
+        assert!(form.contains("client_id=mine"), "{}", form);
+        assert!(form.contains("client_secret=shh"), "{}", form);
+        assert!(form.contains("grant_type=refresh_token"), "{}", form);
+
+        // The new tokens are saved for the next upload.
+        assert_eq!(upload.saved["access_token"], "new");
+        assert_eq!(upload.saved["refresh_token"], "refresh2");
+    }
+
+    #[test]
+    fn rejected_refreshes_are_auth_errors() {
+        let server = MockServer::start(|_| Response::new(400).body("invalid_grant"));
+        let upload = test_upload(config(json!({
+            "api_url": server.url(),
+            "client_id": "mine",
+            "client_secret": "shh",
+            "refresh_token": "refresh1",
+        })));
+        match upload.result {
+            Err(UploadError::Auth { message }) => assert!(message.contains("invalid_grant"), "{}", message),
+            other => panic!("expected an auth error, got {:?}", other),
+        }
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn refreshes_with_the_default_client_id() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/oauth2/token" => Response::json(json!({"access_token": "new"})),
+            _ => uploaded(),
+        });
+        let upload = test_upload(config(json!({
+            "api_url": server.url(),
+            "client_secret": "shh",
+            "refresh_token": "refresh1",
+        })));
+        assert_eq!(upload.result.unwrap(), "https://i.imgur.com/abc.png");
+        let form = server.requests()[0].body_str();
+        assert!(form.contains(&format!("client_id={}", DEFAULT_CLIENT_ID)), "{}", form);
+        assert_eq!(upload.saved.keys().collect::<Vec<_>>(), vec!["access_token"]);
+    }
+
+    #[test]
+    fn refreshing_needs_the_client_secret() {
+        let server = MockServer::start(|_| Response::new(401));
+        let upload = test_upload(config(json!({
+            "api_url": server.url(),
+            "access_token": "old",
+            "refresh_token": "refresh1",
+        })));
+        match upload.result {
+            Err(UploadError::Config { field, message }) => {
+                assert_eq!(field.as_deref(), Some("client_secret"));
+                assert_eq!(message, "The client secret is needed to refresh the access token.");
+            }
+            other => panic!("expected a config error, got {:?}", other),
+        }
+        assert!(upload.saved.is_empty());
     }
 }
@@ -1,37 +1,26 @@
+use super::{UploadError, UploadSource};
 use mime::Mime;
 use mime_sniffer::MimeTypeSniffer;
-use std::{
-    io::{BufRead, BufReader, Error},
-    path::Path,
-    str::FromStr,
-};
+use std::{io::Read, path::Path, str::FromStr};
 
-pub fn guess_mime_type(
-    filename: &str,
-    reader: Box<dyn std::io::Read + Send + Sync>,
-) -> Result<(Mime, Box<dyn std::io::Read + Send + Sync>), Error> {
+pub fn guess_mime_type(filename: &str, source: &UploadSource) -> Result<Mime, UploadError> {
     // Try to guess the MIME type by the file extension.
     let mime = mime_guess::from_path(Path::new(filename)).first();
     if let Some(mime) = mime {
-        return Ok((mime, reader));
+        return Ok(mime);
     }
 
-    // Try to guess the MIME type by the file contents. Use a buffered reader to avoid consuming the
-    // reader in a way that a consumer later breaks.
-    let mut reader = BufReader::with_capacity(512, reader);
-    if let Err(e) = reader.fill_buf() {
-        return Err(e);
+    // Try to guess the MIME type by the start of the file contents.
+    let mut buf = Vec::with_capacity(512);
+    if let Err(e) = source.open()?.take(512).read_to_end(&mut buf) {
+        return Err(UploadError::io(format!("Failed to read the file: {}", e)));
     }
-    let buf = reader.buffer();
-    let mime = buf.sniff_mime_type();
+    let mime = buf.as_slice().sniff_mime_type();
 
     // Check if a MIME type was found.
-    let mime = Mime::from_str(match mime {
+    Ok(Mime::from_str(match mime {
         Some(mime) => mime,
         None => "application/octet-stream",
     })
-    .unwrap();
-
-    // Return the MIME type and the reader rewound.
-    Ok((mime, Box::new(reader)))
+    .unwrap())
 }
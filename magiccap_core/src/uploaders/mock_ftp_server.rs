@@ -0,0 +1,217 @@
+// Defines a small FTP server which runs in the test process. It keeps the files in memory and only supports
+// the commands the FTP uploader sends.
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+// Defines the login the server accepts.
+pub const USERNAME: &str = "magiccap";
+pub const PASSWORD: &str = "hunter2";
+
+// Defines the state shared with the connections.
+#[derive(Default)]
+struct State {
+    folders: HashSet<String>,
+    files: HashMap<String, Vec<u8>>,
+    commands: Vec<String>,
+}
+
+// Defines the running server. It stops when this is dropped.
+pub struct MockFtpServer {
+    port: u16,
+    state: Arc<Mutex<State>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockFtpServer {
+    // Starts the server on a free port with the folders specified. The root folder always exists.
+    pub fn start(folders: &[&str]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut state = State::default();
+        state.folders.insert("/".to_string());
+        for folder in folders {
+            state.folders.insert(normalize("/", folder));
+        }
+        let state = Arc::new(Mutex::new(state));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let state_cpy = state.clone();
+        let stopped_cpy = stopped.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopped_cpy.load(Ordering::SeqCst) {
+                    return;
+                }
+                if let Ok(stream) = stream {
+                    let state = state_cpy.clone();
+                    std::thread::spawn(move || handle_connection(stream, &state));
+                }
+            }
+        });
+
+        Self {
+            port,
+            state,
+            stopped,
+        }
+    }
+
+    // Gets the port of the server.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    // Gets the file at the absolute path specified.
+    pub fn file(&self, path: &str) -> Option<Vec<u8>> {
+        self.state.lock().unwrap().files.get(path).cloned()
+    }
+
+    // Gets the commands the server received so far. Passwords are hidden.
+    pub fn commands(&self) -> Vec<String> {
+        self.state.lock().unwrap().commands.clone()
+    }
+}
+
+impl Drop for MockFtpServer {
+    fn drop(&mut self) {
+        // Wake up the accept loop so it sees the server was stopped.
+        self.stopped.store(true, Ordering::SeqCst);
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+    }
+}
+
+// Joins the path onto the folder and removes any trailing slash.
+fn normalize(cwd: &str, path: &str) -> String {
+    let joined = match path.starts_with('/') {
+        true => path.to_string(),
+        false => format!("{}/{}", cwd.trim_end_matches('/'), path),
+    };
+    match joined.trim_end_matches('/') {
+        "" => "/".to_string(),
+        p => p.to_string(),
+    }
+}
+
+// Sends a reply line to the client.
+fn reply(writer: &mut TcpStream, line: &str) -> std::io::Result<()> {
+    writer.write_all(format!("{}\r\n", line).as_bytes())
+}
+
+// Answers the commands on a control connection until the client quits.
+fn handle_connection(stream: TcpStream, state: &Mutex<State>) {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(10)));
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    if reply(&mut writer, "220 Mock FTP server ready.").is_err() {
+        return;
+    }
+
+    let mut username = String::new();
+    let mut logged_in = false;
+    let mut cwd = "/".to_string();
+    let mut passive: Option<TcpListener> = None;
+    loop {
+        // Read the command.
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        let (command, arg) = match line.split_once(' ') {
+            Some((command, arg)) => (command.to_uppercase(), arg.to_string()),
+            None => (line.to_uppercase(), String::new()),
+        };
+        state.lock().unwrap().commands.push(match command.as_str() {
+            "PASS" => "PASS ****".to_string(),
+            _ => line.to_string(),
+        });
+
+        // Everything but the login needs the user to be logged in.
+        if !logged_in && !matches!(command.as_str(), "USER" | "PASS" | "QUIT") {
+            if reply(&mut writer, "530 Please login with USER and PASS.").is_err() {
+                return;
+            }
+            continue;
+        }
+
+        let response = match command.as_str() {
+            "USER" => {
+                username = arg;
+                "331 Password required.".to_string()
+            }
+            "PASS" => match username == USERNAME && arg == PASSWORD {
+                true => {
+                    logged_in = true;
+                    "230 Logged in.".to_string()
+                }
+                false => "530 Login incorrect.".to_string(),
+            },
+            "TYPE" => "200 Type set.".to_string(),
+            "CWD" => {
+                let path = normalize(&cwd, &arg);
+                match state.lock().unwrap().folders.contains(&path) {
+                    true => {
+                        cwd = path;
+                        "250 Directory changed.".to_string()
+                    }
+                    false => "550 No such directory.".to_string(),
+                }
+            }
+            "PASV" => {
+                let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+                let port = listener.local_addr().unwrap().port();
+                passive = Some(listener);
+                format!("227 Entering Passive Mode (127,0,0,1,{},{}).", port / 256, port % 256)
+            }
+            "STOR" => {
+                let listener = match passive.take() {
+                    Some(listener) => listener,
+                    None => {
+                        if reply(&mut writer, "425 Use PASV first.").is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                };
+                if reply(&mut writer, "150 Ready to receive.").is_err() {
+                    return;
+                }
+
+                // Read the file until the client closes the data connection.
+                let mut data = Vec::new();
+                let received = listener.accept().and_then(|(mut stream, _)| stream.read_to_end(&mut data));
+                match received {
+                    Ok(_) => {
+                        state.lock().unwrap().files.insert(normalize(&cwd, &arg), data);
+                        "226 Transfer complete.".to_string()
+                    }
+                    Err(_) => "426 Transfer aborted.".to_string(),
+                }
+            }
+            "DELE" => match state.lock().unwrap().files.remove(&normalize(&cwd, &arg)) {
+                Some(_) => "250 File deleted.".to_string(),
+                None => "550 No such file.".to_string(),
+            },
+            "QUIT" => {
+                let _ = reply(&mut writer, "221 Goodbye.");
+                return;
+            }
+            _ => "502 Command not implemented.".to_string(),
+        };
+        if reply(&mut writer, &response).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,152 @@
+// Defines a small S3 compatible server which runs in the test process. It acts like a path style endpoint
+// with a single bucket and keeps the objects in memory.
+use super::mock_server::{config, MockServer, Request, Response};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+// Defines the bucket and the keys the server accepts.
+pub const BUCKET: &str = "bucket";
+pub const ACCESS_KEY_ID: &str = "AKID";
+pub const SECRET_ACCESS_KEY: &str = "secret";
+
+// Defines the objects and multipart uploads stored by the server.
+#[derive(Default)]
+pub struct Bucket {
+    pub objects: HashMap<String, Vec<u8>>,
+    pub parts: HashMap<String, Vec<(u32, Vec<u8>)>>,
+    pub aborted: Vec<String>,
+}
+
+// Defines the running server. It stops when this is dropped.
+pub struct MockS3Server {
+    server: MockServer,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl MockS3Server {
+    // Starts the server on a free port. The second part of any key containing "reject" is refused so failed
+    // multipart uploads can be tested.
+    pub fn start() -> Self {
+        let bucket = Arc::new(Mutex::new(Bucket::default()));
+        let bucket_cpy = bucket.clone();
+        let server = MockServer::start(move |req| handle(req, &mut bucket_cpy.lock().unwrap()));
+        Self { server, bucket }
+    }
+
+    // Gets the URL of the server without a trailing slash.
+    pub fn url(&self) -> &str {
+        self.server.url()
+    }
+
+    // Gets the requests the server received so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.server.requests()
+    }
+
+    // Gets the bucket so the objects can be checked or added.
+    pub fn bucket(&self) -> MutexGuard<'_, Bucket> {
+        self.bucket.lock().unwrap()
+    }
+
+    // Gets the uploader config for the server with the extra options specified.
+    pub fn config(&self, extra: Value) -> HashMap<String, Value> {
+        let mut s3_config = config(serde_json::json!({
+            "endpoint": self.url(),
+            "access_key_id": ACCESS_KEY_ID,
+            "secret_access_key": SECRET_ACCESS_KEY,
+            "bucket": BUCKET,
+            "path_style": true,
+        }));
+        s3_config.extend(config(extra));
+        s3_config
+    }
+}
+
+// Parses the query string into a map.
+fn query(req: &Request) -> HashMap<String, String> {
+    req.query
+        .split('&')
+        .filter(|part| !part.is_empty())
+        .map(|part| match part.split_once('=') {
+            Some((k, v)) => (k.to_string(), v.to_string()),
+            None => (part.to_string(), String::new()),
+        })
+        .collect()
+}
+
+// Gets the object data from the body. Newer SDKs send checksums by wrapping the body in aws-chunked encoding,
+// which is undone here.
+fn object_data(req: &Request) -> Vec<u8> {
+    let chunked = req.header("Content-Encoding").is_some_and(|v| v.contains("aws-chunked"))
+        || req.header("x-amz-content-sha256").is_some_and(|v| v.starts_with("STREAMING-"));
+    if !chunked {
+        return req.body.clone();
+    }
+    let mut data = Vec::new();
+    let mut rest = req.body.as_slice();
+    loop {
+        let line_end = rest.windows(2).position(|w| w == b"\r\n").unwrap();
+        let size_line = String::from_utf8_lossy(&rest[..line_end]).to_string();
+        let size = usize::from_str_radix(size_line.split(';').next().unwrap().trim(), 16).unwrap();
+        if size == 0 {
+            return data;
+        }
+        rest = &rest[line_end + 2..];
+        data.extend_from_slice(&rest[..size]);
+        rest = &rest[size + 2..];
+    }
+}
+
+// Answers a request to the bucket.
+fn handle(req: &Request, bucket: &mut Bucket) -> Response {
+    // Only requests signed with the access key are let through.
+    let credential = format!("AWS4-HMAC-SHA256 Credential={}/", ACCESS_KEY_ID);
+    if req.header("Authorization").map_or(true, |v| !v.starts_with(&credential)) {
+        return Response::new(403).body("<Error><Code>AccessDenied</Code></Error>");
+    }
+
+    let query = query(req);
+    let key = req.path.trim_start_matches(&format!("/{}/", BUCKET)).to_string();
+    match (req.method.as_str(), query.get("uploadId")) {
+        ("POST", None) if query.contains_key("uploads") => Response::new(200).body(format!(
+            "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>upload-1</UploadId></InitiateMultipartUploadResult>",
+            BUCKET, key
+        )),
+        ("PUT", Some(upload_id)) => {
+            let part_number: u32 = query["partNumber"].parse().unwrap();
+            if key.contains("reject") && part_number == 2 {
+                return Response::new(400).body("<Error><Code>InvalidPart</Code></Error>");
+            }
+            let data = object_data(req);
+            bucket.parts.entry(upload_id.clone()).or_default().push((part_number, data));
+            Response::new(200).header("ETag", &format!("\"etag-{}\"", part_number))
+        }
+        ("POST", Some(upload_id)) => {
+            let mut parts = bucket.parts.remove(upload_id).unwrap_or_default();
+            parts.sort_by_key(|(n, _)| *n);
+            bucket.objects.insert(key.clone(), parts.into_iter().flat_map(|(_, d)| d).collect());
+            Response::new(200).body(format!(
+                "<CompleteMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><ETag>\"done\"</ETag></CompleteMultipartUploadResult>",
+                BUCKET, key
+            ))
+        }
+        ("DELETE", Some(upload_id)) => {
+            bucket.parts.remove(upload_id);
+            bucket.aborted.push(upload_id.clone());
+            Response::new(204)
+        }
+        ("PUT", None) => {
+            let data = object_data(req);
+            bucket.objects.insert(key, data);
+            Response::new(200).header("ETag", "\"etag\"")
+        }
+        ("DELETE", None) => {
+            bucket.objects.remove(&key);
+            Response::new(204)
+        }
+        _ => Response::new(400).body("<Error><Code>NotImplemented</Code></Error>"),
+    }
+}
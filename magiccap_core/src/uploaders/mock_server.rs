@@ -0,0 +1,249 @@
+// Defines a small HTTP server which runs in the test process. The uploaders are pointed at it so the requests
+// they make can be checked without a real server.
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+// Defines a request the server received.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    // Gets the header with the name specified. Header names are not case sensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // Gets the body as a string.
+    pub fn body_str(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+}
+
+// Defines the response the handler sends back.
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    // Creates an empty response with the status specified.
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    // Creates a 200 response with the JSON specified.
+    pub fn json(value: Value) -> Self {
+        Self::new(200)
+            .header("Content-Type", "application/json")
+            .body(value.to_string())
+    }
+
+    // Adds a header to the response.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    // Sets the body of the response.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+// Defines the function which answers each request.
+type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+// Defines the running server. It stops when this is dropped.
+pub struct MockServer {
+    url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    // Starts the server on a free port. Each request is recorded and answered with the handler.
+    pub fn start<F: Fn(&Request) -> Response + Send + Sync + 'static>(handler: F) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let requests_cpy = requests.clone();
+        let stopped_cpy = stopped.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopped_cpy.load(Ordering::SeqCst) {
+                    return;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let requests = requests_cpy.clone();
+                let handler = handler.clone();
+                std::thread::spawn(move || handle_connection(stream, &requests, handler.as_ref()));
+            }
+        });
+
+        Self {
+            url,
+            requests,
+            stopped,
+        }
+    }
+
+    // Gets the URL of the server without a trailing slash.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // Gets the host and port of the server.
+    pub fn host(&self) -> &str {
+        self.url.trim_start_matches("http://")
+    }
+
+    // Gets the requests the server received so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        // Wake up the accept loop so it sees the server was stopped.
+        self.stopped.store(true, Ordering::SeqCst);
+        let _ = TcpStream::connect(self.host());
+    }
+}
+
+// Reads each request on the connection and answers it. Connections are kept alive since clients pool them.
+fn handle_connection(stream: TcpStream, requests: &Mutex<Vec<Request>>, handler: &Handler) {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(10)));
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    while let Some(req) = read_request(&mut reader, &mut writer) {
+        let resp = handler(&req);
+        let head_request = req.method == "HEAD";
+        requests.lock().unwrap().push(req);
+
+        let mut data = format!("HTTP/1.1 {} Mock\r\n", resp.status);
+        for (name, value) in &resp.headers {
+            data += &format!("{}: {}\r\n", name, value);
+        }
+        data += &format!("Content-Length: {}\r\n\r\n", resp.body.len());
+        let mut data = data.into_bytes();
+        if !head_request {
+            data.extend_from_slice(&resp.body);
+        }
+        if writer.write_all(&data).is_err() {
+            return;
+        }
+    }
+}
+
+// Reads a line without the line ending.
+fn read_line(reader: &mut impl BufRead) -> Option<String> {
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+    }
+}
+
+// Reads a request from the connection. Returns None when the client closes it.
+fn read_request(reader: &mut BufReader<TcpStream>, writer: &mut TcpStream) -> Option<Request> {
+    // Read the request line.
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split(' ');
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (target, String::new()),
+    };
+
+    // Read the headers.
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(k, _): &&(String, String)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.to_lowercase())
+    };
+
+    // Tell the client to send the body if it is waiting for us.
+    if header("expect").as_deref() == Some("100-continue") {
+        writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").ok()?;
+    }
+
+    // Read the body.
+    let mut body = Vec::new();
+    if header("transfer-encoding").is_some_and(|v| v.contains("chunked")) {
+        loop {
+            let size_line = read_line(reader)?;
+            let size = usize::from_str_radix(size_line.split(';').next()?.trim(), 16).ok()?;
+            if size == 0 {
+                // Skip any trailers.
+                while !read_line(reader)?.is_empty() {}
+                break;
+            }
+            let mut chunk = vec![0; size];
+            reader.read_exact(&mut chunk).ok()?;
+            body.extend_from_slice(&chunk);
+            read_line(reader)?;
+        }
+    } else if let Some(len) = header("content-length") {
+        body = vec![0; len.parse().ok()?];
+        reader.read_exact(&mut body).ok()?;
+    }
+
+    Some(Request {
+        method,
+        path,
+        query,
+        headers,
+        body,
+    })
+}
+
+// Turns JSON into the config passed to the uploaders.
+pub fn config(value: Value) -> HashMap<String, Value> {
+    serde_json::from_value(value).unwrap()
+}
@@ -7,8 +7,25 @@ mod shell;
 mod ftp;
 mod s3;
 mod sftp;
-
-use std::{collections::HashMap, sync::{atomic::Ordering, RwLock}};
+mod webdav;
+mod source;
+pub(crate) mod multipart;
+mod context;
+mod error;
+mod validation;
+#[cfg(test)]
+mod mock_ftp_server;
+#[cfg(test)]
+mod mock_s3_server;
+#[cfg(test)]
+mod mock_server;
+pub use source::UploadSource;
+pub use context::{CancellationToken, CaptureMetadata, ProgressCallback, UploadContext};
+pub use error::{read_json_response, UploadError};
+pub use self::mime::guess_mime_type;
+pub use validation::validate_config;
+
+use std::{collections::HashMap, sync::{atomic::Ordering, Arc, RwLock}};
 use custom::IntoUploader;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
@@ -73,6 +90,18 @@ impl ConfigOption {
     }
 }
 
+// Defines the function that performs an upload. It is given the filename, the uploader configuration, the
+// file to upload, and the context used to report progress and check for cancellation.
+pub type UploadHandler = Box<dyn Fn(
+    &str, HashMap<String, serde_json::Value>, &UploadSource, &UploadContext,
+) -> Result<String, UploadError> + Send + Sync>;
+
+// Defines the function that deletes an uploaded file. It is given the deletion token the uploader set on the
+// context during the upload and the uploader configuration.
+pub type DeleteHandler = Box<dyn Fn(
+    &str, HashMap<String, serde_json::Value>,
+) -> Result<(), UploadError> + Send + Sync>;
+
 // Defines the type for a uploader.
 #[derive(Serialize)]
 pub struct Uploader {
@@ -82,98 +111,202 @@ pub struct Uploader {
     pub options: Vec<(String, ConfigOption)>,
 
     #[serde(skip)]
-    pub upload: Box<dyn Fn(
-        &str, HashMap<String, serde_json::Value>,
-        Box<dyn std::io::Read + Send + Sync>,
-    ) -> Result<String, String> + Send + Sync>,
+    pub upload: UploadHandler,
+
+    // Defines the handler used to delete files. This is None if the uploader cannot delete files.
+    #[serde(skip)]
+    pub delete: Option<DeleteHandler>,
+
+    // Defines the MIME types the uploader accepts. These can end in /* to match any subtype, and an empty list
+    // means any file is accepted.
+    pub accepted_mime_types: Vec<String>,
+
+    // Defines the largest file in bytes the uploader accepts. None means there is no limit.
+    pub max_file_size: Option<u64>,
+}
+
+// Checks if the MIME type matches the pattern, which can end in /* to match any subtype.
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    if pattern == "*/*" || pattern.eq_ignore_ascii_case(mime) {
+        return true;
+    }
+    match pattern.strip_suffix("/*") {
+        Some(prefix) => mime
+            .split_once('/')
+            .map(|(kind, _)| kind.eq_ignore_ascii_case(prefix))
+            .unwrap_or(false),
+        None => false,
+    }
+}
+
+impl Uploader {
+    // Returns an error if the uploader cannot take a file with the MIME type and size.
+    pub fn check_file(&self, mime: &str, size: u64) -> Result<(), String> {
+        if !self.accepted_mime_types.is_empty()
+            && !self.accepted_mime_types.iter().any(|p| mime_matches(p, mime))
+        {
+            return Err(format!("{} does not accept {} files.", self.name, mime));
+        }
+        if let Some(max_file_size) = self.max_file_size {
+            if size > max_file_size {
+                return Err(format!(
+                    "The file is {:.1} MB but {} only accepts files up to {:.1} MB.",
+                    size as f64 / (1024.0 * 1024.0),
+                    self.name,
+                    max_file_size as f64 / (1024.0 * 1024.0),
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 // Defines the uploaders.
-pub static UPLOADERS: Lazy<HashMap<String, Uploader>> = Lazy::new(|| {
+pub static UPLOADERS: Lazy<HashMap<String, Arc<Uploader>>> = Lazy::new(|| {
     let mut uploaders = HashMap::new();
 
-    uploaders.insert("elixire".to_string(), elixire::elixire_support());
-    uploaders.insert("imgur".to_string(), imgur::imgur_support());
-    uploaders.insert("shell".to_string(), shell::shell_support());
-    uploaders.insert("ftp".to_string(), ftp::ftp_support());
-    uploaders.insert("sftp".to_string(), sftp::sftp_support());
-    uploaders.insert("s3".to_string(), s3::s3_support());
+    uploaders.insert("elixire".to_string(), Arc::new(elixire::elixire_support()));
+    uploaders.insert("imgur".to_string(), Arc::new(imgur::imgur_support()));
+    uploaders.insert("shell".to_string(), Arc::new(shell::shell_support()));
+    uploaders.insert("ftp".to_string(), Arc::new(ftp::ftp_support()));
+    uploaders.insert("sftp".to_string(), Arc::new(sftp::sftp_support()));
+    uploaders.insert("s3".to_string(), Arc::new(s3::s3_support()));
+    uploaders.insert("webdav".to_string(), Arc::new(webdav::webdav_support()));
 
     uploaders
 });
 
-// Defines all of the leaked uploaders. This is honestly fine since the user won't be flooding this with custom uploaders.
-pub static LEAKED_UPLOADERS: Lazy<RwLock<HashMap<String, &'static Uploader>>> = Lazy::new(|| Default::default());
+// Defines the custom uploaders which have been built, keyed by ID. The hash of the config the uploader was
+// built from is stored with it so a config changed behind our back is rebuilt. Uploads which are running
+// hold their own reference, so evicting a uploader does not affect them.
+static CUSTOM_UPLOADER_CACHE: Lazy<RwLock<HashMap<String, (String, Arc<Uploader>)>>> =
+    Lazy::new(Default::default);
 
 // Check if the uploader is a cache hit.
-fn uploader_cache_hit(hash: &str) -> Option<&'static Uploader> {
-    let leaked_uploaders = LEAKED_UPLOADERS.read().unwrap();
-    let res = leaked_uploaders.get(hash).copied();
-    drop(leaked_uploaders);
-    res
+fn uploader_cache_hit(uploader_id: &str, hash: &str) -> Option<Arc<Uploader>> {
+    let cache = CUSTOM_UPLOADER_CACHE.read().unwrap();
+    match cache.get(uploader_id) {
+        Some((h, uploader)) if h == hash => Some(uploader.clone()),
+        _ => None,
+    }
+}
+
+// Set the uploader in the cache. This replaces any older version of it.
+fn uploader_cache_set(uploader_id: &str, hash: String, uploader: Arc<Uploader>) {
+    let mut cache = CUSTOM_UPLOADER_CACHE.write().unwrap();
+    cache.insert(uploader_id.to_string(), (hash, uploader));
 }
 
-// Set the hash in the uploader cache.
-fn uploader_cache_set(hash: String, uploader: &'static Uploader) {
-    let mut leaked_uploaders = LEAKED_UPLOADERS.write().unwrap();
-    leaked_uploaders.insert(hash, uploader);
-    drop(leaked_uploaders);
+// Removes the uploader from the cache. This is called when a custom uploader is edited or deleted.
+fn uploader_cache_evict(uploader_id: &str) {
+    let mut cache = CUSTOM_UPLOADER_CACHE.write().unwrap();
+    cache.remove(uploader_id);
+}
+
+// Builds a custom uploader from its saved config after migrating it to the latest version. The migrated config
+// is returned if it changed so it can be saved.
+fn build_custom_uploader(
+    uploader_id: &str, custom_uploader: serde_json::Value,
+) -> Result<(Uploader, Option<serde_json::Value>), UploadError> {
+    // Migrate the custom uploader to the latest version.
+    let (custom_uploader, migrated) = match custom::migrate_uploader(custom_uploader) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(UploadError::Config {
+                field: None,
+                message: format!("The custom uploader {} could not be migrated: {}", uploader_id, e),
+            });
+        }
+    };
+    let migrated = match migrated {
+        true => Some(custom_uploader.clone()),
+        false => None,
+    };
+
+    // Deserialize the custom uploader.
+    let custom_uploader: custom::CustomUploader = match serde_json::from_value(custom_uploader) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(UploadError::Config {
+                field: None,
+                message: format!("The custom uploader {} is not valid: {}", uploader_id, e),
+            });
+        }
+    };
+
+    // Create the uploader.
+    let delete_func = custom_uploader.handler.delete_handler();
+    let handler_func = custom_uploader.handler.into_uploader(uploader_id.to_string());
+    let uploader = Uploader {
+        name: uploader_id.to_string(),
+        description: custom_uploader.description,
+        icon_path: custom_uploader.encoded_icon,
+        options: custom_uploader.config.into_inner(),
+        upload: Box::new(handler_func),
+        delete: delete_func,
+        accepted_mime_types: custom_uploader.metadata.supported_mime_types,
+        max_file_size: custom_uploader.metadata.max_file_size,
+    };
+    Ok((uploader, migrated))
 }
 
 // Get the uploader by ID.
-pub fn get_uploader(uploader_id: &str) -> Option<&'static Uploader> {
+pub fn get_uploader(uploader_id: &str) -> Option<Arc<Uploader>> {
+    load_uploader(uploader_id).ok()
+}
+
+// Loads the uploader by ID. Unlike get_uploader, this says why a custom uploader could not be loaded.
+pub fn load_uploader(uploader_id: &str) -> Result<Arc<Uploader>, UploadError> {
     // Try to get from the official uploaders.
     if let Some(uploader) = UPLOADERS.get(uploader_id) {
-        return Some(uploader);
+        return Ok(uploader.clone());
     }
 
     // Get the custom uploader from the configuration.
     let key = "custom_uploader_".to_string() + uploader_id;
+    let not_found = || UploadError::Config {
+        field: None,
+        message: format!("The uploader {} does not exist.", uploader_id),
+    };
     let custom_uploader = match database::get_config_option(&key) {
         Some(v) => v,
-        None => return None,
+        None => return Err(not_found()),
     };
 
     // Hash the value.
     let h = match custom_uploader.as_object() {
         Some(v) => v,
-        None => return None,
+        None => return Err(not_found()),
     };
     let hash = sha256::digest(
         serde_json::to_string(&h).unwrap().as_bytes()
     );
 
     // Check if the uploader is a cache hit.
-    if let Some(uploader) = uploader_cache_hit(&hash) {
-        return Some(uploader);
+    if let Some(uploader) = uploader_cache_hit(uploader_id, &hash) {
+        return Ok(uploader);
     }
 
-    // Deserialize the custom uploader.
-    let custom_uploader: custom::CustomUploader = match serde_json::from_value(custom_uploader) {
-        Ok(v) => v,
-        Err(_) => return None,
-    };
-
-    // Create the uploader.
-    let handler_func = custom_uploader.handler.into_uploader(uploader_id.to_string());
-    let uploader = Uploader {
-        name: uploader_id.to_string(),
-        description: custom_uploader.description,
-        icon_path: custom_uploader.encoded_icon,
-        options: custom_uploader.config.into_inner(),
-        upload: Box::new(handler_func),
+    // Build the uploader. If it was migrated, the new version is written back so this only happens once.
+    let (uploader, migrated) = build_custom_uploader(uploader_id, custom_uploader)?;
+    let hash = match migrated {
+        Some(custom_uploader) => {
+            database::set_config_option(&key, &custom_uploader);
+            sha256::digest(serde_json::to_string(&custom_uploader).unwrap().as_bytes())
+        }
+        None => hash,
     };
 
-    // Leak the memory and insert into the cache.
-    let uploader_mem_leak = Box::leak(Box::new(uploader));
-    uploader_cache_set(hash.clone(), uploader_mem_leak);
+    // Insert into the cache.
+    let uploader = Arc::new(uploader);
+    uploader_cache_set(uploader_id, hash, uploader.clone());
 
     // Return the uploader.
-    Some(uploader_mem_leak)
+    Ok(uploader)
 }
 
 // Gets all the custom uploaders loaded into MagicCap.
-pub fn get_custom_uploaders() -> HashMap<String, &'static Uploader> {
+pub fn get_custom_uploaders() -> HashMap<String, Arc<Uploader>> {
     // Get the custom uploader keys.
     let uploader_keys = match database::get_config_option("custom_uploaders") {
         Some(v) => v,
@@ -201,14 +334,42 @@ pub fn get_custom_uploaders() -> HashMap<String, &'static Uploader> {
     uploaders
 }
 
+// Gets why each custom uploader which is saved could not be loaded.
+pub fn get_custom_uploader_errors() -> HashMap<String, String> {
+    // Get the custom uploader keys.
+    let uploader_keys = match database::get_config_option("custom_uploaders") {
+        Some(v) => v,
+        None => return HashMap::new(),
+    };
+    let uploader_keys = match uploader_keys.as_array() {
+        Some(v) => v,
+        None => return HashMap::new(),
+    };
+
+    // Try to load each uploader and keep the errors.
+    let mut errors = HashMap::new();
+    for key in uploader_keys.iter().filter_map(|k| k.as_str()) {
+        if let Err(e) = load_uploader(key) {
+            errors.insert(key.to_string(), e.to_string());
+        }
+    }
+    errors
+}
+
 // Defines a custom uploader insert error.
 pub enum CustomUploaderInsertError {
     SerializationError(String),
     AlreadyExists,
+    InvalidName(String),
 }
 
 // Inserts a custom uploader into MagicCap.
 pub fn insert_custom_uploader(uploader: custom::CustomUploader, replace: bool) -> Result<(), CustomUploaderInsertError> {
+    // The name is used in config keys and as the folder for PHP data, so make sure it is a safe name.
+    if let Err(e) = custom::check_uploader_name(&uploader.name) {
+        return Err(CustomUploaderInsertError::InvalidName(e));
+    }
+
     // Serialize the uploader.
     let serialized = match serde_json::to_value(&uploader) {
         Ok(v) => v,
@@ -223,8 +384,9 @@ pub fn insert_custom_uploader(uploader: custom::CustomUploader, replace: bool) -
         }
     }
 
-    // Insert the uploader.
+    // Insert the uploader and drop the old version of it.
     database::set_config_option(&uploader_key, &serialized);
+    uploader_cache_evict(&uploader.name);
     let mut custom_uploaders = match database::get_config_option("custom_uploaders") {
         Some(v) => v,
         None => serde_json::Value::Array(Vec::new()),
@@ -234,8 +396,10 @@ pub fn insert_custom_uploader(uploader: custom::CustomUploader, replace: bool) -
         None => return Err(CustomUploaderInsertError::SerializationError("The custom uploaders is not an array.".to_string())),
     };
 
-    // Write the uploader key.
-    custom_uploaders.push(serde_json::Value::String(uploader.name));
+    // Write the uploader key if it is not already there from the version being replaced.
+    if !custom_uploaders.iter().any(|v| v.as_str() == Some(uploader.name.as_str())) {
+        custom_uploaders.push(serde_json::Value::String(uploader.name));
+    }
 
     // Set the custom uploaders.
     database::set_config_option("custom_uploaders", &serde_json::Value::Array(custom_uploaders.clone()));
@@ -247,6 +411,7 @@ pub fn delete_custom_uploader(uploader_name: &str) {
     // Delete the custom uploader.
     let uploader_key = "custom_uploader_".to_string() + uploader_name;
     database::delete_config_option(&uploader_key);
+    uploader_cache_evict(uploader_name);
 
     // Delete the custom uploader from the custom uploaders.
     let mut custom_uploaders = match database::get_config_option("custom_uploaders") {
@@ -272,31 +437,236 @@ pub fn delete_custom_uploader(uploader_name: &str) {
     }
 }
 
+// Gets a list of uploaders from the config. The option can either be a single uploader ID or an ordered list
+// of them.
+fn get_uploader_list(key: &str) -> Vec<String> {
+    match database::get_config_option(key) {
+        Some(serde_json::Value::String(uploader)) => vec![uploader],
+        Some(serde_json::Value::Array(uploaders)) => {
+            // Duplicates are dropped since each capture only has one upload per uploader.
+            let mut list: Vec<String> = Vec::with_capacity(uploaders.len());
+            for uploader in uploaders.iter().filter_map(|v| v.as_str()) {
+                if !list.iter().any(|u| u == uploader) {
+                    list.push(uploader.to_string());
+                }
+            }
+            list
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Gets the uploaders that captures are sent to in order.
+pub fn get_default_uploaders() -> Vec<String> {
+    let uploaders = get_uploader_list("uploader_type");
+
+    // Defaults to uploading to imgur.
+    if uploaders.is_empty() {
+        return vec!["imgur".to_string()];
+    }
+    uploaders
+}
+
+// Checks if the uploader can take the file. Uploaders which do not exist are let through so the upload fails
+// with the usual error.
+fn uploader_accepts(uploader_id: &str, mime: &str, size: u64) -> bool {
+    match get_uploader(uploader_id) {
+        Some(uploader) => uploader.check_file(mime, size).is_ok(),
+        None => true,
+    }
+}
+
+// Gets the uploaders for a file with the MIME type and size. Images, videos, and text go to the
+// uploader_type_image, uploader_type_video, and uploader_type_text options if they are set, and everything
+// else goes to the default uploaders. Any
+// uploader which cannot take the file is swapped for the first one in fallback_uploader_type which can. If
+// none can, the uploader is kept so the user sees why it failed.
+pub fn get_uploaders_for_file(mime: &str, size: u64) -> Vec<String> {
+    let kind_key = match mime.split('/').next() {
+        Some("image") => Some("uploader_type_image"),
+        Some("video") => Some("uploader_type_video"),
+        Some("text") => Some("uploader_type_text"),
+        _ => None,
+    };
+    let mut uploaders = kind_key.map(get_uploader_list).unwrap_or_default();
+    if uploaders.is_empty() {
+        uploaders = get_default_uploaders();
+    }
+
+    // Swap out any uploaders which cannot take the file.
+    let fallbacks = get_uploader_list("fallback_uploader_type");
+    let mut routed: Vec<String> = Vec::with_capacity(uploaders.len());
+    for uploader_id in uploaders {
+        let uploader_id = fallback_uploader(uploader_id, &fallbacks, &routed, |id| uploader_accepts(id, mime, size));
+        if !routed.contains(&uploader_id) {
+            routed.push(uploader_id);
+        }
+    }
+    routed
+}
+
+// Swaps the uploader for the first fallback which can take the file if it cannot. Fallbacks which are already
+// used are skipped. If none can, the uploader is kept so the user sees why it failed.
+fn fallback_uploader<F: Fn(&str) -> bool>(
+    uploader_id: String, fallbacks: &[String], used: &[String], accepts: F,
+) -> String {
+    match accepts(&uploader_id) {
+        true => uploader_id,
+        false => fallbacks
+            .iter()
+            .find(|f| !used.contains(f) && accepts(f))
+            .cloned()
+            .unwrap_or(uploader_id),
+    }
+}
+
+// Gets the uploader for a file the user chose to send to a specific uploader. If that uploader cannot take
+// the file, it goes to the first one in fallback_uploader_type which can.
+pub fn get_uploader_for_file(uploader_id: &str, mime: &str, size: u64) -> String {
+    let fallbacks = get_uploader_list("fallback_uploader_type");
+    fallback_uploader(uploader_id.to_string(), &fallbacks, &[], |id| uploader_accepts(id, mime, size))
+}
+
 // Calls the uploader.
 pub fn call_uploader(
-    uploader_name: &str, reader: Box<dyn std::io::Read + Send + Sync>, filename: &str,
-) -> Result<String, String>{
-    // Check if the kill switch was activated.
+    uploader_name: &str, source: &UploadSource, filename: &str, ctx: &UploadContext,
+) -> Result<String, UploadError> {
+    // Check if the kill switch was activated. This error is retryable so the upload is queued for when MagicCap
+    // starts again.
     if statics::KILL_SWITCH.load(Ordering::Relaxed) {
-        return Err("The application is unloading.".to_string());
+        return Err(UploadError::ShuttingDown);
+    }
+
+    // Get the uploader. If a custom uploader is broken, the error says why.
+    let uploader = load_uploader(uploader_name)?;
+
+    // Make sure the uploader can take the file.
+    if !uploader.accepted_mime_types.is_empty() || uploader.max_file_size.is_some() {
+        let mime = self::mime::guess_mime_type(filename, source)?;
+        if let Err(message) = uploader.check_file(mime.essence_str(), source.size()) {
+            return Err(UploadError::Config { field: None, message });
+        }
     }
 
-    // Get the uploader.
-    let uploader = match get_uploader(uploader_name) {
-        Some(uploader) => uploader,
+    // Validate the options and fill in the defaults.
+    let options = validate_config(
+        &uploader.options, database::get_uploader_config_items(uploader_name),
+    )?;
+
+    // Call the uploader unless it was cancelled before it started.
+    ctx.check_cancelled()?;
+    (uploader.upload)(filename, options, source, ctx)
+}
+
+// Deletes a file which was uploaded with the uploader. The token is the one the uploader set on the context
+// during the upload.
+pub fn delete_remote(uploader_name: &str, deletion_token: &str) -> Result<(), UploadError> {
+    // Get the uploader. If a custom uploader is broken, the error says why.
+    let uploader = load_uploader(uploader_name)?;
+
+    // Make sure the uploader supports deleting files.
+    let delete = match &uploader.delete {
+        Some(delete) => delete,
         None => {
-            return Err(format!("The uploader {} does not exist.", uploader_name));
+            return Err(UploadError::Config {
+                field: None,
+                message: format!("The uploader {} cannot delete files.", uploader.name),
+            });
         },
     };
 
-    // Validate all required options are present.
-    let options = database::get_uploader_config_items(uploader_name);
-    for (name, config) in &uploader.options {
-        if config.is_required() && !options.contains_key(name) {
-            return Err("The uploader is missing required options.".to_string());
+    // Validate the options and call the delete handler.
+    let options = validate_config(
+        &uploader.options, database::get_uploader_config_items(uploader_name),
+    )?;
+    delete(deletion_token, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Creates a saved V1 custom uploader which uploads to example.com.
+    fn v1_custom_uploader() -> serde_json::Value {
+        serde_json::json!({
+            "version": "v1",
+            "id": "example",
+            "name": "example",
+            "description": "Uploads to example.com.",
+            "encoded_icon": "",
+            "config": [],
+            "handler": {
+                "type": "http",
+                "rewrites": {},
+                "url_template": "https://example.com/upload",
+                "method": "POST",
+                "header_templates": {},
+                "body": {"type": "Raw"},
+                "response_expr": "json_path(\"url\")",
+            },
+        })
+    }
+
+    #[test]
+    fn builds_migrated_custom_uploaders() {
+        let (uploader, migrated) = build_custom_uploader("example", v1_custom_uploader()).unwrap();
+        assert_eq!(uploader.name, "example");
+        assert_eq!(uploader.description, "Uploads to example.com.");
+
+        // The migrated config is given back so it can be saved, and building that does not migrate it again.
+        let migrated = migrated.unwrap();
+        assert_eq!(migrated["version"], "v2");
+        assert_eq!(migrated["metadata"], serde_json::json!({}));
+        assert!(build_custom_uploader("example", migrated).unwrap().1.is_none());
+    }
+
+    #[test]
+    fn broken_custom_uploaders_say_why() {
+        // The migration error is given to the caller instead of the uploader looking missing.
+        match build_custom_uploader("broken", serde_json::json!({"version": 99})) {
+            Err(UploadError::Config { message, .. }) => {
+                assert!(message.contains("The custom uploader broken could not be migrated"), "{}", message);
+                assert!(message.contains("version 99 is not supported"), "{}", message);
+            }
+            _ => panic!("expected a config error"),
+        }
+        match build_custom_uploader("broken", serde_json::json!({"version": "v2"})) {
+            Err(UploadError::Config { message, .. }) => {
+                assert!(message.contains("The custom uploader broken is not valid"), "{}", message);
+            }
+            _ => panic!("expected a config error"),
         }
     }
 
-    // Call the uploader.
-    (uploader.upload)(filename, options, reader)
+    // Checks if the official uploader can take the file. Custom uploaders are not needed so the database is not.
+    fn official_accepts(mime: &str, size: u64) -> impl Fn(&str) -> bool + '_ {
+        move |id| UPLOADERS[id].check_file(mime, size).is_ok()
+    }
+
+    #[test]
+    fn chosen_uploader_falls_back() {
+        let fallbacks = vec!["ftp".to_string(), "s3".to_string()];
+        let fallback = |id: &str, used: &[String], mime, size| {
+            fallback_uploader(id.to_string(), &fallbacks, used, official_accepts(mime, size))
+        };
+
+        // imgur only takes images, so other files go to the first fallback which can take them.
+        assert_eq!(fallback("imgur", &[], "image/png", 10), "imgur");
+        assert_eq!(fallback("imgur", &[], "video/mp4", 10), "ftp");
+        assert_eq!(fallback("imgur", &[], "image/png", 100 * 1024 * 1024), "ftp");
+        assert_eq!(fallback("ftp", &[], "video/mp4", 10), "ftp");
+
+        // Fallbacks which are already used are skipped.
+        assert_eq!(fallback("imgur", &["ftp".to_string()], "video/mp4", 10), "s3");
+    }
+
+    #[test]
+    fn keeps_the_uploader_if_no_fallback_can_take_the_file() {
+        let fallbacks = vec!["imgur".to_string()];
+        assert_eq!(
+            fallback_uploader("imgur".to_string(), &fallbacks, &[], official_accepts("video/mp4", 10)),
+            "imgur",
+        );
+        assert_eq!(fallback_uploader("imgur".to_string(), &[], &[], |_| false), "imgur");
+    }
 }
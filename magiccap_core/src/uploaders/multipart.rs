@@ -0,0 +1,144 @@
+use super::{UploadError, UploadSource};
+use mime::Mime;
+use rand::{distributions::Alphanumeric, Rng};
+use std::io::{Cursor, Read};
+
+// Defines a multipart form body which streams the file from the disk.
+pub struct MultipartBody {
+    pub content_type: String,
+    pub content_length: u64,
+    pub reader: Box<dyn Read + Send>,
+}
+
+// Escapes a value for use inside of a quoted Content-Disposition parameter.
+fn escape_quoted(value: &str) -> String {
+    value
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+        .replace('"', "%22")
+}
+
+// Defines the content of a file in a multipart form.
+pub enum MultipartContent<'a> {
+    // Streams the file from the disk.
+    Source(&'a UploadSource),
+
+    // Sends data that was generated in memory, such as a thumbnail.
+    Bytes(Vec<u8>),
+}
+
+// Defines a file in a multipart form.
+pub struct MultipartFile<'a> {
+    pub field: &'a str,
+    pub filename: &'a str,
+    pub mime: &'a Mime,
+    pub content: MultipartContent<'a>,
+}
+
+// Builds a multipart form with the text fields followed by the file. Only the text parts are held in memory.
+pub fn multipart_stream<'a, I>(
+    fields: I,
+    file_field: &str,
+    filename: &str,
+    mime: &Mime,
+    source: &UploadSource,
+) -> Result<MultipartBody, UploadError>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    multipart_stream_files(
+        fields,
+        vec![MultipartFile {
+            field: file_field,
+            filename,
+            mime,
+            content: MultipartContent::Source(source),
+        }],
+    )
+}
+
+// Builds a multipart form with the text fields followed by each of the files. Files from a source are streamed
+// from the disk.
+pub fn multipart_stream_files<'a, I>(
+    fields: I,
+    files: Vec<MultipartFile<'_>>,
+) -> Result<MultipartBody, UploadError>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    // Generate the boundary.
+    let boundary: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+
+    // Build the text fields.
+    let mut head = String::new();
+    for (key, value) in fields {
+        head += &format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+            boundary,
+            escape_quoted(key),
+            value,
+        );
+    }
+    let mut content_length = head.len() as u64;
+    let mut reader: Box<dyn Read + Send> = Box::new(Cursor::new(head.into_bytes()));
+
+    // Chain each file. Files from a source are limited to the size we know about so the length is always right.
+    for (i, file) in files.into_iter().enumerate() {
+        let file_head = format!(
+            "{}--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+            if i == 0 { "" } else { "\r\n" },
+            boundary,
+            escape_quoted(file.field),
+            escape_quoted(file.filename),
+            file.mime,
+        );
+        content_length += file_head.len() as u64;
+        reader = Box::new(reader.chain(Cursor::new(file_head.into_bytes())));
+        match file.content {
+            MultipartContent::Source(source) => {
+                content_length += source.size();
+                reader = Box::new(reader.chain(source.open()?.take(source.size())));
+            }
+            MultipartContent::Bytes(data) => {
+                content_length += data.len() as u64;
+                reader = Box::new(reader.chain(Cursor::new(data)));
+            }
+        }
+    }
+
+    // Build everything that goes after the files.
+    let tail = format!("\r\n--{}--\r\n", boundary);
+    content_length += tail.len() as u64;
+    let reader = reader.chain(Cursor::new(tail.into_bytes()));
+    Ok(MultipartBody {
+        content_type: format!("multipart/form-data; boundary={}", boundary),
+        content_length,
+        reader: Box::new(reader),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_the_multipart_body() {
+        let source = UploadSource::from_bytes(b"hello", "a.txt").unwrap();
+        let body = multipart_stream(vec![("a\"b", "1")], "file", "x\".txt", &mime::TEXT_PLAIN, &source).unwrap();
+        let boundary = body.content_type.strip_prefix("multipart/form-data; boundary=").unwrap().to_string();
+        let mut data = String::new();
+        body.reader.take(u64::MAX).read_to_string(&mut data).unwrap();
+        assert_eq!(data.len() as u64, body.content_length);
+        assert_eq!(
+            data,
+            format!(
+                "--{b}\r\nContent-Disposition: form-data; name=\"a%22b\"\r\n\r\n1\r\n--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"x%22.txt\"\r\nContent-Type: text/plain\r\n\r\nhello\r\n--{b}--\r\n",
+                b = boundary
+            )
+        );
+    }
+}
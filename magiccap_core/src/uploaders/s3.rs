@@ -1,70 +1,212 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, io::Read, sync::Arc, time::Duration};
 use aws_config::{BehaviorVersion, Region};
 use aws_sdk_s3::{
-    config::{IdentityCache, ProvideCredentials, SharedCredentialsProvider},
-    primitives::ByteStream, types::ObjectCannedAcl, Client,
+    config::{http::HttpResponse, IdentityCache, ProvideCredentials, SharedCredentialsProvider},
+    error::{DisplayErrorContext, SdkError},
+    presigning::PresigningConfig,
+    primitives::ByteStream,
+    types::{
+        CompletedMultipartUpload, CompletedPart, ObjectCannedAcl, ServerSideEncryption, StorageClass,
+    },
+    Client,
 };
 use aws_credential_types::Credentials;
 
-use super::{mime, ConfigOption, Uploader};
+use super::{
+    mime,
+    validation::{config_str, required_str},
+    ConfigOption, UploadContext, UploadError, UploadSource, Uploader,
+};
 
-async fn s3_async_task(
-    filename: &str, config: HashMap<String, serde_json::Value>,
-    reader: Box<dyn std::io::Read + Send + Sync>,
-) -> Result<String, String> {
-    // Get the MIME type of the file.
-    let (mime, mut reader) = match mime::guess_mime_type(filename, reader) {
-        Ok((mime, reader)) => (mime, reader),
-        Err(err) => return Err(err.to_string()),
-    };
+// Defines the size after which we switch to a multipart upload.
+const MULTIPART_THRESHOLD: u64 = 16 * 1024 * 1024;
 
-    // Get the endpoint, access key ID, and secret access key.
-    let endpoint = match config.get("endpoint") {
-        Some(endpoint) => match endpoint.as_str() {
-            Some(endpoint) => endpoint,
-            None => return Err("The endpoint is not a string.".to_string()),
-        },
-        None => return Err("The endpoint is missing.".to_string()),
-    };
-    let access_key_id = match config.get("access_key_id") {
-        Some(access_key_id) => match access_key_id.as_str() {
-            Some(access_key_id) => access_key_id,
-            None => return Err("The access key ID is not a string.".to_string()),
-        },
-        None => return Err("The access key ID is missing.".to_string()),
-    };
-    let secret_access_key = match config.get("secret_access_key") {
-        Some(secret_access_key) => match secret_access_key.as_str() {
-            Some(secret_access_key) => secret_access_key,
-            None => return Err("The secret access key is not a string.".to_string()),
-        },
-        None => return Err("The secret access key is missing.".to_string()),
+// Defines the size of each part in a multipart upload. S3 requires at least 5MB for all but the last part.
+const MULTIPART_PART_SIZE: usize = 8 * 1024 * 1024;
+
+// Turns an error from the SDK into an upload error. Errors from S3 itself carry the status code so that
+// credential problems are reported as authentication errors.
+fn sdk_error<E>(action: &str, err: SdkError<E, HttpResponse>) -> UploadError
+where
+    E: std::error::Error + 'static,
+{
+    let message = format!("{}: {}", action, DisplayErrorContext(&err));
+    match &err {
+        SdkError::ServiceError(service_err) => {
+            UploadError::from_status(service_err.raw().status().as_u16(), message)
+        }
+        _ => UploadError::network(message),
+    }
+}
+
+// Defines the optional settings applied to the object when it is created.
+struct ObjectOptions {
+    acl: Option<ObjectCannedAcl>,
+    storage_class: Option<StorageClass>,
+    sse: Option<ServerSideEncryption>,
+    sse_kms_key_id: Option<String>,
+    cache_control: Option<String>,
+    content_disposition: Option<String>,
+}
+
+impl ObjectOptions {
+    // Gets the object options from the config. The ACL is skipped when presigning since the bucket is private.
+    fn from_config(config: &HashMap<String, serde_json::Value>, filename: &str, presign: bool) -> Self {
+        let acl = match config_str(config, "acl").unwrap_or("public-read") {
+            _ if presign => None,
+            "none" => None,
+            acl => Some(ObjectCannedAcl::from(acl)),
+        };
+        let sse = config_str(config, "sse").map(ServerSideEncryption::from);
+        let sse_kms_key_id = match sse {
+            Some(ServerSideEncryption::AwsKms) => config_str(config, "sse_kms_key_id").map(|s| s.to_string()),
+            _ => None,
+        };
+        ObjectOptions {
+            acl,
+            storage_class: config_str(config, "storage_class").map(StorageClass::from),
+            sse,
+            sse_kms_key_id,
+            cache_control: config_str(config, "cache_control").map(|s| s.to_string()),
+            content_disposition: config_str(config, "content_disposition")
+                .map(|s| s.replace("$filename", filename)),
+        }
+    }
+}
+
+// Uploads the file in parts so that only one part is in memory at a time.
+async fn s3_multipart_upload(
+    client: &Client, bucket: &str, key: &str, options: &ObjectOptions, mime: &str, source: &UploadSource,
+    ctx: &UploadContext,
+) -> Result<(), UploadError> {
+    // Start the multipart upload.
+    let created = client.create_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .set_acl(options.acl.clone())
+        .set_storage_class(options.storage_class.clone())
+        .set_server_side_encryption(options.sse.clone())
+        .set_ssekms_key_id(options.sse_kms_key_id.clone())
+        .set_cache_control(options.cache_control.clone())
+        .set_content_disposition(options.content_disposition.clone())
+        .content_type(mime)
+        .send()
+        .await
+        .map_err(|err| sdk_error("Failed to start the multipart upload", err))?;
+    let upload_id = match created.upload_id() {
+        Some(upload_id) => upload_id.to_string(),
+        None => return Err(UploadError::network("S3 did not return a multipart upload ID.")),
     };
 
-    // Get the bucket.
-    let bucket = match config.get("bucket") {
-        Some(bucket) => match bucket.as_str() {
-            Some(bucket) => bucket,
-            None => return Err("The bucket is not a string.".to_string()),
-        },
-        None => return Err("The bucket is missing.".to_string()),
+    // Upload each part. If anything goes wrong, abort the upload so the parts are not left on the bucket.
+    let parts_result: Result<Vec<CompletedPart>, UploadError> = async {
+        let mut file = source.open()?;
+        let mut parts = Vec::new();
+        let mut buf = vec![0; MULTIPART_PART_SIZE];
+        let mut sent = 0;
+        ctx.report_progress(0, source.size());
+        loop {
+            // Stop between parts if the upload was cancelled.
+            ctx.check_cancelled()?;
+
+            // Fill the buffer as much as possible.
+            let mut filled = 0;
+            while filled < buf.len() {
+                match file.read(&mut buf[filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(err) => {
+                        return Err(UploadError::io(format!("Failed to read the file: {}.", err)))
+                    }
+                }
+            }
+            if filled == 0 && !parts.is_empty() {
+                break;
+            }
+
+            // Upload the part.
+            let part_number = parts.len() as i32 + 1;
+            let upload_part = client.upload_part()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(&upload_id)
+                .part_number(part_number)
+                .body(ByteStream::from(buf[..filled].to_vec()))
+                .send();
+            let uploaded = until_cancelled(ctx, upload_part)
+                .await?
+                .map_err(|err| sdk_error(&format!("Failed to upload part {}", part_number), err))?;
+            parts.push(
+                CompletedPart::builder()
+                    .part_number(part_number)
+                    .set_e_tag(uploaded.e_tag().map(|s| s.to_string()))
+                    .build(),
+            );
+            sent += filled as u64;
+            ctx.report_progress(sent, source.size());
+            if filled < buf.len() {
+                break;
+            }
+        }
+        Ok(parts)
+    }.await;
+    let parts = match parts_result {
+        Ok(parts) => parts,
+        Err(err) => {
+            let _ = client.abort_multipart_upload()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(&upload_id)
+                .send()
+                .await;
+            return Err(err);
+        }
     };
 
-    // Get the region.
-    let mut region = match config.get("region") {
-        Some(region) => match region.as_str() {
-            Some(region) => region.to_string(),
-            None => return Err("The region is not a string.".to_string()),
-        },
-        None => "us-east-1".to_string(),
+    // Complete the upload.
+    client.complete_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .upload_id(&upload_id)
+        .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+        .send()
+        .await
+        .map_err(|err| sdk_error("Failed to complete the multipart upload", err))?;
+    Ok(())
+}
+
+// Runs the request until it finishes or the upload is cancelled. Dropping the request on cancel closes the
+// connection, so this stops requests which are part way through sending the body.
+async fn until_cancelled<F: std::future::Future>(
+    ctx: &UploadContext, request: F,
+) -> Result<F::Output, UploadError> {
+    let cancelled = async {
+        while !ctx.is_cancelled() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
     };
-    if region.is_empty() {
-        region = "us-east-1".to_string();
+    tokio::select! {
+        output = request => Ok(output),
+        _ = cancelled => Err(UploadError::Cancelled),
     }
+}
 
-    // Read the file into a buffer.
-    let mut data = Vec::new();
-    reader.read_to_end(&mut data).map_err(|err| format!("Failed to read the file: {}.", err))?;
+// Builds the S3 client from the config. Returns the client and the endpoint URL.
+fn s3_client(config: &HashMap<String, serde_json::Value>) -> Result<(Client, String), UploadError> {
+    // Get the endpoint, access key ID, and secret access key. These are validated before the upload starts.
+    let endpoint = required_str(config, "endpoint")?;
+    let access_key_id = required_str(config, "access_key_id")?;
+    let secret_access_key = required_str(config, "secret_access_key")?;
+
+    // Get the region.
+    let region = config_str(config, "region").unwrap_or("us-east-1").to_string();
+
+    // Get the endpoint URL. This defaults to HTTPS, but a scheme can be given for local servers.
+    let endpoint_url = match endpoint.starts_with("http://") || endpoint.starts_with("https://") {
+        true => endpoint.trim_end_matches('/').to_string(),
+        false => format!("https://{}", endpoint.trim_end_matches('/')),
+    };
+    let path_style = config.get("path_style").and_then(|v| v.as_bool()).unwrap_or(false);
 
     // Setup the SDK.
     let credentials_provider: Arc<dyn ProvideCredentials> = Arc::new(Credentials::new(
@@ -78,76 +220,130 @@ async fn s3_async_task(
         .set_region(Region::new(region))
         .set_identity_cache(Some(IdentityCache::no_cache()))
         .set_credentials_provider(Some(SharedCredentialsProvider::new(credentials_provider)))
-        .set_endpoint_url(Some("https://".to_string() + endpoint));
+        .set_endpoint_url(Some(endpoint_url.clone()));
     let sdk_config_built = builder.build();
-    let client = Client::new(&sdk_config_built);
+    let s3_config = aws_sdk_s3::config::Builder::from(&sdk_config_built)
+        .force_path_style(path_style)
+        .build();
+    Ok((Client::from_conf(s3_config), endpoint_url))
+}
+
+async fn s3_async_task(
+    filename: &str, config: HashMap<String, serde_json::Value>,
+    source: &UploadSource, ctx: &UploadContext,
+) -> Result<String, UploadError> {
+    // Get the MIME type of the file.
+    let mime = mime::guess_mime_type(filename, source)?;
+
+    // Setup the client and get the bucket.
+    let (client, endpoint_url) = s3_client(&config)?;
+    let bucket = required_str(&config, "bucket")?;
+    let path_style = config.get("path_style").and_then(|v| v.as_bool()).unwrap_or(false);
+
+    // Get how long presigned URLs last. If this is unset, the object is expected to be public.
+    let presign_expiry = config.get("presign_expiry").and_then(|v| v.as_u64()).unwrap_or(0);
+    let options = ObjectOptions::from_config(&config, filename, presign_expiry > 0);
 
     // Run PutObject.
-    let mut folder = match config.get("folder") {
-        Some(folder) => match folder.as_str() {
-            Some(folder) => folder,
-            None => return Err("The folder is not a string.".to_string()),
-        },
-        None => "",
-    };
-    folder = folder.trim_matches('/');
-    let acl = match config.get("acl") {
-        Some(acl) => match acl.as_str() {
-            Some(acl) => acl,
-            None => return Err("The ACL is not a string.".to_string()),
-        },
-        None => "public-read",
-    };
+    let folder = config_str(&config, "folder").unwrap_or("").trim_matches('/');
     let folder_plus_slash = if folder.is_empty() {
         "".to_string()
     } else {
         format!("{}/", folder)
     };
-    client.put_object()
-        .bucket(bucket)
-        .key(format!("{}{}", folder_plus_slash, filename))
-        .acl(ObjectCannedAcl::from(acl))
-        .body(ByteStream::from(data))
-        .content_type(mime.to_string())
-        .send()
-        .await
-        .map_err(|err| format!("Failed to upload the file: {}.", err))?;
-
-    // Get the URL rewrite.
-    let url_rewrite = match config.get("url_rewrite") {
-        Some(url_rewrite) => match url_rewrite.as_str() {
-            Some(url_rewrite) => url_rewrite,
-            None => return Err("The URL rewrite is not a string.".to_string()),
-        },
-        None => "https://$bucket/$folder_path$filename",
+    let key = format!("{}{}", folder_plus_slash, filename);
+    if source.size() > MULTIPART_THRESHOLD {
+        s3_multipart_upload(&client, bucket, &key, &options, mime.as_ref(), source, ctx).await?;
+    } else {
+        // Read the file through the progress reader. This is small enough to hold in memory, and reading
+        // this way stops as soon as the upload is cancelled.
+        let mut body = Vec::with_capacity(source.size() as usize);
+        if let Err(err) = ctx.progress_reader(source.open()?, source.size()).read_to_end(&mut body) {
+            ctx.check_cancelled()?;
+            return Err(UploadError::io(format!("Failed to read the file: {}.", err)));
+        }
+        let put = client.put_object()
+            .bucket(bucket)
+            .key(&key)
+            .set_acl(options.acl)
+            .set_storage_class(options.storage_class)
+            .set_server_side_encryption(options.sse)
+            .set_ssekms_key_id(options.sse_kms_key_id)
+            .set_cache_control(options.cache_control)
+            .set_content_disposition(options.content_disposition)
+            .body(ByteStream::from(body))
+            .content_type(mime.to_string())
+            .send();
+        until_cancelled(ctx, put)
+            .await?
+            .map_err(|err| sdk_error("Failed to upload the file", err))?;
+    }
+
+    // The key is used to delete the object later.
+    ctx.set_deletion_token(key.clone());
+
+    // If presigning is on, return a time limited link to the object.
+    if presign_expiry > 0 {
+        let presigning_config = PresigningConfig::expires_in(Duration::from_secs(presign_expiry))
+            .map_err(|err| UploadError::config("presign_expiry", err.to_string()))?;
+        let presigned = client.get_object()
+            .bucket(bucket)
+            .key(&key)
+            .presigned(presigning_config)
+            .await
+            .map_err(|err| sdk_error("Failed to presign the URL", err))?;
+        return Ok(presigned.uri().to_string());
+    }
+
+    // Get the URL rewrite. Path style buckets are served from the endpoint.
+    let default_rewrite = match path_style {
+        true => format!("{}/$bucket/$key", endpoint_url),
+        false => "https://$bucket/$key".to_string(),
     };
+    let url_rewrite = config_str(&config, "url_rewrite").unwrap_or(&default_rewrite);
 
     // Return the URL.
     Ok(url_rewrite
         .replace("$bucket", bucket)
+        .replace("$key", &key)
         .replace("$folder_path", folder)
         .replace("$filename", filename))
 }
 
 fn s3_support_upload(
     filename: &str, config: HashMap<String, serde_json::Value>,
-    reader: Box<dyn std::io::Read + Send + Sync>,
-) -> Result<String, String> {
+    source: &UploadSource,
+    ctx: &UploadContext,
+) -> Result<String, UploadError> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(s3_async_task(filename, config, source, ctx))
+}
+
+// Deletes the object with the key from the bucket.
+fn s3_support_delete(key: &str, config: HashMap<String, serde_json::Value>) -> Result<(), UploadError> {
+    let (client, _) = s3_client(&config)?;
+    let bucket = required_str(&config, "bucket")?;
     tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .unwrap()
-        .block_on(s3_async_task(filename, config, reader))
+        .block_on(client.delete_object().bucket(bucket).key(key).send())
+        .map_err(|err| sdk_error("Failed to delete the file", err))?;
+    Ok(())
 }
 
-// Make sure this is a valid domain.
-const DOMAIN_REGEX: &str = r"^(?:[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?\.)+[a-zA-Z]{2,}$";
+// Make sure this is a valid domain or IP with an optional scheme and port.
+const ENDPOINT_REGEX: &str = r"^(?:https?://)?(?:(?:[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?\.)+[a-zA-Z]{2,}|localhost|\d{1,3}(?:\.\d{1,3}){3})(?::\d{1,5})?/?$";
 
 // Defines the description for URL rewrites since it is fairly long.
 const URL_REWRITE_DESCRIPTION: &str = concat!(
     "The string to rewrite the URL to. In this URL, you can use `$bucket` to represent the bucket, ",
-    "`$folder_path` to represent the folder path, and `$filename` to represent the filename. The default ",
-    "is `https://$bucket/$folder_path$filename`.",
+    "`$key` to represent the full object key, `$folder_path` to represent the folder path, and `$filename` ",
+    "to represent the filename. The default is `https://$bucket/$key`, or `<endpoint>/$bucket/$key` when ",
+    "path style addressing is on. This is ignored when presigned URLs are used.",
 );
 
 pub fn s3_support() -> Uploader {
@@ -160,11 +356,14 @@ pub fn s3_support() -> Uploader {
                 "endpoint".to_string(),
                 ConfigOption::String {
                     name: "Endpoint".to_string(),
-                    description: "The endpoint of the S3 bucket.".to_string(),
+                    description: concat!(
+                        "The endpoint of the S3 bucket. This uses HTTPS unless it starts with `http://`, which is ",
+                        "useful for local servers such as MinIO.",
+                    ).to_string(),
                     default: None,
                     required: true,
                     password: false,
-                    regex: Some(DOMAIN_REGEX.to_string()),
+                    regex: Some(ENDPOINT_REGEX.to_string()),
                     validation_error_message: Some("The endpoint is not a valid domain.".to_string()),
                 },
             ),
@@ -244,7 +443,10 @@ pub fn s3_support() -> Uploader {
                 "acl".to_string(),
                 ConfigOption::String {
                     name: "ACL".to_string(),
-                    description: "The ACL for the file. Defaults to `public-read`.".to_string(),
+                    description: concat!(
+                        "The ACL for the file. Defaults to `public-read`. Set this to `none` for buckets which ",
+                        "do not allow ACLs. This is not sent when presigned URLs are used.",
+                    ).to_string(),
                     default: Some("public-read".to_string()),
                     required: false,
                     password: false,
@@ -252,7 +454,217 @@ pub fn s3_support() -> Uploader {
                     validation_error_message: None,
                 },
             ),
+            (
+                "path_style".to_string(),
+                ConfigOption::Boolean {
+                    name: "Path Style Addressing".to_string(),
+                    description: "Whether to put the bucket in the path rather than the hostname. MinIO usually needs this.".to_string(),
+                    default: Some(false),
+                },
+            ),
+            (
+                "presign_expiry".to_string(),
+                ConfigOption::Number {
+                    name: "Presigned URL Expiry".to_string(),
+                    description: concat!(
+                        "If set, the link returned is a presigned URL which lasts this many seconds. This is for ",
+                        "private buckets. The maximum is 7 days (604800 seconds).",
+                    ).to_string(),
+                    default: None,
+                    min: Some(1),
+                    max: Some(604800),
+                    required: false,
+                },
+            ),
+            (
+                "storage_class".to_string(),
+                ConfigOption::String {
+                    name: "Storage Class".to_string(),
+                    description: "The storage class for the file, such as `STANDARD_IA`. Uses the bucket default if unset.".to_string(),
+                    default: None,
+                    required: false,
+                    password: false,
+                    regex: Some("^[A-Z_]+$".to_string()),
+                    validation_error_message: Some("The storage class must be upper case, such as `STANDARD_IA`.".to_string()),
+                },
+            ),
+            (
+                "sse".to_string(),
+                ConfigOption::String {
+                    name: "Server-Side Encryption".to_string(),
+                    description: "The server-side encryption to use. This can be `AES256` for SSE-S3 or `aws:kms` for SSE-KMS.".to_string(),
+                    default: None,
+                    required: false,
+                    password: false,
+                    regex: Some("^(AES256|aws:kms)$".to_string()),
+                    validation_error_message: Some("The server-side encryption must be `AES256` or `aws:kms`.".to_string()),
+                },
+            ),
+            (
+                "sse_kms_key_id".to_string(),
+                ConfigOption::String {
+                    name: "SSE-KMS Key ID".to_string(),
+                    description: "The KMS key to use when the server-side encryption is `aws:kms`. Uses the AWS managed key if unset.".to_string(),
+                    default: None,
+                    required: false,
+                    password: false,
+                    regex: None,
+                    validation_error_message: None,
+                },
+            ),
+            (
+                "cache_control".to_string(),
+                ConfigOption::String {
+                    name: "Cache-Control".to_string(),
+                    description: "The Cache-Control header to store with the file, such as `public, max-age=31536000`.".to_string(),
+                    default: None,
+                    required: false,
+                    password: false,
+                    regex: None,
+                    validation_error_message: None,
+                },
+            ),
+            (
+                "content_disposition".to_string(),
+                ConfigOption::String {
+                    name: "Content-Disposition".to_string(),
+                    description: "The Content-Disposition header to store with the file. `$filename` is replaced with the filename.".to_string(),
+                    default: None,
+                    required: false,
+                    password: false,
+                    regex: None,
+                    validation_error_message: None,
+                },
+            ),
         ],
         upload: Box::new(s3_support_upload),
+        delete: Some(Box::new(s3_support_delete)),
+        accepted_mime_types: Vec::new(),
+        max_file_size: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uploaders::{mock_s3_server::MockS3Server, mock_server::config, CancellationToken};
+    use serde_json::json;
+
+    #[test]
+    fn uploads_small_files_with_put_object() {
+        let server = MockS3Server::start();
+        let config = server.config(json!({"folder": "/shots/", "cache_control": "max-age=60"}));
+        let source = UploadSource::from_bytes(b"\x89PNG\r\n\x1a\nimage", "a.png").unwrap();
+        let ctx = UploadContext::background();
+        let url = s3_support_upload("a.png", config, &source, &ctx).unwrap();
+        assert_eq!(url, format!("{}/bucket/shots/a.png", server.url()));
+        assert_eq!(ctx.take_deletion_token().as_deref(), Some("shots/a.png"));
+        assert_eq!(server.bucket().objects["shots/a.png"], b"\x89PNG\r\n\x1a\nimage");
+
+        let put = &server.requests()[0];
+        assert_eq!(put.method, "PUT");
+        assert_eq!(put.path, "/bucket/shots/a.png");
+        assert_eq!(put.header("Content-Type"), Some("image/png"));
+        assert_eq!(put.header("x-amz-acl"), Some("public-read"));
+        assert_eq!(put.header("Cache-Control"), Some("max-age=60"));
+    }
+
+    #[test]
+    fn uploads_large_files_in_parts() {
+        let server = MockS3Server::start();
+        let data: Vec<u8> = (0..MULTIPART_THRESHOLD as usize + 1024).map(|i| (i % 251) as u8).collect();
+        let source = UploadSource::from_bytes(&data, "large.bin").unwrap();
+        let ctx = UploadContext::background();
+        let url = s3_support_upload("large.bin", server.config(json!({})), &source, &ctx).unwrap();
+        assert_eq!(url, format!("{}/bucket/large.bin", server.url()));
+
+        // 16MB and a bit is two full parts and a small one.
+        let uploads = server.requests().iter().filter(|r| r.query.contains("partNumber")).count();
+        assert_eq!(uploads, 3);
+        assert!(server.bucket().objects["large.bin"] == data);
+    }
+
+    #[test]
+    fn aborts_multipart_uploads_which_fail() {
+        let server = MockS3Server::start();
+        let source = UploadSource::from_bytes(&vec![0; MULTIPART_THRESHOLD as usize + 1], "reject.bin").unwrap();
+        let err = s3_support_upload("reject.bin", server.config(json!({})), &source, &UploadContext::background())
+            .unwrap_err();
+        assert!(matches!(err, UploadError::RemoteRejected { status: 400, .. }), "{:?}", err);
+        let bucket = server.bucket();
+        assert_eq!(bucket.aborted, vec!["upload-1".to_string()]);
+        assert!(bucket.objects.is_empty());
+    }
+
+    #[test]
+    fn stops_cancelled_uploads() {
+        let server = MockS3Server::start();
+        let token = CancellationToken::new();
+        token.cancel();
+        let ctx = UploadContext::new(token, None);
+        let source = UploadSource::from_bytes(b"image", "a.png").unwrap();
+        let err = s3_support_upload("a.png", server.config(json!({})), &source, &ctx).unwrap_err();
+        assert!(matches!(err, UploadError::Cancelled));
+        assert!(server.requests().is_empty());
+    }
+
+    #[test]
+    fn reports_bad_credentials() {
+        let server = MockS3Server::start();
+        let config = server.config(json!({"access_key_id": "WRONG"}));
+        let source = UploadSource::from_bytes(b"image", "a.png").unwrap();
+        let err = s3_support_upload("a.png", config, &source, &UploadContext::background()).unwrap_err();
+        assert!(matches!(err, UploadError::Auth { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn presigns_private_objects() {
+        let server = MockS3Server::start();
+        let config = server.config(json!({"presign_expiry": 60}));
+        let source = UploadSource::from_bytes(b"image", "a.png").unwrap();
+        let url = s3_support_upload("a.png", config, &source, &UploadContext::background()).unwrap();
+        assert!(url.starts_with(&format!("{}/bucket/a.png?", server.url())), "{}", url);
+        assert!(url.contains("X-Amz-Expires=60") && url.contains("X-Amz-Signature="), "{}", url);
+        assert_eq!(server.requests()[0].header("x-amz-acl"), None);
+    }
+
+    #[test]
+    fn deletes_objects() {
+        let server = MockS3Server::start();
+        server.bucket().objects.insert("shots/a.png".to_string(), b"image".to_vec());
+        s3_support_delete("shots/a.png", server.config(json!({}))).unwrap();
+        assert!(server.bucket().objects.is_empty());
+        assert_eq!(server.requests()[0].method, "DELETE");
+        assert_eq!(server.requests()[0].path, "/bucket/shots/a.png");
+    }
+
+    // Uploads to a real MinIO server, such as one started with `minio server`. Set MAGICCAP_TEST_MINIO to the
+    // endpoint to run this. The bucket must exist, and the keys default to the MinIO defaults.
+    #[test]
+    fn uploads_and_deletes_with_minio() {
+        let endpoint = match std::env::var("MAGICCAP_TEST_MINIO") {
+            Ok(endpoint) => endpoint,
+            Err(_) => return eprintln!("MAGICCAP_TEST_MINIO is not set, skipping"),
+        };
+        let env = |key: &str, default: &str| std::env::var(key).unwrap_or_else(|_| default.to_string());
+        let config = config(json!({
+            "endpoint": endpoint,
+            "access_key_id": env("MAGICCAP_TEST_MINIO_ACCESS_KEY", "minioadmin"),
+            "secret_access_key": env("MAGICCAP_TEST_MINIO_SECRET_KEY", "minioadmin"),
+            "bucket": env("MAGICCAP_TEST_MINIO_BUCKET", "magiccap"),
+            "path_style": true,
+            "folder": "magiccap-tests",
+            "presign_expiry": 60,
+        }));
+
+        // Upload the file and read it back with the presigned URL.
+        let source = UploadSource::from_bytes(b"hello minio", "test file.txt").unwrap();
+        let ctx = UploadContext::background();
+        let url = s3_support_upload("test file.txt", config.clone(), &source, &ctx).unwrap();
+        assert_eq!(ureq::get(&url).call().unwrap().into_string().unwrap(), "hello minio");
+
+        // Delete it with the token the upload set.
+        s3_support_delete(&ctx.take_deletion_token().unwrap(), config).unwrap();
+        assert!(matches!(ureq::get(&url).call(), Err(ureq::Error::Status(404, _))));
     }
 }
@@ -1,13 +1,18 @@
 use super::{
     utils::{DOMAIN_OR_IP_REGEX, URL_FTP_REWRITE_DESCRIPTION},
-    ConfigOption, Uploader,
+    validation::{config_str, required_str},
+    ConfigOption, UploadContext, UploadError, UploadSource, Uploader,
 };
-use std::collections::HashMap;
-#[allow(unused_imports)]
-use std::process::{Child, Command};
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+#[cfg(target_os = "windows")]
+use std::process::Child;
 
 // Defines an atomic that increments to make sure we do not collide with other threads.
-#[cfg(target_os = "windows")]
 static SFTP_UPLOAD_COUNTER: once_cell::sync::Lazy<std::sync::atomic::AtomicUsize> =
     once_cell::sync::Lazy::new(|| std::sync::atomic::AtomicUsize::new(0));
 
@@ -84,84 +89,330 @@ struct SSHConnectionOptions {
     username: String,
     private_key: Option<String>,
     password: Option<String>,
+    host_key: Option<String>,
+    trust_new_hosts: bool,
 }
 
-fn sftp_do_upload(
-    connection: SSHConnectionOptions,
-    folder_path: &Option<String>,
-    filename: &str,
-    reader: Box<dyn std::io::Read + Send + Sync>,
-) -> Option<String> {
-    // TODO
-    Some("oops".to_string())
+// Gets a temporary path that will not collide with other uploads.
+fn temp_path(suffix: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "magiccap-sftp-{}-{}{}",
+        std::process::id(),
+        SFTP_UPLOAD_COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst),
+        suffix,
+    ))
 }
 
-fn sftp_support_upload(
-    filename: &str,
-    config: HashMap<String, serde_json::Value>,
-    reader: Box<dyn std::io::Read + Send + Sync>,
-) -> Result<String, String> {
-    // Get the hostname.
-    let hostname = match config.get("hostname") {
-        Some(hostname) => match hostname.as_str() {
-            Some(hostname) => hostname,
-            None => return Err("The hostname must be a string.".to_string()),
-        },
-        None => return Err("The hostname is required.".to_string()),
+// Writes a file that only the current user can read.
+fn write_private_file(path: &PathBuf, data: &[u8], executable: bool) -> Result<(), String> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(if executable { 0o700 } else { 0o600 });
+    }
+    #[cfg(target_os = "windows")]
+    let _ = executable;
+
+    let mut file = match options.open(path) {
+        Ok(file) => file,
+        Err(err) => return Err(format!("Failed to create a temporary file: {}", err)),
     };
+    match file.write_all(data) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("Failed to write a temporary file: {}", err)),
+    }
+}
 
-    // Get the port.
-    let port = match config.get("port") {
-        Some(port) => match port.as_u64() {
-            Some(port) => port as u16,
-            None => return Err("The port must be a number.".to_string()),
-        },
-        None => 22,
+// Quotes a path for use within a sftp batch file.
+fn sftp_quote(path: &str) -> String {
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Builds the sftp batch which creates the folder path if needed and then puts the file.
+fn sftp_batch(
+    folder_path: &Option<String>,
+    local_path: &Path,
+    filename: &str,
+) -> Result<String, UploadError> {
+    // sftp batch files are text, so a path which is not UTF-8 cannot be put in one.
+    let local_path = match local_path.to_str() {
+        Some(path) => path,
+        None => {
+            return Err(UploadError::io(format!(
+                "The file path {} cannot be passed to sftp since it is not valid UTF-8.",
+                local_path.display()
+            )))
+        }
     };
 
-    // Get the username.
-    let username = match config.get("username") {
-        Some(username) => match username.as_str() {
-            Some(username) => username.to_string(),
-            None => return Err("The username must be a string.".to_string()),
-        },
-        None => whoami::username(),
+    let mut batch = String::new();
+
+    // Make each folder in the path. The dash prefix makes sftp ignore the error if it exists.
+    let mut remote_path = String::new();
+    if let Some(folder_path) = folder_path {
+        if folder_path.starts_with('/') {
+            remote_path.push('/');
+        }
+        for part in folder_path.split('/').filter(|part| !part.is_empty()) {
+            remote_path += part;
+            batch += &format!("-mkdir {}\n", sftp_quote(&remote_path));
+            remote_path.push('/');
+        }
+    }
+
+    // Put the file into the folder.
+    batch += &format!(
+        "put {} {}\n",
+        sftp_quote(local_path),
+        sftp_quote(&(remote_path + filename)),
+    );
+    Ok(batch)
+}
+
+// Gets the host pattern ssh uses in known_hosts for the hostname and port.
+fn known_hosts_pattern(hostname: &str, port: u16) -> String {
+    match port {
+        22 => hostname.to_string(),
+        _ => format!("[{}]:{}", hostname, port),
+    }
+}
+
+// Writes a known_hosts file which only trusts the pinned host key.
+fn write_known_hosts(connection: &SSHConnectionOptions, host_key: &str) -> Result<PathBuf, String> {
+    let path = temp_path(".known_hosts");
+    let line = format!(
+        "{} {}\n",
+        known_hosts_pattern(&connection.hostname, connection.port),
+        host_key.trim(),
+    );
+    write_private_file(&path, line.as_bytes(), false)?;
+    Ok(path)
+}
+
+// Adds the private key to the agent we started.
+fn add_private_key(agent_socket: &str, private_key: &str) -> Option<String> {
+    // ssh-add needs the key on disk and refuses keys other users can read.
+    let key_path = temp_path(".key");
+    let mut private_key = private_key.trim().to_string();
+    private_key.push('\n');
+    if let Err(err) = write_private_file(&key_path, private_key.as_bytes(), false) {
+        return Some(err);
+    }
+
+    // Add the key to the agent and then remove it from the disk whatever happens.
+    let output = Command::new("ssh-add")
+        .arg(&key_path)
+        .env("SSH_AUTH_SOCK", agent_socket)
+        .stdin(Stdio::null())
+        .output();
+    let _ = std::fs::remove_file(&key_path);
+
+    match output {
+        Ok(output) => {
+            if output.status.success() {
+                None
+            } else {
+                Some(format!(
+                    "Failed to add the private key to {}: {}",
+                    AGENT_NAME,
+                    String::from_utf8_lossy(&output.stderr).trim(),
+                ))
+            }
+        }
+        Err(err) => Some(format!("Failed to run ssh-add: {}", err)),
+    }
+}
+
+// Writes a askpass program which echoes the password from the environment back to ssh. On Windows, delayed
+// expansion is used since the expanded value is not parsed again, so characters such as & and | are safe.
+fn write_askpass() -> Result<PathBuf, String> {
+    #[cfg(target_os = "windows")]
+    let (path, script) = (
+        temp_path(".bat"),
+        "@echo off\r\nsetlocal EnableDelayedExpansion\r\necho(!MAGICCAP_SFTP_PASSWORD!\r\n",
+    );
+    #[cfg(not(target_os = "windows"))]
+    let (path, script) = (temp_path(".sh"), "#!/bin/sh\nprintf '%s\\n' \"$MAGICCAP_SFTP_PASSWORD\"\n");
+
+    write_private_file(&path, script.as_bytes(), true)?;
+    Ok(path)
+}
+
+// Runs sftp with the batch on stdin. Returns an error if one occurs.
+fn run_sftp(
+    connection: &SSHConnectionOptions,
+    askpass: &Option<PathBuf>,
+    known_hosts: &Option<PathBuf>,
+    batch: &str,
+) -> Option<UploadError> {
+    let mut command = Command::new("sftp");
+
+    // sftp adds BatchMode=yes when a batch file is used, which stops askpass being used. ssh uses the
+    // first value it sees for an option, so we put ours before the batch flag.
+    command
+        .arg("-o")
+        .arg(format!("BatchMode={}", if askpass.is_some() { "no" } else { "yes" }))
+        .arg("-P")
+        .arg(connection.port.to_string())
+        .arg("-b")
+        .arg("-");
+
+    // Handle how the host key is checked. A pinned key replaces the users known_hosts, and otherwise unknown
+    // hosts are only trusted if the user opted in.
+    match known_hosts {
+        Some(known_hosts) => {
+            command
+                .arg("-o")
+                .arg(format!("UserKnownHostsFile=\"{}\"", known_hosts.display()))
+                .arg("-o")
+                .arg("GlobalKnownHostsFile=none")
+                .arg("-o")
+                .arg("StrictHostKeyChecking=yes");
+        }
+        None => {
+            command.arg("-o").arg(match connection.trust_new_hosts {
+                true => "StrictHostKeyChecking=accept-new",
+                false => "StrictHostKeyChecking=yes",
+            });
+        }
+    }
+
+    // Handle the authentication method.
+    if connection.private_key.is_some() {
+        command
+            .env("SSH_AUTH_SOCK", &connection.agent_socket)
+            .arg("-o")
+            .arg("PreferredAuthentications=publickey");
+    }
+    if let Some(askpass) = askpass {
+        command
+            .env("SSH_ASKPASS", askpass)
+            .env("SSH_ASKPASS_REQUIRE", "force")
+            .env("MAGICCAP_SFTP_PASSWORD", connection.password.as_ref().unwrap());
+        if std::env::var("DISPLAY").is_err() {
+            command.env("DISPLAY", ":0");
+        }
+    }
+
+    // Handle the destination. IPv6 addresses need to be wrapped in brackets.
+    let hostname = if connection.hostname.contains(':') {
+        format!("[{}]", connection.hostname)
+    } else {
+        connection.hostname.clone()
     };
+    command.arg(format!("{}@{}", connection.username, hostname));
 
-    // Get the private key and password.
-    let private_key = match config.get("private_key") {
-        Some(private_key) => match private_key.as_str() {
-            Some(private_key) => Some(private_key.to_string()),
-            None => return Err("The private key must be a string.".to_string()),
-        },
-        None => None,
+    // Start the process.
+    let mut child = match command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => return Some(UploadError::io(format!("Failed to start sftp: {}", err))),
     };
-    let password = match config.get("password") {
-        Some(password) => match password.as_str() {
-            Some(password) => Some(password.to_string()),
-            None => return Err("The password must be a string.".to_string()),
+
+    // Write the batch and close stdin so sftp exits when it is done.
+    let mut stdin = child.stdin.take().unwrap();
+    if let Err(err) = stdin.write_all(batch.as_bytes()) {
+        let _ = child.kill();
+        return Some(UploadError::io(format!("Failed to write to sftp: {}", err)));
+    }
+    drop(stdin);
+
+    // Wait for the process to finish. ssh says permission denied when none of the authentication methods worked.
+    match child.wait_with_output() {
+        Ok(output) => {
+            if output.status.success() {
+                None
+            } else {
+                let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                if stderr.contains("Host key verification failed") {
+                    Some(UploadError::config(
+                        "host_key",
+                        format!(
+                            "The host key of the server is not trusted. Set the host key or allow trusting new hosts: {}",
+                            stderr
+                        ),
+                    ))
+                } else if stderr.contains("Permission denied") {
+                    Some(UploadError::auth(stderr))
+                } else {
+                    Some(UploadError::network(format!("The SFTP command failed: {}", stderr)))
+                }
+            }
+        }
+        Err(err) => Some(UploadError::io(format!(
+            "Failed to wait for sftp to finish: {}",
+            err
+        ))),
+    }
+}
+
+// Runs the sftp batch with the authentication method in the connection options.
+fn sftp_do_batch(connection: SSHConnectionOptions, batch: &str) -> Option<UploadError> {
+    // Load the private key into the agent if we have one, otherwise setup askpass if there is a password.
+    // If neither is set, sftp will use the users own agent and keys.
+    let mut askpass = None;
+    if let Some(private_key) = &connection.private_key {
+        if let Some(err) = add_private_key(&connection.agent_socket, private_key) {
+            return Some(UploadError::config("private_key", err));
+        }
+    } else if connection.password.is_some() {
+        askpass = match write_askpass() {
+            Ok(path) => Some(path),
+            Err(err) => return Some(UploadError::io(err)),
+        };
+    }
+
+    // Write the pinned host key if there is one.
+    let known_hosts = match &connection.host_key {
+        Some(host_key) => match write_known_hosts(&connection, host_key) {
+            Ok(path) => Some(path),
+            Err(err) => {
+                if let Some(askpass) = askpass {
+                    let _ = std::fs::remove_file(askpass);
+                }
+                return Some(UploadError::io(err));
+            }
         },
         None => None,
     };
 
-    // Call the command handling function.
-    let folder_path = match config.get("path") {
-        Some(folder_path) => match folder_path.as_str() {
-            Some(folder_path) => Some(folder_path.to_string()),
-            None => return Err("The folder path must be a string.".to_string()),
-        },
-        None => None,
+    // Run sftp and then cleanup the temporary files whatever happens.
+    let possible_error = run_sftp(&connection, &askpass, &known_hosts, batch);
+    for path in [askpass, known_hosts].into_iter().flatten() {
+        let _ = std::fs::remove_file(path);
+    }
+    possible_error
+}
+
+// Starts an agent, runs the sftp batch against the server in the config, and then kills the agent.
+fn sftp_run_batch(config: &HashMap<String, serde_json::Value>, batch: &str) -> Result<(), UploadError> {
+    // Get the connection details. These are validated before the upload starts.
+    let hostname = required_str(config, "hostname")?;
+    let port = config.get("port").and_then(|v| v.as_u64()).unwrap_or(22) as u16;
+    let username = match config_str(config, "username") {
+        Some(username) => username.to_string(),
+        None => whoami::username(),
     };
+    let private_key = config_str(config, "private_key").map(|s| s.to_string());
+    let password = config_str(config, "password").map(|s| s.to_string());
+    let host_key = config_str(config, "host_key").map(|s| s.to_string());
+    let trust_new_hosts = config.get("trust_new_hosts").and_then(|v| v.as_bool()).unwrap_or(false);
 
     // Start the SSH agent.
     #[allow(unused_mut)] // This is only used on Windows.
     let (socket, mut pid_or_child) = match agent_socket() {
         Ok((socket, pid_or_child)) => (socket, pid_or_child),
-        Err(err) => return Err(err),
+        Err(err) => return Err(UploadError::io(err)),
     };
 
     // Call the handler function and then kill the agent whatever happens.
-    let possible_error = sftp_do_upload(
+    let possible_error = sftp_do_batch(
         SSHConnectionOptions {
             agent_socket: socket,
             hostname: hostname.to_string(),
@@ -169,10 +420,10 @@ fn sftp_support_upload(
             username,
             private_key,
             password,
+            host_key,
+            trust_new_hosts,
         },
-        &folder_path,
-        filename,
-        reader,
+        batch,
     );
     #[cfg(target_os = "windows")]
     {
@@ -183,9 +434,26 @@ fn sftp_support_upload(
     {
         let _ = unsafe { libc::kill(pid_or_child as i32, libc::SIGKILL) };
     }
-    if let Some(error) = possible_error {
-        return Err(error);
+    match possible_error {
+        Some(error) => Err(error),
+        None => Ok(()),
     }
+}
+
+fn sftp_support_upload(
+    filename: &str,
+    config: HashMap<String, serde_json::Value>,
+    source: &UploadSource,
+    ctx: &UploadContext,
+) -> Result<String, UploadError> {
+    let hostname = required_str(&config, "hostname")?;
+    let folder_path = config_str(&config, "path").map(|s| s.to_string());
+
+    // sftp does not report progress, so the best we can do is check for cancellation before starting.
+    ctx.check_cancelled()?;
+
+    // Run the batch. sftp reads the file straight from the source.
+    sftp_run_batch(&config, &sftp_batch(&folder_path, source.path(), filename)?)?;
 
     // Remove the slash from the end of the folder path if it exists.
     let folder_path = match folder_path {
@@ -199,20 +467,32 @@ fn sftp_support_upload(
         None => "".to_string(),
     };
 
+    // The remote path is used to delete the file later.
+    match folder_path.is_empty() {
+        true => ctx.set_deletion_token(filename),
+        false => ctx.set_deletion_token(format!("{}/{}", folder_path, filename)),
+    }
+
     // Get the URL rewrite.
-    let url_rewrite = match config.get("url_rewrite") {
-        Some(url_rewrite) => match url_rewrite.as_str() {
-            Some(url_rewrite) => url_rewrite.to_string(),
-            None => return Err("The URL rewrite must be a string.".to_string()),
-        },
-        None => "https://$hostname$folder_path/$filename".to_string(),
-    };
+    let url_rewrite = config_str(&config, "url_rewrite")
+        .unwrap_or("https://$hostname$folder_path/$filename");
     Ok(url_rewrite
-        .replace("$hostname", &hostname)
+        .replace("$hostname", hostname)
         .replace("$folder_path", &folder_path)
-        .replace("$filename", filename))
+        .replace("$filename", &urlencoding::encode(filename)))
 }
 
+// Deletes the file at the remote path from the SFTP server.
+fn sftp_support_delete(
+    remote_path: &str,
+    config: HashMap<String, serde_json::Value>,
+) -> Result<(), UploadError> {
+    sftp_run_batch(&config, &format!("rm {}\n", sftp_quote(remote_path)))
+}
+
+// Defines the format of a host key. This is the key type, the base64 key, and an optional comment on one line.
+const HOST_KEY_REGEX: &str = "^[a-z0-9@.-]+ [A-Za-z0-9+/]+=*( [^\\r\\n]*)?$";
+
 const AGENT_NAME: &str = if cfg!(target_os = "windows") {
     "pageant (PuTTY)"
 } else {
@@ -284,6 +564,26 @@ pub fn sftp_support() -> Uploader {
                     validation_error_message: None,
                 },
             ),
+            (
+                "host_key".to_string(),
+                ConfigOption::String {
+                    name: "Host Key".to_string(),
+                    description: "The public key of the SSH server as it appears in known_hosts (for example, ssh-ed25519 AAAA...). If set, only this key is trusted.".to_string(),
+                    default: None,
+                    required: false,
+                    password: false,
+                    regex: Some(HOST_KEY_REGEX.to_string()),
+                    validation_error_message: Some("The host key must be the key type followed by the base64 key.".to_string()),
+                },
+            ),
+            (
+                "trust_new_hosts".to_string(),
+                ConfigOption::Boolean {
+                    name: "Trust New Hosts".to_string(),
+                    description: "If no host key is set and the server is not in your known_hosts, trust the key it sends the first time. This is unsafe on networks you do not trust.".to_string(),
+                    default: Some(false),
+                },
+            ),
             (
                 "path".to_string(),
                 ConfigOption::String {
@@ -310,5 +610,192 @@ pub fn sftp_support() -> Uploader {
             ),
         ],
         upload: Box::new(sftp_support_upload),
+        delete: Some(Box::new(sftp_support_delete)),
+        accepted_mime_types: Vec::new(),
+        max_file_size: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Child;
+
+    #[test]
+    fn quotes_sftp_paths() {
+        assert_eq!(sftp_quote("a b.png"), "\"a b.png\"");
+        assert_eq!(sftp_quote("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+
+    #[test]
+    fn batch_makes_each_folder_before_the_put() {
+        let batch = sftp_batch(&Some("/srv/uploads/".to_string()), Path::new("/tmp/a.png"), "a.png").unwrap();
+        assert_eq!(
+            batch,
+            "-mkdir \"/srv\"\n-mkdir \"/srv/uploads\"\nput \"/tmp/a.png\" \"/srv/uploads/a.png\"\n"
+        );
+    }
+
+    #[test]
+    fn batch_without_a_folder_puts_in_the_home_folder() {
+        let batch = sftp_batch(&None, Path::new("/tmp/a.png"), "a.png").unwrap();
+        assert_eq!(batch, "put \"/tmp/a.png\" \"a.png\"\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn batch_rejects_paths_which_are_not_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"/tmp/\xff.png"));
+        assert!(matches!(sftp_batch(&None, path, "a.png"), Err(UploadError::Io { .. })));
+    }
+
+    #[test]
+    fn known_hosts_pattern_includes_non_default_ports() {
+        assert_eq!(known_hosts_pattern("example.com", 22), "example.com");
+        assert_eq!(known_hosts_pattern("example.com", 2222), "[example.com]:2222");
+    }
+
+    #[test]
+    fn host_key_regex_only_allows_one_key() {
+        let re = regex::Regex::new(HOST_KEY_REGEX).unwrap();
+        assert!(re.is_match("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIA=="));
+        assert!(re.is_match("ecdsa-sha2-nistp256 AAAAE2VjZHNh user@host"));
+        assert!(!re.is_match("AAAAC3NzaC1lZDI1NTE5AAAAIA=="));
+        assert!(!re.is_match("ssh-ed25519 AAAA\nevil.example ssh-ed25519 AAAA"));
+    }
+
+    // Defines a sshd running from a temporary folder. It is killed and the folder removed when dropped.
+    struct TestServer {
+        child: Child,
+        dir: PathBuf,
+        port: u16,
+    }
+
+    impl Drop for TestServer {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    // Finds sshd. It is usually in a sbin folder which is not in the PATH.
+    fn find_sshd() -> Option<PathBuf> {
+        let mut dirs: Vec<PathBuf> = ["/usr/sbin", "/usr/local/sbin", "/opt/homebrew/sbin"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        if let Some(path) = std::env::var_os("PATH") {
+            dirs.extend(std::env::split_paths(&path));
+        }
+        dirs.into_iter().map(|dir| dir.join("sshd")).find(|path| path.is_file())
+    }
+
+    // Makes a ed25519 key pair without a passphrase.
+    fn keygen(path: &Path) {
+        let status = Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+            .arg(path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    // Gets the key type and key from a public key file.
+    fn public_key(path: &Path) -> String {
+        let key = std::fs::read_to_string(path.with_extension("pub")).unwrap();
+        key.split_whitespace().take(2).collect::<Vec<_>>().join(" ")
+    }
+
+    // Starts sshd on a free port which accepts the client key for the current user. Returns None if OpenSSH
+    // is not installed.
+    fn start_sshd() -> Option<TestServer> {
+        let sshd = find_sshd()?;
+        let dir = temp_path("-sshd");
+        std::fs::create_dir_all(&dir).unwrap();
+        keygen(&dir.join("host_key"));
+        keygen(&dir.join("client_key"));
+        std::fs::copy(dir.join("client_key.pub"), dir.join("authorized_keys")).unwrap();
+
+        // Get a free port by binding to port 0.
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+
+        let config = format!(
+            "Port {port}\nListenAddress 127.0.0.1\nHostKey {dir}/host_key\nPidFile {dir}/sshd.pid\n\
+             AuthorizedKeysFile {dir}/authorized_keys\nStrictModes no\nPasswordAuthentication no\n\
+             Subsystem sftp internal-sftp\n",
+            port = port,
+            dir = dir.display(),
+        );
+        std::fs::write(dir.join("sshd_config"), config).unwrap();
+        let child = Command::new(sshd)
+            .arg("-D")
+            .arg("-e")
+            .arg("-f")
+            .arg(dir.join("sshd_config"))
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let server = TestServer { child, dir, port };
+
+        // Wait for sshd to listen.
+        for _ in 0..100 {
+            if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                return Some(server);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        panic!("sshd did not start listening");
+    }
+
+    fn test_config(server: &TestServer, host_key: String, folder: &Path) -> HashMap<String, serde_json::Value> {
+        let private_key = std::fs::read_to_string(server.dir.join("client_key")).unwrap();
+        HashMap::from([
+            ("hostname".to_string(), "127.0.0.1".into()),
+            ("port".to_string(), server.port.into()),
+            ("username".to_string(), whoami::username().into()),
+            ("private_key".to_string(), private_key.into()),
+            ("host_key".to_string(), host_key.into()),
+            ("path".to_string(), folder.display().to_string().into()),
+            ("url_rewrite".to_string(), "https://example.com$folder_path/$filename".into()),
+        ])
+    }
+
+    #[test]
+    fn uploads_and_deletes_with_local_openssh() {
+        let server = match start_sshd() {
+            Some(server) => server,
+            None => return eprintln!("sshd is not installed, skipping"),
+        };
+        let folder = server.dir.join("uploads/nested");
+        let config = test_config(&server, public_key(&server.dir.join("host_key")), &folder);
+
+        // Upload the file and make sure the missing folders were made.
+        let source = UploadSource::from_bytes(b"hello sftp", "test file.txt").unwrap();
+        let ctx = UploadContext::background();
+        let url = sftp_support_upload("test file.txt", config.clone(), &source, &ctx).unwrap();
+        assert_eq!(url, format!("https://example.com{}/test%20file.txt", folder.display()));
+        assert_eq!(std::fs::read(folder.join("test file.txt")).unwrap(), b"hello sftp");
+
+        // Delete it with the token the upload set.
+        let token = ctx.take_deletion_token().unwrap();
+        sftp_support_delete(&token, config).unwrap();
+        assert!(!folder.join("test file.txt").exists());
+    }
+
+    #[test]
+    fn refuses_a_host_key_which_does_not_match() {
+        let server = match start_sshd() {
+            Some(server) => server,
+            None => return eprintln!("sshd is not installed, skipping"),
+        };
+        let folder = server.dir.join("uploads");
+        let config = test_config(&server, public_key(&server.dir.join("client_key")), &folder);
+
+        let source = UploadSource::from_bytes(b"hello sftp", "test.txt").unwrap();
+        let err = sftp_support_upload("test.txt", config, &source, &UploadContext::background()).unwrap_err();
+        assert!(matches!(err, UploadError::Config { field: Some(ref field), .. } if field == "host_key"));
+        assert!(!folder.join("test.txt").exists());
     }
 }
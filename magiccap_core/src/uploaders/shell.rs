@@ -1,22 +1,154 @@
-use super::{ConfigOption, Uploader};
+use super::{
+    mime::guess_mime_type,
+    validation::{config_str, required_str},
+    ConfigOption, UploadContext, UploadError, UploadSource, Uploader,
+};
+use serde::Deserialize;
 use std::{
     collections::HashMap,
-    process::{Command, Stdio},
+    io::Read,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::mpsc,
+    time::{Duration, Instant},
 };
 
+// Defines the default timeout in seconds.
+const DEFAULT_TIMEOUT: u64 = 60;
+
+// Defines how long we wait for the output pipes to close after the process exits.
+const PIPE_DEADLINE: Duration = Duration::from_secs(5);
+
+// Defines the most of the stderr that is put in an error.
+const MAX_STDERR_LENGTH: usize = 2000;
+
+// Defines the output of the script when the JSON output option is on.
+#[derive(Deserialize)]
+struct ShellOutput {
+    url: String,
+    #[serde(default)]
+    deletion_url: Option<String>,
+    #[serde(default)]
+    thumbnail_url: Option<String>,
+}
+
+// Makes sure the output from the script is a URL.
+fn validate_url(url: &str, what: &str) -> Result<String, UploadError> {
+    match uriparse::URI::try_from(url.trim()) {
+        Ok(u) => Ok(u.to_string()),
+        Err(e) => Err(UploadError::local(format!(
+            "The {} the command returned is not valid: {}",
+            what, e
+        ))),
+    }
+}
+
+// Kills the process and everything it started. On Unix, the process is the leader of its own process group,
+// so the whole group is killed.
+fn kill_tree(process: &mut Child) {
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(-(process.id() as i32), libc::SIGKILL);
+    }
+
+    #[cfg(windows)]
+    let _ = Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &process.id().to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+
+    let _ = process.kill();
+    let _ = process.wait();
+}
+
+// Kills anything the process started which is still running after it exited, such as commands run in the
+// background. These would otherwise keep the pipes open. On Unix, they are still in the process group even
+// though the leader exited.
+fn kill_leftovers(process: &Child) {
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(-(process.id() as i32), libc::SIGKILL);
+    }
+
+    #[cfg(not(unix))]
+    let _ = process;
+}
+
+// Reads the pipe to the end in a thread so it does not fill up. The output is sent once the pipe closes.
+fn read_pipe<R: Read + Send + 'static>(mut pipe: R) -> mpsc::Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        let _ = sender.send(buf);
+    });
+    receiver
+}
+
+// Makes the stderr short enough to show to the user.
+fn format_stderr(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let stderr = stderr.trim();
+    if stderr.is_empty() {
+        return "".to_string();
+    }
+    match stderr.char_indices().nth(MAX_STDERR_LENGTH) {
+        Some((i, _)) => format!(": {}...", &stderr[..i]),
+        None => format!(": {}", stderr),
+    }
+}
+
+// Waits for the process to finish, killing it if the upload is cancelled or the timeout is hit.
+fn wait_for_process(
+    process: &mut Child,
+    ctx: &UploadContext,
+    timeout: Option<Duration>,
+) -> Result<ExitStatus, UploadError> {
+    let started = Instant::now();
+    loop {
+        if ctx.is_cancelled() {
+            kill_tree(process);
+            return Err(UploadError::Cancelled);
+        }
+        if let Some(timeout) = timeout {
+            if started.elapsed() >= timeout {
+                kill_tree(process);
+                return Err(UploadError::io(format!(
+                    "The command did not finish within {} seconds.",
+                    timeout.as_secs()
+                )));
+            }
+        }
+        match process.try_wait() {
+            Ok(Some(status)) => return Ok(status),
+            Ok(None) => std::thread::sleep(Duration::from_millis(50)),
+            Err(e) => {
+                kill_tree(process);
+                return Err(UploadError::io(format!(
+                    "Failed to wait for the process to finish: {}",
+                    e
+                )));
+            }
+        }
+    }
+}
+
 fn shell_support_upload(
     filename: &str,
     config: HashMap<String, serde_json::Value>,
-    mut reader: Box<dyn std::io::Read + Send + Sync>,
-) -> Result<String, String> {
-    // Get the command from the config.
-    let command = match config.get("command") {
-        Some(c) => match c.as_str() {
-            Some(c) => c,
-            None => return Err("The command must be a string.".to_string()),
-        },
-        None => return Err("The command is required.".to_string()),
+    source: &UploadSource,
+    ctx: &UploadContext,
+) -> Result<String, UploadError> {
+    // Open the file first so we do not start the process if it is missing.
+    let file = ctx.progress_reader(source.open()?, source.size());
+
+    // Get the options from the config.
+    let command = required_str(&config, "command")?;
+    let timeout = match config.get("timeout").and_then(|v| v.as_u64()).unwrap_or(DEFAULT_TIMEOUT) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
     };
+    let json_output = config.get("json_output").and_then(|v| v.as_bool()).unwrap_or(false);
 
     // Get the users preferred shell.
     let shell = match std::env::var("SHELL") {
@@ -24,52 +156,124 @@ fn shell_support_upload(
         Err(_) => "/bin/sh".to_string(),
     };
 
-    // Run the command.
-    let process = Command::new(shell)
-        .arg("-c")
+    // Build the command. Anything we do not know is passed as a blank string.
+    let capture = ctx.capture();
+    let mime_type = match guess_mime_type(filename, source) {
+        Ok(mime) => mime.to_string(),
+        Err(_) => "".to_string(),
+    };
+    let mut cmd = Command::new(shell);
+    cmd.arg("-c")
         .arg(command)
         .env("FILENAME", filename)
+        .env("MIME_TYPE", mime_type)
+        .env("FILE_SIZE", source.size().to_string())
+        .env("CAPTURE_TYPE", capture.capture_type.as_deref().unwrap_or(""))
+        .env(
+            "CAPTURE_ID",
+            capture.capture_id.map(|id| id.to_string()).unwrap_or_default(),
+        )
+        .env("FILE_PATH", capture.file_path.as_deref().unwrap_or(""))
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
-        .spawn();
+        .stderr(Stdio::piped());
+
+    // Put the process in its own group so anything it starts can be killed with it.
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
 
     // Unwrap any errors starting the process.
-    let mut process = match process {
+    let mut process = match cmd.spawn() {
         Ok(p) => p,
-        Err(e) => return Err(format!("Failed to start the process: {}", e)),
+        Err(e) => return Err(UploadError::local(format!("Failed to start the process: {}", e))),
     };
 
-    // Handle writing the reader to the stdin.
+    // Read the stdout and stderr in threads so the pipes do not fill up.
+    let stdout = read_pipe(process.stdout.take().unwrap());
+    let stderr = read_pipe(process.stderr.take().unwrap());
+
+    // Stream the file to the stdin in a thread so the timeout and cancellation work while the process is
+    // not reading it. Killing the process closes the pipe, which ends the copy. Write errors are ignored
+    // since a script using FILE_PATH can exit without reading the stdin. Anything left running in the
+    // background is killed before the copy is joined since it could be holding the stdin open.
     let mut stdin = process.stdin.take().unwrap();
-    if let Some(_) = std::io::copy(&mut reader, &mut stdin).err() {
-        // Make a best effort to kill the process.
-        let _ = process.kill();
-
-        // Return the error.
-        return Err("Failed to write the reader to the stdin.".to_string());
-    }
-
-    // Wait for the process to finish and output the result.
-    match process.wait_with_output() {
-        Ok(output) => {
-            if !output.status.success() {
-                return Err(format!(
-                    "The command failed with a non-zero exit code: {}",
-                    output.status
-                ));
-            }
-            match String::from_utf8(output.stdout) {
-                Ok(url) => Ok(url),
-                Err(e) => return Err(format!("Failed to read the URL from the stdout: {}", e)),
-            }
+    let status = std::thread::scope(|s| {
+        let mut file = file;
+        s.spawn(move || {
+            let _ = std::io::copy(&mut file, &mut stdin);
+        });
+        let status = wait_for_process(&mut process, ctx, timeout);
+        kill_leftovers(&process);
+        status
+    })?;
+
+    // Get the output. If something still has the pipes open, give up on them rather than hanging forever.
+    let stdout = stdout.recv_timeout(PIPE_DEADLINE).unwrap_or_default();
+    let stderr = format_stderr(&stderr.recv_timeout(PIPE_DEADLINE).unwrap_or_default());
+
+    // Output the result.
+    if !status.success() {
+        return Err(UploadError::local(format!(
+            "The command failed with a non-zero exit code ({}){}",
+            status, stderr
+        )));
+    }
+    let stdout = match String::from_utf8(stdout) {
+        Ok(s) => s,
+        Err(e) => {
+            return Err(UploadError::local(format!(
+                "Failed to read the URL from the stdout: {}",
+                e
+            )))
         }
-        Err(e) => Err(format!("Failed to wait for the process to finish: {}", e)),
+    };
+    if !json_output {
+        return validate_url(&stdout, "URL");
+    }
+
+    // Handle the JSON output.
+    let output: ShellOutput = match serde_json::from_str(stdout.trim()) {
+        Ok(o) => o,
+        Err(e) => {
+            return Err(UploadError::local(format!(
+                "Failed to parse the JSON from the stdout: {}",
+                e
+            )))
+        }
+    };
+    let url = validate_url(&output.url, "URL")?;
+    if let Some(deletion_url) = output.deletion_url.filter(|u| !u.trim().is_empty()) {
+        ctx.set_deletion_token(validate_url(&deletion_url, "deletion URL")?);
+    }
+    if let Some(thumbnail_url) = output.thumbnail_url.filter(|u| !u.trim().is_empty()) {
+        ctx.set_thumbnail_url(validate_url(&thumbnail_url, "thumbnail URL")?);
+    }
+    Ok(url)
+}
+
+// Deletes the file by requesting the deletion URL the command returned with the deletion method.
+fn shell_support_delete(
+    deletion_url: &str,
+    config: HashMap<String, serde_json::Value>,
+) -> Result<(), UploadError> {
+    let method = config_str(&config, "deletion_method").unwrap_or("GET");
+    match ureq::request(method, deletion_url).call() {
+        // 404 means the file is already gone.
+        Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+        Err(e) => Err(e.into()),
     }
 }
 
 const DESCRIPTION: &str = concat!(
-    "Uploads the screenshot using a shell program. The filename is passed in with the FILENAME environment variable. ",
-    "The shell script should return a 0 on success and put the URL in the stdout."
+    "Uploads the screenshot using a shell program. The file is written to the stdin, and the FILENAME, MIME_TYPE, ",
+    "FILE_SIZE, CAPTURE_TYPE, CAPTURE_ID, and FILE_PATH environment variables are set (blank if not known). ",
+    "The shell script should return a 0 on success and put the URL in the stdout. If JSON output is on, the ",
+    "stdout should instead be a JSON object with a url and an optional deletion_url and thumbnail_url. The ",
+    "deletion URL is requested with the deletion method to delete the file. Anything the command leaves running ",
+    "in the background is stopped when it exits."
 );
 
 pub fn shell_support() -> Uploader {
@@ -77,18 +281,130 @@ pub fn shell_support() -> Uploader {
         name: "Shell".to_string(),
         description: DESCRIPTION.to_string(),
         icon_path: "/icons/shell.png".to_string(),
-        options: vec![(
-            "command".to_string(),
-            ConfigOption::String {
-                name: "Command".to_string(),
-                description: "The command to run to upload the screenshot.".to_string(),
-                default: None,
-                required: true,
-                password: false,
-                regex: None,
-                validation_error_message: None,
-            },
-        )],
+        options: vec![
+            (
+                "command".to_string(),
+                ConfigOption::String {
+                    name: "Command".to_string(),
+                    description: "The command to run to upload the screenshot.".to_string(),
+                    default: None,
+                    required: true,
+                    password: false,
+                    regex: None,
+                    validation_error_message: None,
+                },
+            ),
+            (
+                "timeout".to_string(),
+                ConfigOption::Number {
+                    name: "Timeout".to_string(),
+                    description: "The seconds to wait before killing the command. 0 waits forever.".to_string(),
+                    default: Some(DEFAULT_TIMEOUT as i64),
+                    required: false,
+                    min: Some(0),
+                    max: None,
+                },
+            ),
+            (
+                "json_output".to_string(),
+                ConfigOption::Boolean {
+                    name: "JSON Output".to_string(),
+                    description: "Whether the command outputs JSON with a url, deletion_url, and thumbnail_url.".to_string(),
+                    default: Some(false),
+                },
+            ),
+            (
+                "deletion_method".to_string(),
+                ConfigOption::String {
+                    name: "Deletion Method".to_string(),
+                    description: "The HTTP method used to request the deletion URL. Defaults to GET.".to_string(),
+                    default: Some("GET".to_string()),
+                    required: false,
+                    password: false,
+                    regex: Some("^(GET|POST|DELETE)$".to_string()),
+                    validation_error_message: Some("The deletion method must be GET, POST, or DELETE.".to_string()),
+                },
+            ),
+        ],
         upload: Box::new(shell_support_upload),
+        delete: Some(Box::new(shell_support_delete)),
+        accepted_mime_types: Vec::new(),
+        max_file_size: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uploaders::mock_server::{config, MockServer, Response};
+    use serde_json::json;
+
+    // Runs the command as a upload of a small text file.
+    fn run(config: HashMap<String, serde_json::Value>, ctx: &UploadContext) -> Result<String, UploadError> {
+        let source = UploadSource::from_bytes(b"hello shell", "a.txt").unwrap();
+        shell_support_upload("a.txt", config, &source, ctx)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn returns_the_url_from_the_stdout() {
+        let config = config(json!({"command": "test \"$(cat)\" = 'hello shell' && echo https://example.com/$FILENAME"}));
+        assert_eq!(run(config, &UploadContext::background()).unwrap(), "https://example.com/a.txt");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reads_json_output() {
+        let config = config(json!({
+            "command": r#"echo '{"url": "https://example.com/a.txt", "deletion_url": "https://example.com/delete/a"}'"#,
+            "json_output": true,
+        }));
+        let ctx = UploadContext::background();
+        assert_eq!(run(config, &ctx).unwrap(), "https://example.com/a.txt");
+        assert_eq!(ctx.take_deletion_token().as_deref(), Some("https://example.com/delete/a"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn does_not_retry_commands_which_fail() {
+        let err = run(config(json!({"command": "echo broken >&2; exit 3"})), &UploadContext::background())
+            .unwrap_err();
+        assert!(matches!(err, UploadError::Local { .. }), "{:?}", err);
+        assert!(err.to_string().ends_with(": broken"), "{}", err);
+        assert!(!err.is_retryable());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn does_not_wait_for_background_commands() {
+        // The background sleep holds the pipes open until it is killed. This should be well within the pipe
+        // deadline since the sleep is killed when the shell exits.
+        let started = Instant::now();
+        let config = config(json!({"command": "sleep 30 & echo https://example.com/a.txt"}));
+        assert_eq!(run(config, &UploadContext::background()).unwrap(), "https://example.com/a.txt");
+        assert!(started.elapsed() < PIPE_DEADLINE, "took {:?}", started.elapsed());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn times_out_commands() {
+        let started = Instant::now();
+        let config = config(json!({"command": "sleep 30", "timeout": 1}));
+        let err = run(config, &UploadContext::background()).unwrap_err();
+        assert!(matches!(err, UploadError::Io { .. }), "{:?}", err);
+        assert!(started.elapsed() < Duration::from_secs(10), "took {:?}", started.elapsed());
+    }
+
+    #[test]
+    fn deletes_with_the_deletion_method() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/gone" => Response::new(404),
+            _ => Response::new(200),
+        });
+        shell_support_delete(&format!("{}/delete/a", server.url()), config(json!({}))).unwrap();
+        shell_support_delete(&format!("{}/a", server.url()), config(json!({"deletion_method": "DELETE"}))).unwrap();
+        shell_support_delete(&format!("{}/gone", server.url()), config(json!({}))).unwrap();
+        let methods: Vec<String> = server.requests().into_iter().map(|r| r.method).collect();
+        assert_eq!(methods, vec!["GET", "DELETE", "GET"]);
     }
 }
@@ -0,0 +1,134 @@
+use super::UploadError;
+use rand::{distributions::Alphanumeric, Rng};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+// Defines the file being uploaded. Uploaders open it as many times as they need and stream from it
+// rather than holding the whole capture in memory, and the size is known so they can set lengths up front.
+pub struct UploadSource {
+    path: PathBuf,
+    size: u64,
+
+    // Defines if we wrote the file ourselves and should remove it when the source is dropped.
+    temporary: bool,
+}
+
+// Turns an error reading the file into an upload error. A missing file will not come back, so retrying it is
+// pointless.
+fn file_error(action: &str, e: std::io::Error) -> UploadError {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => UploadError::local(format!("{}: {}", action, e)),
+        _ => UploadError::io(format!("{}: {}", action, e)),
+    }
+}
+
+impl UploadSource {
+    // Creates a source from a file that is already on the disk.
+    pub fn from_path<P: Into<PathBuf>>(path: P) -> Result<Self, UploadError> {
+        let path = path.into();
+        let size = match std::fs::metadata(&path) {
+            Ok(m) => m.len(),
+            Err(e) => return Err(file_error("Failed to read the file metadata", e)),
+        };
+        Ok(UploadSource {
+            path,
+            size,
+            temporary: false,
+        })
+    }
+
+    // Creates a source from data in memory by writing it to a temporary file. The file is only readable by
+    // the user since the temporary folder is shared, and any path separators in the filename are replaced so
+    // it cannot end up outside of the folder.
+    pub fn from_bytes(data: &[u8], filename: &str) -> Result<Self, UploadError> {
+        let random: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect();
+        let filename = filename.replace(['/', '\\', '\0'], "_");
+        let path = std::env::temp_dir().join(format!("magiccap-upload-{}-{}", random, filename));
+
+        // Create the file. This fails rather than following anything already at the path.
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let written = options.open(&path).and_then(|mut f| f.write_all(data));
+        if let Err(e) = written {
+            let _ = std::fs::remove_file(&path);
+            return Err(UploadError::io(format!("Failed to write a temporary file: {}", e)));
+        }
+        Ok(UploadSource {
+            path,
+            size: data.len() as u64,
+            temporary: true,
+        })
+    }
+
+    // Gets the path to the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Gets the size of the file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    // Opens the file for reading from the start.
+    pub fn open(&self) -> Result<File, UploadError> {
+        match File::open(&self.path) {
+            Ok(f) => Ok(f),
+            Err(e) => Err(file_error("Failed to open the file", e)),
+        }
+    }
+
+    // Reads the whole file into memory. Only use this when the upload format cannot be streamed.
+    pub fn read_all(&self) -> Result<Vec<u8>, UploadError> {
+        let mut data = Vec::with_capacity(self.size as usize);
+        match self.open()?.read_to_end(&mut data) {
+            Ok(_) => Ok(data),
+            Err(e) => Err(UploadError::io(format!("Failed to read the file: {}", e))),
+        }
+    }
+}
+
+impl Drop for UploadSource {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_temporary_files_in_the_folder() {
+        let source = UploadSource::from_bytes(b"data", "../../a/b\\c.png").unwrap();
+        assert_eq!(source.path().parent(), Some(std::env::temp_dir().as_path()));
+        let name = source.path().file_name().unwrap().to_str().unwrap();
+        assert!(name.ends_with("-.._.._a_b_c.png"), "{}", name);
+        assert_eq!(source.read_all().unwrap(), b"data");
+
+        // The file is removed with the source.
+        let path = source.path().to_path_buf();
+        drop(source);
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn temporary_files_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let source = UploadSource::from_bytes(b"data", "a.png").unwrap();
+        let mode = std::fs::metadata(source.path()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -1,5 +1,6 @@
-// Defines a regex for RFC 1123 compliant domain names or IP addresses.
-pub const DOMAIN_OR_IP_REGEX: &str = "^(([a-zA-Z]|[a-zA-Z][a-zA-Z0-9\\-]*[a-zA-Z0-9])\\.)*([A-Za-z]|[A-Za-z][A-Za-z0-9\\-]*[A-Za-z0-9])$";
+// Defines a regex for RFC 1123 compliant domain names or IPv4 addresses. Labels can start with a digit, but
+// the last one cannot so it is not mistaken for an address.
+pub const DOMAIN_OR_IP_REGEX: &str = "^((\\d{1,3}\\.){3}\\d{1,3}|(([a-zA-Z0-9]|[a-zA-Z0-9][a-zA-Z0-9\\-]*[a-zA-Z0-9])\\.)*([A-Za-z]|[A-Za-z][A-Za-z0-9\\-]*[A-Za-z0-9]))$";
 
 // Defines the FTP description for URL rewrites since it is fairly long.
 pub const URL_FTP_REWRITE_DESCRIPTION: &str = concat!(
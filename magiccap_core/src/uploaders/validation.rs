@@ -0,0 +1,313 @@
+use super::{ConfigOption, UploadError};
+use serde_json::Value;
+use std::collections::HashMap;
+
+impl ConfigOption {
+    // Gets the name shown to the user for the option.
+    fn display_name(&self) -> &str {
+        match self {
+            ConfigOption::String { name, .. } => name,
+            ConfigOption::LongString { name, .. } => name,
+            ConfigOption::Number { name, .. } => name,
+            ConfigOption::Boolean { name, .. } => name,
+            ConfigOption::Embedded { name, .. } => name,
+            ConfigOption::Custom { name, .. } => name,
+        }
+    }
+
+    // Gets the default value for the option as JSON if there is one.
+    fn default_value(&self) -> Option<Value> {
+        match self {
+            ConfigOption::String { default, .. } => default.clone().map(Value::String),
+            ConfigOption::LongString { default, .. } => default.clone().map(Value::String),
+            ConfigOption::Number { default, .. } => default.map(Value::from),
+            ConfigOption::Boolean { default, .. } => default.map(Value::Bool),
+            ConfigOption::Embedded { .. } | ConfigOption::Custom { .. } => None,
+        }
+    }
+
+    // Validates a value for this option and returns the value that should be used. Empty strings are treated
+    // as unset so clearing a textbox falls back to the default. Returns None if the value is unset.
+    pub fn validate(&self, key: &str, value: &Value) -> Result<Option<Value>, UploadError> {
+        // Handle unset values.
+        if value.is_null() || value.as_str() == Some("") {
+            return Ok(None);
+        }
+
+        match self {
+            ConfigOption::String {
+                name,
+                regex,
+                validation_error_message,
+                ..
+            } => {
+                let s = match value.as_str() {
+                    Some(s) => s,
+                    None => return Err(UploadError::config(key, format!("{} must be a string.", name))),
+                };
+
+                // A regex which does not compile is a problem with the uploader, not the value, so it is ignored.
+                if let Some(regex) = regex {
+                    if let Ok(re) = regex::Regex::new(regex) {
+                        if !re.is_match(s) {
+                            let message = match validation_error_message {
+                                Some(message) => message.clone(),
+                                None => format!("{} is not valid.", name),
+                            };
+                            return Err(UploadError::config(key, message));
+                        }
+                    }
+                }
+                Ok(Some(value.clone()))
+            }
+            ConfigOption::LongString { name, .. } => match value.is_string() {
+                true => Ok(Some(value.clone())),
+                false => Err(UploadError::config(key, format!("{} must be a string.", name))),
+            },
+            ConfigOption::Number { name, min, max, .. } => {
+                // The frontend sends numbers as floats, so accept them if they are whole.
+                let n = match value.as_i64() {
+                    Some(n) => n,
+                    None => match value.as_f64() {
+                        Some(f) if f.fract() == 0.0 => f as i64,
+                        _ => return Err(UploadError::config(key, format!("{} must be a whole number.", name))),
+                    },
+                };
+                if let Some(min) = min {
+                    if n < *min {
+                        return Err(UploadError::config(
+                            key,
+                            format!("{} must be at least {}.", name, min),
+                        ));
+                    }
+                }
+                if let Some(max) = max {
+                    if n > *max {
+                        return Err(UploadError::config(
+                            key,
+                            format!("{} must be at most {}.", name, max),
+                        ));
+                    }
+                }
+                Ok(Some(Value::from(n)))
+            }
+            ConfigOption::Boolean { name, .. } => match value.is_boolean() {
+                true => Ok(Some(value.clone())),
+                false => Err(UploadError::config(key, format!("{} must be true or false.", name))),
+            },
+
+            // These are set by components we do not know the shape of.
+            ConfigOption::Embedded { .. } | ConfigOption::Custom { .. } => Ok(Some(value.clone())),
+        }
+    }
+}
+
+// Validates the config for an uploader against its options. Values are checked against their types, regex, and
+// ranges, and unset options are filled in with their defaults. Uploaders can rely on every option in the result
+// being the right type. Keys the uploader does not define are passed through.
+pub fn validate_config(
+    options: &[(String, ConfigOption)],
+    mut config: HashMap<String, Value>,
+) -> Result<HashMap<String, Value>, UploadError> {
+    for (key, option) in options {
+        let value = match config.remove(key) {
+            Some(value) => option.validate(key, &value)?,
+            None => None,
+        };
+        match value.or_else(|| option.default_value()) {
+            Some(value) => {
+                config.insert(key.clone(), value);
+            }
+            None => {
+                if option.is_required() {
+                    return Err(UploadError::config(
+                        key,
+                        format!("{} is required.", option.display_name()),
+                    ));
+                }
+            }
+        }
+    }
+    Ok(config)
+}
+
+// Gets a string option from a validated config.
+pub fn config_str<'a>(config: &'a HashMap<String, Value>, key: &str) -> Option<&'a str> {
+    config.get(key).and_then(Value::as_str)
+}
+
+// Gets a required string option from a validated config. This only fails if the uploader was not validated.
+pub fn required_str<'a>(
+    config: &'a HashMap<String, Value>,
+    key: &str,
+) -> Result<&'a str, UploadError> {
+    match config_str(config, key) {
+        Some(s) => Ok(s),
+        None => Err(UploadError::config(key, format!("The option {} is required.", key))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Creates a string option.
+    fn string_option(required: bool, default: Option<&str>, regex: Option<&str>) -> ConfigOption {
+        ConfigOption::String {
+            name: "Token".to_string(),
+            description: String::new(),
+            default: default.map(str::to_string),
+            required,
+            password: false,
+            regex: regex.map(str::to_string),
+            validation_error_message: None,
+        }
+    }
+
+    // Creates a number option.
+    fn number_option(default: Option<i64>, min: Option<i64>, max: Option<i64>) -> ConfigOption {
+        ConfigOption::Number {
+            name: "Port".to_string(),
+            description: String::new(),
+            default,
+            min,
+            max,
+            required: false,
+        }
+    }
+
+    // Gets the message of a config error for the field specified.
+    fn config_error(result: Result<impl std::fmt::Debug, UploadError>, key: &str) -> String {
+        match result {
+            Err(UploadError::Config { field: Some(field), message }) if field == key => message,
+            other => panic!("expected a config error for {}, got {:?}", key, other),
+        }
+    }
+
+    #[test]
+    fn missing_required_options_are_errors() {
+        let options = vec![("token".to_string(), string_option(true, None, None))];
+        assert_eq!(config_error(validate_config(&options, HashMap::new()), "token"), "Token is required.");
+
+        // Empty strings count as unset.
+        let config = HashMap::from([("token".to_string(), json!(""))]);
+        assert_eq!(config_error(validate_config(&options, config), "token"), "Token is required.");
+
+        let config = HashMap::from([("token".to_string(), json!("abc"))]);
+        assert_eq!(validate_config(&options, config).unwrap()["token"], json!("abc"));
+    }
+
+    #[test]
+    fn missing_optional_options_are_left_out() {
+        let options = vec![("token".to_string(), string_option(false, None, None))];
+        let config = HashMap::from([("other".to_string(), json!(1))]);
+        let config = validate_config(&options, config).unwrap();
+        assert!(!config.contains_key("token"));
+        assert_eq!(config["other"], json!(1));
+    }
+
+    #[test]
+    fn unset_options_use_the_default() {
+        let options = vec![
+            ("token".to_string(), string_option(true, Some("default"), None)),
+            ("port".to_string(), number_option(Some(22), None, None)),
+            (
+                "secure".to_string(),
+                ConfigOption::Boolean {
+                    name: "Secure".to_string(),
+                    description: String::new(),
+                    default: Some(true),
+                },
+            ),
+        ];
+        let config = validate_config(&options, HashMap::from([("port".to_string(), Value::Null)])).unwrap();
+        assert_eq!(config["token"], json!("default"));
+        assert_eq!(config["port"], json!(22));
+        assert_eq!(config["secure"], json!(true));
+
+        // Set values win over the default.
+        let config = HashMap::from([("port".to_string(), json!(2222))]);
+        assert_eq!(validate_config(&options, config).unwrap()["port"], json!(2222));
+    }
+
+    #[test]
+    fn checks_strings_against_the_regex() {
+        let option = string_option(false, None, Some("^[a-z]+$"));
+        assert_eq!(option.validate("token", &json!("abc")).unwrap(), Some(json!("abc")));
+        assert_eq!(config_error(option.validate("token", &json!("ABC")), "token"), "Token is not valid.");
+
+        // The uploader can set its own message.
+        let mut option = string_option(false, None, Some("^[a-z]+$"));
+        if let ConfigOption::String { validation_error_message, .. } = &mut option {
+            *validation_error_message = Some("Use lowercase letters.".to_string());
+        }
+        assert_eq!(config_error(option.validate("token", &json!("1")), "token"), "Use lowercase letters.");
+
+        // A broken regex is a problem with the uploader, so the value is let through.
+        let option = string_option(false, None, Some("("));
+        assert_eq!(option.validate("token", &json!("abc")).unwrap(), Some(json!("abc")));
+    }
+
+    #[test]
+    fn checks_numbers_are_in_range() {
+        let option = number_option(None, Some(1), Some(65535));
+        assert_eq!(option.validate("port", &json!(1)).unwrap(), Some(json!(1)));
+        assert_eq!(option.validate("port", &json!(65535)).unwrap(), Some(json!(65535)));
+        assert_eq!(config_error(option.validate("port", &json!(0)), "port"), "Port must be at least 1.");
+        assert_eq!(config_error(option.validate("port", &json!(65536)), "port"), "Port must be at most 65535.");
+
+        // The frontend sends whole numbers as floats.
+        assert_eq!(option.validate("port", &json!(22.0)).unwrap(), Some(json!(22)));
+        assert_eq!(
+            config_error(option.validate("port", &json!(22.5)), "port"),
+            "Port must be a whole number."
+        );
+    }
+
+    #[test]
+    fn rejects_the_wrong_json_type() {
+        let option = string_option(false, None, None);
+        assert_eq!(config_error(option.validate("token", &json!(1)), "token"), "Token must be a string.");
+
+        let option = ConfigOption::LongString {
+            name: "Script".to_string(),
+            description: String::new(),
+            default: None,
+            required: false,
+        };
+        assert_eq!(config_error(option.validate("script", &json!([])), "script"), "Script must be a string.");
+
+        let option = number_option(None, None, None);
+        assert_eq!(
+            config_error(option.validate("port", &json!("22")), "port"),
+            "Port must be a whole number."
+        );
+
+        let option = ConfigOption::Boolean {
+            name: "Secure".to_string(),
+            description: String::new(),
+            default: None,
+        };
+        assert_eq!(
+            config_error(option.validate("secure", &json!("true")), "secure"),
+            "Secure must be true or false."
+        );
+
+        // The error stops the whole config from validating.
+        let options = vec![("secure".to_string(), option)];
+        let config = HashMap::from([("secure".to_string(), json!(1))]);
+        assert_eq!(config_error(validate_config(&options, config), "secure"), "Secure must be true or false.");
+    }
+
+    #[test]
+    fn required_str_needs_a_string() {
+        let config = HashMap::from([
+            ("url".to_string(), json!("https://example.com")),
+            ("port".to_string(), json!(1)),
+        ]);
+        assert_eq!(required_str(&config, "url").unwrap(), "https://example.com");
+        assert_eq!(config_error(required_str(&config, "port"), "port"), "The option port is required.");
+        assert_eq!(config_error(required_str(&config, "missing"), "missing"), "The option missing is required.");
+    }
+}
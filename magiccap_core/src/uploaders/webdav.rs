@@ -0,0 +1,387 @@
+use super::{
+    mime::guess_mime_type,
+    read_json_response,
+    validation::{config_str, required_str},
+    ConfigOption, UploadContext, UploadError, UploadSource, Uploader,
+};
+use base64::{engine::general_purpose, Engine};
+use std::collections::HashMap;
+
+// Defines the description for URL rewrites since it is fairly long.
+const URL_REWRITE_DESCRIPTION: &str = concat!(
+    "The string to rewrite the URL to. In this URL, you can use `$url` to represent the WebDAV URL, ",
+    "`$folder_path` to represent the folder path, and `$filename` to represent the filename. The default ",
+    "is `$url$folder_path/$filename`. This is ignored if a Nextcloud share link is created.",
+);
+
+// Encodes each part of a path so it can be added to a URL.
+fn encode_path(path: &str) -> String {
+    path.split('/')
+        .filter(|part| !part.is_empty())
+        .map(|part| urlencoding::encode(part).to_string())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+// Makes each folder in the encoded path. Folders which already exist are fine.
+fn make_folders(
+    agent: &ureq::Agent,
+    base_url: &str,
+    auth: &Option<String>,
+    folder_path: &str,
+) -> Result<(), UploadError> {
+    let mut url = base_url.to_string();
+    for part in folder_path.split('/').filter(|part| !part.is_empty()) {
+        url += "/";
+        url += part;
+        let mut req = agent.request("MKCOL", &url);
+        if let Some(auth) = auth {
+            req = req.set("Authorization", auth);
+        }
+        match req.call() {
+            // 405 means the folder is already there.
+            Ok(_) | Err(ureq::Error::Status(405, _)) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+// Creates a public share link for the file using the Nextcloud OCS API. The server is everything before
+// remote.php in the WebDAV URL.
+fn create_share_link(
+    agent: &ureq::Agent,
+    base_url: &str,
+    auth: &Option<String>,
+    remote_path: &str,
+) -> Result<String, UploadError> {
+    let server = match base_url.find("/remote.php") {
+        Some(i) => &base_url[..i],
+        None => {
+            return Err(UploadError::config(
+                "url",
+                "The WebDAV URL must contain remote.php to create a Nextcloud share link.",
+            ))
+        }
+    };
+    let mut req = agent
+        .post(&format!("{}/ocs/v2.php/apps/files_sharing/api/v1/shares", server))
+        .query("format", "json")
+        .set("OCS-APIRequest", "true");
+    if let Some(auth) = auth {
+        req = req.set("Authorization", auth);
+    }
+
+    // Share type 3 is a public link.
+    let resp = req.send_form(&[("path", remote_path), ("shareType", "3")])?;
+    let status = resp.status();
+    let json = read_json_response(resp)?;
+    match json["ocs"]["data"]["url"].as_str() {
+        Some(url) => Ok(url.to_string()),
+        None => Err(UploadError::RemoteRejected {
+            status,
+            body: json.to_string(),
+        }),
+    }
+}
+
+// Gets the authorization header for the username and password if they are set.
+fn basic_auth(config: &HashMap<String, serde_json::Value>) -> Option<String> {
+    let username = config_str(config, "username")?;
+    let password = config_str(config, "password").unwrap_or("");
+    Some(format!(
+        "Basic {}",
+        general_purpose::STANDARD.encode(format!("{}:{}", username, password)),
+    ))
+}
+
+fn webdav_support_upload(
+    filename: &str,
+    config: HashMap<String, serde_json::Value>,
+    source: &UploadSource,
+    ctx: &UploadContext,
+) -> Result<String, UploadError> {
+    // Get the base URL without the trailing slash.
+    let base_url = required_str(&config, "url")?.trim_end_matches('/');
+
+    // Handle authentication.
+    let auth = basic_auth(&config);
+
+    // Make the folders if we should.
+    let folder_path = encode_path(config_str(&config, "path").unwrap_or(""));
+    let agent = ureq::agent();
+    let create_folders = config.get("create_folders").and_then(|v| v.as_bool()).unwrap_or(true);
+    if create_folders && !folder_path.is_empty() {
+        make_folders(&agent, base_url, &auth, &folder_path)?;
+    }
+
+    // Put the file.
+    let mime = guess_mime_type(filename, source)?;
+    let folder_path = match folder_path.is_empty() {
+        true => "".to_string(),
+        false => format!("/{}", folder_path),
+    };
+    let remote_path = format!("{}/{}", folder_path, urlencoding::encode(filename));
+    let mut req = agent
+        .put(&format!("{}{}", base_url, remote_path))
+        .set("Content-Type", mime.as_ref())
+        .set("Content-Length", &source.size().to_string());
+    if let Some(auth) = &auth {
+        req = req.set("Authorization", auth);
+    }
+    match req.send(ctx.progress_reader(source.open()?, source.size())) {
+        Ok(_) => {}
+        Err(_) if ctx.is_cancelled() => return Err(UploadError::Cancelled),
+        Err(err) => return Err(err.into()),
+    }
+
+    // The encoded path within the WebDAV folder is used to delete the file later.
+    ctx.set_deletion_token(remote_path.clone());
+
+    // Create the share link if this is Nextcloud and it is enabled. The OCS API wants the path unencoded.
+    if config.get("nextcloud_share").and_then(|v| v.as_bool()).unwrap_or(false) {
+        let unencoded_path = match config_str(&config, "path") {
+            Some(path) => format!("/{}/{}", path.trim_matches('/'), filename),
+            None => format!("/{}", filename),
+        };
+        return create_share_link(&agent, base_url, &auth, &unencoded_path.replace("//", "/"));
+    }
+
+    // Return the rewritten URL.
+    let url_rewrite = config_str(&config, "url_rewrite").unwrap_or("$url$folder_path/$filename");
+    Ok(url_rewrite
+        .replace("$url", base_url)
+        .replace("$folder_path", &folder_path)
+        .replace("$filename", &urlencoding::encode(filename)))
+}
+
+// Deletes the file at the encoded path within the WebDAV folder. This also removes any share links to it.
+fn webdav_support_delete(
+    remote_path: &str,
+    config: HashMap<String, serde_json::Value>,
+) -> Result<(), UploadError> {
+    let base_url = required_str(&config, "url")?.trim_end_matches('/');
+    let mut req = ureq::delete(&format!("{}{}", base_url, remote_path));
+    if let Some(auth) = basic_auth(&config) {
+        req = req.set("Authorization", &auth);
+    }
+    match req.call() {
+        // 404 means the file is already gone.
+        Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub fn webdav_support() -> Uploader {
+    Uploader {
+        name: "WebDAV".to_string(),
+        description: "Uploads the screenshot to a WebDAV server such as Nextcloud or ownCloud.".to_string(),
+        icon_path: "/icons/webdav.svg".to_string(),
+        options: vec![
+            (
+                "url".to_string(),
+                ConfigOption::String {
+                    name: "URL".to_string(),
+                    description: concat!(
+                        "The URL of the WebDAV folder to upload to. For Nextcloud, this is ",
+                        "`https://<server>/remote.php/dav/files/<username>`.",
+                    ).to_string(),
+                    default: None,
+                    required: true,
+                    password: false,
+                    regex: Some("^https?://.+$".to_string()),
+                    validation_error_message: Some("The URL must start with http:// or https://.".to_string()),
+                },
+            ),
+            (
+                "username".to_string(),
+                ConfigOption::String {
+                    name: "Username".to_string(),
+                    description: "The username to use for the WebDAV server.".to_string(),
+                    default: None,
+                    required: false,
+                    password: false,
+                    regex: None,
+                    validation_error_message: None,
+                },
+            ),
+            (
+                "password".to_string(),
+                ConfigOption::String {
+                    name: "Password".to_string(),
+                    description: "The password to use for the WebDAV server. For Nextcloud, use an app password.".to_string(),
+                    default: None,
+                    required: false,
+                    password: true,
+                    regex: None,
+                    validation_error_message: None,
+                },
+            ),
+            (
+                "path".to_string(),
+                ConfigOption::String {
+                    name: "Folder Path".to_string(),
+                    description: "The folder path to upload the screenshot to within the WebDAV folder.".to_string(),
+                    default: None,
+                    required: false,
+                    password: false,
+                    regex: None,
+                    validation_error_message: None,
+                },
+            ),
+            (
+                "create_folders".to_string(),
+                ConfigOption::Boolean {
+                    name: "Create Missing Folders".to_string(),
+                    description: "Whether to create the folders in the folder path if they do not exist.".to_string(),
+                    default: Some(true),
+                },
+            ),
+            (
+                "nextcloud_share".to_string(),
+                ConfigOption::Boolean {
+                    name: "Create Nextcloud Share Link".to_string(),
+                    description: "Whether to create a public share link with the Nextcloud share API and return it as the URL.".to_string(),
+                    default: Some(false),
+                },
+            ),
+            (
+                "url_rewrite".to_string(),
+                ConfigOption::String {
+                    name: "URL Rewrite".to_string(),
+                    description: URL_REWRITE_DESCRIPTION.to_string(),
+                    default: None,
+                    required: false,
+                    password: false,
+                    regex: None,
+                    validation_error_message: None,
+                },
+            ),
+        ],
+        upload: Box::new(webdav_support_upload),
+        delete: Some(Box::new(webdav_support_delete)),
+        accepted_mime_types: Vec::new(),
+        max_file_size: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uploaders::mock_server::{config, MockServer, Response};
+    use serde_json::json;
+
+    // Uploads a small PNG to the server with the config specified.
+    fn upload(config: HashMap<String, serde_json::Value>) -> (Result<String, UploadError>, Option<String>) {
+        let source = UploadSource::from_bytes(b"\x89PNG\r\n\x1a\nimage", "a b.png").unwrap();
+        let ctx = UploadContext::background();
+        let result = webdav_support_upload("a b.png", config, &source, &ctx);
+        (result, ctx.take_deletion_token())
+    }
+
+    #[test]
+    fn uploads_into_new_folders() {
+        let server = MockServer::start(|req| match (req.method.as_str(), req.path.as_str()) {
+            // The first folder already exists.
+            ("MKCOL", "/dav/shots") => Response::new(405),
+            ("MKCOL", _) => Response::new(201),
+            ("PUT", _) => Response::new(201),
+            _ => Response::new(500),
+        });
+        let (result, deletion_token) = upload(config(json!({
+            "url": format!("{}/dav/", server.url()),
+            "username": "user",
+            "password": "pass",
+            "path": "/shots/2024 01/",
+        })));
+        assert_eq!(result.unwrap(), format!("{}/dav/shots/2024%2001/a%20b.png", server.url()));
+        assert_eq!(deletion_token.as_deref(), Some("/shots/2024%2001/a%20b.png"));
+
+        let requests = server.requests();
+        let calls: Vec<(&str, &str)> = requests.iter().map(|r| (r.method.as_str(), r.path.as_str())).collect();
+        assert_eq!(
+            calls,
+            vec![
+                ("MKCOL", "/dav/shots"),
+                ("MKCOL", "/dav/shots/2024%2001"),
+                ("PUT", "/dav/shots/2024%2001/a%20b.png"),
+            ]
+        );
+        let put = &requests[2];
+        assert_eq!(put.body, b"\x89PNG\r\n\x1a\nimage");
+        assert_eq!(put.header("Content-Type"), Some("image/png"));
+        assert_eq!(put.header("Authorization"), Some("Basic dXNlcjpwYXNz"));
+    }
+
+    #[test]
+    fn skips_folders_when_disabled() {
+        let server = MockServer::start(|_| Response::new(201));
+        let (result, _) = upload(config(json!({
+            "url": server.url(),
+            "path": "shots",
+            "create_folders": false,
+            "url_rewrite": "https://cdn.example.com$folder_path/$filename",
+        })));
+        assert_eq!(result.unwrap(), "https://cdn.example.com/shots/a%20b.png");
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "PUT");
+        assert_eq!(requests[0].header("Authorization"), None);
+    }
+
+    #[test]
+    fn creates_nextcloud_share_links() {
+        let server = MockServer::start(|req| match req.method.as_str() {
+            "POST" => Response::json(json!({"ocs": {"data": {"url": "https://cloud.example.com/s/abc"}}})),
+            _ => Response::new(201),
+        });
+        let (result, deletion_token) = upload(config(json!({
+            "url": format!("{}/remote.php/dav/files/user", server.url()),
+            "username": "user",
+            "path": "shots",
+            "create_folders": false,
+            "nextcloud_share": true,
+        })));
+        assert_eq!(result.unwrap(), "https://cloud.example.com/s/abc");
+        assert_eq!(deletion_token.as_deref(), Some("/shots/a%20b.png"));
+
+        let share = &server.requests()[1];
+        assert_eq!(share.path, "/ocs/v2.php/apps/files_sharing/api/v1/shares");
+        assert_eq!(share.query, "format=json");
+        assert_eq!(share.header("OCS-APIRequest"), Some("true"));
+        assert_eq!(share.body_str(), "path=%2Fshots%2Fa+b.png&shareType=3");
+    }
+
+    #[test]
+    fn reports_rejected_uploads() {
+        let server = MockServer::start(|req| match req.method.as_str() {
+            "PUT" => Response::new(401),
+            _ => Response::new(201),
+        });
+        let (result, deletion_token) = upload(config(json!({"url": server.url()})));
+        assert!(matches!(result, Err(UploadError::Auth { .. })));
+        assert_eq!(deletion_token, None);
+
+        let server = MockServer::start(|_| Response::new(507).body("Insufficient Storage"));
+        let (result, _) = upload(config(json!({"url": server.url()})));
+        assert!(matches!(result, Err(UploadError::RemoteRejected { status: 507, .. })));
+    }
+
+    #[test]
+    fn deletes_files() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/dav/gone.png" => Response::new(404),
+            "/dav/locked.png" => Response::new(423),
+            _ => Response::new(204),
+        });
+        let cfg = json!({"url": format!("{}/dav", server.url()), "username": "user"});
+        webdav_support_delete("/shots/a%20b.png", config(cfg.clone())).unwrap();
+        webdav_support_delete("/gone.png", config(cfg.clone())).unwrap();
+        assert!(webdav_support_delete("/locked.png", config(cfg)).is_err());
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "DELETE");
+        assert_eq!(requests[0].path, "/dav/shots/a%20b.png");
+        assert_eq!(requests[0].header("Authorization"), Some("Basic dXNlcjo="));
+    }
+}
@@ -18,7 +18,7 @@ pub fn start_recorder(gif: bool, monitor: Monitor, region: Region) -> Vec<u8> {
     // Start the recorder and temporary icon.
     let recorder_arc = Arc::new(Recorder::new(gif, monitor, region));
     let clone1 = Arc::clone(&recorder_arc);
-    let mut temp_icon = IconHandler::new(Box::new(move || clone1.stop_record_thread()));
+    let temp_icon = IconHandler::new(Box::new(move || clone1.stop_record_thread()));
 
     // Wait for stop to be called.
     recorder_arc.wait_for_stop();
@@ -0,0 +1,76 @@
+// Measures how much memory uploads use. This is its own test binary because it replaces the global allocator,
+// which would otherwise slow down every other test.
+use magiccap_core::test_support::{multipart_stream, UploadSource};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    io::Read,
+};
+
+// Counts the bytes allocated by each thread so the peak memory of an upload can be measured without
+// the other tests running at the same time getting in the way.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    static PEAK: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATED.try_with(|allocated| {
+            let total = allocated.get() + layout.size();
+            allocated.set(total);
+            let _ = PEAK.try_with(|peak| peak.set(peak.get().max(total)));
+        });
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get().saturating_sub(layout.size())));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// Runs the function and returns how many bytes above the starting point this thread had allocated at most.
+fn peak_memory<F: FnOnce()>(f: F) -> usize {
+    let start = ALLOCATED.with(|a| a.get());
+    PEAK.with(|p| p.set(start));
+    f();
+    PEAK.with(|p| p.get()) - start
+}
+
+// Defines the size of the file used to measure memory. This is much larger than any buffer we use.
+const LARGE_FILE_SIZE: usize = 32 * 1024 * 1024;
+
+// Creates a large source on the disk.
+fn large_source() -> UploadSource {
+    UploadSource::from_bytes(&vec![b'a'; LARGE_FILE_SIZE], "large.bin").unwrap()
+}
+
+#[test]
+fn streams_large_files_without_buffering_them() {
+    let source = large_source();
+    let mime = mime::APPLICATION_OCTET_STREAM;
+    let mut copied = 0;
+    let peak = peak_memory(|| {
+        let body = multipart_stream(vec![("key", "value")], "file", "large.bin", &mime, &source).unwrap();
+        copied = std::io::copy(&mut body.reader.take(u64::MAX), &mut std::io::sink()).unwrap();
+        assert_eq!(copied, body.content_length);
+    });
+    assert!(copied > LARGE_FILE_SIZE as u64);
+    assert!(peak < 1024 * 1024, "streaming peaked at {} bytes", peak);
+}
+
+#[test]
+fn measures_buffered_reads() {
+    // Make sure the allocator sees a buffered read so the test above means something.
+    let source = large_source();
+    let peak = peak_memory(|| {
+        assert_eq!(source.read_all().unwrap().len(), LARGE_FILE_SIZE);
+    });
+    assert!(peak >= LARGE_FILE_SIZE, "buffering peaked at {} bytes", peak);
+}